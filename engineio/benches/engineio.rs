@@ -45,8 +45,8 @@ pub mod util {
 #[cfg(not(feature = "async"))]
 pub mod tests {
     use bytes::Bytes;
-    use reqwest::Url;
     use rust_engineio::{Client, ClientBuilder, Error, Packet, PacketId};
+    use url::Url;
 
     use crate::tls_connector;
 
@@ -197,7 +197,7 @@ mod criterion_wrappers {
     }
 }
 
-/// async benches
+// async benches
 
 #[cfg(feature = "async")]
 pub mod tests {
@@ -1,65 +1,237 @@
 use std::{
+    collections::VecDeque,
     fmt::Debug,
+    io,
     pin::Pin,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use async_stream::try_stream;
 use bytes::Bytes;
-use futures_util::{Stream, StreamExt};
-use tokio::{runtime::Handle, sync::Mutex, time::Instant};
+use futures_util::{future::BoxFuture, Stream, StreamExt};
+use tokio::sync::{oneshot, Mutex, MutexGuard};
+use url::Url;
 
 use crate::{
-    asynchronous::{callback::OptionalCallback, transport::AsyncTransportType},
+    asynchronous::{
+        callback::OptionalCallback,
+        runtime::{with_timeout, Runtime},
+        transport::AsyncTransportType,
+    },
     error::Result,
+    metrics::{Metrics, MetricsSnapshot},
     packet::{HandshakePacket, Payload},
-    Error, Packet, PacketId,
+    recorder::{Direction, Recorder},
+    trace::{Instrument, Span},
+    DisconnectReason, Error, Packet, PacketId,
 };
 
 use super::generator::StreamGenerator;
 
+/// Opens and probes the transport a socket upgrades to once connected.
+pub(crate) type Upgrade =
+    Arc<dyn Fn() -> BoxFuture<'static, Result<AsyncTransportType>> + Send + Sync>;
+
+/// The transports of a socket, which change when it upgrades, and the packets
+/// waiting to be written.
+#[derive(Debug)]
+struct Transports {
+    current: AsyncTransportType,
+    /// A probed transport waiting for the polls and writes in flight to return.
+    pending: Option<AsyncTransportType>,
+    /// The packets waiting to be written.
+    buffer: VecDeque<Packet>,
+    /// Whether a write is in flight, which writes the buffer afterwards.
+    writing: bool,
+    /// The number of polls in flight on the current transport.
+    polls: usize,
+    /// The maximum length of the payloads written by the polling transport.
+    max_payload: usize,
+    recorder: Option<Recorder>,
+    metrics: Arc<Metrics>,
+    span: Span,
+}
+
+impl Transports {
+    /// Whether neither polls nor writes are in flight.
+    fn is_idle(&self) -> bool {
+        self.polls == 0 && !self.writing
+    }
+
+    /// Sends the upgrade packet over `transport`, which becomes the current
+    /// transport if that succeeds, and writes the buffered packets.
+    async fn switch(&mut self, transport: AsyncTransportType) -> Result<()> {
+        let packet = Packet::new(PacketId::Upgrade, Bytes::new());
+        let upgraded = transport
+            .as_transport()
+            .emit(packet.clone().into(), false)
+            .await
+            .map(|()| {
+                if let Some(recorder) = &self.recorder {
+                    recorder.record_packet(Direction::Out, transport.name(), &packet);
+                }
+                event!(info, parent: &self.span, transport = transport.name(); "upgraded transport");
+                self.metrics.upgraded(transport.name());
+                self.current = transport
+            });
+
+        // the buffer is flushed best-effort, a failed write keeps its packets
+        // in the buffer for the next one
+        while !self.buffer.is_empty() {
+            let payload = Payload::take(&mut self.buffer, self.max_payload);
+            let unsent = payload.clone();
+            let written = write(
+                &self.current,
+                payload,
+                self.recorder.as_ref(),
+                &self.metrics,
+            )
+            .instrument(self.span.clone())
+            .await;
+            if let Err(error) = written {
+                event!(warn, parent: &self.span; "could not flush the buffer: {}", error);
+                unsent.put_back(&mut self.buffer);
+                break;
+            }
+        }
+        self.metrics.set_buffered(self.buffer.len());
+        upgraded
+    }
+}
+
+/// Writes the packets of `payload` over `transport`, the polling transport
+/// sends them in a single request. The packets are recorded by `recorder`,
+/// if any, and counted by `metrics`.
+async fn write(
+    transport: &AsyncTransportType,
+    payload: Payload,
+    recorder: Option<&Recorder>,
+    metrics: &Metrics,
+) -> Result<()> {
+    for packet in payload.iter() {
+        event!(trace, packet_type = packet.packet_id, size = packet.data.len(), transport = transport.name(); "sending packet");
+        metrics.sent(packet);
+    }
+    if let Some(recorder) = recorder {
+        recorder.record_payload(Direction::Out, transport.name(), &payload);
+    }
+
+    if let AsyncTransportType::Polling(_) = transport {
+        return transport
+            .as_transport()
+            .emit(Bytes::try_from(payload)?, false)
+            .await;
+    }
+
+    for packet in payload {
+        let is_binary = packet.packet_id == PacketId::MessageBinary;
+
+        // if this is a binary attachment, then send the raw bytes
+        let data: Bytes = if is_binary {
+            packet.data
+        } else {
+            packet.into()
+        };
+        transport.as_transport().emit(data, is_binary).await?;
+    }
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Socket {
-    handle: Handle,
-    transport: Arc<Mutex<AsyncTransportType>>,
-    on_close: OptionalCallback<()>,
+    runtime: Arc<dyn Runtime>,
+    transports: Arc<Mutex<Transports>>,
+    upgrade: Option<Upgrade>,
+    on_close: OptionalCallback<DisconnectReason>,
     on_data: OptionalCallback<Bytes>,
     on_error: OptionalCallback<String>,
     on_open: OptionalCallback<()>,
     on_packet: OptionalCallback<Packet>,
+    on_upgrade: OptionalCallback<()>,
     connected: Arc<AtomicBool>,
+    close_reason: Arc<std::sync::Mutex<Option<DisconnectReason>>>,
     last_ping: Arc<Mutex<Instant>>,
     last_pong: Arc<Mutex<Instant>>,
     connection_data: Arc<HandshakePacket>,
     generator: StreamGenerator<Packet>,
+    recorder: Option<Recorder>,
+    metrics: Arc<Metrics>,
+    span: Span,
 }
 
 impl Socket {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         transport: AsyncTransportType,
         handshake: HandshakePacket,
-        on_close: OptionalCallback<()>,
+        url: &Url,
+        on_close: OptionalCallback<DisconnectReason>,
         on_data: OptionalCallback<Bytes>,
         on_error: OptionalCallback<String>,
         on_open: OptionalCallback<()>,
         on_packet: OptionalCallback<Packet>,
+        on_upgrade: OptionalCallback<()>,
+        read_timeout: Option<Duration>,
+        max_incoming_payload: Option<usize>,
+        recorder: Option<Recorder>,
+        runtime: Arc<dyn Runtime>,
+        upgrade: Option<Upgrade>,
     ) -> Self {
+        let span = span!(
+            INFO,
+            "engineio.connection",
+            sid = handshake.sid,
+            url = url.as_str(),
+            transport = transport.name()
+        );
+        let metrics = Arc::new(Metrics::new(transport.name()));
+        let transports = Arc::new(Mutex::new(Transports {
+            current: transport,
+            pending: None,
+            buffer: VecDeque::new(),
+            writing: false,
+            polls: 0,
+            max_payload: handshake.max_payload.unwrap_or(Payload::DEFAULT_MAX_LEN),
+            recorder: recorder.clone(),
+            metrics: metrics.clone(),
+            span: span.clone(),
+        }));
+        let last_ping = Arc::new(Mutex::new(Instant::now()));
+        // the server pings within its ping interval and timeout
+        let ping_timeout = Duration::from_millis(handshake.ping_interval + handshake.ping_timeout);
         Socket {
-            handle: Handle::current(),
+            runtime: runtime.clone(),
             on_close,
             on_data,
-            on_error,
+            on_error: on_error.clone(),
             on_open,
             on_packet,
-            transport: Arc::new(Mutex::new(transport.clone())),
+            on_upgrade: on_upgrade.clone(),
+            transports: transports.clone(),
+            upgrade,
             connected: Arc::new(AtomicBool::default()),
-            last_ping: Arc::new(Mutex::new(Instant::now())),
+            close_reason: Arc::new(std::sync::Mutex::new(None)),
+            last_ping: last_ping.clone(),
             last_pong: Arc::new(Mutex::new(Instant::now())),
             connection_data: Arc::new(handshake),
-            generator: StreamGenerator::new(Self::stream(transport)),
+            generator: StreamGenerator::new(Self::stream(
+                transports,
+                read_timeout,
+                last_ping,
+                ping_timeout,
+                max_incoming_payload,
+                recorder.clone(),
+                runtime,
+                on_upgrade,
+                on_error,
+            )),
+            recorder,
+            metrics,
+            span,
         }
     }
 
@@ -68,10 +240,22 @@ impl Socket {
     pub async fn connect(&self) -> Result<()> {
         // SAFETY: Has valid handshake due to type
         self.connected.store(true, Ordering::Release);
+        event!(debug, parent: &self.span; "connected");
+
+        if let Some(recorder) = &self.recorder {
+            let transport = self.transports.lock().await.current.name();
+            let handshake = serde_json::to_string(&*self.connection_data)?;
+            recorder.record_packet(
+                Direction::In,
+                transport,
+                &Packet::new(PacketId::Open, handshake),
+            );
+        }
 
         if let Some(on_open) = self.on_open.as_ref() {
             let on_open = on_open.clone();
-            self.handle.spawn(async move { on_open(()).await });
+            self.runtime
+                .spawn(Box::pin(async move { on_open(()).await }));
         }
 
         // set the last ping to now and set the connected state
@@ -80,9 +264,84 @@ impl Socket {
         // emit a pong packet to keep trigger the ping cycle on the server
         self.emit(Packet::new(PacketId::Pong, Bytes::new())).await?;
 
+        // probe the transport to upgrade to in the background
+        if let Some(upgrade) = self.upgrade.clone() {
+            let socket = self.clone();
+            let span = span!(INFO, parent: &self.span, "engineio.upgrade");
+            self.runtime.spawn(Box::pin(
+                async move {
+                    match upgrade().await {
+                        Ok(transport) => {
+                            event!(debug, transport = transport.name(); "probed transport");
+                            socket.upgrade_when_drained(transport).await
+                        }
+                        Err(error) => socket.call_error_callback(error.to_string()),
+                    }
+                }
+                .instrument(span),
+            ));
+        }
+
         Ok(())
     }
 
+    /// Switches to the probed `transport` once no polls and writes are in
+    /// flight. Until then, emitted packets are buffered.
+    async fn upgrade_when_drained(&self, transport: AsyncTransportType) {
+        let mut transports = self.transports.lock().await;
+        if !self.is_connected() {
+            return;
+        }
+
+        transports.pending = Some(transport);
+        Self::switch_if_idle(transports, &*self.runtime, &self.on_upgrade, &self.on_error).await;
+    }
+
+    /// Switches to the pending transport, if any, unless polls or writes are
+    /// in flight. The last of them to return switches then.
+    async fn switch_if_idle(
+        mut transports: MutexGuard<'_, Transports>,
+        runtime: &dyn Runtime,
+        on_upgrade: &OptionalCallback<()>,
+        on_error: &OptionalCallback<String>,
+    ) {
+        if !transports.is_idle() {
+            return;
+        }
+
+        if let Some(transport) = transports.pending.take() {
+            let result = transports.switch(transport).await;
+            drop(transports);
+            Self::handle_upgrade(runtime, on_upgrade, on_error, result);
+        }
+    }
+
+    /// Calls the `on_upgrade` callback or, if the upgrade failed, the error
+    /// callback. A failed upgrade keeps the current transport.
+    fn handle_upgrade(
+        runtime: &dyn Runtime,
+        on_upgrade: &OptionalCallback<()>,
+        on_error: &OptionalCallback<String>,
+        result: Result<()>,
+    ) {
+        match result {
+            Ok(()) => {
+                if let Some(on_upgrade) = on_upgrade.as_ref() {
+                    let on_upgrade = on_upgrade.clone();
+                    runtime.spawn(Box::pin(async move { on_upgrade(()).await }));
+                }
+            }
+            Err(error) => {
+                event!(warn; "upgrade failed: {}", error);
+                if let Some(on_error) = on_error.as_ref() {
+                    let on_error = on_error.clone();
+                    let text = error.to_string();
+                    runtime.spawn(Box::pin(async move { on_error(text).await }));
+                }
+            }
+        }
+    }
+
     /// A helper method that distributes
     pub(super) async fn handle_inconming_packet(&self, packet: Packet) -> Result<()> {
         // check for the appropriate action or callback
@@ -95,7 +354,7 @@ impl Socket {
                 self.handle_data(packet.data.clone());
             }
             PacketId::Close => {
-                self.handle_close();
+                self.handle_close(DisconnectReason::TransportClose);
             }
             PacketId::Upgrade => {
                 // this is already checked during the handshake, so just do nothing here
@@ -103,6 +362,7 @@ impl Socket {
             PacketId::Ping => {
                 self.pinged().await;
                 self.emit(Packet::new(PacketId::Pong, Bytes::new())).await?;
+                self.metrics.ponged(self.last_ping.lock().await.elapsed());
             }
             PacketId::Pong | PacketId::Open => {
                 // this will never happen as the pong and open
@@ -114,82 +374,161 @@ impl Socket {
         Ok(())
     }
 
-    /// Helper method that parses bytes and returns an iterator over the elements.
-    fn parse_payload(bytes: Bytes) -> impl Stream<Item = Result<Packet>> {
-        try_stream! {
-            let payload = Payload::try_from(bytes);
-
-            for elem in payload?.into_iter() {
-                yield elem;
-            }
-        }
-    }
-
     /// Creates a stream over the incoming packets, uses the streams provided by the
-    /// underlying transport types.
+    /// underlying transport types. Each payload is polled from the transport
+    /// that is current at that time and recorded by `recorder`, if any. Fails
+    /// with [`Error::PingTimeout`] if there was no ping within `ping_timeout`
+    /// after `last_ping`.
+    #[allow(clippy::too_many_arguments)]
     fn stream(
-        mut transport: AsyncTransportType,
+        transports: Arc<Mutex<Transports>>,
+        read_timeout: Option<Duration>,
+        last_ping: Arc<Mutex<Instant>>,
+        ping_timeout: Duration,
+        max_incoming_payload: Option<usize>,
+        recorder: Option<Recorder>,
+        runtime: Arc<dyn Runtime>,
+        on_upgrade: OptionalCallback<()>,
+        on_error: OptionalCallback<String>,
     ) -> Pin<Box<impl Stream<Item = Result<Packet>> + 'static + Send>> {
         // map the byte stream of the underlying transport
         // to a packet stream
         Box::pin(try_stream! {
-            for await payload in transport.as_pin_box() {
-                for await packet in Self::parse_payload(payload?) {
-                    yield packet?;
+            loop {
+                let mut transport = {
+                    let mut transports = transports.lock().await;
+                    transports.polls += 1;
+                    transports.current.clone()
+                };
+                let mut payloads = transport.as_pin_box();
+                let remaining = ping_timeout.saturating_sub(last_ping.lock().await.elapsed());
+                let payload = if remaining.is_zero() {
+                    Err(Error::ReadTimeout(remaining))
+                } else {
+                    let timeout = read_timeout.map_or(remaining, |t| t.min(remaining));
+                    with_timeout(&*runtime, Some(timeout), Error::ReadTimeout, async { Ok(payloads.next().await) })
+                        .await
+                };
+                let mut lock = transports.lock().await;
+                lock.polls -= 1;
+                Self::switch_if_idle(lock, &*runtime, &on_upgrade, &on_error).await;
+
+                let payload = match payload {
+                    Err(Error::ReadTimeout(_)) if last_ping.lock().await.elapsed() >= ping_timeout => {
+                        Err(Error::PingTimeout(ping_timeout))?
+                    }
+                    payload => payload?,
+                };
+                let payload = match payload {
+                    Some(payload) => payload?,
+                    None => break,
+                };
+                if let Some(max) = max_incoming_payload {
+                    if payload.len() > max {
+                        Err(Error::InvalidPayloadSize(payload.len(), max))?;
+                    }
+                }
+                let payload = Payload::try_from(payload)?;
+                if let Some(recorder) = &recorder {
+                    recorder.record_payload(Direction::In, transport.name(), &payload);
+                }
+                for packet in payload {
+                    yield packet;
                 }
             }
         })
     }
 
     pub async fn disconnect(&self) -> Result<()> {
-        if let Some(on_close) = self.on_close.as_ref() {
-            let on_close = on_close.clone();
-            self.handle.spawn(async move { on_close(()).await });
-        }
-
         self.emit(Packet::new(PacketId::Close, Bytes::new()))
             .await?;
 
-        self.connected.store(false, Ordering::Release);
+        self.close(DisconnectReason::ForcedClose);
+        event!(debug, parent: &self.span; "disconnected");
 
         Ok(())
     }
 
     /// Sends a packet to the server.
     pub async fn emit(&self, packet: Packet) -> Result<()> {
+        self.emit_batch(vec![packet]).await
+    }
+
+    /// Sends packets to the server in order, without other packets in between.
+    /// Packets emitted while a write is in flight are buffered and written
+    /// after it, the polling transport batches them into as few requests as
+    /// the maximum payload allows. The packets of a failed write stay
+    /// buffered for the next one.
+    pub async fn emit_batch(&self, packets: Vec<Packet>) -> Result<()> {
         if !self.connected.load(Ordering::Acquire) {
             let error = Error::IllegalActionBeforeOpen();
             self.call_error_callback(format!("{}", error));
             return Err(error);
         }
 
-        let is_binary = packet.packet_id == PacketId::MessageBinary;
+        let mut transports = self.transports.lock().await;
+        transports.buffer.extend(packets);
+        self.metrics.set_buffered(transports.buffer.len());
+        // the write in flight or the pending upgrade writes the buffer
+        if transports.writing || transports.pending.is_some() {
+            return Ok(());
+        }
 
-        // send a post request with the encoded payload as body
-        // if this is a binary attachment, then send the raw bytes
-        let data: Bytes = if is_binary {
-            packet.data
-        } else {
-            packet.into()
-        };
+        transports.writing = true;
+        drop(transports);
+
+        // the buffer is written by a task of its own, so that dropping the
+        // returned future neither loses the write in flight nor leaves
+        // `writing` set
+        let (sender, receiver) = oneshot::channel();
+        let socket = self.clone();
+        self.runtime.spawn(Box::pin(async move {
+            let _ = sender.send(socket.flush().await);
+        }));
+        receiver
+            .await
+            .unwrap_or_else(|_| Err(Error::IncompleteIo(io::ErrorKind::Interrupted.into())))
+    }
 
-        let lock = self.transport.lock().await;
-        let fut = lock.as_transport().emit(data, is_binary);
+    /// Writes the buffer until it's empty, a write failed or an upgrade is
+    /// pending.
+    async fn flush(&self) -> Result<()> {
+        let mut transports = self.transports.lock().await;
+        let mut result = Ok(());
+        while result.is_ok() && !transports.buffer.is_empty() && transports.pending.is_none() {
+            let max_payload = transports.max_payload;
+            let payload = Payload::take(&mut transports.buffer, max_payload);
+            self.metrics.set_buffered(transports.buffer.len());
+            let transport = transports.current.clone();
+            drop(transports);
+
+            let unsent = payload.clone();
+            result = write(&transport, payload, self.recorder.as_ref(), &self.metrics)
+                .instrument(self.span.clone())
+                .await;
+            transports = self.transports.lock().await;
+            if result.is_err() {
+                unsent.put_back(&mut transports.buffer);
+                self.metrics.set_buffered(transports.buffer.len());
+            }
+        }
+        transports.writing = false;
+        Self::switch_if_idle(transports, &*self.runtime, &self.on_upgrade, &self.on_error).await;
 
-        if let Err(error) = fut.await {
+        if let Err(error) = &result {
             self.call_error_callback(error.to_string());
-            return Err(error);
         }
-
-        Ok(())
+        result
     }
 
     /// Calls the error callback with a given message.
     #[inline]
     fn call_error_callback(&self, text: String) {
+        event!(warn, parent: &self.span; "{}", text);
         if let Some(on_error) = self.on_error.as_ref() {
             let on_error = on_error.clone();
-            self.handle.spawn(async move { on_error(text).await });
+            self.runtime
+                .spawn(Box::pin(async move { on_error(text).await }));
         }
     }
 
@@ -202,27 +541,51 @@ impl Socket {
         *self.last_ping.lock().await = Instant::now();
     }
 
+    pub(crate) fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// The reason the socket closed for, if it did.
+    pub(crate) fn close_reason(&self) -> Option<DisconnectReason> {
+        self.close_reason.lock().ok().and_then(|reason| *reason)
+    }
+
     pub(crate) fn handle_packet(&self, packet: Packet) {
+        event!(trace, parent: &self.span, packet_type = packet.packet_id, size = packet.data.len(); "received packet");
+        self.metrics.received(&packet);
         if let Some(on_packet) = self.on_packet.as_ref() {
             let on_packet = on_packet.clone();
-            self.handle.spawn(async move { on_packet(packet).await });
+            self.runtime
+                .spawn(Box::pin(async move { on_packet(packet).await }));
         }
     }
 
     pub(crate) fn handle_data(&self, data: Bytes) {
         if let Some(on_data) = self.on_data.as_ref() {
             let on_data = on_data.clone();
-            self.handle.spawn(async move { on_data(data).await });
+            self.runtime
+                .spawn(Box::pin(async move { on_data(data).await }));
         }
     }
 
-    pub(crate) fn handle_close(&self) {
+    pub(crate) fn handle_close(&self, reason: DisconnectReason) {
+        event!(debug, parent: &self.span; "closed: {}", reason);
+        self.close(reason);
+    }
+
+    /// Marks the socket as closed and calls the close callback, once.
+    fn close(&self, reason: DisconnectReason) {
+        if !self.connected.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        if let Ok(mut close_reason) = self.close_reason.lock() {
+            *close_reason = Some(reason);
+        }
         if let Some(on_close) = self.on_close.as_ref() {
             let on_close = on_close.clone();
-            self.handle.spawn(async move { on_close(()).await });
+            self.runtime
+                .spawn(Box::pin(async move { on_close(reason).await }));
         }
-
-        self.connected.store(false, Ordering::Release);
     }
 }
 
@@ -241,12 +604,13 @@ impl Stream for Socket {
 impl Debug for Socket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Socket")
-            .field("transport", &self.transport)
+            .field("transports", &self.transports)
             .field("on_close", &self.on_close)
             .field("on_data", &self.on_data)
             .field("on_error", &self.on_error)
             .field("on_open", &self.on_open)
             .field("on_packet", &self.on_packet)
+            .field("on_upgrade", &self.on_upgrade)
             .field("connected", &self.connected)
             .field("last_ping", &self.last_ping)
             .field("last_pong", &self.last_pong)
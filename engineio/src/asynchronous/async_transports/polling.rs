@@ -1,20 +1,22 @@
-use adler32::adler32;
 use async_stream::try_stream;
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
 use bytes::{BufMut, Bytes, BytesMut};
 use futures_util::{Stream, StreamExt};
 use http::HeaderMap;
-use native_tls::TlsConnector;
 use reqwest::{Client, ClientBuilder, Response};
 use std::fmt::Debug;
-use std::time::SystemTime;
 use std::{pin::Pin, sync::Arc};
 use tokio::sync::RwLock;
 use url::Url;
 
-use crate::asynchronous::generator::StreamGenerator;
-use crate::{asynchronous::transport::AsyncTransport, error::Result, Error};
+use crate::asynchronous::{generator::StreamGenerator, runtime::TokioContext};
+use crate::tls::use_preconfigured_tls;
+use crate::transport::timestamp;
+use crate::{
+    asynchronous::transport::AsyncTransport, error::Result, CookieJar, Error, Proxy, Timeouts,
+    TlsConfig,
+};
 
 /// An asynchronous polling type. Makes use of the nonblocking reqwest types and
 /// methods.
@@ -23,27 +25,42 @@ pub struct PollingTransport {
     client: Client,
     base_url: Arc<RwLock<Url>>,
     generator: StreamGenerator<Bytes>,
+    timeouts: Timeouts,
+    timestamp_param: Option<String>,
 }
 
 impl PollingTransport {
+    /// Creates an instance of `PollingTransport`. Requests carry a cache busting
+    /// timestamp in the query parameter `timestamp_param`, if given. Response
+    /// bodies exceeding `max_payload` bytes are rejected while being read.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         base_url: Url,
-        tls_config: Option<TlsConnector>,
+        tls_config: Option<TlsConfig>,
         opening_headers: Option<HeaderMap>,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        timestamp_param: Option<String>,
+        max_payload: Option<usize>,
     ) -> Self {
-        let client = match (tls_config, opening_headers) {
-            (Some(config), Some(map)) => ClientBuilder::new()
-                .use_preconfigured_tls(config)
-                .default_headers(map)
-                .build()
-                .unwrap(),
-            (Some(config), None) => ClientBuilder::new()
-                .use_preconfigured_tls(config)
-                .build()
-                .unwrap(),
-            (None, Some(map)) => ClientBuilder::new().default_headers(map).build().unwrap(),
-            (None, None) => Client::new(),
-        };
+        let mut builder = ClientBuilder::new();
+        if let Some(config) = tls_config {
+            builder = use_preconfigured_tls!(builder, config);
+        }
+        if let Some(map) = opening_headers {
+            builder = builder.default_headers(map);
+        }
+        if let Some(timeout) = timeouts.connect {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy.into_reqwest());
+        }
+        if let Some(jar) = cookie_jar {
+            builder = builder.cookie_provider(jar);
+        }
+        let client = builder.build().unwrap();
 
         let mut url = base_url;
         url.query_pairs_mut().append_pair("transport", "polling");
@@ -51,41 +68,84 @@ impl PollingTransport {
         PollingTransport {
             client: client.clone(),
             base_url: Arc::new(RwLock::new(url.clone())),
-            generator: StreamGenerator::new(Self::stream(url, client)),
+            // `reqwest` requires a tokio context
+            generator: StreamGenerator::new(Box::pin(TokioContext::new(Self::stream(
+                url,
+                client,
+                timeouts,
+                timestamp_param.clone(),
+                max_payload,
+            )))),
+            timeouts,
+            timestamp_param,
         }
     }
 
-    fn address(mut url: Url) -> Result<Url> {
-        let reader = format!("{:#?}", SystemTime::now());
-        let hash = adler32(reader.as_bytes()).unwrap();
-        url.query_pairs_mut().append_pair("t", &hash.to_string());
-        Ok(url)
+    fn address(mut url: Url, timestamp_param: Option<&str>) -> Url {
+        if let Some(param) = timestamp_param {
+            url.query_pairs_mut().append_pair(param, &timestamp());
+        }
+        url
     }
 
-    fn send_request(url: Url, client: Client) -> impl Stream<Item = Result<Response>> {
+    fn send_request(
+        url: Url,
+        client: Client,
+        timeouts: Timeouts,
+        timestamp_param: Option<String>,
+    ) -> impl Stream<Item = Result<Response>> {
         try_stream! {
-            let address = Self::address(url);
+            let address = Self::address(url, timestamp_param.as_deref());
 
             yield client
-                .get(address?)
-                .send().await?
+                .get(address)
+                .send().await
+                .map_err(|err| timeouts.reqwest_error(err, None, Error::ReadTimeout))?
         }
     }
 
     fn stream(
         url: Url,
         client: Client,
+        timeouts: Timeouts,
+        timestamp_param: Option<String>,
+        max_payload: Option<usize>,
     ) -> Pin<Box<dyn Stream<Item = Result<Bytes>> + 'static + Send>> {
         Box::pin(try_stream! {
             loop {
-                for await elem in Self::send_request(url.clone(), client.clone()) {
-                    for await bytes in elem?.bytes_stream() {
-                        yield bytes?;
-                    }
+                let request =
+                    Self::send_request(url.clone(), client.clone(), timeouts, timestamp_param.clone());
+                // yields whole payloads, so a returned poll is fully drained
+                for await elem in request {
+                    yield Self::read_body(elem?, max_payload).await?;
                 }
             }
         })
     }
+
+    /// Reads the body of `response` in chunks, failing with
+    /// [`Error::InvalidPayloadSize`] as soon as it exceeds `max_payload` bytes
+    /// instead of buffering all of it.
+    async fn read_body(mut response: Response, max_payload: Option<usize>) -> Result<Bytes> {
+        let max = match max_payload {
+            Some(max) => max,
+            None => return Ok(response.bytes().await?),
+        };
+        if let Some(length) = response.content_length() {
+            if length > max as u64 {
+                return Err(Error::InvalidPayloadSize(length as usize, max));
+            }
+        }
+
+        let mut body = BytesMut::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.put(chunk);
+            if body.len() > max {
+                return Err(Error::InvalidPayloadSize(body.len(), max));
+            }
+        }
+        Ok(body.freeze())
+    }
 }
 
 impl Stream for PollingTransport {
@@ -115,12 +175,16 @@ impl AsyncTransport for PollingTransport {
             data
         };
 
-        let status = self
-            .client
-            .post(self.address().await?)
-            .body(data_to_send)
-            .send()
-            .await?
+        let mut request = self.client.post(self.address().await?).body(data_to_send);
+        if let Some(timeout) = self.timeouts.request {
+            request = request.timeout(timeout);
+        }
+        let status = TokioContext::new(Box::pin(request.send()))
+            .await
+            .map_err(|err| {
+                self.timeouts
+                    .reqwest_error(err, self.timeouts.request, Error::RequestTimeout)
+            })?
             .status()
             .as_u16();
 
@@ -136,6 +200,13 @@ impl AsyncTransport for PollingTransport {
         Ok(self.base_url.read().await.clone())
     }
 
+    async fn address(&self) -> Result<Url> {
+        Ok(Self::address(
+            self.base_url().await?,
+            self.timestamp_param.as_deref(),
+        ))
+    }
+
     async fn set_base_url(&self, base_url: Url) -> Result<()> {
         let mut url = base_url;
         if !url
@@ -168,7 +239,16 @@ mod test {
     #[tokio::test]
     async fn polling_transport_base_url() -> Result<()> {
         let url = crate::test::engine_io_server()?.to_string();
-        let transport = PollingTransport::new(Url::from_str(&url[..]).unwrap(), None, None);
+        let transport = PollingTransport::new(
+            Url::from_str(&url[..]).unwrap(),
+            None,
+            None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
+            None,
+        );
         assert_eq!(
             transport.base_url().await?.to_string(),
             url.clone() + "?transport=polling"
@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::asynchronous::transport::AsyncTransport;
+use crate::error::Result;
+use crate::Packet;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::Stream;
+use tokio::sync::RwLock;
+use url::Url;
+
+/// An asynchronous transport which yields recorded packets instead of
+/// connecting to a server. Emitted packets are discarded.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    packets: Arc<Mutex<VecDeque<Packet>>>,
+    base_url: Arc<RwLock<Url>>,
+}
+
+impl ReplayTransport {
+    /// Creates an instance of `ReplayTransport` yielding `packets` in order,
+    /// the stream ends after the last one.
+    pub fn new(base_url: Url, packets: VecDeque<Packet>) -> Self {
+        ReplayTransport {
+            packets: Arc::new(Mutex::new(packets)),
+            base_url: Arc::new(RwLock::new(base_url)),
+        }
+    }
+}
+
+impl Stream for ReplayTransport {
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(match self.packets.lock() {
+            Ok(mut packets) => packets.pop_front().map(|packet| Ok(packet.into())),
+            Err(error) => Some(Err(error.into())),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncTransport for ReplayTransport {
+    async fn emit(&self, _: Bytes, _: bool) -> Result<()> {
+        Ok(())
+    }
+
+    async fn base_url(&self) -> Result<Url> {
+        Ok(self.base_url.read().await.clone())
+    }
+
+    async fn set_base_url(&self, base_url: Url) -> Result<()> {
+        *self.base_url.write().await = base_url;
+        Ok(())
+    }
+}
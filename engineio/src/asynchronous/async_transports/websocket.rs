@@ -2,19 +2,20 @@ use std::fmt::Debug;
 use std::pin::Pin;
 use std::sync::Arc;
 
+use crate::asynchronous::runtime::{with_timeout, Runtime};
 use crate::asynchronous::transport::AsyncTransport;
 use crate::error::Result;
+use crate::{CookieJar, Error, Proxy, Timeouts};
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::stream::StreamExt;
 use futures_util::Stream;
 use http::HeaderMap;
 use tokio::sync::RwLock;
-use tokio_tungstenite::connect_async;
 use tungstenite::client::IntoClientRequest;
 use url::Url;
 
-use super::websocket_general::AsyncWebsocketGeneralTransport;
+use super::websocket_general::{connect, AsyncWebsocketGeneralTransport};
 
 /// An asynchronous websocket transport type.
 /// This type only allows for plain websocket
@@ -27,7 +28,16 @@ pub struct WebsocketTransport {
 
 impl WebsocketTransport {
     /// Creates a new instance over a request that might hold additional headers and an URL.
-    pub async fn new(base_url: Url, headers: Option<HeaderMap>) -> Result<Self> {
+    /// Messages exceeding `max_payload` bytes are rejected while being read.
+    pub async fn new(
+        base_url: Url,
+        headers: Option<HeaderMap>,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        max_payload: Option<usize>,
+        runtime: Arc<dyn Runtime>,
+    ) -> Result<Self> {
         let mut url = base_url;
         url.query_pairs_mut().append_pair("transport", "websocket");
         url.set_scheme("ws").unwrap();
@@ -38,7 +48,21 @@ impl WebsocketTransport {
             req.headers_mut().extend(map);
         }
 
-        let (ws_stream, _) = connect_async(req).await?;
+        let ws_stream = with_timeout(
+            &*runtime,
+            timeouts.connect,
+            Error::ConnectTimeout,
+            connect(
+                req,
+                &url,
+                None,
+                &*runtime,
+                proxy.as_ref(),
+                cookie_jar.as_deref(),
+                max_payload,
+            ),
+        )
+        .await?;
         let (sen, rec) = ws_stream.split();
 
         let inner = AsyncWebsocketGeneralTransport::new(sen, rec).await;
@@ -48,15 +72,16 @@ impl WebsocketTransport {
         })
     }
 
+    /// Sends probe packet to ensure connection is valid
+    pub(crate) async fn probe(&self) -> Result<()> {
+        self.inner.probe().await
+    }
+
     /// Sends probe packet to ensure connection is valid, then sends upgrade
     /// request
     pub(crate) async fn upgrade(&self) -> Result<()> {
         self.inner.upgrade().await
     }
-
-    pub(crate) async fn poll_next(&self) -> Result<Option<Bytes>> {
-        self.inner.poll_next().await
-    }
 }
 
 #[async_trait]
@@ -111,14 +136,23 @@ impl Debug for WebsocketTransport {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::ENGINE_IO_VERSION;
+    use crate::{asynchronous::TokioRuntime, ENGINE_IO_VERSION};
     use std::str::FromStr;
 
     async fn new() -> Result<WebsocketTransport> {
         let url = crate::test::engine_io_server()?.to_string()
             + "engine.io/?EIO="
             + &ENGINE_IO_VERSION.to_string();
-        WebsocketTransport::new(Url::from_str(&url[..])?, None).await
+        WebsocketTransport::new(
+            Url::from_str(&url[..])?,
+            None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
+            Arc::new(TokioRuntime::current()),
+        )
+        .await
     }
 
     #[tokio::test]
@@ -167,4 +201,54 @@ mod test {
         println!("{:?}", transport.next().await.unwrap());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn websocket_transport_cookie_jar() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/engine.io/", listener.local_addr()?))?;
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            let key = request
+                .lines()
+                .find(|line| line.to_lowercase().starts_with("sec-websocket-key:"))
+                .and_then(|line| line.split_once(": "))
+                .unwrap()
+                .1;
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: websocket\r\n\
+                 sec-websocket-accept: {}\r\nset-cookie: upgraded=yes\r\n\r\n",
+                tungstenite::handshake::derive_accept_key(key.as_bytes())
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+
+        let jar = Arc::new(CookieJar::default());
+        jar.add_cookie_str("AWSALB=node1", &url);
+        let _transport = WebsocketTransport::new(
+            url.clone(),
+            None,
+            Timeouts::default(),
+            None,
+            Some(jar.clone()),
+            None,
+            Arc::new(TokioRuntime::current()),
+        )
+        .await?;
+
+        let request = server.await.unwrap();
+        assert!(request.contains("cookie: AWSALB=node1\r\n"));
+        let cookies = jar.cookies(&url).unwrap();
+        assert!(cookies.contains("upgraded=yes"));
+        Ok(())
+    }
 }
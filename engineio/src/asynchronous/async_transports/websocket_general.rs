@@ -1,19 +1,74 @@
 use std::{borrow::Cow, str::from_utf8, sync::Arc, task::Poll};
 
-use crate::{error::Result, Error, Packet, PacketId};
+use crate::{
+    asynchronous::runtime::{Runtime, TcpStream},
+    error::Result,
+    proxy::destination,
+    transports::websocket_general::{add_cookies, read_error, store_cookies, websocket_config},
+    CookieJar, Error, Packet, PacketId, Proxy,
+};
 use bytes::{BufMut, Bytes, BytesMut};
 use futures_util::{
     ready,
     stream::{SplitSink, SplitStream},
     FutureExt, SinkExt, Stream, StreamExt,
 };
-use tokio::{net::TcpStream, sync::Mutex};
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use tungstenite::Message;
+use tokio::sync::Mutex;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use tokio_tungstenite::client_async_tls_with_config;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+use tungstenite::{handshake::client::Request, Message};
+use url::Url;
 
 type AsyncWebsocketSender = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
 type AsyncWebsocketReceiver = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
+/// Opens a websocket connection to `url` through `runtime`, tunneled through
+/// `proxy` unless the proxy excludes the host. Cookies of `cookie_jar` are
+/// sent along with the upgrade request and cookies set in its response are
+/// stored. Messages exceeding `max_payload` bytes are rejected while being
+/// read.
+pub(crate) async fn connect(
+    mut request: Request,
+    url: &Url,
+    connector: Option<Connector>,
+    runtime: &dyn Runtime,
+    proxy: Option<&Proxy>,
+    cookie_jar: Option<&CookieJar>,
+    max_payload: Option<usize>,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let cookie_url = add_cookies(&mut request, url, cookie_jar);
+
+    let stream = match proxy.and_then(|proxy| proxy.intercept(url)) {
+        Some(target) => target.connect(url, runtime).await?,
+        None => {
+            let (host, port) = destination(url)?;
+            runtime.connect(host.to_owned(), port).await?
+        }
+    };
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    let (ws_stream, response) =
+        client_async_tls_with_config(request, stream, websocket_config(max_payload), connector)
+            .await?;
+    // without a TLS backend only plain connections are possible
+    #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+    let (ws_stream, response) = {
+        let _ = connector;
+        if url.scheme() == "wss" {
+            return Err(tungstenite::Error::Url(
+                tungstenite::error::UrlError::TlsFeatureNotEnabled,
+            )
+            .into());
+        }
+        let config = websocket_config(max_payload);
+        tokio_tungstenite::client_async_with_config(request, MaybeTlsStream::Plain(stream), config)
+            .await?
+    };
+
+    store_cookies(&response, &cookie_url, cookie_jar);
+    Ok(ws_stream)
+}
+
 /// A general purpose asynchronous websocket transport type. Holds
 /// the sender and receiver stream of a websocket connection
 /// and implements the common methods `update` and `emit`. This also
@@ -35,9 +90,8 @@ impl AsyncWebsocketGeneralTransport {
         }
     }
 
-    /// Sends probe packet to ensure connection is valid, then sends upgrade
-    /// request
-    pub(crate) async fn upgrade(&self) -> Result<()> {
+    /// Sends probe packet to ensure connection is valid
+    pub(crate) async fn probe(&self) -> Result<()> {
         let mut receiver = self.receiver.lock().await;
         let mut sender = self.sender.lock().await;
 
@@ -56,15 +110,20 @@ impl AsyncWebsocketGeneralTransport {
             return Err(Error::InvalidPacket());
         }
 
-        sender
-            .send(Message::text(Cow::Borrowed(from_utf8(&Bytes::from(
-                Packet::new(PacketId::Upgrade, Bytes::from("")),
-            ))?)))
-            .await?;
-
         Ok(())
     }
 
+    /// Sends probe packet to ensure connection is valid, then sends upgrade
+    /// request
+    pub(crate) async fn upgrade(&self) -> Result<()> {
+        self.probe().await?;
+        self.emit(
+            Bytes::from(Packet::new(PacketId::Upgrade, Bytes::from(""))),
+            false,
+        )
+        .await
+    }
+
     pub(crate) async fn emit(&self, data: Bytes, is_binary_att: bool) -> Result<()> {
         let mut sender = self.sender.lock().await;
 
@@ -78,27 +137,6 @@ impl AsyncWebsocketGeneralTransport {
 
         Ok(())
     }
-
-    pub(crate) async fn poll_next(&self) -> Result<Option<Bytes>> {
-        loop {
-            let mut receiver = self.receiver.lock().await;
-            let next = receiver.next().await;
-            match next {
-                Some(Ok(Message::Text(str))) => return Ok(Some(Bytes::from(str))),
-                Some(Ok(Message::Binary(data))) => {
-                    let mut msg = BytesMut::with_capacity(data.len() + 1);
-                    msg.put_u8(PacketId::Message as u8);
-                    msg.put(data.as_ref());
-
-                    return Ok(Some(msg.freeze()));
-                }
-                // ignore packets other than text and binary
-                Some(Ok(_)) => (),
-                Some(Err(err)) => return Err(err.into()),
-                None => return Ok(None),
-            }
-        }
-    }
 }
 
 impl Stream for AsyncWebsocketGeneralTransport {
@@ -123,7 +161,7 @@ impl Stream for AsyncWebsocketGeneralTransport {
                 }
                 // ignore packets other than text and binary
                 Some(Ok(_)) => (),
-                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                Some(Err(err)) => return Poll::Ready(Some(Err(read_error(err)))),
                 None => return Poll::Ready(None),
             }
         }
@@ -2,21 +2,20 @@ use std::fmt::Debug;
 use std::pin::Pin;
 use std::sync::Arc;
 
+use crate::asynchronous::runtime::{with_timeout, Runtime};
 use crate::asynchronous::transport::AsyncTransport;
 use crate::error::Result;
+use crate::{CookieJar, Error, Proxy, Timeouts, TlsConfig};
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::Stream;
 use futures_util::StreamExt;
 use http::HeaderMap;
-use native_tls::TlsConnector;
 use tokio::sync::RwLock;
-use tokio_tungstenite::connect_async_tls_with_config;
-use tokio_tungstenite::Connector;
 use tungstenite::client::IntoClientRequest;
 use url::Url;
 
-use super::websocket_general::AsyncWebsocketGeneralTransport;
+use super::websocket_general::{connect, AsyncWebsocketGeneralTransport};
 
 /// An asynchronous websocket transport type.
 /// This type only allows for secure websocket
@@ -29,11 +28,18 @@ pub struct WebsocketSecureTransport {
 
 impl WebsocketSecureTransport {
     /// Creates a new instance over a request that might hold additional headers, a possible
-    /// Tls connector and an URL.
+    /// Tls connector and an URL. Messages exceeding `max_payload` bytes are rejected
+    /// while being read.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn new(
         base_url: Url,
-        tls_config: Option<TlsConnector>,
+        tls_config: Option<TlsConfig>,
         headers: Option<HeaderMap>,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        max_payload: Option<usize>,
+        runtime: Arc<dyn Runtime>,
     ) -> Result<Self> {
         let mut url = base_url;
         url.query_pairs_mut().append_pair("transport", "websocket");
@@ -45,8 +51,21 @@ impl WebsocketSecureTransport {
             req.headers_mut().extend(map);
         }
 
-        let (ws_stream, _) =
-            connect_async_tls_with_config(req, None, tls_config.map(Connector::NativeTls)).await?;
+        let ws_stream = with_timeout(
+            &*runtime,
+            timeouts.connect,
+            Error::ConnectTimeout,
+            connect(
+                req,
+                &url,
+                tls_config.map(TlsConfig::into_connector),
+                &*runtime,
+                proxy.as_ref(),
+                cookie_jar.as_deref(),
+                max_payload,
+            ),
+        )
+        .await?;
 
         let (sen, rec) = ws_stream.split();
         let inner = AsyncWebsocketGeneralTransport::new(sen, rec).await;
@@ -57,15 +76,16 @@ impl WebsocketSecureTransport {
         })
     }
 
+    /// Sends probe packet to ensure connection is valid
+    pub(crate) async fn probe(&self) -> Result<()> {
+        self.inner.probe().await
+    }
+
     /// Sends probe packet to ensure connection is valid, then sends upgrade
     /// request
     pub(crate) async fn upgrade(&self) -> Result<()> {
         self.inner.upgrade().await
     }
-
-    pub(crate) async fn poll_next(&self) -> Result<Option<Bytes>> {
-        self.inner.poll_next().await
-    }
 }
 
 impl Stream for WebsocketSecureTransport {
@@ -120,7 +140,7 @@ impl Debug for WebsocketSecureTransport {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::ENGINE_IO_VERSION;
+    use crate::{asynchronous::TokioRuntime, ENGINE_IO_VERSION};
     use std::str::FromStr;
 
     async fn new() -> Result<WebsocketSecureTransport> {
@@ -131,6 +151,11 @@ mod test {
             Url::from_str(&url[..])?,
             Some(crate::test::tls_connector()?),
             None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
+            Arc::new(TokioRuntime::current()),
         )
         .await
     }
@@ -2,7 +2,7 @@ use bytes::Bytes;
 use futures_util::future::BoxFuture;
 use std::{fmt::Debug, ops::Deref, sync::Arc};
 
-use crate::Packet;
+use crate::{DisconnectReason, Packet};
 
 /// Internal type, provides a way to store futures and return them in a boxed manner.
 pub(crate) type DynAsyncCallback<I> = dyn 'static + Send + Sync + Fn(I) -> BoxFuture<'static, ()>;
@@ -84,6 +84,20 @@ impl Debug for OptionalCallback<Bytes> {
     }
 }
 
+#[cfg_attr(tarpaulin, ignore)]
+impl Debug for OptionalCallback<DisconnectReason> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.write_fmt(format_args!(
+            "Callback({:?})",
+            if self.inner.is_some() {
+                "Fn(DisconnectReason)"
+            } else {
+                "None"
+            }
+        ))
+    }
+}
+
 impl<I> Deref for OptionalCallback<I> {
     type Target = Option<Arc<DynAsyncCallback<I>>>;
     fn deref(&self) -> &<Self as std::ops::Deref>::Target {
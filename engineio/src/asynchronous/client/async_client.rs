@@ -3,7 +3,8 @@ use std::{fmt::Debug, pin::Pin};
 use crate::{
     asynchronous::{async_socket::Socket as InnerSocket, generator::StreamGenerator},
     error::Result,
-    Packet,
+    metrics::MetricsSnapshot,
+    DisconnectReason, Error, Packet,
 };
 use async_stream::try_stream;
 use futures_util::{Stream, StreamExt};
@@ -49,16 +50,28 @@ impl Client {
         self.socket.emit(packet).await
     }
 
+    /// Sends packets to the server in order, without other packets in between.
+    /// The polling transport sends them in a single request if they fit into
+    /// the maximum payload.
+    pub async fn emit_batch(&self, packets: Vec<Packet>) -> Result<()> {
+        self.socket.emit_batch(packets).await
+    }
+
     /// Static method that returns a generator for each element of the stream.
     fn stream(
         socket: InnerSocket,
     ) -> Pin<Box<impl Stream<Item = Result<Packet>> + 'static + Send>> {
         Box::pin(try_stream! {
             for await item in socket.clone() {
+                if let Err(Error::PingTimeout(_)) = &item {
+                    socket.handle_close(DisconnectReason::PingTimeout);
+                }
                 let packet = item?;
                 socket.handle_inconming_packet(packet.clone()).await?;
                 yield packet;
             }
+            // the transport ended without a close packet
+            socket.handle_close(DisconnectReason::TransportClose);
         })
     }
 
@@ -66,6 +79,16 @@ impl Client {
     pub fn is_connected(&self) -> bool {
         self.socket.is_connected()
     }
+
+    /// Returns a snapshot of the metrics of the connection.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.socket.metrics()
+    }
+
+    /// Returns the reason the connection closed for, if it did.
+    pub fn close_reason(&self) -> Option<DisconnectReason> {
+        self.socket.close_reason()
+    }
 }
 
 impl Stream for Client {
@@ -87,14 +110,18 @@ impl Debug for Client {
     }
 }
 
-#[cfg(all(test))]
+#[cfg(test)]
 mod test {
 
     use super::*;
-    use crate::{asynchronous::ClientBuilder, header::HeaderMap, packet::PacketId, Error};
+    use crate::{asynchronous::ClientBuilder, packet::PacketId, Error};
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    use crate::{header::HeaderMap, TlsConfig};
     use bytes::Bytes;
     use futures_util::StreamExt;
-    use native_tls::TlsConnector;
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    use http::header::HOST;
+    use std::time::Duration;
     use url::Url;
 
     /// The purpose of this test is to check whether the Client is properly cloneable or not.
@@ -162,8 +189,6 @@ mod test {
         Ok(())
     }
 
-    use reqwest::header::HOST;
-
     use crate::packet::Packet;
 
     fn builder(url: Url) -> ClientBuilder {
@@ -275,6 +300,7 @@ mod test {
         test_connection(socket).await
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[tokio::test]
     async fn test_connection_dynamic_secure() -> Result<()> {
         let url = crate::test::engine_io_server_secure()?;
@@ -291,6 +317,7 @@ mod test {
         test_connection(socket).await
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[tokio::test]
     async fn test_connection_wss() -> Result<()> {
         let url = crate::test::engine_io_polling_server()?;
@@ -355,6 +382,7 @@ mod test {
         Ok(())
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[tokio::test]
     async fn test_open_invariants() -> Result<()> {
         let url = crate::test::engine_io_server()?;
@@ -383,14 +411,258 @@ mod test {
 
         let _ = builder(url.clone())
             .tls_config(
-                TlsConnector::builder()
+                TlsConfig::builder()
                     .danger_accept_invalid_certs(true)
-                    .build()
-                    .unwrap(),
+                    .build()?,
             )
             .build()
             .await?;
         let _ = builder(url).headers(headers).build().await?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() -> Result<()> {
+        // a server that accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+
+        let timeout = Duration::from_millis(50);
+        let result = ClientBuilder::new(url.clone())
+            .handshake_timeout(timeout)
+            .build_polling()
+            .await;
+        assert!(matches!(result, Err(Error::HandshakeTimeout(duration)) if duration == timeout));
+
+        // the websocket upgrade request is part of establishing the connection
+        let result = ClientBuilder::new(url)
+            .connect_timeout(timeout)
+            .build_websocket()
+            .await;
+        assert!(matches!(result, Err(Error::ConnectTimeout(duration)) if duration == timeout));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_in_session() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (probed_tx, probed) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel();
+        let server = crate::test::serve_upgrade(listener, probed_tx, release_rx);
+
+        let (upgraded_tx, upgraded) = std::sync::mpsc::channel();
+        let upgraded_tx = std::sync::Mutex::new(upgraded_tx);
+        let mut client = ClientBuilder::new(url)
+            .on_upgrade(move |_| {
+                upgraded_tx.lock().unwrap().send(()).unwrap();
+                Box::pin(async {})
+            })
+            .build()
+            .await?;
+        client.connect().await?;
+
+        // emits while the poll drains after the probe
+        let emitter = client.clone();
+        let emitter = tokio::spawn(async move {
+            tokio::task::spawn_blocking(move || probed.recv().unwrap())
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            emitter
+                .emit(Packet::new(PacketId::Message, "queued"))
+                .await?;
+            release.send(()).unwrap();
+            Ok::<_, Error>(())
+        });
+
+        let mut stream = client
+            .by_ref()
+            .map(|packet| packet.unwrap())
+            .filter(|packet| futures_util::future::ready(packet.packet_id != PacketId::Noop));
+        assert_eq!(
+            stream.next().await,
+            Some(Packet::new(PacketId::Message, "drained"))
+        );
+        emitter.await.unwrap()?;
+        assert_eq!(
+            stream.next().await,
+            Some(Packet::new(PacketId::Message, "upgraded"))
+        );
+        tokio::task::spawn_blocking(move || upgraded.recv_timeout(Duration::from_secs(1)))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (requests, messages) = tokio::task::spawn_blocking(|| server.join().unwrap())
+            .await
+            .unwrap();
+        assert!(requests.iter().all(|request| !request.contains("queued")));
+        assert_eq!(messages, vec!["2probe", "5", "4queued"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batched_emits() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (posted, bodies) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel();
+        let mut release_rx = Some(release_rx);
+        let server = crate::test::serve_with(
+            listener,
+            vec![crate::test::HANDSHAKE, "ok", "ok"],
+            move |line, body| {
+                if line.starts_with("POST") {
+                    posted.send(body.to_owned()).unwrap();
+                    // holds the first write until the test emitted
+                    if let Some(release) = release_rx.take() {
+                        release.recv().unwrap();
+                    }
+                }
+            },
+        );
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(bodies));
+        let next_body = || {
+            let bodies = bodies.clone();
+            tokio::task::spawn_blocking(move || bodies.lock().unwrap().recv().unwrap())
+        };
+
+        let client = ClientBuilder::new(url).build_polling().await?;
+        let connecting = client.clone();
+        let connecting = tokio::spawn(async move { connecting.connect().await });
+        assert_eq!(next_body().await.unwrap(), "3");
+
+        client.emit(Packet::new(PacketId::Message, "a")).await?;
+        client
+            .emit_batch(vec![
+                Packet::new(PacketId::Message, "header"),
+                Packet::new(PacketId::MessageBinary, vec![1, 2]),
+            ])
+            .await?;
+        release.send(()).unwrap();
+        connecting.await.unwrap()?;
+
+        assert_eq!(next_body().await.unwrap(), "4a\x1e4header\x1ebAQI=");
+        tokio::task::spawn_blocking(|| server.join().unwrap())
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_emit() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (posted, bodies) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel::<()>();
+        let server = crate::test::serve_with(
+            listener,
+            vec![crate::test::HANDSHAKE, "ok", "ok", "ok"],
+            move |line, body| {
+                if line.starts_with("POST") {
+                    posted.send(body.to_owned()).unwrap();
+                    // holds the write the test cancels
+                    if body == "4a" {
+                        release_rx.recv().unwrap();
+                    }
+                }
+            },
+        );
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(bodies));
+        let next_body = || {
+            let bodies = bodies.clone();
+            let received = tokio::task::spawn_blocking(move || {
+                bodies.lock().unwrap().recv_timeout(Duration::from_secs(5))
+            });
+            async move { received.await.unwrap().unwrap() }
+        };
+
+        let client = ClientBuilder::new(url).build_polling().await?;
+        client.connect().await?;
+        assert_eq!(next_body().await, "3");
+
+        let emit = client.emit(Packet::new(PacketId::Message, "a"));
+        assert!(tokio::time::timeout(Duration::from_millis(100), emit)
+            .await
+            .is_err());
+        assert_eq!(next_body().await, "4a");
+        release.send(()).unwrap();
+
+        // the cancelled emit left no write in flight behind
+        client.emit(Packet::new(PacketId::Message, "b")).await?;
+        assert_eq!(next_body().await, "4b");
+        tokio::task::spawn_blocking(|| server.join().unwrap())
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_incoming_payload() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = crate::test::serve(
+            listener,
+            vec![crate::test::HANDSHAKE, "ok", "4exceeding the limit"],
+        );
+
+        let mut client = ClientBuilder::new(url)
+            .max_incoming_payload(10)
+            .build_polling()
+            .await?;
+        client.connect().await?;
+
+        assert!(matches!(
+            client.next().await,
+            Some(Err(Error::InvalidPayloadSize(20, 10)))
+        ));
+        tokio::task::spawn_blocking(|| server.join().unwrap())
+            .await
+            .unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_incoming_payload_streamed() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = crate::test::serve_endless(listener, vec![crate::test::HANDSHAKE, "ok"]);
+
+        let mut client = ClientBuilder::new(url)
+            .max_incoming_payload(1024 * 1024)
+            .build_polling()
+            .await?;
+        client.connect().await?;
+
+        // the poll fails once the limit is passed, not at the end of the body
+        assert!(matches!(
+            client.next().await,
+            Some(Err(Error::InvalidPayloadSize(size, 1048576))) if size > 1048576
+        ));
+        drop(client);
+        let written = tokio::task::spawn_blocking(|| server.join().unwrap())
+            .await
+            .unwrap();
+        assert!(written < 64 * 1024 * 1024);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_incoming_payload_websocket() -> Result<()> {
+        let server = crate::mock::MockServer::builder().build()?;
+        let mut client = ClientBuilder::new(server.url())
+            .max_incoming_payload(1024)
+            .build_websocket()
+            .await?;
+        client.connect().await?;
+        let session = server.accept(Duration::from_secs(5)).unwrap();
+
+        session.send(Packet::new(PacketId::Message, "a".repeat(2048)));
+        assert!(matches!(
+            client.next().await,
+            Some(Err(Error::InvalidPayloadSize(2049, 1024)))
+        ));
+        Ok(())
+    }
 }
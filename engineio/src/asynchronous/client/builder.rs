@@ -1,18 +1,24 @@
 use crate::{
     asynchronous::{
-        async_socket::Socket as InnerSocket,
-        async_transports::{PollingTransport, WebsocketSecureTransport, WebsocketTransport},
+        async_socket::{Socket as InnerSocket, Upgrade},
+        async_transports::{
+            PollingTransport, ReplayTransport, WebsocketSecureTransport, WebsocketTransport,
+        },
         callback::OptionalCallback,
-        transport::AsyncTransport,
+        runtime::{default_runtime, with_timeout, Runtime},
+        transport::{AsyncTransport, AsyncTransportType},
     },
     error::Result,
     header::HeaderMap,
     packet::HandshakePacket,
-    Error, Packet, ENGINE_IO_VERSION,
+    recorder::{replay_frames, Frame, Recorder},
+    trace::Instrument,
+    CookieJar, DisconnectReason, Error, Packet, Proxy, Timeouts, TlsConfig, ENGINE_IO_VERSION,
 };
 use bytes::Bytes;
 use futures_util::{future::BoxFuture, StreamExt};
-use native_tls::TlsConnector;
+use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
 use super::Client;
@@ -20,14 +26,23 @@ use super::Client;
 #[derive(Clone, Debug)]
 pub struct ClientBuilder {
     url: Url,
-    tls_config: Option<TlsConnector>,
+    tls_config: Option<TlsConfig>,
     headers: Option<HeaderMap>,
     handshake: Option<HandshakePacket>,
+    timeouts: Timeouts,
+    proxy: Option<Proxy>,
+    cookie_jar: Option<Arc<CookieJar>>,
+    timestamp_requests: bool,
+    timestamp_param: String,
+    max_incoming_payload: Option<usize>,
+    recorder: Option<Recorder>,
+    runtime: Option<Arc<dyn Runtime>>,
     on_error: OptionalCallback<String>,
     on_open: OptionalCallback<()>,
-    on_close: OptionalCallback<()>,
+    on_close: OptionalCallback<DisconnectReason>,
     on_data: OptionalCallback<Bytes>,
     on_packet: OptionalCallback<Packet>,
+    on_upgrade: OptionalCallback<()>,
 }
 
 impl ClientBuilder {
@@ -45,17 +60,28 @@ impl ClientBuilder {
             headers: None,
             tls_config: None,
             handshake: None,
+            timeouts: Timeouts::default(),
+            proxy: None,
+            cookie_jar: None,
+            timestamp_requests: true,
+            timestamp_param: "t".to_owned(),
+            max_incoming_payload: None,
+            recorder: None,
+            runtime: None,
             on_close: OptionalCallback::default(),
             on_data: OptionalCallback::default(),
             on_error: OptionalCallback::default(),
             on_open: OptionalCallback::default(),
             on_packet: OptionalCallback::default(),
+            on_upgrade: OptionalCallback::default(),
         }
     }
 
-    /// Specify transport's tls config
-    pub fn tls_config(mut self, tls_config: TlsConnector) -> Self {
-        self.tls_config = Some(tls_config);
+    /// Specify transport's tls config, available with the `native-tls` or
+    /// `rustls` feature.
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub fn tls_config<T: Into<TlsConfig>>(mut self, tls_config: T) -> Self {
+        self.tls_config = Some(tls_config.into());
         self
     }
 
@@ -65,11 +91,102 @@ impl ClientBuilder {
         self
     }
 
-    /// Registers the `on_close` callback.
+    /// Specify a proxy used by the polling and websocket transports.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Specify a cookie jar shared by the handshake, the polling requests and
+    /// the websocket upgrade. Required by load balancers with sticky sessions.
+    pub fn cookie_jar(mut self, cookie_jar: Arc<CookieJar>) -> Self {
+        self.cookie_jar = Some(cookie_jar);
+        self
+    }
+
+    /// Appends a query parameter to the handshake, polling and websocket urls.
+    pub fn query_param<K: AsRef<str>, V: AsRef<str>>(mut self, key: K, value: V) -> Self {
+        self.url
+            .query_pairs_mut()
+            .append_pair(key.as_ref(), value.as_ref());
+        self
+    }
+
+    /// Specify the path of the engine.io endpoint, `/engine.io/` by default.
+    pub fn path<T: AsRef<str>>(mut self, path: T) -> Self {
+        self.url.set_path(path.as_ref());
+        self
+    }
+
+    /// Specify whether polling requests carry a cache busting timestamp, which
+    /// is enabled by default.
+    pub fn timestamp_requests(mut self, timestamp_requests: bool) -> Self {
+        self.timestamp_requests = timestamp_requests;
+        self
+    }
+
+    /// Specify the name of the timestamp query parameter, `t` by default.
+    pub fn timestamp_param<T: Into<String>>(mut self, param: T) -> Self {
+        self.timestamp_param = param.into();
+        self
+    }
+
+    /// Specify the runtime the client spawns its callbacks on and connects
+    /// through. Defaults to the `tokio` runtime the client is built on or to
+    /// the runtime of the enabled `async-std` or `smol` feature.
+    pub fn runtime(mut self, runtime: Arc<dyn Runtime>) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Specify the maximum time for establishing the connection to the server.
+    /// For websocket transports this includes the http upgrade request.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum time to wait for the server to answer the handshake.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.handshake = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum time for a single http request of the polling transport.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum time to wait for data from the server once connected.
+    /// As the server may stay silent between heartbeats, this should be larger
+    /// than the sum of its `pingInterval` and `pingTimeout`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum size in bytes of a single payload received from
+    /// the server. Larger payloads fail with [`Error::InvalidPayloadSize`](crate::Error::InvalidPayloadSize)
+    /// while being read, without buffering them. The handshake is not limited.
+    pub fn max_incoming_payload(mut self, size: usize) -> Self {
+        self.max_incoming_payload = Some(size);
+        self
+    }
+
+    /// Records every packet sent and received once connected, including the
+    /// handshake. See [`crate::recorder`] for the format of recordings.
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Registers the `on_close` callback, which is passed the reason the
+    /// connection closed for.
     #[cfg(feature = "async-callbacks")]
     pub fn on_close<T>(mut self, callback: T) -> Self
     where
-        T: 'static + Send + Sync + Fn(()) -> BoxFuture<'static, ()>,
+        T: 'static + Send + Sync + Fn(DisconnectReason) -> BoxFuture<'static, ()>,
     {
         self.on_close = OptionalCallback::new(callback);
         self
@@ -115,6 +232,17 @@ impl ClientBuilder {
         self
     }
 
+    /// Registers the `on_upgrade` callback, called once the client switched
+    /// from polling to websocket.
+    #[cfg(feature = "async-callbacks")]
+    pub fn on_upgrade<T>(mut self, callback: T) -> Self
+    where
+        T: 'static + Send + Sync + Fn(()) -> BoxFuture<'static, ()>,
+    {
+        self.on_upgrade = OptionalCallback::new(callback);
+        self
+    }
+
     /// Performs the handshake
     async fn handshake_with_transport<T: AsyncTransport + Unpin>(
         &mut self,
@@ -126,10 +254,27 @@ impl ClientBuilder {
         }
 
         let mut url = self.url.clone();
-
-        let handshake: HandshakePacket =
-            Packet::try_from(transport.next().await.ok_or(Error::IncompletePacket())??)?
-                .try_into()?;
+        let span = span!(INFO, "engineio.handshake", url = url.as_str());
+
+        let runtime = self.runtime_or_default()?;
+        let data = with_timeout(
+            &*runtime,
+            self.timeouts.handshake,
+            Error::HandshakeTimeout,
+            async { transport.next().await.ok_or(Error::IncompletePacket())? },
+        )
+        .instrument(span.clone())
+        .await
+        .inspect_err(|err| event!(warn, parent: &span; "handshake failed: {}", err))?;
+        let handshake: HandshakePacket = Packet::try_from(data)?.try_into()?;
+        event!(
+            debug,
+            parent: &span,
+            sid = handshake.sid,
+            upgrades = handshake.upgrades,
+            max_payload = handshake.max_payload;
+            "handshake completed"
+        );
 
         // update the base_url with the new sid
         url.query_pairs_mut().append_pair("sid", &handshake.sid[..]);
@@ -153,8 +298,17 @@ impl ClientBuilder {
         };
 
         // Start with polling transport
-        let mut transport =
-            PollingTransport::new(self.url.clone(), self.tls_config.clone(), headers);
+        let mut transport = PollingTransport::new(
+            self.url.clone(),
+            self.tls_config.clone(),
+            headers,
+            self.timeouts,
+            self.proxy.clone(),
+            self.cookie_jar.clone(),
+            self.polling_timestamp_param(),
+            // the limit applies once connected
+            None,
+        );
 
         self.handshake_with_transport(&mut transport).await
     }
@@ -171,41 +325,114 @@ impl ClientBuilder {
     }
 
     /// Build socket with polling transport
-    pub async fn build_polling(mut self) -> Result<Client> {
+    pub async fn build_polling(self) -> Result<Client> {
+        self.build_polling_with_upgrade(None).await
+    }
+
+    /// Build socket with polling transport, which switches to the transport
+    /// of `upgrade` once connected
+    async fn build_polling_with_upgrade(mut self, upgrade: Option<Upgrade>) -> Result<Client> {
         self.handshake().await?;
 
+        let runtime = self.runtime_or_default()?;
         // Make a polling transport with new sid
+        let timestamp_param = self.polling_timestamp_param();
         let transport = PollingTransport::new(
-            self.url,
+            self.url.clone(),
             self.tls_config,
             self.headers.map(|v| v.try_into().unwrap()),
+            self.timeouts,
+            self.proxy.clone(),
+            self.cookie_jar.clone(),
+            timestamp_param,
+            self.max_incoming_payload,
         );
 
         // SAFETY: handshake function called previously.
         Ok(Client::new(InnerSocket::new(
             transport.into(),
             self.handshake.unwrap(),
+            &self.url,
             self.on_close,
             self.on_data,
             self.on_error,
             self.on_open,
             self.on_packet,
+            self.on_upgrade,
+            self.timeouts.read,
+            self.max_incoming_payload,
+            self.recorder,
+            runtime,
+            upgrade,
         )))
     }
 
-    /// Build socket with a polling transport then upgrade to websocket transport
+    /// Build socket with a polling transport then upgrade to websocket transport.
+    /// Once connected, the websocket is probed in the background and replaces
+    /// the polling transport after its pending poll returned. Packets emitted
+    /// in between are sent over the websocket.
     pub async fn build_websocket_with_upgrade(mut self) -> Result<Client> {
         self.handshake().await?;
 
-        if self.websocket_upgrade()? {
-            self.build_websocket().await
+        if !self.websocket_upgrade()? {
+            return Err(Error::IllegalWebsocketUpgrade());
+        }
+
+        let builder = self.clone();
+        let upgrade: Upgrade = Arc::new(move || {
+            let builder = builder.clone();
+            Box::pin(async move { builder.probe_websocket().await })
+        });
+        self.build_polling_with_upgrade(Some(upgrade)).await
+    }
+
+    /// Opens a websocket transport for the session of the handshake and
+    /// probes it.
+    async fn probe_websocket(&self) -> Result<AsyncTransportType> {
+        let runtime = self.runtime_or_default()?;
+        let headers = if let Some(map) = self.headers.clone() {
+            Some(map.try_into()?)
         } else {
-            Err(Error::IllegalWebsocketUpgrade())
+            None
+        };
+
+        match self.url.scheme() {
+            "http" | "ws" => {
+                let transport = WebsocketTransport::new(
+                    self.url.clone(),
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                    runtime,
+                )
+                .await?;
+                transport.probe().await?;
+                Ok(transport.into())
+            }
+            "https" | "wss" => {
+                let transport = WebsocketSecureTransport::new(
+                    self.url.clone(),
+                    self.tls_config.clone(),
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                    runtime,
+                )
+                .await?;
+                transport.probe().await?;
+                Ok(transport.into())
+            }
+            _ => Err(Error::InvalidUrlScheme(self.url.scheme().to_string())),
         }
     }
 
     /// Build socket with only a websocket transport
     pub async fn build_websocket(mut self) -> Result<Client> {
+        let runtime = self.runtime_or_default()?;
         let headers = if let Some(map) = self.headers.clone() {
             Some(map.try_into()?)
         } else {
@@ -214,7 +441,16 @@ impl ClientBuilder {
 
         match self.url.scheme() {
             "http" | "ws" => {
-                let mut transport = WebsocketTransport::new(self.url.clone(), headers).await?;
+                let mut transport = WebsocketTransport::new(
+                    self.url.clone(),
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                    runtime.clone(),
+                )
+                .await?;
 
                 if self.handshake.is_some() {
                     transport.upgrade().await?;
@@ -226,11 +462,18 @@ impl ClientBuilder {
                 Ok(Client::new(InnerSocket::new(
                     transport.into(),
                     self.handshake.unwrap(),
+                    &self.url,
                     self.on_close,
                     self.on_data,
                     self.on_error,
                     self.on_open,
                     self.on_packet,
+                    self.on_upgrade,
+                    self.timeouts.read,
+                    self.max_incoming_payload,
+                    self.recorder,
+                    runtime,
+                    None,
                 )))
             }
             "https" | "wss" => {
@@ -238,6 +481,11 @@ impl ClientBuilder {
                     self.url.clone(),
                     self.tls_config.clone(),
                     headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                    runtime.clone(),
                 )
                 .await?;
 
@@ -251,11 +499,18 @@ impl ClientBuilder {
                 Ok(Client::new(InnerSocket::new(
                     transport.into(),
                     self.handshake.unwrap(),
+                    &self.url,
                     self.on_close,
                     self.on_data,
                     self.on_error,
                     self.on_open,
                     self.on_packet,
+                    self.on_upgrade,
+                    self.timeouts.read,
+                    self.max_incoming_payload,
+                    self.recorder,
+                    runtime,
+                    None,
                 )))
             }
             _ => Err(Error::InvalidUrlScheme(self.url.scheme().to_string())),
@@ -273,6 +528,46 @@ impl ClientBuilder {
         }
     }
 
+    /// Build socket with a transport replaying the packets received in a
+    /// recording instead of connecting to the server. Packets sent by the
+    /// client are discarded and its stream ends with the recording.
+    pub fn build_replay(self, frames: Vec<Frame>) -> Result<Client> {
+        let (handshake, packets) = replay_frames(frames)?;
+        let runtime = self.runtime_or_default()?;
+        let transport = ReplayTransport::new(self.url.clone(), packets);
+
+        Ok(Client::new(InnerSocket::new(
+            transport.into(),
+            handshake,
+            &self.url,
+            self.on_close,
+            self.on_data,
+            self.on_error,
+            self.on_open,
+            self.on_packet,
+            self.on_upgrade,
+            self.timeouts.read,
+            self.max_incoming_payload,
+            self.recorder,
+            runtime,
+            None,
+        )))
+    }
+
+    /// The configured runtime, falling back to the default one.
+    fn runtime_or_default(&self) -> Result<Arc<dyn Runtime>> {
+        match &self.runtime {
+            Some(runtime) => Ok(runtime.clone()),
+            None => default_runtime(),
+        }
+    }
+
+    /// The timestamp query parameter of polling requests, if enabled.
+    fn polling_timestamp_param(&self) -> Option<String> {
+        self.timestamp_requests
+            .then(|| self.timestamp_param.clone())
+    }
+
     /// Checks the handshake to see if websocket upgrades are allowed
     fn websocket_upgrade(&mut self) -> Result<bool> {
         if self.handshake.is_none() {
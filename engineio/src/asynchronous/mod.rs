@@ -1,15 +1,22 @@
 pub mod async_transports;
 pub mod transport;
 
-pub(self) mod async_socket;
+mod async_socket;
 #[cfg(feature = "async-callbacks")]
 mod callback;
 #[cfg(feature = "async")]
 pub mod client;
 mod generator;
+pub mod runtime;
 
 #[cfg(feature = "async")]
 pub use client::Client;
 
 #[cfg(feature = "async")]
 pub use client::ClientBuilder;
+
+#[cfg(feature = "async-std")]
+pub use runtime::AsyncStdRuntime;
+#[cfg(feature = "smol")]
+pub use runtime::SmolRuntime;
+pub use runtime::{Runtime, TokioRuntime};
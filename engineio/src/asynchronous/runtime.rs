@@ -0,0 +1,389 @@
+//! The async runtime abstraction. The async client spawns its callbacks, waits
+//! for timeouts and opens websocket connections through a [`Runtime`], so it
+//! runs on `tokio` as well as on `async-std` or `smol` (features `async-std`
+//! and `smol`).
+use std::{
+    fmt::Debug,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::{future::BoxFuture, Stream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    runtime::{EnterGuard, Handle},
+};
+
+use crate::{error::Result, Error};
+
+/// A bidirectional byte stream, usually a TCP connection.
+pub trait AsyncStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncStream for T {}
+
+/// A TCP connection opened by a [`Runtime`].
+pub type TcpStream = Box<dyn AsyncStream>;
+
+/// Spawns tasks, provides timers and opens TCP connections for the async
+/// clients. Implemented for `tokio` by [`TokioRuntime`] and, behind the
+/// respective features, for `async-std` and `smol`.
+pub trait Runtime: Debug + Send + Sync + 'static {
+    /// Runs `future` in the background.
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+
+    /// Completes once `duration` has passed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Resolves `host` to its socket addresses.
+    fn resolve(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>>;
+
+    /// Opens a TCP connection to `host:port`.
+    fn connect(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<TcpStream>>;
+}
+
+/// Returns the runtime used by builders that were not given one explicitly:
+/// the `tokio` runtime of the caller if there is one, otherwise the runtime
+/// of the enabled `async-std` or `smol` feature.
+pub fn default_runtime() -> Result<Arc<dyn Runtime>> {
+    if let Ok(handle) = Handle::try_current() {
+        return Ok(Arc::new(TokioRuntime::new(handle)));
+    }
+    #[cfg(feature = "async-std")]
+    return Ok(Arc::new(AsyncStdRuntime));
+    #[cfg(all(feature = "smol", not(feature = "async-std")))]
+    return Ok(Arc::new(SmolRuntime));
+    #[allow(unreachable_code)]
+    Err(Error::InvalidRuntime())
+}
+
+/// Awaits the given future for at most `timeout`, resolving to the error
+/// created by `on_timeout` if the deadline passes first.
+pub(crate) async fn with_timeout<T, F>(
+    runtime: &dyn Runtime,
+    timeout: Option<Duration>,
+    on_timeout: fn(Duration) -> Error,
+    future: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    use futures_util::future::{select, Either};
+
+    match timeout {
+        Some(duration) => match select(std::pin::pin!(future), runtime.sleep(duration)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(on_timeout(duration)),
+        },
+        None => future.await,
+    }
+}
+
+/// A [`Runtime`] spawning on a `tokio` runtime.
+#[derive(Clone, Debug)]
+pub struct TokioRuntime {
+    handle: Handle,
+}
+
+impl TokioRuntime {
+    /// Uses the runtime `handle` belongs to.
+    pub fn new(handle: Handle) -> Self {
+        TokioRuntime { handle }
+    }
+
+    /// Uses the runtime the caller runs on.
+    ///
+    /// # Panics
+    ///
+    /// When called outside of a `tokio` runtime.
+    pub fn current() -> Self {
+        Self::new(Handle::current())
+    }
+}
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        self.handle.spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        // timers register with the runtime they are created in
+        let _guard = self.handle.enter();
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn resolve(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        let task = self.handle.spawn(async move {
+            tokio::net::lookup_host((host, port))
+                .await
+                .map(Iterator::collect)
+        });
+        Box::pin(async move { task.await.map_err(io::Error::other)? })
+    }
+
+    fn connect(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<TcpStream>> {
+        let task = self.handle.spawn(async move {
+            let stream = tokio::net::TcpStream::connect((host, port)).await?;
+            Ok(Box::new(stream) as TcpStream)
+        });
+        Box::pin(async move { task.await.map_err(io::Error::other)? })
+    }
+}
+
+/// A [`Runtime`] spawning on the global `async-std` executor.
+#[cfg(feature = "async-std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        async_std::task::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+
+    fn resolve(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        use async_std::net::ToSocketAddrs;
+
+        Box::pin(async move { Ok((host.as_str(), port).to_socket_addrs().await?.collect()) })
+    }
+
+    fn connect(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<TcpStream>> {
+        use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+        Box::pin(async move {
+            let stream = async_std::net::TcpStream::connect((host.as_str(), port)).await?;
+            Ok(Box::new(stream.compat()) as TcpStream)
+        })
+    }
+}
+
+/// A [`Runtime`] spawning on the global `smol` executor.
+#[cfg(feature = "smol")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "smol")]
+impl Runtime for SmolRuntime {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        smol::spawn(future).detach();
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let timer = smol::Timer::after(duration);
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+
+    fn resolve(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        Box::pin(async move { smol::net::resolve((host.as_str(), port)).await })
+    }
+
+    fn connect(&self, host: String, port: u16) -> BoxFuture<'static, io::Result<TcpStream>> {
+        use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+        Box::pin(async move {
+            let stream = smol::net::TcpStream::connect((host.as_str(), port)).await?;
+            Ok(Box::new(stream.compat()) as TcpStream)
+        })
+    }
+}
+
+/// Polls the wrapped future or stream within the context of a `tokio`
+/// runtime, which the async `reqwest` client requires. Outside of `tokio`, a
+/// runtime is started on a background thread on first use.
+pub(crate) struct TokioContext<T: ?Sized>(Pin<Box<T>>);
+
+impl<T: ?Sized> TokioContext<T> {
+    pub(crate) fn new(inner: Pin<Box<T>>) -> Self {
+        TokioContext(inner)
+    }
+}
+
+/// Enters the background runtime unless the caller runs on `tokio` already.
+fn enter_tokio_context() -> Option<EnterGuard<'static>> {
+    static BACKGROUND: OnceLock<Handle> = OnceLock::new();
+
+    if Handle::try_current().is_ok() {
+        return None;
+    }
+    let handle = BACKGROUND.get_or_init(|| {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the background tokio runtime");
+        let handle = runtime.handle().clone();
+        std::thread::Builder::new()
+            .name("engineio-tokio".to_owned())
+            .spawn(move || runtime.block_on(std::future::pending::<()>()))
+            .expect("failed to spawn the background tokio runtime");
+        handle
+    });
+    Some(handle.enter())
+}
+
+impl<T: Future + ?Sized> Future for TokioContext<T> {
+    type Output = T::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = enter_tokio_context();
+        self.0.as_mut().poll(cx)
+    }
+}
+
+impl<T: Stream + ?Sized> Stream for TokioContext<T> {
+    type Item = T::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let _guard = enter_tokio_context();
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        asynchronous::ClientBuilder,
+        test::{serve, HANDSHAKE},
+    };
+    use futures_util::FutureExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Instant;
+    use url::Url;
+
+    /// A runtime running every task on its own thread.
+    #[derive(Debug)]
+    struct ThreadRuntime;
+
+    impl Runtime for ThreadRuntime {
+        fn spawn(&self, future: BoxFuture<'static, ()>) {
+            std::thread::spawn(move || futures_executor::block_on(future));
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            let deadline = Instant::now() + duration;
+            Box::pin(futures_util::future::poll_fn(move |cx| {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Poll::Ready(());
+                }
+                let waker = cx.waker().clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(deadline - now);
+                    waker.wake();
+                });
+                Poll::Pending
+            }))
+        }
+
+        fn resolve(
+            &self,
+            host: String,
+            port: u16,
+        ) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+            use std::net::ToSocketAddrs;
+
+            Box::pin(async move { Ok((host.as_str(), port).to_socket_addrs()?.collect()) })
+        }
+
+        fn connect(&self, _host: String, _port: u16) -> BoxFuture<'static, io::Result<TcpStream>> {
+            Box::pin(async { Err(io::ErrorKind::Unsupported.into()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let runtime = TokioRuntime::current();
+        let result = with_timeout(
+            &runtime,
+            Some(Duration::from_millis(10)),
+            Error::ReadTimeout,
+            std::future::pending::<Result<()>>(),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(Error::ReadTimeout(duration)) if duration == Duration::from_millis(10)
+        ));
+
+        let result = with_timeout(&runtime, None, Error::ReadTimeout, async { Ok(42) }).await;
+        assert!(matches!(result, Ok(42)));
+
+        let result = with_timeout(
+            &ThreadRuntime,
+            Some(Duration::from_millis(10)),
+            Error::ReadTimeout,
+            std::future::pending::<Result<()>>(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::ReadTimeout(_))));
+    }
+
+    #[test]
+    fn test_default_runtime() {
+        #[cfg(not(any(feature = "async-std", feature = "smol")))]
+        assert!(matches!(default_runtime(), Err(Error::InvalidRuntime())));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async { assert!(default_runtime().is_ok()) });
+    }
+
+    #[test]
+    fn test_custom_runtime() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = serve(listener, vec![HANDSHAKE, "ok"]);
+
+        let opened = Arc::new(AtomicBool::new(false));
+        let on_open = opened.clone();
+        // neither the caller nor the runtime provide a tokio context
+        futures_executor::block_on(async {
+            let client = ClientBuilder::new(url)
+                .runtime(Arc::new(ThreadRuntime))
+                .handshake_timeout(Duration::from_secs(5))
+                .on_open(move |_| {
+                    let on_open = on_open.clone();
+                    async move { on_open.store(true, Ordering::SeqCst) }.boxed()
+                })
+                .build_polling()
+                .await?;
+            client.connect().await
+        })?;
+
+        server.join().unwrap();
+        for _ in 0..100 {
+            if opened.load(Ordering::SeqCst) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(opened.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn test_async_std_runtime() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = serve(listener, vec![HANDSHAKE, "ok"]);
+
+        async_std::task::block_on(async {
+            let client = ClientBuilder::new(url).build_polling().await?;
+            client.connect().await
+        })?;
+        server.join().unwrap();
+        Ok(())
+    }
+}
@@ -1,12 +1,14 @@
 use crate::error::Result;
-use adler32::adler32;
+use crate::transport::timestamp;
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::Stream;
-use std::{pin::Pin, time::SystemTime};
+use std::pin::Pin;
 use url::Url;
 
-use super::async_transports::{PollingTransport, WebsocketSecureTransport, WebsocketTransport};
+use super::async_transports::{
+    PollingTransport, ReplayTransport, WebsocketSecureTransport, WebsocketTransport,
+};
 
 #[async_trait]
 pub trait AsyncTransport: Stream<Item = Result<Bytes>> + Unpin {
@@ -26,10 +28,8 @@ pub trait AsyncTransport: Stream<Item = Result<Bytes>> + Unpin {
     where
         Self: Sized,
     {
-        let reader = format!("{:#?}", SystemTime::now());
-        let hash = adler32(reader.as_bytes()).unwrap();
         let mut url = self.base_url().await?;
-        url.query_pairs_mut().append_pair("t", &hash.to_string());
+        url.query_pairs_mut().append_pair("t", &timestamp());
         Ok(url)
     }
 }
@@ -39,6 +39,7 @@ pub enum AsyncTransportType {
     Polling(PollingTransport),
     Websocket(WebsocketTransport),
     WebsocketSecure(WebsocketSecureTransport),
+    Replay(ReplayTransport),
 }
 
 impl From<PollingTransport> for AsyncTransportType {
@@ -59,6 +60,12 @@ impl From<WebsocketSecureTransport> for AsyncTransportType {
     }
 }
 
+impl From<ReplayTransport> for AsyncTransportType {
+    fn from(transport: ReplayTransport) -> Self {
+        AsyncTransportType::Replay(transport)
+    }
+}
+
 #[cfg(feature = "async")]
 impl AsyncTransportType {
     pub fn as_transport(&self) -> &(dyn AsyncTransport + Send) {
@@ -66,6 +73,18 @@ impl AsyncTransportType {
             AsyncTransportType::Polling(transport) => transport,
             AsyncTransportType::Websocket(transport) => transport,
             AsyncTransportType::WebsocketSecure(transport) => transport,
+            AsyncTransportType::Replay(transport) => transport,
+        }
+    }
+
+    /// The name of the transport in recordings.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            AsyncTransportType::Polling(_) => "polling",
+            AsyncTransportType::Websocket(_) | AsyncTransportType::WebsocketSecure(_) => {
+                "websocket"
+            }
+            AsyncTransportType::Replay(_) => "replay",
         }
     }
 
@@ -74,6 +93,7 @@ impl AsyncTransportType {
             AsyncTransportType::Polling(transport) => Box::pin(transport),
             AsyncTransportType::Websocket(transport) => Box::pin(transport),
             AsyncTransportType::WebsocketSecure(transport) => Box::pin(transport),
+            AsyncTransportType::Replay(transport) => Box::pin(transport),
         }
     }
 }
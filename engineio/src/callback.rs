@@ -1,4 +1,4 @@
-use crate::Packet;
+use crate::{DisconnectReason, Packet};
 use bytes::Bytes;
 use std::fmt::Debug;
 use std::ops::Deref;
@@ -85,6 +85,20 @@ impl Debug for OptionalCallback<Bytes> {
     }
 }
 
+#[cfg_attr(tarpaulin, ignore)]
+impl Debug for OptionalCallback<DisconnectReason> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.write_fmt(format_args!(
+            "Callback({:?})",
+            if self.inner.is_some() {
+                "Fn(DisconnectReason)"
+            } else {
+                "None"
+            }
+        ))
+    }
+}
+
 impl<I> Deref for OptionalCallback<I> {
     type Target = Option<Box<DynCallback<I>>>;
     fn deref(&self) -> &<Self as std::ops::Deref>::Target {
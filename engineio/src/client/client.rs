@@ -1,17 +1,22 @@
-use super::super::socket::Socket as InnerSocket;
+use super::super::socket::{Socket as InnerSocket, Upgrade};
 use crate::callback::OptionalCallback;
-use crate::transport::Transport;
+use crate::transport::{Transport, TransportType};
 
 use crate::error::{Error, Result};
 use crate::header::HeaderMap;
+use crate::metrics::MetricsSnapshot;
 use crate::packet::{HandshakePacket, Packet, PacketId};
-use crate::transports::{PollingTransport, WebsocketSecureTransport, WebsocketTransport};
-use crate::ENGINE_IO_VERSION;
+use crate::recorder::{replay_frames, Frame, Recorder};
+use crate::transports::{
+    PollingTransport, ReplayTransport, WebsocketSecureTransport, WebsocketTransport,
+};
+use crate::{CookieJar, DisconnectReason, Proxy, Timeouts, TlsConfig, ENGINE_IO_VERSION};
 use bytes::Bytes;
-use native_tls::TlsConnector;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
 /// An engine.io client that allows interaction with the connected engine.io
@@ -29,14 +34,22 @@ pub struct Client {
 #[derive(Clone, Debug)]
 pub struct ClientBuilder {
     url: Url,
-    tls_config: Option<TlsConnector>,
+    tls_config: Option<TlsConfig>,
     headers: Option<HeaderMap>,
     handshake: Option<HandshakePacket>,
+    timeouts: Timeouts,
+    proxy: Option<Proxy>,
+    cookie_jar: Option<Arc<CookieJar>>,
+    timestamp_requests: bool,
+    timestamp_param: String,
+    max_incoming_payload: Option<usize>,
+    recorder: Option<Recorder>,
     on_error: OptionalCallback<String>,
     on_open: OptionalCallback<()>,
-    on_close: OptionalCallback<()>,
+    on_close: OptionalCallback<DisconnectReason>,
     on_data: OptionalCallback<Bytes>,
     on_packet: OptionalCallback<Packet>,
+    on_upgrade: OptionalCallback<()>,
 }
 
 impl ClientBuilder {
@@ -54,17 +67,27 @@ impl ClientBuilder {
             headers: None,
             tls_config: None,
             handshake: None,
+            timeouts: Timeouts::default(),
+            proxy: None,
+            cookie_jar: None,
+            timestamp_requests: true,
+            timestamp_param: "t".to_owned(),
+            max_incoming_payload: None,
+            recorder: None,
             on_close: OptionalCallback::default(),
             on_data: OptionalCallback::default(),
             on_error: OptionalCallback::default(),
             on_open: OptionalCallback::default(),
             on_packet: OptionalCallback::default(),
+            on_upgrade: OptionalCallback::default(),
         }
     }
 
-    /// Specify transport's tls config
-    pub fn tls_config(mut self, tls_config: TlsConnector) -> Self {
-        self.tls_config = Some(tls_config);
+    /// Specify transport's tls config, available with the `native-tls` or
+    /// `rustls` feature.
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub fn tls_config<T: Into<TlsConfig>>(mut self, tls_config: T) -> Self {
+        self.tls_config = Some(tls_config.into());
         self
     }
 
@@ -74,10 +97,93 @@ impl ClientBuilder {
         self
     }
 
-    /// Registers the `on_close` callback.
+    /// Specify a proxy used by the polling and websocket transports.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Specify a cookie jar shared by the handshake, the polling requests and
+    /// the websocket upgrade. Required by load balancers with sticky sessions.
+    pub fn cookie_jar(mut self, cookie_jar: Arc<CookieJar>) -> Self {
+        self.cookie_jar = Some(cookie_jar);
+        self
+    }
+
+    /// Appends a query parameter to the handshake, polling and websocket urls.
+    pub fn query_param<K: AsRef<str>, V: AsRef<str>>(mut self, key: K, value: V) -> Self {
+        self.url
+            .query_pairs_mut()
+            .append_pair(key.as_ref(), value.as_ref());
+        self
+    }
+
+    /// Specify the path of the engine.io endpoint, `/engine.io/` by default.
+    pub fn path<T: AsRef<str>>(mut self, path: T) -> Self {
+        self.url.set_path(path.as_ref());
+        self
+    }
+
+    /// Specify whether polling requests carry a cache busting timestamp, which
+    /// is enabled by default.
+    pub fn timestamp_requests(mut self, timestamp_requests: bool) -> Self {
+        self.timestamp_requests = timestamp_requests;
+        self
+    }
+
+    /// Specify the name of the timestamp query parameter, `t` by default.
+    pub fn timestamp_param<T: Into<String>>(mut self, param: T) -> Self {
+        self.timestamp_param = param.into();
+        self
+    }
+
+    /// Specify the maximum time for establishing the connection to the server.
+    /// For websocket transports this includes the http upgrade request.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum time to wait for the server to answer the handshake.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.handshake = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum time for a single http request of the polling transport.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum time to wait for data from the server once connected.
+    /// As the server may stay silent between heartbeats, this should be larger
+    /// than the sum of its `pingInterval` and `pingTimeout`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+
+    /// Specify the maximum size in bytes of a single payload received from
+    /// the server. Larger payloads fail with [`Error::InvalidPayloadSize`]
+    /// while being read, without buffering them. The handshake is not limited.
+    pub fn max_incoming_payload(mut self, size: usize) -> Self {
+        self.max_incoming_payload = Some(size);
+        self
+    }
+
+    /// Records every packet sent and received once connected, including the
+    /// handshake. See [`crate::recorder`] for the format of recordings.
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Registers the `on_close` callback, which is passed the reason the
+    /// connection closed for.
     pub fn on_close<T>(mut self, callback: T) -> Self
     where
-        T: Fn(()) + 'static + Sync + Send,
+        T: Fn(DisconnectReason) + 'static + Sync + Send,
     {
         self.on_close = OptionalCallback::new(callback);
         self
@@ -119,6 +225,16 @@ impl ClientBuilder {
         self
     }
 
+    /// Registers the `on_upgrade` callback, called once the client switched
+    /// from polling to websocket.
+    pub fn on_upgrade<T>(mut self, callback: T) -> Self
+    where
+        T: Fn(()) + 'static + Sync + Send,
+    {
+        self.on_upgrade = OptionalCallback::new(callback);
+        self
+    }
+
     /// Performs the handshake
     fn handshake_with_transport<T: Transport>(&mut self, transport: &T) -> Result<()> {
         // No need to handshake twice
@@ -127,8 +243,24 @@ impl ClientBuilder {
         }
 
         let mut url = self.url.clone();
-
-        let handshake: HandshakePacket = Packet::try_from(transport.poll()?)?.try_into()?;
+        let span = span!(INFO, "engineio.handshake", url = url.as_str());
+        let _enter = span.enter();
+
+        let data = transport
+            .poll_timeout(self.timeouts.handshake)
+            .map_err(|err| match err {
+                Error::ReadTimeout(timeout) => Error::HandshakeTimeout(timeout),
+                err => err,
+            })
+            .inspect_err(|err| event!(warn; "handshake failed: {}", err))?;
+        let handshake: HandshakePacket = Packet::try_from(data)?.try_into()?;
+        event!(
+            debug,
+            sid = handshake.sid,
+            upgrades = handshake.upgrades,
+            max_payload = handshake.max_payload;
+            "handshake completed"
+        );
 
         // update the base_url with the new sid
         url.query_pairs_mut().append_pair("sid", &handshake.sid[..]);
@@ -150,6 +282,12 @@ impl ClientBuilder {
             self.url.clone(),
             self.tls_config.clone(),
             self.headers.clone().map(|v| v.try_into().unwrap()),
+            self.timeouts,
+            self.proxy.clone(),
+            self.cookie_jar.clone(),
+            self.polling_timestamp_param(),
+            // the limit applies once connected
+            None,
         );
 
         self.handshake_with_transport(&transport)
@@ -167,14 +305,26 @@ impl ClientBuilder {
     }
 
     /// Build socket with polling transport
-    pub fn build_polling(mut self) -> Result<Client> {
+    pub fn build_polling(self) -> Result<Client> {
+        self.build_polling_with_upgrade(None)
+    }
+
+    /// Build socket with polling transport, which switches to the transport
+    /// of `upgrade` once connected
+    fn build_polling_with_upgrade(mut self, upgrade: Option<Upgrade>) -> Result<Client> {
         self.handshake()?;
 
         // Make a polling transport with new sid
+        let timestamp_param = self.polling_timestamp_param();
         let transport = PollingTransport::new(
-            self.url,
+            self.url.clone(),
             self.tls_config,
             self.headers.map(|v| v.try_into().unwrap()),
+            self.timeouts,
+            self.proxy.clone(),
+            self.cookie_jar.clone(),
+            timestamp_param,
+            self.max_incoming_payload,
         );
 
         // SAFETY: handshake function called previously.
@@ -182,23 +332,72 @@ impl ClientBuilder {
             socket: InnerSocket::new(
                 transport.into(),
                 self.handshake.unwrap(),
+                &self.url,
                 self.on_close,
                 self.on_data,
                 self.on_error,
                 self.on_open,
                 self.on_packet,
+                self.on_upgrade,
+                self.timeouts.read,
+                self.max_incoming_payload,
+                self.recorder,
+                upgrade,
             ),
         })
     }
 
-    /// Build socket with a polling transport then upgrade to websocket transport
+    /// Build socket with a polling transport then upgrade to websocket transport.
+    /// Once connected, the websocket is probed in the background and replaces
+    /// the polling transport after its pending poll returned. Packets emitted
+    /// in between are sent over the websocket.
     pub fn build_websocket_with_upgrade(mut self) -> Result<Client> {
         self.handshake()?;
 
-        if self.websocket_upgrade()? {
-            self.build_websocket()
+        if !self.websocket_upgrade()? {
+            return Err(Error::IllegalWebsocketUpgrade());
+        }
+
+        let builder = self.clone();
+        self.build_polling_with_upgrade(Some(Arc::new(move || builder.probe_websocket())))
+    }
+
+    /// Opens a websocket transport for the session of the handshake and
+    /// probes it.
+    fn probe_websocket(&self) -> Result<TransportType> {
+        let headers: Option<http::HeaderMap> = if let Some(map) = self.headers.clone() {
+            Some(map.try_into()?)
         } else {
-            Err(Error::IllegalWebsocketUpgrade())
+            None
+        };
+
+        match self.url.scheme() {
+            "http" | "ws" => {
+                let transport = WebsocketTransport::new(
+                    self.url.clone(),
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                )?;
+                transport.probe()?;
+                Ok(transport.into())
+            }
+            "https" | "wss" => {
+                let transport = WebsocketSecureTransport::new(
+                    self.url.clone(),
+                    self.tls_config.clone(),
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                )?;
+                transport.probe()?;
+                Ok(transport.into())
+            }
+            _ => Err(Error::InvalidUrlScheme(self.url.scheme().to_string())),
         }
     }
 
@@ -215,7 +414,14 @@ impl ClientBuilder {
 
         match url.scheme() {
             "http" | "ws" => {
-                let transport = WebsocketTransport::new(url, headers)?;
+                let transport = WebsocketTransport::new(
+                    url,
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                )?;
                 if self.handshake.is_some() {
                     transport.upgrade()?;
                 } else {
@@ -227,17 +433,30 @@ impl ClientBuilder {
                     socket: InnerSocket::new(
                         transport.into(),
                         self.handshake.unwrap(),
+                        &self.url,
                         self.on_close,
                         self.on_data,
                         self.on_error,
                         self.on_open,
                         self.on_packet,
+                        self.on_upgrade,
+                        self.timeouts.read,
+                        self.max_incoming_payload,
+                        self.recorder,
+                        None,
                     ),
                 })
             }
             "https" | "wss" => {
-                let transport =
-                    WebsocketSecureTransport::new(url, self.tls_config.clone(), headers)?;
+                let transport = WebsocketSecureTransport::new(
+                    url,
+                    self.tls_config.clone(),
+                    headers,
+                    self.timeouts,
+                    self.proxy.clone(),
+                    self.cookie_jar.clone(),
+                    self.max_incoming_payload,
+                )?;
                 if self.handshake.is_some() {
                     transport.upgrade()?;
                 } else {
@@ -249,11 +468,17 @@ impl ClientBuilder {
                     socket: InnerSocket::new(
                         transport.into(),
                         self.handshake.unwrap(),
+                        &self.url,
                         self.on_close,
                         self.on_data,
                         self.on_error,
                         self.on_open,
                         self.on_packet,
+                        self.on_upgrade,
+                        self.timeouts.read,
+                        self.max_incoming_payload,
+                        self.recorder,
+                        None,
                     ),
                 })
             }
@@ -272,6 +497,38 @@ impl ClientBuilder {
         }
     }
 
+    /// Build socket with a transport replaying the packets received in a
+    /// recording instead of connecting to the server. Packets sent by the
+    /// client are discarded and it's closed at the end of the recording.
+    pub fn build_replay(self, frames: Vec<Frame>) -> Result<Client> {
+        let (handshake, packets) = replay_frames(frames)?;
+        let transport = ReplayTransport::new(self.url.clone(), packets);
+
+        Ok(Client {
+            socket: InnerSocket::new(
+                transport.into(),
+                handshake,
+                &self.url,
+                self.on_close,
+                self.on_data,
+                self.on_error,
+                self.on_open,
+                self.on_packet,
+                self.on_upgrade,
+                self.timeouts.read,
+                self.max_incoming_payload,
+                self.recorder,
+                None,
+            ),
+        })
+    }
+
+    /// The timestamp query parameter of polling requests, if enabled.
+    fn polling_timestamp_param(&self) -> Option<String> {
+        self.timestamp_requests
+            .then(|| self.timestamp_param.clone())
+    }
+
     /// Checks the handshake to see if websocket upgrades are allowed
     fn websocket_upgrade(&mut self) -> Result<bool> {
         // SAFETY: handshake set by above function.
@@ -306,10 +563,59 @@ impl Client {
         self.socket.emit(packet)
     }
 
-    /// Polls for next payload
-    #[doc(hidden)]
+    /// Sends packets to the server in order, without other packets in between.
+    /// The polling transport sends them in a single request if they fit into
+    /// the maximum payload.
+    pub fn emit_batch(&self, packets: Vec<Packet>) -> Result<()> {
+        self.socket.emit_batch(packets)
+    }
+
+    /// Polls for the next packet, blocking until it arrives. Returns `None`
+    /// once the connection closed. Pings are answered while polling, so a
+    /// client that isn't iterated on a thread has to be polled regularly, see
+    /// [`Client::try_poll`].
     pub fn poll(&self) -> Result<Option<Packet>> {
         let packet = self.socket.poll()?;
+        self.handle_packet(packet)
+    }
+
+    /// Polls for the next packet without blocking, e.g. once per frame of a
+    /// game loop. Returns `None` if no packet is available, see
+    /// [`Client::poll_timeout`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rust_engineio::ClientBuilder;
+    /// use url::Url;
+    ///
+    /// let client = ClientBuilder::new(Url::parse("http://localhost:4201").unwrap())
+    ///     .build()
+    ///     .unwrap();
+    /// client.connect().unwrap();
+    ///
+    /// while client.is_connected().unwrap() {
+    ///     while let Some(packet) = client.try_poll().unwrap() {
+    ///         println!("{:?}", packet);
+    ///     }
+    ///     // render the frame
+    /// }
+    /// ```
+    pub fn try_poll(&self) -> Result<Option<Packet>> {
+        self.poll_timeout(Duration::ZERO)
+    }
+
+    /// Polls for the next packet, waiting at most `timeout` for it. Returns
+    /// `None` if none arrived in time or the connection closed, see
+    /// [`Client::is_connected`]. A read of the transport that doesn't complete
+    /// in time goes on in the background and is taken up by the next poll.
+    /// The packet is handled and pings are answered before this returns.
+    pub fn poll_timeout(&self, timeout: Duration) -> Result<Option<Packet>> {
+        let packet = self.socket.poll_timeout(timeout)?;
+        self.handle_packet(packet)
+    }
+
+    /// Calls the callbacks of a polled packet and answers pings.
+    fn handle_packet(&self, packet: Option<Packet>) -> Result<Option<Packet>> {
         if let Some(packet) = packet {
             // check for the appropriate action or callback
             self.socket.handle_packet(packet.clone());
@@ -321,7 +627,7 @@ impl Client {
                     self.socket.handle_data(packet.data.clone());
                 }
                 PacketId::Close => {
-                    self.socket.handle_close();
+                    self.socket.handle_close(DisconnectReason::TransportClose);
                 }
                 PacketId::Open => {
                     unreachable!("Won't happen as we open the connection beforehand");
@@ -332,6 +638,7 @@ impl Client {
                 PacketId::Ping => {
                     self.socket.pinged()?;
                     self.emit(Packet::new(PacketId::Pong, Bytes::new()))?;
+                    self.socket.ponged()?;
                 }
                 PacketId::Pong => {
                     // this will never happen as the pong packet is
@@ -351,7 +658,17 @@ impl Client {
         self.socket.is_connected()
     }
 
-    pub fn iter(&self) -> Iter {
+    /// Returns a snapshot of the metrics of the connection.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.socket.metrics()
+    }
+
+    /// Returns the reason the connection closed for, if it did.
+    pub fn close_reason(&self) -> Option<DisconnectReason> {
+        self.socket.close_reason()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
         Iter { socket: self }
     }
 }
@@ -449,9 +766,11 @@ mod test {
 
         Ok(())
     }
-    use reqwest::header::HOST;
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    use http::header::HOST;
 
     use crate::packet::Packet;
+    use crate::test::{serve, serve_upgrade, serve_with, HANDSHAKE};
 
     fn builder(url: Url) -> ClientBuilder {
         ClientBuilder::new(url)
@@ -542,6 +861,7 @@ mod test {
         test_connection(socket)
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[test]
     fn test_connection_dynamic_secure() -> Result<()> {
         let url = crate::test::engine_io_server_secure()?;
@@ -558,6 +878,7 @@ mod test {
         test_connection(socket)
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[test]
     fn test_connection_wss() -> Result<()> {
         let url = crate::test::engine_io_polling_server()?;
@@ -622,6 +943,7 @@ mod test {
         Ok(())
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[test]
     fn test_open_invariants() -> Result<()> {
         let url = crate::test::engine_io_server()?;
@@ -648,13 +970,274 @@ mod test {
 
         let _ = builder(url.clone())
             .tls_config(
-                TlsConnector::builder()
+                TlsConfig::builder()
                     .danger_accept_invalid_certs(true)
-                    .build()
-                    .unwrap(),
+                    .build()?,
             )
             .build()?;
         let _ = builder(url).headers(headers).build()?;
         Ok(())
     }
+
+    #[test]
+    fn test_handshake_timeout() -> Result<()> {
+        // a server that accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+
+        let timeout = Duration::from_millis(50);
+        let result = ClientBuilder::new(url)
+            .handshake_timeout(timeout)
+            .build_polling();
+        assert!(matches!(result, Err(Error::HandshakeTimeout(duration)) if duration == timeout));
+        Ok(())
+    }
+
+    /// Answers one http request per connection with the given bodies and
+    /// returns the request lines.
+    #[test]
+    fn test_url_options() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = serve(listener, vec![HANDSHAKE, "ok"]);
+
+        let client = ClientBuilder::new(url)
+            .path("/custom/")
+            .query_param("token", "a b")
+            .timestamp_param("ts")
+            .build_polling()?;
+        // sends a pong packet
+        client.connect()?;
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /custom/?EIO=4&token=a+b&transport=polling&ts="));
+        assert!(
+            requests[1].starts_with("POST /custom/?EIO=4&token=a+b&sid=abc&transport=polling&ts=")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled_timestamp_requests() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = serve(listener, vec![HANDSHAKE]);
+
+        ClientBuilder::new(url)
+            .timestamp_requests(false)
+            .build_polling()?;
+
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests[0],
+            "GET /engine.io/?EIO=4&transport=polling HTTP/1.1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_in_session() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (probed_tx, probed) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel();
+        let server = serve_upgrade(listener, probed_tx, release_rx);
+
+        let (upgraded_tx, upgraded) = std::sync::mpsc::channel();
+        let upgraded_tx = std::sync::Mutex::new(upgraded_tx);
+        let client = ClientBuilder::new(url)
+            .on_upgrade(move |_| upgraded_tx.lock().unwrap().send(()).unwrap())
+            .build()?;
+        client.connect()?;
+
+        // emits while the poll drains after the probe
+        let emitter = client.clone();
+        let emitter = std::thread::spawn(move || {
+            probed.recv().unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            emitter.emit(Packet::new(PacketId::Message, "queued"))?;
+            release.send(()).unwrap();
+            Ok::<_, Error>(())
+        });
+
+        let mut iter = client
+            .iter()
+            .map(|packet| packet.unwrap())
+            .filter(|packet| packet.packet_id != PacketId::Noop);
+        assert_eq!(iter.next(), Some(Packet::new(PacketId::Message, "drained")));
+        emitter.join().unwrap()?;
+        assert_eq!(
+            iter.next(),
+            Some(Packet::new(PacketId::Message, "upgraded"))
+        );
+        upgraded.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        let (requests, messages) = server.join().unwrap();
+        assert!(requests.iter().all(|request| !request.contains("queued")));
+        assert_eq!(messages, vec!["2probe", "5", "4queued"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_batched_emits() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (posted, bodies) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel();
+        let mut release_rx = Some(release_rx);
+        let server = serve_with(listener, vec![HANDSHAKE, "ok", "ok"], move |line, body| {
+            if line.starts_with("POST") {
+                posted.send(body.to_owned()).unwrap();
+                // holds the first write until the test emitted
+                if let Some(release) = release_rx.take() {
+                    release.recv().unwrap();
+                }
+            }
+        });
+
+        let client = ClientBuilder::new(url).build_polling()?;
+        let connecting = client.clone();
+        let connecting = std::thread::spawn(move || connecting.connect());
+        assert_eq!(bodies.recv().unwrap(), "3");
+
+        client.emit(Packet::new(PacketId::Message, "a"))?;
+        client.emit_batch(vec![
+            Packet::new(PacketId::Message, "header"),
+            Packet::new(PacketId::MessageBinary, vec![1, 2]),
+        ])?;
+        release.send(()).unwrap();
+        connecting.join().unwrap()?;
+
+        assert_eq!(bodies.recv().unwrap(), "4a\x1e4header\x1ebAQI=");
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_handshake_max_payload() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (posted, bodies) = std::sync::mpsc::channel();
+        let handshake = r#"0{"sid":"abc","upgrades":[],"pingInterval":25000,"pingTimeout":20000,"maxPayload":10}"#;
+        let server = serve_with(
+            listener,
+            vec![handshake, "ok", "ok", "ok"],
+            move |line, body| {
+                if line.starts_with("POST") {
+                    posted.send(body.to_owned()).unwrap();
+                }
+            },
+        );
+
+        let client = ClientBuilder::new(url).build_polling()?;
+        client.connect()?;
+        client.emit_batch(vec![
+            Packet::new(PacketId::Message, "hello"),
+            Packet::new(PacketId::Message, "world"),
+        ])?;
+
+        server.join().unwrap();
+        assert_eq!(bodies.iter().collect::<Vec<_>>(), ["3", "4hello", "4world"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_incoming_payload() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = serve(listener, vec![HANDSHAKE, "ok", "4exceeding the limit"]);
+
+        let client = ClientBuilder::new(url)
+            .max_incoming_payload(10)
+            .build_polling()?;
+        client.connect()?;
+
+        assert!(matches!(
+            client.poll(),
+            Err(Error::InvalidPayloadSize(20, 10))
+        ));
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_incoming_payload_streamed() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let server = crate::test::serve_endless(listener, vec![HANDSHAKE, "ok"]);
+
+        let client = ClientBuilder::new(url)
+            .max_incoming_payload(1024 * 1024)
+            .build_polling()?;
+        client.connect()?;
+
+        // the poll fails once the limit is passed, not at the end of the body
+        assert!(matches!(
+            client.poll(),
+            Err(Error::InvalidPayloadSize(size, 1048576)) if size > 1048576
+        ));
+        drop(client);
+        assert!(server.join().unwrap() < 64 * 1024 * 1024);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_incoming_payload_websocket() -> Result<()> {
+        let timeout = Duration::from_secs(5);
+        let server = crate::mock::MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .max_incoming_payload(1024)
+            .build_websocket()?;
+        client.connect()?;
+        let session = server.accept(timeout).unwrap();
+
+        session.send(Packet::new(PacketId::Message, "a".repeat(2048)));
+        assert!(matches!(
+            client.poll(),
+            Err(Error::InvalidPayloadSize(2049, 1024))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_poll() -> Result<()> {
+        let timeout = Duration::from_secs(5);
+        let server = crate::mock::MockServer::builder()
+            .ping_interval(Duration::from_millis(200))
+            .ping_timeout(Duration::from_millis(200))
+            .upgrades(false)
+            .build()?;
+
+        for client in [
+            ClientBuilder::new(server.url()).build_polling()?,
+            ClientBuilder::new(server.url()).build_websocket()?,
+        ] {
+            client.connect()?;
+            let session = server.accept(timeout).unwrap();
+
+            // the pings are answered in time while pumping the client
+            let start = std::time::Instant::now();
+            let mut pings = 0;
+            while start.elapsed() < Duration::from_secs(1) {
+                while let Some(packet) = client.try_poll()? {
+                    if packet.packet_id == PacketId::Ping {
+                        pings += 1;
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            assert!(pings >= 3);
+            assert!(client.is_connected()?);
+            assert!(client.metrics().pong_latency.is_some());
+
+            session.send(Packet::new(PacketId::Message, "hello"));
+            let message = std::iter::from_fn(|| client.poll_timeout(timeout).transpose())
+                .map(|packet| packet.unwrap())
+                .find(|packet| packet.packet_id == PacketId::Message);
+            assert_eq!(message, Some(Packet::new(PacketId::Message, "hello")));
+            client.disconnect()?;
+            assert_eq!(client.try_poll()?, None);
+        }
+        Ok(())
+    }
 }
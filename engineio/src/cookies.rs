@@ -0,0 +1,98 @@
+use cookie::Cookie;
+use cookie_store::CookieStore;
+use std::fmt::{self, Debug};
+use std::sync::{PoisonError, RwLock};
+use url::Url;
+
+/// A cookie jar that can be shared between the transports of a client, e.g.
+/// to keep the stickiness cookie of a load balancer.
+///
+/// # Example
+/// ```rust
+/// use rust_engineio::CookieJar;
+/// use url::Url;
+///
+/// let jar = CookieJar::default();
+/// let url = Url::parse("http://localhost:4200/").unwrap();
+/// jar.add_cookie_str("session=abc; Path=/", &url);
+/// ```
+#[derive(Default)]
+pub struct CookieJar(RwLock<CookieStore>);
+
+impl CookieJar {
+    /// Adds a cookie in the format of a `Set-Cookie` header as if it was set
+    /// by a response from `url`.
+    pub fn add_cookie_str(&self, cookie: &str, url: &Url) {
+        self.set_cookies([cookie], url);
+    }
+
+    /// The value of the `Cookie` header of a request to `url`, if any cookie
+    /// applies to it.
+    pub(crate) fn cookies(&self, url: &Url) -> Option<String> {
+        let store = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        let cookies = store
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Some(cookies).filter(|cookies| !cookies.is_empty())
+    }
+
+    /// Stores the cookies of the `Set-Cookie` headers of a response from
+    /// `url`, ignoring invalid ones.
+    pub(crate) fn set_cookies<'a, I>(&self, cookies: I, url: &Url)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let cookies = cookies
+            .into_iter()
+            .filter_map(|cookie| Cookie::parse(cookie.to_owned()).ok());
+        self.0
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .store_response_cookies(cookies, url);
+    }
+}
+
+impl Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieJar").finish_non_exhaustive()
+    }
+}
+
+/// Lets the async polling transport share the jar with `reqwest`.
+#[cfg(feature = "async")]
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &http::HeaderValue>, url: &Url) {
+        CookieJar::set_cookies(
+            self,
+            cookie_headers.filter_map(|value| value.to_str().ok()),
+            url,
+        );
+    }
+
+    fn cookies(&self, url: &Url) -> Option<http::HeaderValue> {
+        CookieJar::cookies(self, url).and_then(|cookies| cookies.parse().ok())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cookie_jar() {
+        let jar = CookieJar::default();
+        let url = Url::parse("http://localhost:4200/engine.io/").unwrap();
+        assert_eq!(jar.cookies(&url), None);
+
+        jar.add_cookie_str("session=abc; Path=/", &url);
+        jar.set_cookies(["lb=1", "not a cookie"], &url);
+        let cookies = jar.cookies(&url).unwrap();
+        let mut cookies: Vec<&str> = cookies.split("; ").collect();
+        cookies.sort_unstable();
+        assert_eq!(cookies, vec!["lb=1", "session=abc"]);
+        let other = Url::parse("http://example.com/").unwrap();
+        assert_eq!(jar.cookies(&other), None);
+    }
+}
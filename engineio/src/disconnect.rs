@@ -0,0 +1,109 @@
+use std::fmt::{self, Display, Formatter};
+
+/// The reason a connection closed for, which is passed to the `on_close`
+/// callback. The names follow the reasons of the JavaScript client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// The client closed the connection, `"forced close"`.
+    ForcedClose,
+    /// The server closed the connection, `"transport close"`.
+    TransportClose,
+    /// The server sent no ping within the ping interval and timeout of the
+    /// handshake, `"ping timeout"`.
+    PingTimeout,
+}
+
+impl DisconnectReason {
+    /// The reason as named by the JavaScript client.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisconnectReason::ForcedClose => "forced close",
+            DisconnectReason::TransportClose => "transport close",
+            DisconnectReason::PingTimeout => "ping timeout",
+        }
+    }
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::{Error, Result};
+    use crate::mock::MockServer;
+    use crate::{ClientBuilder, Packet, PacketId};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_close_reasons() -> Result<()> {
+        let server = MockServer::builder().upgrades(false).build()?;
+        let (sender, receiver) = mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+        let client = ClientBuilder::new(server.url())
+            .on_close(move |reason| {
+                let _ = sender.lock().unwrap().send(reason);
+            })
+            .build_polling()?;
+        client.connect()?;
+        let session = server.accept(TIMEOUT).unwrap();
+
+        session.close();
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Close, bytes::Bytes::new()))
+        );
+        assert_eq!(
+            receiver.recv_timeout(TIMEOUT),
+            Ok(DisconnectReason::TransportClose)
+        );
+        assert_eq!(
+            client.close_reason(),
+            Some(DisconnectReason::TransportClose)
+        );
+        // a closed connection closes once
+        client.disconnect()?;
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+
+        let client = ClientBuilder::new(server.url()).build_polling()?;
+        client.connect()?;
+        assert_eq!(client.close_reason(), None);
+        client.disconnect()?;
+        assert_eq!(client.close_reason(), Some(DisconnectReason::ForcedClose));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ping_timeout() -> Result<()> {
+        let server = MockServer::builder()
+            .ping_interval(Duration::from_millis(50))
+            .ping_timeout(Duration::from_millis(50))
+            .pings(false)
+            .upgrades(false)
+            .build()?;
+        let client = ClientBuilder::new(server.url()).build_polling()?;
+        client.connect()?;
+
+        let result = loop {
+            match client.poll() {
+                Ok(Some(_)) => continue,
+                result => break result,
+            }
+        };
+        assert!(matches!(
+            result,
+            Err(Error::PingTimeout(timeout)) if timeout == Duration::from_millis(100)
+        ));
+        assert!(!client.is_connected()?);
+        assert_eq!(client.close_reason(), Some(DisconnectReason::PingTimeout));
+        assert_eq!(DisconnectReason::PingTimeout.to_string(), "ping timeout");
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
 use base64::DecodeError;
+#[cfg(feature = "async")]
 use reqwest::Error as ReqwestError;
 use serde_json::Error as JsonError;
 use std::io::Error as IoError;
 use std::str::Utf8Error;
+use std::time::Duration;
 use thiserror::Error;
 use tungstenite::Error as TungsteniteError;
 use url::ParseError as UrlParseError;
@@ -28,10 +30,13 @@ pub enum Error {
     InvalidUrl(#[from] UrlParseError),
     #[error("Invalid Url Scheme: {0}")]
     InvalidUrlScheme(String),
+    #[cfg(feature = "async")]
     #[error("Error during connection via http: {0}")]
     IncompleteResponseFromReqwest(#[from] ReqwestError),
+    #[error("Error during connection via http: {0}")]
+    IncompleteResponseFromUreq(Box<ureq::Transport>),
     #[error("Error with websocket connection: {0}")]
-    WebsocketError(#[from] TungsteniteError),
+    WebsocketError(Box<TungsteniteError>),
     #[error("Network request returned with status code: {0}")]
     IncompleteHttp(u16),
     #[error("Got illegal handshake response: {0}")]
@@ -49,9 +54,29 @@ pub enum Error {
     #[error("Server did not allow upgrading to websockets")]
     IllegalWebsocketUpgrade(),
     #[error("Invalid header name")]
-    InvalidHeaderNameFromReqwest(#[from] reqwest::header::InvalidHeaderName),
+    InvalidHeaderNameFromReqwest(#[from] http::header::InvalidHeaderName),
     #[error("Invalid header value")]
-    InvalidHeaderValueFromReqwest(#[from] reqwest::header::InvalidHeaderValue),
+    InvalidHeaderValueFromReqwest(#[from] http::header::InvalidHeaderValue),
+    #[error("Connecting to the server timed out after {0:?}")]
+    ConnectTimeout(Duration),
+    #[error("The server did not answer the handshake within {0:?}")]
+    HandshakeTimeout(Duration),
+    #[error("Network request timed out after {0:?}")]
+    RequestTimeout(Duration),
+    #[error("Received no data from the server within {0:?}")]
+    ReadTimeout(Duration),
+    #[error("The server sent no ping within {0:?}")]
+    PingTimeout(Duration),
+    #[error("Invalid proxy url: {0}")]
+    InvalidProxy(String),
+    #[error("Could not connect through the proxy: {0}")]
+    IncompleteProxyConnect(String),
+    #[error("Invalid TLS configuration: {0}")]
+    InvalidTlsConfig(String),
+    #[error("Received a payload of {0} bytes, exceeding the maximum of {1} bytes")]
+    InvalidPayloadSize(usize, usize),
+    #[error("Found no async runtime, pass one to the builder or enable the `async-std` or `smol` feature")]
+    InvalidRuntime(),
 }
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
@@ -62,9 +87,15 @@ impl<T> From<std::sync::PoisonError<T>> for Error {
     }
 }
 
+impl From<TungsteniteError> for Error {
+    fn from(err: TungsteniteError) -> Self {
+        Self::WebsocketError(Box::new(err))
+    }
+}
+
 impl From<Error> for std::io::Error {
     fn from(err: Error) -> std::io::Error {
-        std::io::Error::new(std::io::ErrorKind::Other, err)
+        std::io::Error::other(err)
     }
 }
 
@@ -82,8 +113,7 @@ mod tests {
         assert!(matches!(Error::InvalidPoisonedLock(), _error));
 
         let _io_error = std::io::Error::from(Error::IllegalWebsocketUpgrade());
-        let _error =
-            std::io::Error::new(std::io::ErrorKind::Other, Error::IllegalWebsocketUpgrade());
+        let _error = std::io::Error::other(Error::IllegalWebsocketUpgrade());
         assert!(matches!(_io_error, _error));
     }
 }
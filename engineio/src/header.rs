@@ -27,9 +27,9 @@ pub struct IntoIter {
     inner: std::collections::hash_map::IntoIter<HeaderName, HeaderValue>,
 }
 
-impl ToString for HeaderName {
-    fn to_string(&self) -> std::string::String {
-        self.inner.clone()
+impl std::fmt::Display for HeaderName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.inner)
     }
 }
 
@@ -39,6 +39,12 @@ impl From<String> for HeaderName {
     }
 }
 
+impl From<&str> for HeaderName {
+    fn from(string: &str) -> Self {
+        Self::from(string.to_owned())
+    }
+}
+
 impl TryFrom<HeaderName> for HttpHeaderName {
     type Error = Error;
     fn try_from(
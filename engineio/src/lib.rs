@@ -48,15 +48,19 @@
 //! * on_error
 //! * on_packet
 //!
-//! It is also possible to pass in custom tls configurations via the `TlsConnector` as well
-//! as custom headers for the opening request.
+//! It is also possible to pass in custom tls configurations via the [`TlsConfig`] as well
+//! as custom headers for the opening request. TLS is provided by the platform's native
+//! implementation (feature `native-tls`, enabled by default) or by `rustls` (feature `rustls`).
+//! An optional [`CookieJar`] keeps cookies set by the server across the handshake, the
+//! polling requests and the websocket upgrade.
 //!
 //! ## Async version
 //!
 //! The crate also ships with an asynchronous version that can be enabled with a feature flag.
 //! The async version implements the same features mentioned above.
-//! The asynchronous version has a similar API, just with async functions. Currently the futures
-//! can only be executed with [`tokio`](https://tokio.rs). In the first benchmarks the async version
+//! The asynchronous version has a similar API, just with async functions. The futures run on
+//! [`tokio`](https://tokio.rs) by default, the `async-std` and `smol` features or a custom
+//! [`asynchronous::Runtime`] passed to the builder allow other executors. In the first benchmarks the async version
 //! showed improvements of up to 93% in speed.
 //! To make use of the async version, import the crate as follows:
 //! ```toml
@@ -79,13 +83,26 @@ macro_rules! spawn_scoped {
     };
 }
 
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "async")]
 pub mod asynchronous;
 mod callback;
 pub mod client;
+mod cookies;
+mod disconnect;
 /// Generic header map
 pub mod header;
+pub mod metrics;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock;
 pub mod packet;
-pub(self) mod socket;
+mod proxy;
+pub mod recorder;
+mod socket;
+mod timeouts;
+pub mod tls;
 pub mod transport;
 pub mod transports;
 
@@ -96,30 +113,37 @@ pub const ENGINE_IO_VERSION: i32 = 4;
 pub mod error;
 
 pub use client::{Client, ClientBuilder};
+pub use cookies::CookieJar;
+pub use disconnect::DisconnectReason;
 pub use error::Error;
 pub use packet::{Packet, PacketId};
+pub use proxy::Proxy;
+pub use recorder::Recorder;
+pub use timeouts::Timeouts;
+pub use tls::TlsConfig;
 
 #[cfg(test)]
 pub(crate) mod test {
     use super::*;
-    use native_tls::TlsConnector;
     const CERT_PATH: &str = "../ci/cert/ca.crt";
-    use native_tls::Certificate;
     use std::fs::File;
     use std::io::Read;
 
-    pub(crate) fn tls_connector() -> error::Result<TlsConnector> {
+    pub(crate) fn tls_connector() -> error::Result<TlsConfig> {
         let cert_path = std::env::var("CA_CERT_PATH").unwrap_or_else(|_| CERT_PATH.to_owned());
         let mut cert_file = File::open(cert_path)?;
         let mut buf = vec![];
         cert_file.read_to_end(&mut buf)?;
-        let cert: Certificate = Certificate::from_pem(&buf[..]).unwrap();
-        Ok(TlsConnector::builder()
+        #[cfg(feature = "native-tls")]
+        return Ok(native_tls::TlsConnector::builder()
             // ONLY USE FOR TESTING!
             .danger_accept_invalid_hostnames(true)
-            .add_root_certificate(cert)
+            .add_root_certificate(native_tls::Certificate::from_pem(&buf[..]).unwrap())
             .build()
-            .unwrap())
+            .unwrap()
+            .into());
+        #[cfg(not(feature = "native-tls"))]
+        return TlsConfig::builder().add_root_certificate(&buf).build();
     }
     /// The `engine.io` server for testing runs on port 4201
     const SERVER_URL: &str = "http://localhost:4201";
@@ -144,4 +168,202 @@ pub(crate) mod test {
             .unwrap_or_else(|_| SERVER_URL_SECURE.to_owned());
         Ok(Url::parse(&url)?)
     }
+
+    /// Answers one http request per body in `bodies` and returns the request
+    /// lines.
+    pub(crate) fn serve(
+        listener: std::net::TcpListener,
+        bodies: Vec<&'static str>,
+    ) -> std::thread::JoinHandle<Vec<String>> {
+        serve_with(listener, bodies, |_, _| ())
+    }
+
+    /// Like [`serve`], but calls `on_request` with the request line and the
+    /// body of every request before answering it.
+    pub(crate) fn serve_with<F>(
+        listener: std::net::TcpListener,
+        bodies: Vec<&'static str>,
+        mut on_request: F,
+    ) -> std::thread::JoinHandle<Vec<String>>
+    where
+        F: FnMut(&str, &str) + Send + 'static,
+    {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        std::thread::spawn(move || {
+            let mut request_lines = Vec::new();
+            for body in bodies {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut request_body = String::new();
+                reader
+                    .by_ref()
+                    .take(content_length)
+                    .read_to_string(&mut request_body)
+                    .unwrap();
+                on_request(request_line.trim_end(), &request_body);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                request_lines.push(request_line.trim_end().to_owned());
+            }
+            request_lines
+        })
+    }
+
+    /// Answers the requests for `bodies` like [`serve`], then answers the
+    /// next request with a chunked message body of up to 64 MiB. Returns the
+    /// number of body bytes written before the client hung up.
+    pub(crate) fn serve_endless(
+        listener: std::net::TcpListener,
+        bodies: Vec<&'static str>,
+    ) -> std::thread::JoinHandle<usize> {
+        use std::io::{BufRead, BufReader, Write};
+
+        let local = listener.try_clone().unwrap();
+        let served = serve(local, bodies);
+        std::thread::spawn(move || {
+            served.join().unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+            let stream = reader.get_mut();
+            let chunk = format!("{:x}\r\n{}\r\n", 64 * 1024, "4".repeat(64 * 1024));
+            let mut written = 0;
+            let head = "HTTP/1.1 200 OK\r\nconnection: close\r\ntransfer-encoding: chunked\r\n\r\n";
+            if stream.write_all(head.as_bytes()).is_err() {
+                return written;
+            }
+            while written < 64 * 1024 * 1024 {
+                if stream.write_all(chunk.as_bytes()).is_err() {
+                    break;
+                }
+                written += 64 * 1024;
+            }
+            let _ = stream.write_all(b"0\r\n\r\n");
+            written
+        })
+    }
+
+    pub(crate) const HANDSHAKE: &str =
+        r#"0{"sid":"abc","upgrades":[],"pingInterval":25000,"pingTimeout":20000}"#;
+
+    /// Serves a session that upgrades from polling to websocket. The first
+    /// poll is answered with a message and a `noop` packet once `release`
+    /// receives. The websocket answers the probe once that poll arrived,
+    /// signals `probed`, and sends a message after reading the upgrade packet
+    /// and one more packet. Returns the polling requests with their bodies and
+    /// the websocket messages.
+    pub(crate) fn serve_upgrade(
+        listener: std::net::TcpListener,
+        probed: std::sync::mpsc::Sender<()>,
+        release: std::sync::mpsc::Receiver<()>,
+    ) -> std::thread::JoinHandle<(Vec<String>, Vec<String>)> {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use tungstenite::Message;
+
+        const HANDSHAKE: &str =
+            r#"0{"sid":"abc","upgrades":["websocket"],"pingInterval":25000,"pingTimeout":20000}"#;
+
+        std::thread::spawn(move || {
+            let (polled_tx, polled) = std::sync::mpsc::channel();
+            let (mut probed, mut polled, mut release) = (Some(probed), Some(polled), Some(release));
+            let mut requests = Vec::new();
+            let mut websocket = None;
+
+            // the handshake, the pong, the poll and the websocket
+            for _ in 0..4 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut head = [0; 256];
+                let len = stream.peek(&mut head).unwrap();
+                let head = String::from_utf8_lossy(&head[..len]).into_owned();
+                if head.contains("transport=websocket") {
+                    let (probed, polled) = (probed.take().unwrap(), polled.take().unwrap());
+                    websocket = Some(std::thread::spawn(move || {
+                        let mut socket = tungstenite::accept(stream).unwrap();
+                        let mut messages = vec![socket.read_message().unwrap().to_string()];
+                        polled.recv().unwrap();
+                        socket.write_message(Message::text("3probe")).unwrap();
+                        probed.send(()).unwrap();
+                        for _ in 0..2 {
+                            messages.push(socket.read_message().unwrap().to_string());
+                        }
+                        socket.write_message(Message::text("4upgraded")).unwrap();
+                        messages
+                    }));
+                    continue;
+                }
+
+                let polled_tx = polled_tx.clone();
+                let release = if head.starts_with("GET") && head.contains("sid=") {
+                    release.take()
+                } else {
+                    None
+                };
+                requests.push(std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream);
+                    let mut request = String::new();
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                            content_length = length.trim().parse().unwrap();
+                        }
+                        request.push_str(&line);
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    reader
+                        .by_ref()
+                        .take(content_length)
+                        .read_to_string(&mut request)
+                        .unwrap();
+
+                    let body = if let Some(release) = release {
+                        polled_tx.send(()).unwrap();
+                        release.recv().unwrap();
+                        "4drained\x1e6"
+                    } else if request.contains("sid=") {
+                        "ok"
+                    } else {
+                        HANDSHAKE
+                    };
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    reader.get_mut().write_all(response.as_bytes()).unwrap();
+                    request
+                }));
+            }
+            let requests = requests
+                .into_iter()
+                .map(|request| request.join().unwrap())
+                .collect();
+            (requests, websocket.unwrap().join().unwrap())
+        })
+    }
 }
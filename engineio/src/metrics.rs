@@ -0,0 +1,302 @@
+//! Metrics of the connection of a client, see [`crate::Client::metrics`].
+//!
+//! With the `metrics` feature they are reported to the [`metrics`](https://docs.rs/metrics)
+//! facade as well, as the counters `engineio_packets_sent_total`,
+//! `engineio_bytes_sent_total`, `engineio_packets_received_total` and
+//! `engineio_bytes_received_total` labeled by packet `type`, the counter
+//! `engineio_upgrades_total`, the gauge `engineio_buffered_packets` and the
+//! histogram `engineio_pong_latency_seconds`.
+//! The gauge sums over the live clients, a dropped client takes its value off.
+use crate::packet::{Packet, PacketId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const PACKET_IDS: [PacketId; 8] = [
+    PacketId::Open,
+    PacketId::Close,
+    PacketId::Ping,
+    PacketId::Pong,
+    PacketId::Message,
+    PacketId::MessageBinary,
+    PacketId::Upgrade,
+    PacketId::Noop,
+];
+
+/// The number of packets and their size in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl std::ops::Add for Traffic {
+    type Output = Traffic;
+
+    fn add(self, other: Traffic) -> Traffic {
+        Traffic {
+            packets: self.packets + other.packets,
+            bytes: self.bytes + other.bytes,
+        }
+    }
+}
+
+/// A snapshot of the metrics of an engine.io client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetricsSnapshot {
+    /// The packets sent by type. The bytes are counted as encoded, binary
+    /// data `base64` encoded.
+    pub sent: HashMap<PacketId, Traffic>,
+    /// The packets received by type.
+    pub received: HashMap<PacketId, Traffic>,
+    /// The current transport, `polling`, `websocket` or `replay`.
+    pub transport: &'static str,
+    /// The number of packets waiting for a write in flight or an upgrade.
+    pub buffered: usize,
+    /// The time from receiving the last ping to having written its pong,
+    /// which includes the round trip of the request for the polling transport.
+    pub pong_latency: Option<Duration>,
+}
+
+impl MetricsSnapshot {
+    /// The packets sent in total.
+    pub fn total_sent(&self) -> Traffic {
+        self.sent.values().fold(Traffic::default(), |a, b| a + *b)
+    }
+
+    /// The packets received in total.
+    pub fn total_received(&self) -> Traffic {
+        self.received
+            .values()
+            .fold(Traffic::default(), |a, b| a + *b)
+    }
+}
+
+/// Counts packets and their size in bytes.
+#[derive(Debug, Default)]
+pub struct TrafficCounter {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl TrafficCounter {
+    /// Counts a packet of `bytes` bytes.
+    pub fn add(&self, bytes: usize) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// The packets counted so far.
+    pub fn get(&self) -> Traffic {
+        Traffic {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The metrics of a socket, shared by its clones.
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    sent: [TrafficCounter; 8],
+    received: [TrafficCounter; 8],
+    transport: Mutex<&'static str>,
+    buffered: AtomicUsize,
+    /// The pong latency in nanoseconds, `0` until the first pong.
+    pong_latency: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn new(transport: &'static str) -> Self {
+        Metrics {
+            sent: Default::default(),
+            received: Default::default(),
+            transport: Mutex::new(transport),
+            buffered: AtomicUsize::new(0),
+            pong_latency: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn sent(&self, packet: &Packet) {
+        let bytes = packet.encoded_len();
+        self.sent[index(packet.packet_id)].add(bytes);
+        #[cfg(feature = "metrics")]
+        {
+            let packet_type = packet_type(packet.packet_id);
+            ::metrics::counter!("engineio_packets_sent_total", "type" => packet_type).increment(1);
+            ::metrics::counter!("engineio_bytes_sent_total", "type" => packet_type)
+                .increment(bytes as u64);
+        }
+    }
+
+    pub(crate) fn received(&self, packet: &Packet) {
+        let bytes = packet.encoded_len();
+        self.received[index(packet.packet_id)].add(bytes);
+        #[cfg(feature = "metrics")]
+        {
+            let packet_type = packet_type(packet.packet_id);
+            ::metrics::counter!("engineio_packets_received_total", "type" => packet_type)
+                .increment(1);
+            ::metrics::counter!("engineio_bytes_received_total", "type" => packet_type)
+                .increment(bytes as u64);
+        }
+    }
+
+    pub(crate) fn upgraded(&self, transport: &'static str) {
+        if let Ok(mut current) = self.transport.lock() {
+            *current = transport;
+        }
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!("engineio_upgrades_total").increment(1);
+    }
+
+    pub(crate) fn set_buffered(&self, buffered: usize) {
+        let _previous = self.buffered.swap(buffered, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!("engineio_buffered_packets")
+            .increment(buffered as f64 - _previous as f64);
+    }
+
+    pub(crate) fn ponged(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX).max(1);
+        self.pong_latency.store(nanos, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::histogram!("engineio_pong_latency_seconds").record(latency.as_secs_f64());
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let traffic = |counters: &[TrafficCounter; 8]| {
+            PACKET_IDS
+                .iter()
+                .map(|id| (*id, counters[index(*id)].get()))
+                .filter(|(_, traffic)| traffic.packets > 0)
+                .collect()
+        };
+        let pong_latency = self.pong_latency.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            sent: traffic(&self.sent),
+            received: traffic(&self.received),
+            transport: self.transport.lock().map_or("", |transport| *transport),
+            buffered: self.buffered.load(Ordering::Relaxed),
+            pong_latency: (pong_latency > 0).then(|| Duration::from_nanos(pong_latency)),
+        }
+    }
+}
+
+/// Takes the buffered packets of the socket off the gauge, which adds up
+/// those of all sockets.
+#[cfg(feature = "metrics")]
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        ::metrics::gauge!("engineio_buffered_packets").decrement(*self.buffered.get_mut() as f64);
+    }
+}
+
+fn index(packet_id: PacketId) -> usize {
+    match packet_id {
+        PacketId::Open => 0,
+        PacketId::Close => 1,
+        PacketId::Ping => 2,
+        PacketId::Pong => 3,
+        PacketId::Message => 4,
+        PacketId::MessageBinary => 5,
+        PacketId::Upgrade => 6,
+        PacketId::Noop => 7,
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn packet_type(packet_id: PacketId) -> &'static str {
+    match packet_id {
+        PacketId::Open => "open",
+        PacketId::Close => "close",
+        PacketId::Ping => "ping",
+        PacketId::Pong => "pong",
+        PacketId::Message => "message",
+        PacketId::MessageBinary => "message_binary",
+        PacketId::Upgrade => "upgrade",
+        PacketId::Noop => "noop",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::ClientBuilder;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = Metrics::new("polling");
+        metrics.sent(&Packet::new(PacketId::Message, "hello"));
+        metrics.sent(&Packet::new(PacketId::Message, "world"));
+        metrics.sent(&Packet::new(PacketId::MessageBinary, vec![1, 2, 3]));
+        metrics.received(&Packet::new(PacketId::Ping, Bytes::new()));
+        metrics.set_buffered(2);
+        metrics.upgraded("websocket");
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot.sent[&PacketId::Message],
+            Traffic {
+                packets: 2,
+                bytes: 12
+            }
+        );
+        assert_eq!(
+            snapshot.sent[&PacketId::MessageBinary],
+            Traffic {
+                packets: 1,
+                bytes: 5
+            }
+        );
+        assert_eq!(
+            snapshot.total_sent(),
+            Traffic {
+                packets: 3,
+                bytes: 17
+            }
+        );
+        assert_eq!(
+            snapshot.total_received(),
+            Traffic {
+                packets: 1,
+                bytes: 1
+            }
+        );
+        assert_eq!(snapshot.transport, "websocket");
+        assert_eq!(snapshot.buffered, 2);
+        assert_eq!(snapshot.pong_latency, None);
+
+        metrics.ponged(Duration::from_millis(3));
+        assert_eq!(
+            metrics.snapshot().pong_latency,
+            Some(Duration::from_millis(3))
+        );
+    }
+
+    #[test]
+    fn test_client_metrics() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url()).build_polling()?;
+        client.connect()?;
+        let session = server.accept(Duration::from_secs(5)).unwrap();
+
+        client.emit(Packet::new(PacketId::Message, "hello"))?;
+        session.send(Packet::new(PacketId::Ping, Bytes::new()));
+        client.poll()?;
+
+        let snapshot = client.metrics();
+        assert_eq!(snapshot.transport, "polling");
+        assert_eq!(snapshot.buffered, 0);
+        assert_eq!(snapshot.sent[&PacketId::Message].packets, 1);
+        assert_eq!(snapshot.sent[&PacketId::Pong].packets, 2);
+        assert_eq!(snapshot.received[&PacketId::Ping].packets, 1);
+        assert!(snapshot.pong_latency.is_some());
+        Ok(())
+    }
+}
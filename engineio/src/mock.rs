@@ -0,0 +1,620 @@
+//! A scriptable `engine.io` server on localhost, which runs inside the test
+//! process instead of the node servers in `ci/`. It speaks polling and
+//! websocket, including the upgrade from polling, sends the packets a test
+//! scripts and hands the messages it receives to the test.
+//!
+//! # Example
+//! ```rust
+//! use rust_engineio::mock::MockServer;
+//! use rust_engineio::{ClientBuilder, Packet, PacketId};
+//! use std::time::Duration;
+//!
+//! let server = MockServer::builder().build().unwrap();
+//! let client = ClientBuilder::new(server.url()).build_polling().unwrap();
+//! client.connect().unwrap();
+//! let session = server.accept(Duration::from_secs(5)).unwrap();
+//!
+//! client.emit(Packet::new(PacketId::Message, "hello")).unwrap();
+//! let packet = session.recv(Duration::from_secs(5)).unwrap();
+//! assert_eq!(packet.data, "hello");
+//! ```
+use crate::error::Result;
+use crate::packet::{HandshakePacket, Packet, PacketId, Payload};
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+/// How long the server waits for a connection or a websocket message at once
+/// before it checks whether there is something else to do.
+const READ_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Configures and starts a [`MockServer`].
+#[derive(Clone, Debug)]
+pub struct MockServerBuilder {
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    pings: bool,
+    upgrades: bool,
+    max_payload: Option<usize>,
+}
+
+impl MockServerBuilder {
+    pub fn new() -> Self {
+        MockServerBuilder {
+            ping_interval: Duration::from_millis(25000),
+            ping_timeout: Duration::from_millis(20000),
+            pings: true,
+            upgrades: true,
+            max_payload: None,
+        }
+    }
+
+    /// Specify the interval in which the server pings its clients.
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// Specify the `pingTimeout` announced in the handshake.
+    pub fn ping_timeout(mut self, timeout: Duration) -> Self {
+        self.ping_timeout = timeout;
+        self
+    }
+
+    /// Specify whether the server pings its clients at all, to let them run
+    /// into their ping timeout. Defaults to `true`.
+    pub fn pings(mut self, pings: bool) -> Self {
+        self.pings = pings;
+        self
+    }
+
+    /// Specify whether the handshake of polling sessions offers the upgrade to
+    /// websocket. Defaults to `true`.
+    pub fn upgrades(mut self, upgrades: bool) -> Self {
+        self.upgrades = upgrades;
+        self
+    }
+
+    /// Specify the `maxPayload` announced in the handshake.
+    pub fn max_payload(mut self, max_payload: usize) -> Self {
+        self.max_payload = Some(max_payload);
+        self
+    }
+
+    /// Binds the server to a free port on localhost and starts serving.
+    pub fn build(self) -> Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+
+        let (sessions, new_sessions) = mpsc::channel();
+        let server = Arc::new(Server {
+            config: self,
+            sessions: Mutex::new(HashMap::new()),
+            new_sessions: Mutex::new(sessions),
+            next_sid: AtomicUsize::new(0),
+        });
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let stop = stopped.clone();
+        thread::spawn(move || {
+            while !stop.load(Ordering::Acquire) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let server = server.clone();
+                        thread::spawn(move || {
+                            let _ = stream
+                                .set_nonblocking(false)
+                                .map_err(Into::into)
+                                .and_then(|_| server.handle_connection(stream));
+                        });
+                    }
+                    Err(_) => thread::sleep(READ_INTERVAL),
+                }
+            }
+        });
+
+        Ok(MockServer {
+            url,
+            new_sessions: Mutex::new(new_sessions),
+            stopped,
+        })
+    }
+}
+
+impl Default for MockServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An `engine.io` server for tests, see the [module documentation](self).
+/// The server stops accepting connections once it is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    url: Url,
+    new_sessions: Mutex<Receiver<MockSession>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    pub fn builder() -> MockServerBuilder {
+        MockServerBuilder::new()
+    }
+
+    /// The url to connect clients to.
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Waits for the next client to open a session, `None` if no client did
+    /// within `timeout`.
+    pub fn accept(&self, timeout: Duration) -> Option<MockSession> {
+        self.new_sessions.lock().ok()?.recv_timeout(timeout).ok()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+}
+
+/// The server side of a session with a client.
+#[derive(Debug)]
+pub struct MockSession {
+    session: Arc<Session>,
+    received: Receiver<Packet>,
+}
+
+impl MockSession {
+    /// The id of the session.
+    pub fn sid(&self) -> &str {
+        &self.session.sid
+    }
+
+    /// Sends `packet` to the client with the next poll or over the websocket.
+    pub fn send(&self, packet: Packet) {
+        self.session.push(packet);
+    }
+
+    /// Waits for the next message or binary message from the client, `None`
+    /// if none arrived within `timeout`.
+    pub fn recv(&self, timeout: Duration) -> Option<Packet> {
+        self.received.recv_timeout(timeout).ok()
+    }
+
+    /// Sends a close packet and ends the session. Later requests of the client
+    /// fail.
+    pub fn close(&self) {
+        let mut state = self.session.state.lock().unwrap();
+        state
+            .outgoing
+            .push_back(Packet::new(PacketId::Close, Bytes::new()));
+        state.closed = true;
+        self.session.changed.notify_all();
+    }
+
+    /// Whether the session uses a websocket, either from the start or after
+    /// an upgrade.
+    pub fn is_websocket(&self) -> bool {
+        self.session.state.lock().unwrap().websocket
+    }
+
+    /// Whether the client or the server closed the session.
+    pub fn is_closed(&self) -> bool {
+        self.session.state.lock().unwrap().closed
+    }
+}
+
+struct Server {
+    config: MockServerBuilder,
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+    new_sessions: Mutex<Sender<MockSession>>,
+    next_sid: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Session {
+    sid: String,
+    state: Mutex<State>,
+    changed: Condvar,
+    received: Mutex<Sender<Packet>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    outgoing: VecDeque<Packet>,
+    websocket: bool,
+    closed: bool,
+}
+
+impl Server {
+    /// Answers the http request on `stream` or serves the websocket it asks
+    /// for. Every request is answered on its own connection.
+    fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+            }
+        }
+
+        let mut request = request_line.split_whitespace();
+        let method = request.next().unwrap_or_default().to_owned();
+        let url = Url::parse("http://localhost")?.join(request.next().unwrap_or("/"))?;
+        let sid = url
+            .query_pairs()
+            .find(|(key, _)| key == "sid")
+            .map(|(_, sid)| sid.into_owned());
+
+        let is_websocket = headers
+            .get("upgrade")
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+        if is_websocket {
+            let key = headers
+                .get("sec-websocket-key")
+                .cloned()
+                .unwrap_or_default();
+            return self.serve_websocket(stream, &key, sid);
+        }
+
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = Vec::new();
+        reader.take(length).read_to_end(&mut body)?;
+
+        let response = match self.serve_polling(&method, sid, Bytes::from(body)) {
+            Some(body) => [
+                format!(
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-type: text/plain; charset=UTF-8\r\ncontent-length: {}\r\n\r\n",
+                    body.len()
+                )
+                .as_bytes(),
+                &body,
+            ]
+            .concat(),
+            None => b"HTTP/1.1 400 Bad Request\r\nconnection: close\r\ncontent-length: 0\r\n\r\n"
+                .to_vec(),
+        };
+        let mut stream = stream;
+        stream.write_all(&response)?;
+        Ok(())
+    }
+
+    /// Returns the body of the answer to a polling request, `None` for
+    /// requests that are rejected.
+    fn serve_polling(&self, method: &str, sid: Option<String>, body: Bytes) -> Option<Bytes> {
+        let session = match sid {
+            None if method == "GET" => return Some(self.open(false).1),
+            None => return None,
+            Some(sid) => self.sessions.lock().unwrap().get(&sid).cloned()?,
+        };
+
+        if method == "POST" {
+            for packet in Payload::try_from(body).ok()? {
+                session.handle(packet);
+            }
+            return Some(Bytes::from_static(b"ok"));
+        }
+        session.poll(self.config.ping_interval, self.config.pings)
+    }
+
+    /// Serves a websocket, which either opens a new session or upgrades the
+    /// session `sid`.
+    fn serve_websocket(&self, mut stream: TcpStream, key: &str, sid: Option<String>) -> Result<()> {
+        stream.write_all(
+            format!(
+                "HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: websocket\r\nsec-websocket-accept: {}\r\n\r\n",
+                derive_accept_key(key.as_bytes())
+            )
+            .as_bytes(),
+        )?;
+        stream.set_read_timeout(Some(READ_INTERVAL))?;
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+        let session = match sid {
+            None => {
+                let (session, handshake) = self.open(true);
+                socket.write_message(Message::text(String::from_utf8_lossy(&handshake)))?;
+                session
+            }
+            Some(sid) => match self.sessions.lock().unwrap().get(&sid).cloned() {
+                Some(session) => session,
+                None => return Ok(()),
+            },
+        };
+
+        let mut next_ping = Instant::now() + self.config.ping_interval;
+        loop {
+            let (packets, websocket, closed) = {
+                let mut state = session.state.lock().unwrap();
+                let packets = if state.websocket {
+                    state.outgoing.drain(..).collect()
+                } else {
+                    Vec::new()
+                };
+                (packets, state.websocket, state.closed)
+            };
+            for packet in packets {
+                socket.write_message(to_message(packet))?;
+            }
+            if closed {
+                let _ = socket.close(None);
+                let _ = socket.write_pending();
+                return Ok(());
+            }
+            if websocket && self.config.pings && Instant::now() >= next_ping {
+                socket.write_message(to_message(Packet::new(PacketId::Ping, Bytes::new())))?;
+                next_ping += self.config.ping_interval;
+            }
+
+            match socket.read_message() {
+                Ok(Message::Text(text)) => {
+                    let packet = Packet::try_from(Bytes::from(text))?;
+                    match packet.packet_id {
+                        PacketId::Ping if packet.data == "probe" => {
+                            socket.write_message(Message::text("3probe"))?;
+                            // lets the pending poll return, so the client switches
+                            session.push(Packet::new(PacketId::Noop, Bytes::new()));
+                        }
+                        PacketId::Upgrade => {
+                            session.state.lock().unwrap().websocket = true;
+                            session.changed.notify_all();
+                        }
+                        _ => session.handle(packet),
+                    }
+                }
+                Ok(Message::Binary(data)) => {
+                    session.handle(Packet::new(PacketId::MessageBinary, data));
+                }
+                Ok(Message::Close(_)) => {
+                    session.state.lock().unwrap().closed = true;
+                    session.changed.notify_all();
+                    return Ok(());
+                }
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(err))
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Opens a new session and returns it with its encoded handshake.
+    fn open(&self, websocket: bool) -> (Arc<Session>, Bytes) {
+        let sid = format!("mock{}", self.next_sid.fetch_add(1, Ordering::SeqCst));
+        let (received, receiver) = mpsc::channel();
+        let session = Arc::new(Session {
+            sid: sid.clone(),
+            state: Mutex::new(State {
+                websocket,
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+            received: Mutex::new(received),
+        });
+
+        let upgrades = if self.config.upgrades && !websocket {
+            vec!["websocket".to_owned()]
+        } else {
+            Vec::new()
+        };
+        let handshake = HandshakePacket {
+            sid: sid.clone(),
+            upgrades,
+            ping_interval: self.config.ping_interval.as_millis() as u64,
+            ping_timeout: self.config.ping_timeout.as_millis() as u64,
+            max_payload: self.config.max_payload,
+        };
+        // SAFETY: the handshake always serializes
+        let handshake = serde_json::to_string(&handshake).unwrap();
+
+        self.sessions.lock().unwrap().insert(sid, session.clone());
+        let _ = self.new_sessions.lock().unwrap().send(MockSession {
+            session: session.clone(),
+            received: receiver,
+        });
+        (session, Bytes::from(Packet::new(PacketId::Open, handshake)))
+    }
+}
+
+impl Session {
+    fn push(&self, packet: Packet) {
+        self.state.lock().unwrap().outgoing.push_back(packet);
+        self.changed.notify_all();
+    }
+
+    /// Handles a packet received from the client.
+    fn handle(&self, packet: Packet) {
+        match packet.packet_id {
+            PacketId::Message | PacketId::MessageBinary => {
+                let _ = self.received.lock().unwrap().send(packet);
+            }
+            PacketId::Close => {
+                self.state.lock().unwrap().closed = true;
+                self.changed.notify_all();
+            }
+            _ => (),
+        }
+    }
+
+    /// Waits for packets to answer a poll with. Sends a ping if there were
+    /// none for `ping_interval`, or a noop if it `pings` not, and a noop once
+    /// the session upgraded.
+    fn poll(&self, ping_interval: Duration, pings: bool) -> Option<Bytes> {
+        let deadline = Instant::now() + ping_interval;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.websocket {
+                return Some(Bytes::from(Packet::new(PacketId::Noop, Bytes::new())));
+            }
+            if !state.outgoing.is_empty() {
+                return Bytes::try_from(Payload::take(&mut state.outgoing, usize::MAX)).ok();
+            }
+            if state.closed {
+                return None;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                let packet_id = if pings {
+                    PacketId::Ping
+                } else {
+                    PacketId::Noop
+                };
+                return Some(Bytes::from(Packet::new(packet_id, Bytes::new())));
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// Converts `packet` into a websocket message, binary data is sent as is.
+fn to_message(packet: Packet) -> Message {
+    match packet.packet_id {
+        PacketId::MessageBinary => Message::binary(packet.data.to_vec()),
+        _ => Message::text(String::from_utf8_lossy(&Bytes::from(packet)).into_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ClientBuilder;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_polling() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url()).build_polling()?;
+        client.connect()?;
+        let session = server.accept(TIMEOUT).unwrap();
+        assert!(!session.is_websocket());
+
+        client.emit(Packet::new(PacketId::Message, "hello"))?;
+        client.emit(Packet::new(PacketId::MessageBinary, vec![1, 2, 3]))?;
+        assert_eq!(
+            session.recv(TIMEOUT),
+            Some(Packet::new(PacketId::Message, "hello"))
+        );
+        assert_eq!(
+            session.recv(TIMEOUT),
+            Some(Packet::new(PacketId::MessageBinary, vec![1, 2, 3]))
+        );
+
+        session.send(Packet::new(PacketId::Message, "world"));
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Message, "world"))
+        );
+
+        session.close();
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Close, Bytes::new()))
+        );
+        assert!(session.is_closed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_websocket() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url()).build_websocket()?;
+        client.connect()?;
+        let session = server.accept(TIMEOUT).unwrap();
+        assert!(session.is_websocket());
+
+        client.emit(Packet::new(PacketId::Message, "hello"))?;
+        assert_eq!(
+            session.recv(TIMEOUT),
+            Some(Packet::new(PacketId::Message, "hello"))
+        );
+        session.send(Packet::new(PacketId::Message, "world"));
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Message, "world"))
+        );
+
+        client.disconnect()?;
+        let start = Instant::now();
+        while !session.is_closed() && start.elapsed() < TIMEOUT {
+            thread::sleep(READ_INTERVAL);
+        }
+        assert!(session.is_closed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url()).build_websocket_with_upgrade()?;
+        client.connect()?;
+        let session = server.accept(TIMEOUT).unwrap();
+
+        // the pending poll returns once the websocket is probed
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Noop, Bytes::new()))
+        );
+        let start = Instant::now();
+        while !session.is_websocket() && start.elapsed() < TIMEOUT {
+            thread::sleep(READ_INTERVAL);
+        }
+        assert!(session.is_websocket());
+
+        session.send(Packet::new(PacketId::Message, "upgraded"));
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Message, "upgraded"))
+        );
+        client.emit(Packet::new(PacketId::Message, "hello"))?;
+        assert_eq!(
+            session.recv(TIMEOUT),
+            Some(Packet::new(PacketId::Message, "hello"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ping() -> Result<()> {
+        let server = MockServer::builder()
+            .ping_interval(Duration::from_millis(50))
+            .upgrades(false)
+            .build()?;
+        let client = ClientBuilder::new(server.url()).build()?;
+        client.connect()?;
+        let session = server.accept(TIMEOUT).unwrap();
+        assert!(!session.is_websocket());
+
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Ping, Bytes::new()))
+        );
+        Ok(())
+    }
+}
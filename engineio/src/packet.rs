@@ -3,13 +3,14 @@ use base64::{engine::general_purpose, Engine as _};
 use bytes::{BufMut, Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 use std::char;
+use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::ops::Index;
 
 use crate::error::{Error, Result};
 /// Enumeration of the `engine.io` `Packet` types.
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum PacketId {
     Open,
     Close,
@@ -79,6 +80,14 @@ pub struct HandshakePacket {
     pub ping_interval: u64,
     #[serde(rename = "pingTimeout")]
     pub ping_timeout: u64,
+    /// The maximum length of a payload the server accepts, sent by engine.io
+    /// v4 servers.
+    #[serde(
+        rename = "maxPayload",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_payload: Option<usize>,
 }
 
 impl TryFrom<Packet> for HandshakePacket {
@@ -96,6 +105,14 @@ impl Packet {
             data: data.into(),
         }
     }
+
+    /// The length of the packet once encoded, binary data is `base64` encoded.
+    pub(crate) fn encoded_len(&self) -> usize {
+        match self.packet_id {
+            PacketId::MessageBinary => 1 + self.data.len().div_ceil(3) * 4,
+            _ => 1 + self.data.len(),
+        }
+    }
 }
 
 impl TryFrom<Bytes> for Packet {
@@ -155,6 +172,38 @@ impl Payload {
     // see https://en.wikipedia.org/wiki/Delimiter#ASCII_delimited_text
     const SEPARATOR: char = '\x1e';
 
+    /// The maximum length of a payload accepted by engine.io servers with
+    /// their default `maxHttpBufferSize`.
+    pub(crate) const DEFAULT_MAX_LEN: usize = 1_000_000;
+
+    /// Takes the leading `packets` that fit into a payload of `max_len` bytes
+    /// once encoded. Takes at least one packet, even if it is larger.
+    pub(crate) fn take(packets: &mut VecDeque<Packet>, max_len: usize) -> Self {
+        let mut len = 0;
+        let mut count = 0;
+        for packet in packets.iter() {
+            // packets after the first one are preceded by a separator
+            len += packet.encoded_len() + usize::from(count > 0);
+            if count > 0 && len > max_len {
+                break;
+            }
+            count += 1;
+        }
+        Payload(packets.drain(..count).collect())
+    }
+
+    /// Puts the packets back in front of `packets`, undoing [`Payload::take`].
+    pub(crate) fn put_back(self, packets: &mut VecDeque<Packet>) {
+        for packet in self.0.into_iter().rev() {
+            packets.push_front(packet);
+        }
+    }
+
+    /// Returns an iterator over the packets.
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Packet> {
+        self.0.iter()
+    }
+
     #[cfg(test)]
     pub fn len(&self) -> usize {
         self.0.len()
@@ -296,6 +345,29 @@ mod tests {
         assert_eq!(Bytes::try_from(packets).unwrap(), data);
     }
 
+    #[test]
+    fn test_take_payload() -> Result<()> {
+        let mut packets = VecDeque::from(vec![
+            Packet::new(PacketId::Message, "hello"),
+            Packet::new(PacketId::MessageBinary, vec![1, 2]),
+            Packet::new(PacketId::Message, "world"),
+        ]);
+
+        // "4hello", a separator and "bAQI="
+        let payload = Payload::take(&mut packets, 12);
+        assert_eq!(Bytes::try_from(payload)?, "4hello\x1ebAQI=");
+        assert_eq!(packets.len(), 1);
+
+        // a packet larger than the payload is taken on its own
+        let payload = Payload::take(&mut packets, 2);
+        assert_eq!(Bytes::try_from(payload.clone())?, "4world");
+        assert!(packets.is_empty());
+
+        payload.put_back(&mut packets);
+        assert_eq!(packets.len(), 1);
+        Ok(())
+    }
+
     #[test]
     fn test_packet_id_conversion_and_incompl_packet() {
         let sut = Packet::try_from(Bytes::from_static(b"4"));
@@ -346,6 +418,7 @@ mod tests {
             ping_timeout: 1000,
             sid: "Test".to_owned(),
             upgrades: vec!["websocket".to_owned(), "test".to_owned()],
+            max_payload: Some(1000000),
         };
         let encoded: String = serde_json::to_string(&packet).unwrap();
 
@@ -354,5 +427,12 @@ mod tests {
             HandshakePacket::try_from(Packet::new(PacketId::Message, Bytes::from(encoded)))
                 .unwrap()
         );
+
+        // older servers do not send the maximum payload
+        let handshake = r#"{"sid":"Test","upgrades":[],"pingInterval":10000,"pingTimeout":1000}"#;
+        let packet =
+            HandshakePacket::try_from(Packet::new(PacketId::Message, Bytes::from(handshake)))
+                .unwrap();
+        assert_eq!(packet.max_payload, None);
     }
 }
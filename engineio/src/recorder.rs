@@ -0,0 +1,358 @@
+//! Recording of the packets a client sends and receives, which can be replayed
+//! into a client later on.
+//!
+//! A [`Recorder`] writes one JSON object per line for each packet crossing the
+//! socket, e.g.
+//!
+//! ```text
+//! {"time":1700000000000,"direction":"in","transport":"polling","packet":"0{\"sid\":\"abc\",\"upgrades\":[],\"pingInterval\":25000,\"pingTimeout\":20000}"}
+//! {"time":1700000000002,"direction":"out","transport":"polling","packet":"3"}
+//! {"time":1700000000010,"direction":"in","transport":"websocket","packet":"4hello"}
+//! {"time":1700000000011,"direction":"out","transport":"websocket","packet":"bAQID"}
+//! ```
+//!
+//! - `time`: milliseconds since the unix epoch.
+//! - `direction`: `in` for packets received from the server, `out` for packets
+//!   sent to it.
+//! - `transport`: `polling`, `websocket` or `replay`.
+//! - `packet`: the packet encoded as in a polling payload, binary data is
+//!   `base64` encoded and prefixed with `b`.
+//!
+//! The handshake is recorded as the first inbound `open` packet. A recording
+//! is replayed with [`ClientBuilder::build_replay`](crate::ClientBuilder::build_replay).
+//! Failures to write a recording are logged and don't affect the connection.
+use crate::error::{Error, Result};
+use crate::packet::{HandshakePacket, Packet, PacketId, Payload};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether a packet was received from or sent to the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// A recorded packet, a single line of a recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Milliseconds since the unix epoch.
+    pub time: u64,
+    pub direction: Direction,
+    pub transport: String,
+    /// The encoded packet.
+    pub packet: String,
+}
+
+impl Frame {
+    /// Creates a frame of `packet` at the current time.
+    pub fn new(direction: Direction, transport: &str, packet: Packet) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        Frame {
+            time,
+            direction,
+            transport: transport.to_owned(),
+            packet: String::from_utf8_lossy(&Bytes::from(packet)).into_owned(),
+        }
+    }
+
+    /// Decodes the recorded packet.
+    pub fn packet(&self) -> Result<Packet> {
+        Packet::try_from(Bytes::from(self.packet.clone()))
+    }
+
+    /// Reads the frames of a recording.
+    pub fn read_all<R: BufRead>(reader: R) -> Result<Vec<Frame>> {
+        let mut frames = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                frames.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(frames)
+    }
+}
+
+/// Writes the packets of a socket to a recording. Cloned recorders write to
+/// the same recording.
+#[derive(Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Recorder {
+    /// Creates a recorder writing to `writer`. Each frame is flushed once
+    /// written.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        Recorder {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Creates a recorder writing to the file at `path`, which is truncated.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Writes `frame` as a single line.
+    pub fn record(&self, frame: &Frame) -> Result<()> {
+        let line = serde_json::to_string(frame)?;
+        let mut writer = self.writer.lock()?;
+        writeln!(writer, "{}", line)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Records `packet` at the current time. A failure to record is logged,
+    /// the connection does not depend on its recording.
+    pub(crate) fn record_packet(&self, direction: Direction, transport: &str, packet: &Packet) {
+        if let Err(error) = self.record(&Frame::new(direction, transport, packet.clone())) {
+            event!(warn, transport = transport; "could not record a packet: {}", error);
+        }
+    }
+
+    /// Records the packets of `payload` at the current time.
+    pub(crate) fn record_payload(&self, direction: Direction, transport: &str, payload: &Payload) {
+        for packet in payload.iter() {
+            self.record_packet(direction, transport, packet);
+        }
+    }
+}
+
+impl Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Recorder")
+    }
+}
+
+/// Splits a recording into its handshake and the packets received after it.
+/// A `close` packet is appended if the recording doesn't end with one, so a
+/// replayed client disconnects at its end.
+pub(crate) fn replay_frames(frames: Vec<Frame>) -> Result<(HandshakePacket, VecDeque<Packet>)> {
+    let mut inbound = frames
+        .into_iter()
+        .filter(|frame| frame.direction == Direction::In)
+        .map(|frame| frame.packet());
+
+    let open = inbound
+        .next()
+        .transpose()?
+        .filter(|packet| packet.packet_id == PacketId::Open)
+        .ok_or_else(|| Error::InvalidHandshake("recording without open packet".to_owned()))?;
+    let handshake = HandshakePacket::try_from(open)?;
+
+    let mut packets = inbound.collect::<Result<VecDeque<_>>>()?;
+    if packets.back().map(|packet| packet.packet_id) != Some(PacketId::Close) {
+        packets.push_back(Packet::new(PacketId::Close, Bytes::new()));
+    }
+    Ok((handshake, packets))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockServer;
+    use crate::ClientBuilder;
+    use std::io::Cursor;
+    use std::time::Duration;
+    use url::Url;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A writer whose content can be read while a recorder owns it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_record_and_read() -> Result<()> {
+        let output = Shared::default();
+        let recorder = Recorder::new(output.clone());
+
+        let binary = Packet::new(PacketId::MessageBinary, vec![1, 2, 3]);
+        recorder.record(&Frame::new(
+            Direction::In,
+            "polling",
+            Packet::new(PacketId::Message, "hello"),
+        ))?;
+        recorder.record(&Frame::new(Direction::Out, "websocket", binary.clone()))?;
+
+        let recording = output.0.lock().unwrap().clone();
+        let frames = Frame::read_all(Cursor::new(recording))?;
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].direction, Direction::In);
+        assert_eq!(frames[0].transport, "polling");
+        assert_eq!(frames[0].packet, "4hello");
+        assert_eq!(frames[1].direction, Direction::Out);
+        assert_eq!(frames[1].packet, "bAQID");
+        assert_eq!(frames[1].packet()?, binary);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_frames() -> Result<()> {
+        let recording = concat!(
+            r#"{"time":1,"direction":"in","transport":"polling","packet":"0{\"sid\":\"abc\",\"upgrades\":[],\"pingInterval\":25000,\"pingTimeout\":20000}"}"#,
+            "\n",
+            r#"{"time":2,"direction":"out","transport":"polling","packet":"3"}"#,
+            "\n",
+            r#"{"time":3,"direction":"in","transport":"polling","packet":"4hello"}"#,
+            "\n",
+        );
+        let frames = Frame::read_all(Cursor::new(recording))?;
+
+        let (handshake, packets) = replay_frames(frames.clone())?;
+        assert_eq!(handshake.sid, "abc");
+        assert_eq!(
+            packets,
+            vec![
+                Packet::new(PacketId::Message, "hello"),
+                Packet::new(PacketId::Close, Bytes::new()),
+            ]
+        );
+
+        assert!(matches!(
+            replay_frames(frames[1..].to_vec()),
+            Err(Error::InvalidHandshake(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_and_replay_session() -> Result<()> {
+        let output = Shared::default();
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .recorder(Recorder::new(output.clone()))
+            .build_polling()?;
+        client.connect()?;
+        let session = server.accept(TIMEOUT).unwrap();
+
+        client.emit(Packet::new(PacketId::Message, "hello"))?;
+        assert_eq!(
+            session.recv(TIMEOUT),
+            Some(Packet::new(PacketId::Message, "hello"))
+        );
+        session.send(Packet::new(PacketId::Message, "world"));
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Message, "world"))
+        );
+        session.close();
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Close, Bytes::new()))
+        );
+
+        let recording = output.0.lock().unwrap().clone();
+        let frames = Frame::read_all(Cursor::new(recording))?;
+        let packets = frames
+            .iter()
+            .map(|frame| {
+                (
+                    frame.direction,
+                    frame.transport.as_str(),
+                    frame.packet.as_str(),
+                )
+            })
+            .filter(|(_, _, packet)| !packet.starts_with('0'))
+            .collect::<Vec<_>>();
+        assert_eq!(frames[0].direction, Direction::In);
+        assert_eq!(frames[0].packet()?.packet_id, PacketId::Open);
+        assert_eq!(
+            packets,
+            vec![
+                (Direction::Out, "polling", "3"),
+                (Direction::Out, "polling", "4hello"),
+                (Direction::In, "polling", "4world"),
+                (Direction::In, "polling", "1"),
+            ]
+        );
+
+        // the replayed client receives the same packets without a server
+        drop(server);
+        let client = ClientBuilder::new(Url::parse("http://localhost:1")?).build_replay(frames)?;
+        client.connect()?;
+        client.emit(Packet::new(PacketId::Message, "hello"))?;
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Message, "world"))
+        );
+        assert_eq!(
+            client.poll()?,
+            Some(Packet::new(PacketId::Close, Bytes::new()))
+        );
+        assert_eq!(client.poll()?, None);
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_replay() -> Result<()> {
+        use futures_util::StreamExt;
+
+        let recording = concat!(
+            r#"{"time":1,"direction":"in","transport":"websocket","packet":"0{\"sid\":\"abc\",\"upgrades\":[],\"pingInterval\":25000,\"pingTimeout\":20000}"}"#,
+            "\n",
+            r#"{"time":2,"direction":"in","transport":"websocket","packet":"4hello"}"#,
+            "\n",
+            r#"{"time":3,"direction":"in","transport":"websocket","packet":"bAQID"}"#,
+            "\n",
+        );
+        let output = Shared::default();
+        let mut client = crate::asynchronous::ClientBuilder::new(Url::parse("http://localhost:1")?)
+            .recorder(Recorder::new(output.clone()))
+            .build_replay(Frame::read_all(Cursor::new(recording))?)?;
+        client.connect().await?;
+
+        assert_eq!(
+            client.next().await.unwrap()?,
+            Packet::new(PacketId::Message, "hello")
+        );
+        assert_eq!(
+            client.next().await.unwrap()?,
+            Packet::new(PacketId::MessageBinary, vec![1, 2, 3])
+        );
+        assert_eq!(
+            client.next().await.unwrap()?,
+            Packet::new(PacketId::Close, Bytes::new())
+        );
+        assert!(client.next().await.is_none());
+        assert!(!client.is_connected());
+
+        // the replay is recorded as well
+        let recording = output.0.lock().unwrap().clone();
+        let frames = Frame::read_all(Cursor::new(recording))?;
+        assert!(frames.iter().all(|frame| frame.transport == "replay"));
+        assert_eq!(
+            frames
+                .iter()
+                .filter(|frame| frame.direction == Direction::In)
+                .count(),
+            4
+        );
+        Ok(())
+    }
+}
@@ -1,57 +1,253 @@
 use crate::callback::OptionalCallback;
+use crate::disconnect::DisconnectReason;
 use crate::transport::TransportType;
 
 use crate::error::{Error, Result};
+use crate::metrics::{Metrics, MetricsSnapshot};
 use crate::packet::{HandshakePacket, Packet, PacketId, Payload};
+use crate::recorder::{Direction, Recorder};
+use crate::trace::Span;
 use bytes::Bytes;
+use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::sync::RwLock;
 use std::{fmt::Debug, sync::atomic::Ordering};
 use std::{
-    sync::{atomic::AtomicBool, Arc, Mutex},
-    time::Instant,
+    sync::{
+        atomic::AtomicBool,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
 };
+use url::Url;
+
+/// Opens and probes the transport a socket upgrades to once connected.
+pub(crate) type Upgrade = Arc<dyn Fn() -> Result<TransportType> + Send + Sync>;
+
+/// The transports of a socket, which change when it upgrades, and the packets
+/// waiting to be written.
+#[derive(Debug)]
+struct Transports {
+    current: Arc<TransportType>,
+    /// A probed transport waiting for the polls and writes in flight to return.
+    pending: Option<TransportType>,
+    /// The packets waiting to be written.
+    buffer: VecDeque<Packet>,
+    /// Whether a write is in flight, which writes the buffer afterwards.
+    writing: bool,
+    /// The number of polls in flight on the current transport.
+    polls: usize,
+    /// The maximum length of the payloads written by the polling transport.
+    max_payload: usize,
+    recorder: Option<Recorder>,
+    metrics: Arc<Metrics>,
+}
+
+impl Transports {
+    /// Whether neither polls nor writes are in flight.
+    fn is_idle(&self) -> bool {
+        self.polls == 0 && !self.writing
+    }
+
+    /// Sends the upgrade packet over `transport`, which becomes the current
+    /// transport if that succeeds, and writes the buffered packets.
+    fn switch(&mut self, transport: TransportType) -> Result<()> {
+        let packet = Packet::new(PacketId::Upgrade, Bytes::new());
+        let upgraded = transport
+            .as_transport()
+            .emit(packet.clone().into(), false)
+            .map(|()| {
+                if let Some(recorder) = &self.recorder {
+                    recorder.record_packet(Direction::Out, transport.name(), &packet);
+                }
+                event!(info, transport = transport.name(); "upgraded transport");
+                self.metrics.upgraded(transport.name());
+                self.current = Arc::new(transport)
+            });
+
+        // the buffer is flushed best-effort, a failed write keeps its packets
+        // in the buffer for the next one
+        while !self.buffer.is_empty() {
+            let payload = Payload::take(&mut self.buffer, self.max_payload);
+            let unsent = payload.clone();
+            let written = write(
+                &self.current,
+                payload,
+                self.recorder.as_ref(),
+                &self.metrics,
+            );
+            if let Err(error) = written {
+                event!(warn; "could not flush the buffer: {}", error);
+                unsent.put_back(&mut self.buffer);
+                break;
+            }
+        }
+        self.metrics.set_buffered(self.buffer.len());
+        upgraded
+    }
+}
+
+/// Writes the packets of `payload` over `transport`, the polling transport
+/// sends them in a single request. The packets are recorded by `recorder`,
+/// if any, and counted by `metrics`.
+fn write(
+    transport: &TransportType,
+    payload: Payload,
+    recorder: Option<&Recorder>,
+    metrics: &Metrics,
+) -> Result<()> {
+    for packet in payload.iter() {
+        event!(trace, packet_type = packet.packet_id, size = packet.data.len(), transport = transport.name(); "sending packet");
+        metrics.sent(packet);
+    }
+    if let Some(recorder) = recorder {
+        recorder.record_payload(Direction::Out, transport.name(), &payload);
+    }
+
+    if let TransportType::Polling(_) = transport {
+        return transport
+            .as_transport()
+            .emit(Bytes::try_from(payload)?, false);
+    }
+
+    for packet in payload {
+        let is_binary = packet.packet_id == PacketId::MessageBinary;
+
+        // if this is a binary attachment, then send the raw bytes
+        let data: Bytes = if is_binary {
+            packet.data
+        } else {
+            packet.into()
+        };
+        transport.as_transport().emit(data, is_binary)?;
+    }
+    Ok(())
+}
+
+/// Reads from `transport` within `timeout`.
+fn read_transport(transport: &TransportType, timeout: Duration) -> Result<Bytes> {
+    if timeout.is_zero() {
+        Err(Error::ReadTimeout(timeout))
+    } else {
+        transport.as_transport().poll_timeout(Some(timeout))
+    }
+}
+
+/// Reads the transport on a long-lived thread of its own, so a poll which
+/// stops waiting for data does not abandon the read in flight.
+struct Reader {
+    requests: Sender<(Arc<TransportType>, Duration)>,
+    data: Receiver<Result<Bytes>>,
+    /// The transport of the read in flight, if any.
+    reading: Option<Arc<TransportType>>,
+}
+
+impl Reader {
+    /// Spawns the reading thread, which stops once the reader is dropped.
+    fn spawn() -> Self {
+        let (requests, received) = mpsc::channel::<(Arc<TransportType>, Duration)>();
+        let (sender, data) = mpsc::channel();
+        thread::spawn(move || {
+            for (transport, timeout) in received {
+                if sender.send(read_transport(&transport, timeout)).is_err() {
+                    break;
+                }
+            }
+        });
+        Reader {
+            requests,
+            data,
+            reading: None,
+        }
+    }
+}
 
 /// An `engine.io` socket which manages a connection with the server and allows
 /// it to register common callbacks.
 #[derive(Clone)]
 pub struct Socket {
-    transport: Arc<TransportType>,
-    on_close: OptionalCallback<()>,
+    transports: Arc<Mutex<Transports>>,
+    upgrade: Option<Upgrade>,
+    on_close: OptionalCallback<DisconnectReason>,
     on_data: OptionalCallback<Bytes>,
     on_error: OptionalCallback<String>,
     on_open: OptionalCallback<()>,
     on_packet: OptionalCallback<Packet>,
+    on_upgrade: OptionalCallback<()>,
     connected: Arc<AtomicBool>,
+    close_reason: Arc<Mutex<Option<DisconnectReason>>>,
     last_ping: Arc<Mutex<Instant>>,
     last_pong: Arc<Mutex<Instant>>,
     connection_data: Arc<HandshakePacket>,
     /// Since we get packets in payloads it's possible to have a state where only some of the packets have been consumed.
     remaining_packets: Arc<RwLock<Option<crate::packet::IntoIter>>>,
+    /// Reads the transport once [`Socket::poll_timeout`] is used, a read
+    /// which did not complete in time is taken up by the next poll.
+    reader: Arc<Mutex<Option<Reader>>>,
+    read_timeout: Option<Duration>,
+    max_incoming_payload: Option<usize>,
+    recorder: Option<Recorder>,
+    metrics: Arc<Metrics>,
+    span: Span,
 }
 
 impl Socket {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         transport: TransportType,
         handshake: HandshakePacket,
-        on_close: OptionalCallback<()>,
+        url: &Url,
+        on_close: OptionalCallback<DisconnectReason>,
         on_data: OptionalCallback<Bytes>,
         on_error: OptionalCallback<String>,
         on_open: OptionalCallback<()>,
         on_packet: OptionalCallback<Packet>,
+        on_upgrade: OptionalCallback<()>,
+        read_timeout: Option<Duration>,
+        max_incoming_payload: Option<usize>,
+        recorder: Option<Recorder>,
+        upgrade: Option<Upgrade>,
     ) -> Self {
+        let span = span!(
+            INFO,
+            "engineio.connection",
+            sid = handshake.sid,
+            url = url.as_str(),
+            transport = transport.name()
+        );
+        let metrics = Arc::new(Metrics::new(transport.name()));
         Socket {
             on_close,
             on_data,
             on_error,
             on_open,
             on_packet,
-            transport: Arc::new(transport),
+            on_upgrade,
+            transports: Arc::new(Mutex::new(Transports {
+                current: Arc::new(transport),
+                pending: None,
+                buffer: VecDeque::new(),
+                writing: false,
+                polls: 0,
+                max_payload: handshake.max_payload.unwrap_or(Payload::DEFAULT_MAX_LEN),
+                recorder: recorder.clone(),
+                metrics: metrics.clone(),
+            })),
+            upgrade,
             connected: Arc::new(AtomicBool::default()),
+            close_reason: Arc::new(Mutex::new(None)),
             last_ping: Arc::new(Mutex::new(Instant::now())),
             last_pong: Arc::new(Mutex::new(Instant::now())),
             connection_data: Arc::new(handshake),
             remaining_packets: Arc::new(RwLock::new(None)),
+            reader: Arc::new(Mutex::new(None)),
+            read_timeout,
+            max_incoming_payload,
+            recorder,
+            metrics,
+            span,
         }
     }
 
@@ -60,6 +256,17 @@ impl Socket {
     pub fn connect(&self) -> Result<()> {
         // SAFETY: Has valid handshake due to type
         self.connected.store(true, Ordering::Release);
+        event!(debug, parent: &self.span; "connected");
+
+        if let Some(recorder) = &self.recorder {
+            let transport = self.transports.lock()?.current.clone();
+            let handshake = serde_json::to_string(&*self.connection_data)?;
+            recorder.record_packet(
+                Direction::In,
+                transport.name(),
+                &Packet::new(PacketId::Open, handshake),
+            );
+        }
 
         if let Some(on_open) = self.on_open.as_ref() {
             spawn_scoped!(on_open(()));
@@ -71,50 +278,146 @@ impl Socket {
         // emit a pong packet to keep trigger the ping cycle on the server
         self.emit(Packet::new(PacketId::Pong, Bytes::new()))?;
 
+        // probe the transport to upgrade to in the background
+        if let Some(upgrade) = self.upgrade.clone() {
+            let socket = self.clone();
+            thread::spawn(move || {
+                let span = span!(INFO, parent: &socket.span, "engineio.upgrade");
+                let _enter = span.enter();
+                match upgrade() {
+                    Ok(transport) => {
+                        event!(debug, transport = transport.name(); "probed transport");
+                        socket.upgrade_when_drained(transport)
+                    }
+                    Err(error) => socket.call_error_callback(error.to_string()),
+                }
+            });
+        }
+
         Ok(())
     }
 
-    pub fn disconnect(&self) -> Result<()> {
-        if let Some(on_close) = self.on_close.as_ref() {
-            spawn_scoped!(on_close(()));
+    /// Switches to the probed `transport` once no polls and writes are in
+    /// flight. Until then, emitted packets are buffered.
+    fn upgrade_when_drained(&self, transport: TransportType) {
+        let mut transports = match self.transports.lock() {
+            Ok(transports) => transports,
+            Err(error) => return self.call_error_callback(error.to_string()),
+        };
+        if !self.connected.load(Ordering::Acquire) {
+            return;
+        }
+
+        transports.pending = Some(transport);
+        self.switch_if_idle(transports);
+    }
+
+    /// Switches to the pending transport, if any, unless polls or writes are
+    /// in flight. The last of them to return switches then.
+    fn switch_if_idle(&self, mut transports: MutexGuard<'_, Transports>) {
+        if !transports.is_idle() {
+            return;
+        }
+
+        if let Some(transport) = transports.pending.take() {
+            let result = transports.switch(transport);
+            drop(transports);
+            self.handle_upgrade(result);
+        }
+    }
+
+    /// Calls the `on_upgrade` callback or, if the upgrade failed, the error
+    /// callback. A failed upgrade keeps the current transport.
+    fn handle_upgrade(&self, result: Result<()>) {
+        match result {
+            Ok(()) => {
+                if let Some(on_upgrade) = self.on_upgrade.as_ref() {
+                    spawn_scoped!(on_upgrade(()));
+                }
+            }
+            Err(error) => self.call_error_callback(error.to_string()),
         }
+    }
 
+    pub fn disconnect(&self) -> Result<()> {
         // will not succeed when connection to the server is interrupted
-        let _ = self.emit(Packet::new(PacketId::Close, Bytes::new()));
+        if let Err(error) = self.emit(Packet::new(PacketId::Close, Bytes::new())) {
+            event!(debug, parent: &self.span; "could not send the close packet: {}", error);
+        }
+        event!(debug, parent: &self.span; "disconnected");
 
-        self.connected.store(false, Ordering::Release);
+        self.close(DisconnectReason::ForcedClose);
 
         Ok(())
     }
 
     /// Sends a packet to the server.
     pub fn emit(&self, packet: Packet) -> Result<()> {
+        self.emit_batch(vec![packet])
+    }
+
+    /// Sends packets to the server in order, without other packets in between.
+    /// Packets emitted while a write is in flight are buffered and written
+    /// after it, the polling transport batches them into as few requests as
+    /// the maximum payload allows. The packets of a failed write stay
+    /// buffered for the next one.
+    pub fn emit_batch(&self, packets: Vec<Packet>) -> Result<()> {
         if !self.connected.load(Ordering::Acquire) {
             let error = Error::IllegalActionBeforeOpen();
             self.call_error_callback(format!("{}", error));
             return Err(error);
         }
 
-        let is_binary = packet.packet_id == PacketId::MessageBinary;
+        let _enter = self.span.enter();
+        let mut transports = self.transports.lock()?;
+        transports.buffer.extend(packets);
+        self.metrics.set_buffered(transports.buffer.len());
+        // the write in flight or the pending upgrade writes the buffer
+        if transports.writing || transports.pending.is_some() {
+            return Ok(());
+        }
 
-        // send a post request with the encoded payload as body
-        // if this is a binary attachment, then send the raw bytes
-        let data: Bytes = if is_binary {
-            packet.data
-        } else {
-            packet.into()
-        };
+        transports.writing = true;
+        let mut result = Ok(());
+        while result.is_ok() && !transports.buffer.is_empty() && transports.pending.is_none() {
+            let max_payload = transports.max_payload;
+            let payload = Payload::take(&mut transports.buffer, max_payload);
+            self.metrics.set_buffered(transports.buffer.len());
+            let transport = transports.current.clone();
+            drop(transports);
 
-        if let Err(error) = self.transport.as_transport().emit(data, is_binary) {
-            self.call_error_callback(error.to_string());
-            return Err(error);
+            let unsent = payload.clone();
+            result = write(&transport, payload, self.recorder.as_ref(), &self.metrics);
+            transports = self.transports.lock()?;
+            if result.is_err() {
+                unsent.put_back(&mut transports.buffer);
+                self.metrics.set_buffered(transports.buffer.len());
+            }
         }
+        transports.writing = false;
+        self.switch_if_idle(transports);
 
-        Ok(())
+        if let Err(error) = &result {
+            self.call_error_callback(error.to_string());
+        }
+        result
     }
 
     /// Polls for next payload
     pub(crate) fn poll(&self) -> Result<Option<Packet>> {
+        self.poll_until(None)
+    }
+
+    /// Polls for the next packet, waiting at most `timeout` for it. A read of
+    /// the transport which does not complete in time goes on in the
+    /// background, so no data is lost. Returns `None` if no packet arrived in
+    /// time or the socket closed.
+    pub(crate) fn poll_timeout(&self, timeout: Duration) -> Result<Option<Packet>> {
+        self.poll_until(Some(Instant::now() + timeout))
+    }
+
+    fn poll_until(&self, deadline: Option<Instant>) -> Result<Option<Packet>> {
+        let _enter = self.span.enter();
         loop {
             if self.connected.load(Ordering::Acquire) {
                 if self.remaining_packets.read()?.is_some() {
@@ -127,14 +430,20 @@ impl Socket {
                 }
 
                 // Iterator has run out of packets, get a new payload
-                // TODO: 0.3.X timeout?
-                let data = self.transport.as_transport().poll()?;
+                let (transport, data) = match self.read(deadline)? {
+                    Some(read) => read,
+                    None => return Ok(None),
+                };
+                self.check_incoming_payload(&data)?;
 
                 if data.is_empty() {
                     continue;
                 }
 
                 let payload = Payload::try_from(data)?;
+                if let Some(recorder) = &self.recorder {
+                    recorder.record_payload(Direction::In, transport.name(), &payload);
+                }
                 let mut iter = payload.into_iter();
 
                 if let Some(packet) = iter.next() {
@@ -147,9 +456,85 @@ impl Socket {
         }
     }
 
+    /// Reads the next payload from the transport. Without a `deadline` and
+    /// without a reader, this blocks on the calling thread. Otherwise the
+    /// reader reads and `None` is returned if the data did not arrive in time.
+    fn read(&self, deadline: Option<Instant>) -> Result<Option<(Arc<TransportType>, Bytes)>> {
+        let mut reader = self.reader.lock()?;
+        let (transport, data) = if reader.is_none() && deadline.is_none() {
+            drop(reader);
+            let (transport, timeout) = self.begin_read()?;
+            let data = read_transport(&transport, timeout);
+            (transport, data)
+        } else {
+            let reader = reader.get_or_insert_with(Reader::spawn);
+            let transport = match reader.reading.clone() {
+                Some(transport) => transport,
+                None => {
+                    let (transport, timeout) = self.begin_read()?;
+                    // a failed request is noticed when receiving the data
+                    let _ = reader.requests.send((transport.clone(), timeout));
+                    reader.reading = Some(transport.clone());
+                    transport
+                }
+            };
+            let received = match deadline {
+                Some(deadline) => reader
+                    .data
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => reader.data.recv().map_err(RecvTimeoutError::from),
+            };
+            let data = match received {
+                Ok(data) => data,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                // the reading thread panicked
+                Err(RecvTimeoutError::Disconnected) => Err(Error::IncompletePacket()),
+            };
+            reader.reading = None;
+            (transport, data)
+        };
+
+        let mut transports = self.transports.lock()?;
+        transports.polls -= 1;
+        self.switch_if_idle(transports);
+        let ping_timeout = self.ping_timeout();
+        match data {
+            Err(Error::ReadTimeout(_)) if self.last_ping.lock()?.elapsed() >= ping_timeout => {
+                self.handle_close(DisconnectReason::PingTimeout);
+                Err(Error::PingTimeout(ping_timeout))
+            }
+            data => data.map(|data| Some((transport, data))),
+        }
+    }
+
+    /// Counts a poll in flight on the current transport, which is returned
+    /// along with the time to wait for data.
+    fn begin_read(&self) -> Result<(Arc<TransportType>, Duration)> {
+        let transport = {
+            let mut transports = self.transports.lock()?;
+            transports.polls += 1;
+            transports.current.clone()
+        };
+        // the server pings within its ping interval and timeout
+        let remaining = self
+            .ping_timeout()
+            .saturating_sub(self.last_ping.lock()?.elapsed());
+        let timeout = self.read_timeout.map_or(remaining, |t| t.min(remaining));
+        Ok((transport, timeout))
+    }
+
+    /// Fails if `data` exceeds the maximum size of incoming payloads.
+    fn check_incoming_payload(&self, data: &Bytes) -> Result<()> {
+        match self.max_incoming_payload {
+            Some(max) if data.len() > max => Err(Error::InvalidPayloadSize(data.len(), max)),
+            _ => Ok(()),
+        }
+    }
+
     /// Calls the error callback with a given message.
     #[inline]
     fn call_error_callback(&self, text: String) {
+        event!(warn, parent: &self.span; "{}", text);
         if let Some(function) = self.on_error.as_ref() {
             spawn_scoped!(function(text));
         }
@@ -165,7 +550,31 @@ impl Socket {
         Ok(())
     }
 
+    /// Records the pong latency once the pong for the last ping is written.
+    pub(crate) fn ponged(&self) -> Result<()> {
+        self.metrics.ponged(self.last_ping.lock()?.elapsed());
+        Ok(())
+    }
+
+    pub(crate) fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// The time after the last ping in which the server has to ping again.
+    fn ping_timeout(&self) -> Duration {
+        Duration::from_millis(
+            self.connection_data.ping_interval + self.connection_data.ping_timeout,
+        )
+    }
+
+    /// The reason the socket closed for, if it did.
+    pub(crate) fn close_reason(&self) -> Option<DisconnectReason> {
+        self.close_reason.lock().ok().and_then(|reason| *reason)
+    }
+
     pub(crate) fn handle_packet(&self, packet: Packet) {
+        event!(trace, parent: &self.span, packet_type = packet.packet_id, size = packet.data.len(); "received packet");
+        self.metrics.received(&packet);
         if let Some(on_packet) = self.on_packet.as_ref() {
             spawn_scoped!(on_packet(packet));
         }
@@ -177,12 +586,22 @@ impl Socket {
         }
     }
 
-    pub(crate) fn handle_close(&self) {
+    pub(crate) fn handle_close(&self, reason: DisconnectReason) {
+        event!(debug, parent: &self.span; "closed: {}", reason);
+        self.close(reason);
+    }
+
+    /// Marks the socket as closed and calls the close callback, once.
+    fn close(&self, reason: DisconnectReason) {
+        if !self.connected.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        if let Ok(mut close_reason) = self.close_reason.lock() {
+            *close_reason = Some(reason);
+        }
         if let Some(on_close) = self.on_close.as_ref() {
-            spawn_scoped!(on_close(()));
+            spawn_scoped!(on_close(reason));
         }
-
-        self.connected.store(false, Ordering::Release);
     }
 }
 
@@ -190,13 +609,14 @@ impl Socket {
 impl Debug for Socket {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_fmt(format_args!(
-            "EngineSocket(transport: {:?}, on_error: {:?}, on_open: {:?}, on_close: {:?}, on_packet: {:?}, on_data: {:?}, connected: {:?}, last_ping: {:?}, last_pong: {:?}, connection_data: {:?})",
-            self.transport,
+            "EngineSocket(transports: {:?}, on_error: {:?}, on_open: {:?}, on_close: {:?}, on_packet: {:?}, on_data: {:?}, on_upgrade: {:?}, connected: {:?}, last_ping: {:?}, last_pong: {:?}, connection_data: {:?})",
+            self.transports,
             self.on_error,
             self.on_open,
             self.on_close,
             self.on_packet,
             self.on_data,
+            self.on_upgrade,
             self.connected,
             self.last_ping,
             self.last_pong,
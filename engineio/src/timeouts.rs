@@ -0,0 +1,124 @@
+use crate::error::Error;
+#[cfg(feature = "async")]
+use reqwest::Error as ReqwestError;
+use std::error::Error as _;
+use std::io;
+use std::time::Duration;
+
+/// The timeouts applied by a client and its transports. A value of `None`
+/// means the corresponding operation may wait indefinitely (or, for the
+/// polling transport, up to the default timeout of the underlying http
+/// client).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Maximum time for establishing the TCP (and TLS) connection.
+    pub connect: Option<Duration>,
+    /// Maximum time to wait for the server to answer the handshake.
+    pub handshake: Option<Duration>,
+    /// Maximum time for a single outgoing http request of the polling transport.
+    pub request: Option<Duration>,
+    /// Maximum time to wait for data from the server on an established
+    /// connection. Should be larger than the sum of the server's `pingInterval`
+    /// and `pingTimeout` as the server is allowed to stay silent that long.
+    pub read: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Converts a `reqwest` error into a crate error, mapping timeouts onto
+    /// the connect timeout or the variant `on_timeout` of the given duration.
+    #[cfg(feature = "async")]
+    pub(crate) fn reqwest_error(
+        &self,
+        err: ReqwestError,
+        timeout: Option<Duration>,
+        on_timeout: fn(Duration) -> Error,
+    ) -> Error {
+        if err.is_timeout() {
+            if let (true, Some(connect)) = (err.is_connect(), self.connect) {
+                return Error::ConnectTimeout(connect);
+            }
+            if let Some(timeout) = timeout {
+                return on_timeout(timeout);
+            }
+        }
+        err.into()
+    }
+
+    /// Converts a `ureq` error into a crate error like
+    /// [`Timeouts::reqwest_error`]. Error statuses become
+    /// [`Error::IncompleteHttp`].
+    pub(crate) fn ureq_error(
+        &self,
+        err: ureq::Error,
+        timeout: Option<Duration>,
+        on_timeout: fn(Duration) -> Error,
+    ) -> Error {
+        let err = match err {
+            ureq::Error::Status(status, _) => return Error::IncompleteHttp(status),
+            ureq::Error::Transport(err) => err,
+        };
+        let timed_out = err
+            .source()
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .is_some_and(|source| source.kind() == io::ErrorKind::TimedOut);
+        if timed_out {
+            if let (ureq::ErrorKind::ConnectionFailed, Some(connect)) = (err.kind(), self.connect) {
+                return Error::ConnectTimeout(connect);
+            }
+            if let Some(timeout) = timeout {
+                return on_timeout(timeout);
+            }
+        }
+        Error::IncompleteResponseFromUreq(Box::new(err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ureq_timeout_error_mapping() {
+        // a server that accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let timeouts = Timeouts {
+            request: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let err = ureq::get(&url)
+            .timeout(Duration::from_millis(50))
+            .call()
+            .unwrap_err();
+
+        assert!(matches!(
+            timeouts.ureq_error(err, timeouts.request, Error::RequestTimeout),
+            Error::RequestTimeout(duration) if duration == Duration::from_millis(50)
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_timeout_error_mapping() {
+        // a server that accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let timeouts = Timeouts {
+            request: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let err = reqwest::Client::new()
+            .get(url)
+            .timeout(Duration::from_millis(50))
+            .send()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            timeouts.reqwest_error(err, timeouts.request, Error::RequestTimeout),
+            Error::RequestTimeout(duration) if duration == Duration::from_millis(50)
+        ));
+    }
+}
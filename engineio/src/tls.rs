@@ -0,0 +1,369 @@
+//! Backend independent TLS configuration. The backend is chosen at compile
+//! time through the `native-tls` and `rustls` features of this crate.
+use crate::error::{Error, Result};
+use std::net::TcpStream;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::sync::Arc;
+use tungstenite::stream::MaybeTlsStream;
+
+/// A TLS configuration for the polling and websocket transports, wrapping the
+/// configuration type of one of the supported backends. Usually created via
+/// [`TlsConfig::builder`] or converted from a backend specific configuration.
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum TlsConfig {
+    /// A configuration for the platform's native TLS implementation.
+    #[cfg(feature = "native-tls")]
+    NativeTls(native_tls::TlsConnector),
+    /// A configuration for the pure rust `rustls` implementation.
+    #[cfg(feature = "rustls")]
+    Rustls(Arc<rustls::ClientConfig>),
+}
+
+impl TlsConfig {
+    /// Returns a builder for a backend independent configuration.
+    pub fn builder() -> TlsConfigBuilder {
+        TlsConfigBuilder::default()
+    }
+
+    /// Converts this configuration into the connector used by `tokio-tungstenite`.
+    #[cfg(feature = "async")]
+    pub(crate) fn into_connector(self) -> tokio_tungstenite::Connector {
+        match self {
+            #[cfg(feature = "native-tls")]
+            TlsConfig::NativeTls(connector) => tokio_tungstenite::Connector::NativeTls(connector),
+            #[cfg(feature = "rustls")]
+            TlsConfig::Rustls(config) => tokio_tungstenite::Connector::Rustls(config),
+        }
+    }
+
+    /// Applies this configuration to the `ureq` agent of the blocking polling
+    /// transport.
+    pub(crate) fn configure_ureq(self, builder: ureq::AgentBuilder) -> ureq::AgentBuilder {
+        #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+        let _ = builder;
+
+        match self {
+            #[cfg(feature = "native-tls")]
+            TlsConfig::NativeTls(connector) => builder.tls_connector(Arc::new(connector)),
+            #[cfg(feature = "rustls")]
+            TlsConfig::Rustls(config) => builder.tls_config(config),
+        }
+    }
+
+    /// Wraps `stream` for the blocking websocket transports. Unlike
+    /// `tungstenite` this does not panic if the handshake of the native
+    /// backend runs into the read timeout of `stream` but returns an error of
+    /// kind `TimedOut`.
+    pub(crate) fn wrap_stream(
+        self,
+        domain: &str,
+        stream: TcpStream,
+    ) -> Result<MaybeTlsStream<TcpStream>> {
+        #[allow(unused_imports)]
+        use tungstenite::error::TlsError;
+        #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+        let _ = (domain, stream);
+
+        match self {
+            #[cfg(feature = "native-tls")]
+            TlsConfig::NativeTls(connector) => match connector.connect(domain, stream) {
+                Ok(stream) => Ok(MaybeTlsStream::NativeTls(stream)),
+                Err(native_tls::HandshakeError::Failure(err)) => {
+                    Err(tungstenite::Error::from(TlsError::Native(err)).into())
+                }
+                Err(native_tls::HandshakeError::WouldBlock(_)) => {
+                    Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into())
+                }
+            },
+            #[cfg(feature = "rustls")]
+            TlsConfig::Rustls(config) => {
+                let server_name = rustls::ServerName::try_from(domain)
+                    .map_err(|_| tungstenite::Error::from(TlsError::InvalidDnsName))?;
+                let connection = rustls::ClientConnection::new(config, server_name)
+                    .map_err(|err| tungstenite::Error::from(TlsError::Rustls(err)))?;
+                Ok(MaybeTlsStream::Rustls(rustls::StreamOwned::new(
+                    connection, stream,
+                )))
+            }
+        }
+    }
+}
+
+/// Applies a [`TlsConfig`] to an async `reqwest` client builder.
+#[cfg(all(feature = "async", any(feature = "native-tls", feature = "rustls")))]
+macro_rules! use_preconfigured_tls {
+    ($builder:expr, $tls_config:expr) => {
+        match $tls_config {
+            #[cfg(feature = "native-tls")]
+            $crate::tls::TlsConfig::NativeTls(connector) => {
+                $builder.use_preconfigured_tls(connector)
+            }
+            #[cfg(feature = "rustls")]
+            $crate::tls::TlsConfig::Rustls(config) => {
+                $builder.use_preconfigured_tls(rustls::ClientConfig::clone(&config))
+            }
+        }
+    };
+}
+/// Without a backend there is no [`TlsConfig`] to apply.
+#[cfg(all(
+    feature = "async",
+    not(any(feature = "native-tls", feature = "rustls"))
+))]
+macro_rules! use_preconfigured_tls {
+    ($builder:expr, $tls_config:expr) => {{
+        let _ = $tls_config;
+        $builder
+    }};
+}
+#[cfg(feature = "async")]
+pub(crate) use use_preconfigured_tls;
+
+impl std::fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+        let _ = f;
+        match *self {
+            #[cfg(feature = "native-tls")]
+            TlsConfig::NativeTls(ref connector) => {
+                f.debug_tuple("NativeTls").field(connector).finish()
+            }
+            #[cfg(feature = "rustls")]
+            TlsConfig::Rustls(_) => f.debug_tuple("Rustls").finish_non_exhaustive(),
+        }
+    }
+}
+
+#[cfg(feature = "native-tls")]
+impl From<native_tls::TlsConnector> for TlsConfig {
+    fn from(connector: native_tls::TlsConnector) -> Self {
+        TlsConfig::NativeTls(connector)
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl From<rustls::ClientConfig> for TlsConfig {
+    fn from(config: rustls::ClientConfig) -> Self {
+        TlsConfig::Rustls(Arc::new(config))
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl From<Arc<rustls::ClientConfig>> for TlsConfig {
+    fn from(config: Arc<rustls::ClientConfig>) -> Self {
+        TlsConfig::Rustls(config)
+    }
+}
+
+/// A builder for a [`TlsConfig`] that works the same with every backend.
+/// Certificates and keys are expected in PEM format, private keys in PKCS#8.
+///
+/// # Example
+/// ```rust,no_run
+/// use rust_engineio::tls::TlsConfig;
+///
+/// let tls_config = TlsConfig::builder()
+///     .add_root_certificate(&std::fs::read("ca.crt").unwrap())
+///     .identity(
+///         &std::fs::read("client.crt").unwrap(),
+///         &std::fs::read("client.key").unwrap(),
+///     )
+///     .alpn_protocols(&["http/1.1"])
+///     .build()
+///     .expect("Found illegal configuration");
+/// ```
+#[derive(Clone, Default)]
+pub struct TlsConfigBuilder {
+    root_certificates: Vec<Vec<u8>>,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+    alpn_protocols: Vec<String>,
+    accept_invalid_certs: bool,
+}
+
+impl TlsConfigBuilder {
+    /// Trusts the given PEM encoded certificate(s) in addition to the
+    /// built-in root certificates.
+    pub fn add_root_certificate(mut self, pem: &[u8]) -> Self {
+        self.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Authenticates the client with the given PEM encoded certificate chain
+    /// and PKCS#8 private key.
+    pub fn identity(mut self, certificate_chain: &[u8], private_key: &[u8]) -> Self {
+        self.identity = Some((certificate_chain.to_vec(), private_key.to_vec()));
+        self
+    }
+
+    /// Sets the protocols offered during ALPN. Note that the websocket
+    /// transport requires `http/1.1`.
+    pub fn alpn_protocols(mut self, protocols: &[&str]) -> Self {
+        self.alpn_protocols = protocols.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Disables the validation of server certificates. Only use this for testing!
+    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Builds the configuration, using `rustls` if the `rustls` feature is
+    /// enabled and the native backend otherwise.
+    pub fn build(self) -> Result<TlsConfig> {
+        #[cfg(feature = "rustls")]
+        return self.build_rustls();
+        #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+        return self.build_native_tls();
+        #[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+        return Err(Error::InvalidTlsConfig(
+            "no TLS backend feature is enabled".to_owned(),
+        ));
+    }
+
+    /// Builds a configuration for the native TLS backend.
+    #[cfg(feature = "native-tls")]
+    pub fn build_native_tls(self) -> Result<TlsConfig> {
+        use native_tls::{Certificate, Identity, TlsConnector};
+
+        let native_err = |err: native_tls::Error| Error::InvalidTlsConfig(err.to_string());
+        let mut builder = TlsConnector::builder();
+        for pem in &self.root_certificates {
+            builder.add_root_certificate(Certificate::from_pem(pem).map_err(native_err)?);
+        }
+        if let Some((certificate_chain, private_key)) = &self.identity {
+            builder.identity(
+                Identity::from_pkcs8(certificate_chain, private_key).map_err(native_err)?,
+            );
+        }
+        let alpn_protocols: Vec<&str> = self.alpn_protocols.iter().map(String::as_str).collect();
+        builder.request_alpns(&alpn_protocols);
+        builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+
+        Ok(TlsConfig::NativeTls(builder.build().map_err(native_err)?))
+    }
+
+    /// Builds a configuration for the `rustls` backend. The built-in root
+    /// certificates are the ones of the `webpki-roots` crate.
+    #[cfg(feature = "rustls")]
+    pub fn build_rustls(self) -> Result<TlsConfig> {
+        use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore};
+
+        let rustls_err = |err: rustls::Error| Error::InvalidTlsConfig(err.to_string());
+        let pem_certs = |pem: &[u8]| -> Result<Vec<Certificate>> {
+            let certs = rustls_pemfile::certs(&mut &pem[..])?;
+            if certs.is_empty() {
+                return Err(Error::InvalidTlsConfig(
+                    "found no PEM encoded certificate".to_owned(),
+                ));
+            }
+            Ok(certs.into_iter().map(Certificate).collect())
+        };
+
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+        for pem in &self.root_certificates {
+            for certificate in pem_certs(pem)? {
+                roots
+                    .add(&certificate)
+                    .map_err(|err| Error::InvalidTlsConfig(err.to_string()))?;
+            }
+        }
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let mut config = match &self.identity {
+            Some((certificate_chain, private_key)) => {
+                let key = rustls_pemfile::pkcs8_private_keys(&mut &private_key[..])?
+                    .pop()
+                    .ok_or_else(|| {
+                        Error::InvalidTlsConfig("found no PKCS#8 private key".to_owned())
+                    })?;
+                builder
+                    .with_client_auth_cert(pem_certs(certificate_chain)?, PrivateKey(key))
+                    .map_err(rustls_err)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = self
+            .alpn_protocols
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+        if self.accept_invalid_certs {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(danger::NoCertificateVerification));
+        }
+
+        Ok(TlsConfig::Rustls(Arc::new(config)))
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod danger {
+    use rustls::client::{ServerCertVerified, ServerCertVerifier};
+    use rustls::{Certificate, Error, ServerName};
+    use std::time::SystemTime;
+
+    /// A certificate verifier that accepts every certificate.
+    pub(super) struct NoCertificateVerification;
+
+    impl ServerCertVerifier for NoCertificateVerification {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &Certificate,
+            _intermediates: &[Certificate],
+            _server_name: &ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_tls_config() -> Result<()> {
+        let builder = TlsConfig::builder()
+            .alpn_protocols(&["http/1.1"])
+            .danger_accept_invalid_certs(true);
+
+        #[cfg(feature = "native-tls")]
+        assert!(matches!(
+            builder.clone().build_native_tls()?,
+            TlsConfig::NativeTls(_)
+        ));
+        #[cfg(feature = "rustls")]
+        match builder.clone().build_rustls()? {
+            TlsConfig::Rustls(config) => {
+                assert_eq!(config.alpn_protocols, vec![b"http/1.1".to_vec()])
+            }
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+        assert!(builder.build().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_invalid_tls_config() {
+        let result = TlsConfig::builder()
+            .add_root_certificate(b"not a certificate")
+            .build();
+        assert!(matches!(result, Err(Error::InvalidTlsConfig(_))));
+    }
+}
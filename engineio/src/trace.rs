@@ -0,0 +1,167 @@
+//! Shims over `tracing`, which fall back to `log` without the `tracing`
+//! feature.
+
+/// Emits an event at `$level` with fields recorded as `Debug` values, e.g.
+/// `event!(debug, parent: &span, sid = sid; "connected")`.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, parent: $parent:expr $(, $field:ident = $value:expr)* ; $($arg:tt)+) => {
+        tracing::$level!(parent: $parent, $($field = ?$value,)* $($arg)+)
+    };
+    ($level:ident $(, $field:ident = $value:expr)* ; $($arg:tt)+) => {
+        tracing::$level!($($field = ?$value,)* $($arg)+)
+    };
+}
+
+/// Logs the message of an event at `$level`, the fields are dropped.
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident, parent: $parent:expr $(, $field:ident = $value:expr)* ; $($arg:tt)+) => {{
+        let _ = ($parent, $(&$value,)*);
+        log::$level!($($arg)+)
+    }};
+    ($level:ident $(, $field:ident = $value:expr)* ; $($arg:tt)+) => {{
+        let _ = ($(&$value,)*);
+        log::$level!($($arg)+)
+    }};
+}
+
+/// Creates a span at `$level` with fields recorded as `Debug` values, e.g.
+/// `span!(INFO, "engineio.connection", sid = sid)`.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($level:ident, parent: $parent:expr, $name:literal $(, $field:ident = $value:expr)*) => {
+        tracing::span!(parent: $parent, tracing::Level::$level, $name $(, $field = ?$value)*)
+    };
+    ($level:ident, $name:literal $(, $field:ident = $value:expr)*) => {
+        tracing::span!(tracing::Level::$level, $name $(, $field = ?$value)*)
+    };
+}
+
+/// Creates a span which records nothing.
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($level:ident, parent: $parent:expr, $name:literal $(, $field:ident = $value:expr)*) => {{
+        let _ = ($parent, $(&$value,)*);
+        $crate::trace::Span
+    }};
+    ($level:ident, $name:literal $(, $field:ident = $value:expr)*) => {{
+        let _ = ($(&$value,)*);
+        $crate::trace::Span
+    }};
+}
+
+#[cfg(all(feature = "tracing", feature = "async"))]
+pub(crate) use tracing::Instrument;
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// A span which records nothing.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Span;
+
+/// The guard of an entered [`Span`].
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+}
+
+/// Attaches a [`Span`] to a future.
+#[cfg(all(not(feature = "tracing"), feature = "async"))]
+pub(crate) trait Instrument: Sized {
+    fn instrument(self, _: Span) -> Self {
+        self
+    }
+}
+
+#[cfg(all(not(feature = "tracing"), feature = "async"))]
+impl<T> Instrument for T {}
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::{ClientBuilder, Packet, PacketId};
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Collects the names of spans and the messages of events.
+    #[derive(Clone, Default)]
+    struct Collector {
+        next_id: Arc<AtomicU64>,
+        spans: Arc<Mutex<Vec<&'static str>>>,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Message<'a>(&'a mut String);
+
+    impl Visit for Message<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "message" {
+                *self.0 = format!("{:?}", value);
+            }
+        }
+    }
+
+    impl Subscriber for Collector {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.spans.lock().unwrap().push(span.metadata().name());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut message = String::new();
+            event.record(&mut Message(&mut message));
+            self.events.lock().unwrap().push(message);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn test_spans_and_events() -> Result<()> {
+        let collector = Collector::default();
+        let server = MockServer::builder().build()?;
+
+        tracing::subscriber::with_default(collector.clone(), || -> Result<()> {
+            let client = ClientBuilder::new(server.url()).build_polling()?;
+            client.connect()?;
+            let session = server.accept(Duration::from_secs(5)).unwrap();
+
+            client.emit(Packet::new(PacketId::Message, "hello"))?;
+            session.send(Packet::new(PacketId::Message, "world"));
+            client.poll()?;
+            Ok(())
+        })?;
+
+        let spans = collector.spans.lock().unwrap();
+        assert!(spans.contains(&"engineio.handshake"));
+        assert!(spans.contains(&"engineio.connection"));
+        let events = collector.events.lock().unwrap();
+        for message in ["handshake completed", "sending packet", "received packet"] {
+            assert!(events.iter().any(|event| event == message), "{}", message);
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,10 @@
-use super::transports::{PollingTransport, WebsocketSecureTransport, WebsocketTransport};
+use super::transports::{
+    PollingTransport, ReplayTransport, WebsocketSecureTransport, WebsocketTransport,
+};
 use crate::error::Result;
 use adler32::adler32;
 use bytes::Bytes;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use url::Url;
 
 pub trait Transport {
@@ -15,6 +17,13 @@ pub trait Transport {
     /// response handling from the server.
     fn poll(&self) -> Result<Bytes>;
 
+    /// Like [`Transport::poll`], but fails with `Error::ReadTimeout` if no
+    /// data arrives within `timeout`. Ignores the timeout by default.
+    fn poll_timeout(&self, timeout: Option<Duration>) -> Result<Bytes> {
+        let _ = timeout;
+        self.poll()
+    }
+
     /// Returns start of the url. ex. http://localhost:2998/engine.io/?EIO=4&transport=polling
     /// Must have EIO and transport already set.
     fn base_url(&self) -> Result<Url>;
@@ -24,19 +33,24 @@ pub trait Transport {
 
     /// Full query address
     fn address(&self) -> Result<Url> {
-        let reader = format!("{:#?}", SystemTime::now());
-        let hash = adler32(reader.as_bytes()).unwrap();
         let mut url = self.base_url()?;
-        url.query_pairs_mut().append_pair("t", &hash.to_string());
+        url.query_pairs_mut().append_pair("t", &timestamp());
         Ok(url)
     }
 }
 
+/// Returns a value for the cache busting timestamp query parameter.
+pub(crate) fn timestamp() -> String {
+    let reader = format!("{:#?}", SystemTime::now());
+    adler32(reader.as_bytes()).unwrap().to_string()
+}
+
 #[derive(Debug)]
 pub enum TransportType {
     Polling(PollingTransport),
     WebsocketSecure(WebsocketSecureTransport),
     Websocket(WebsocketTransport),
+    Replay(ReplayTransport),
 }
 
 impl From<PollingTransport> for TransportType {
@@ -57,12 +71,28 @@ impl From<WebsocketTransport> for TransportType {
     }
 }
 
+impl From<ReplayTransport> for TransportType {
+    fn from(transport: ReplayTransport) -> Self {
+        TransportType::Replay(transport)
+    }
+}
+
 impl TransportType {
     pub fn as_transport(&self) -> &dyn Transport {
         match self {
             TransportType::Polling(transport) => transport,
             TransportType::Websocket(transport) => transport,
             TransportType::WebsocketSecure(transport) => transport,
+            TransportType::Replay(transport) => transport,
+        }
+    }
+
+    /// The name of the transport in recordings.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TransportType::Polling(_) => "polling",
+            TransportType::Websocket(_) | TransportType::WebsocketSecure(_) => "websocket",
+            TransportType::Replay(_) => "replay",
         }
     }
 }
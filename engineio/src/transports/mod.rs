@@ -1,7 +1,10 @@
 mod polling;
+mod replay;
 mod websocket;
+pub(crate) mod websocket_general;
 mod websocket_secure;
 
 pub use self::polling::PollingTransport;
+pub use self::replay::ReplayTransport;
 pub use self::websocket::WebsocketTransport;
 pub use self::websocket_secure::WebsocketSecureTransport;
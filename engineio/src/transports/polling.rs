@@ -1,48 +1,92 @@
 use crate::error::{Error, Result};
-use crate::transport::Transport;
+use crate::transport::{timestamp, Transport};
+use crate::{CookieJar, Proxy, Timeouts, TlsConfig};
 use base64::{engine::general_purpose, Engine as _};
 use bytes::{BufMut, Bytes, BytesMut};
-use native_tls::TlsConnector;
-use reqwest::{
-    blocking::{Client, ClientBuilder},
-    header::HeaderMap,
-};
+use http::header::{HeaderMap, CONTENT_LENGTH, COOKIE, SET_COOKIE};
+use std::io::{self, Read};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use ureq::{Agent, AgentBuilder, Request, Response};
 use url::Url;
 
 #[derive(Debug, Clone)]
 pub struct PollingTransport {
-    client: Arc<Client>,
+    /// The agent, or the url of a proxy `ureq` can't be configured with.
+    agent: std::result::Result<Agent, String>,
+    headers: HeaderMap,
+    cookie_jar: Option<Arc<CookieJar>>,
     base_url: Arc<RwLock<Url>>,
+    timeouts: Timeouts,
+    timestamp_param: Option<String>,
+    max_payload: Option<usize>,
 }
 
 impl PollingTransport {
-    /// Creates an instance of `PollingTransport`.
+    /// Creates an instance of `PollingTransport`. Requests carry a cache busting
+    /// timestamp in the query parameter `timestamp_param`, if given. Response
+    /// bodies exceeding `max_payload` bytes are rejected while being read.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         base_url: Url,
-        tls_config: Option<TlsConnector>,
+        tls_config: Option<TlsConfig>,
         opening_headers: Option<HeaderMap>,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        timestamp_param: Option<String>,
+        max_payload: Option<usize>,
     ) -> Self {
-        let client = match (tls_config, opening_headers) {
-            (Some(config), Some(map)) => ClientBuilder::new()
-                .use_preconfigured_tls(config)
-                .default_headers(map)
-                .build()
-                .unwrap(),
-            (Some(config), None) => ClientBuilder::new()
-                .use_preconfigured_tls(config)
-                .build()
-                .unwrap(),
-            (None, Some(map)) => ClientBuilder::new().default_headers(map).build().unwrap(),
-            (None, None) => Client::new(),
+        let mut builder = AgentBuilder::new();
+        // without a configuration `ureq` can't connect to https urls
+        if let Ok(config) = tls_config.map_or_else(|| TlsConfig::builder().build(), Ok) {
+            builder = config.configure_ureq(builder);
+        }
+        if let Some(timeout) = timeouts.connect {
+            builder = builder.timeout_connect(timeout);
+        }
+        let agent = match proxy.map(|proxy| proxy.to_ureq(&base_url)) {
+            Some(Err(Error::InvalidProxy(url))) => Err(url),
+            Some(Ok(Some(proxy))) => Ok(builder.proxy(proxy).build()),
+            _ => Ok(builder.build()),
         };
 
         let mut url = base_url;
         url.query_pairs_mut().append_pair("transport", "polling");
 
         PollingTransport {
-            client: Arc::new(client),
+            agent,
+            headers: opening_headers.unwrap_or_default(),
+            cookie_jar,
             base_url: Arc::new(RwLock::new(url)),
+            timeouts,
+            timestamp_param,
+            max_payload,
+        }
+    }
+
+    /// A request to `url` carrying the opening headers and the cookies of the
+    /// jar.
+    fn request(&self, method: &str, url: &Url) -> Result<Request> {
+        let agent = self
+            .agent
+            .as_ref()
+            .map_err(|url| Error::InvalidProxy(url.clone()))?;
+        let mut request = agent.request_url(method, url);
+        for (name, value) in &self.headers {
+            if let Ok(value) = value.to_str() {
+                request = request.set(name.as_str(), value);
+            }
+        }
+        if let Some(cookies) = self.cookie_jar.as_ref().and_then(|jar| jar.cookies(url)) {
+            request = request.set(COOKIE.as_str(), &cookies);
+        }
+        Ok(request)
+    }
+
+    fn store_cookies(&self, response: &Response, url: &Url) {
+        if let Some(jar) = &self.cookie_jar {
+            jar.set_cookies(response.all(SET_COOKIE.as_str()), url);
         }
     }
 }
@@ -61,13 +105,17 @@ impl Transport for PollingTransport {
         } else {
             data
         };
-        let status = self
-            .client
-            .post(self.address()?)
-            .body(data_to_send)
-            .send()?
-            .status()
-            .as_u16();
+        let url = self.address()?;
+        let mut request = self.request("POST", &url)?;
+        if let Some(timeout) = self.timeouts.request {
+            request = request.timeout(timeout);
+        }
+        let response = request.send_bytes(&data_to_send).map_err(|err| {
+            self.timeouts
+                .ureq_error(err, self.timeouts.request, Error::RequestTimeout)
+        })?;
+        self.store_cookies(&response, &url);
+        let status = response.status();
 
         if status != 200 {
             let error = Error::IncompleteHttp(status);
@@ -78,13 +126,35 @@ impl Transport for PollingTransport {
     }
 
     fn poll(&self) -> Result<Bytes> {
-        Ok(self.client.get(self.address()?).send()?.bytes()?)
+        self.poll_timeout(None)
+    }
+
+    fn poll_timeout(&self, timeout: Option<Duration>) -> Result<Bytes> {
+        let url = self.address()?;
+        let mut request = self.request("GET", &url)?;
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+
+        let response = request
+            .call()
+            .map_err(|err| self.timeouts.ureq_error(err, timeout, Error::ReadTimeout))?;
+        self.store_cookies(&response, &url);
+        read_body(response, self.max_payload, timeout)
     }
 
     fn base_url(&self) -> Result<Url> {
         Ok(self.base_url.read()?.clone())
     }
 
+    fn address(&self) -> Result<Url> {
+        let mut url = self.base_url()?;
+        if let Some(param) = &self.timestamp_param {
+            url.query_pairs_mut().append_pair(param, &timestamp());
+        }
+        Ok(url)
+    }
+
     fn set_base_url(&self, base_url: Url) -> Result<()> {
         let mut url = base_url;
         if !url
@@ -98,6 +168,44 @@ impl Transport for PollingTransport {
     }
 }
 
+/// Reads the body of `response` in chunks, failing with
+/// [`Error::InvalidPayloadSize`] as soon as it exceeds `max_payload` bytes
+/// instead of buffering all of it.
+fn read_body(
+    response: Response,
+    max_payload: Option<usize>,
+    timeout: Option<Duration>,
+) -> Result<Bytes> {
+    let length = response
+        .header(CONTENT_LENGTH.as_str())
+        .and_then(|length| length.parse::<usize>().ok());
+    if let (Some(length), Some(max)) = (length, max_payload) {
+        if length > max {
+            return Err(Error::InvalidPayloadSize(length, max));
+        }
+    }
+
+    let mut reader = response.into_reader();
+    let mut body = BytesMut::with_capacity(length.unwrap_or_default().min(8192));
+    let mut chunk = [0; 8192];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(body.freeze()),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            // `ureq` reports the expiry of the request timeout while reading
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                return Err(timeout.map_or_else(|| err.into(), Error::ReadTimeout));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        body.put_slice(&chunk[..read]);
+        if let Some(max) = max_payload.filter(|max| body.len() > *max) {
+            return Err(Error::InvalidPayloadSize(body.len(), max));
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -105,7 +213,16 @@ mod test {
     #[test]
     fn polling_transport_base_url() -> Result<()> {
         let url = crate::test::engine_io_server()?.to_string();
-        let transport = PollingTransport::new(Url::from_str(&url[..]).unwrap(), None, None);
+        let transport = PollingTransport::new(
+            Url::from_str(&url[..]).unwrap(),
+            None,
+            None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
+            None,
+        );
         assert_eq!(
             transport.base_url()?.to_string(),
             url.clone() + "?transport=polling"
@@ -129,10 +246,18 @@ mod test {
     #[test]
     fn transport_debug() -> Result<()> {
         let mut url = crate::test::engine_io_server()?;
-        let transport =
-            PollingTransport::new(Url::from_str(&url.to_string()[..]).unwrap(), None, None);
+        let transport = PollingTransport::new(
+            Url::from_str(&url.to_string()[..]).unwrap(),
+            None,
+            None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
+            None,
+        );
         url.query_pairs_mut().append_pair("transport", "polling");
-        assert_eq!(format!("PollingTransport {{ client: {:?}, base_url: RwLock {{ data: {:?}, poisoned: false, .. }} }}", transport.client, url), format!("{:?}", transport));
+        assert_eq!(format!("PollingTransport {{ agent: {:?}, headers: {{}}, cookie_jar: None, base_url: RwLock {{ data: {:?}, poisoned: false, .. }}, timeouts: {:?}, timestamp_param: None, max_payload: None }}", transport.agent, url, Timeouts::default()), format!("{:?}", transport));
         let test: Box<dyn Transport> = Box::new(transport);
         assert_eq!(
             format!("Transport(base_url: Ok({:?}))", url),
@@ -140,4 +265,105 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn polling_transport_read_timeout() -> Result<()> {
+        // a server that accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let transport =
+            PollingTransport::new(url, None, None, Timeouts::default(), None, None, None, None);
+
+        let timeout = Duration::from_millis(50);
+        assert!(matches!(
+            transport.poll_timeout(Some(timeout)),
+            Err(Error::ReadTimeout(duration)) if duration == timeout
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn polling_transport_proxy() -> Result<()> {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let proxy = Proxy::all(format!("http://{}", listener.local_addr()?))?;
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\n6")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let url = Url::parse("http://example.com/engine.io/")?;
+        let transport = PollingTransport::new(
+            url,
+            None,
+            None,
+            Timeouts::default(),
+            Some(proxy),
+            None,
+            None,
+            None,
+        );
+        assert_eq!(transport.poll()?, Bytes::from_static(b"6"));
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET http://example.com/engine.io/?transport=polling"));
+        Ok(())
+    }
+
+    #[test]
+    fn polling_transport_cookie_jar() -> Result<()> {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/engine.io/", listener.local_addr()?))?;
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for cookie in ["set-cookie: AWSALB=node1; Path=/\r\n", ""] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                // the emitted packet may arrive along with the headers
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n{}connection: close\r\ncontent-length: 1\r\n\r\n6",
+                    cookie
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+
+        let jar = Arc::new(CookieJar::default());
+        let transport = PollingTransport::new(
+            url,
+            None,
+            None,
+            Timeouts::default(),
+            None,
+            Some(jar),
+            None,
+            None,
+        );
+        transport.poll()?;
+        transport.emit(Bytes::from_static(b"2"), false)?;
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("cookie"));
+        assert!(requests[1].contains("cookie: AWSALB=node1\r\n"));
+        Ok(())
+    }
 }
@@ -0,0 +1,72 @@
+use crate::error::Result;
+use crate::packet::{Packet, PacketId};
+use crate::transport::Transport;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use url::Url;
+
+/// A transport which receives recorded packets instead of connecting to a
+/// server. Emitted packets are discarded.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    packets: Arc<Mutex<VecDeque<Packet>>>,
+    base_url: Arc<RwLock<Url>>,
+}
+
+impl ReplayTransport {
+    /// Creates an instance of `ReplayTransport` receiving `packets` in order,
+    /// one per poll.
+    pub fn new(base_url: Url, packets: VecDeque<Packet>) -> Self {
+        ReplayTransport {
+            packets: Arc::new(Mutex::new(packets)),
+            base_url: Arc::new(RwLock::new(base_url)),
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn emit(&self, _: Bytes, _: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the next recorded packet, a `close` packet once all are
+    /// received.
+    fn poll(&self) -> Result<Bytes> {
+        let packet = self
+            .packets
+            .lock()?
+            .pop_front()
+            .unwrap_or_else(|| Packet::new(PacketId::Close, Bytes::new()));
+        Ok(packet.into())
+    }
+
+    fn base_url(&self) -> Result<Url> {
+        Ok(self.base_url.read()?.clone())
+    }
+
+    fn set_base_url(&self, base_url: Url) -> Result<()> {
+        *self.base_url.write()? = base_url;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_poll() -> Result<()> {
+        let url = Url::parse("http://localhost/engine.io/")?;
+        let transport = ReplayTransport::new(
+            url.clone(),
+            VecDeque::from(vec![Packet::new(PacketId::Message, "hello")]),
+        );
+
+        transport.emit(Bytes::from_static(b"4discarded"), false)?;
+        assert_eq!(transport.poll()?, Bytes::from_static(b"4hello"));
+        assert_eq!(transport.poll()?, Bytes::from_static(b"1"));
+        assert_eq!(transport.base_url()?, url);
+        Ok(())
+    }
+}
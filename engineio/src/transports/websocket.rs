@@ -1,68 +1,94 @@
-use crate::{
-    asynchronous::{
-        async_transports::WebsocketTransport as AsyncWebsocketTransport, transport::AsyncTransport,
-    },
-    error::Result,
-    transport::Transport,
-    Error,
-};
+use super::websocket_general::{connect, WebsocketGeneralTransport};
+use crate::{error::Result, transport::Transport, CookieJar, Proxy, Timeouts};
 use bytes::Bytes;
 use http::HeaderMap;
-use std::sync::Arc;
-use tokio::runtime::Runtime;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tungstenite::client::IntoClientRequest;
 use url::Url;
 
 #[derive(Clone)]
 pub struct WebsocketTransport {
-    runtime: Arc<Runtime>,
-    inner: Arc<AsyncWebsocketTransport>,
+    inner: WebsocketGeneralTransport,
+    base_url: Arc<RwLock<Url>>,
 }
 
 impl WebsocketTransport {
-    /// Creates an instance of `WebsocketTransport`.
-    pub fn new(base_url: Url, headers: Option<HeaderMap>) -> Result<Self> {
-        let runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()?;
+    /// Creates an instance of `WebsocketTransport`. Messages exceeding `max_payload`
+    /// bytes are rejected while being read.
+    pub fn new(
+        base_url: Url,
+        headers: Option<HeaderMap>,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        max_payload: Option<usize>,
+    ) -> Result<Self> {
+        let mut url = base_url;
+        url.query_pairs_mut().append_pair("transport", "websocket");
+        url.set_scheme("ws").unwrap();
+
+        let mut req = url.clone().into_client_request()?;
+        if let Some(map) = headers {
+            req.headers_mut().extend(map);
+        }
 
-        let inner = runtime.block_on(AsyncWebsocketTransport::new(base_url, headers))?;
+        let (socket, control) = connect(
+            req,
+            &url,
+            None,
+            timeouts,
+            proxy.as_ref(),
+            cookie_jar.as_deref(),
+            max_payload,
+        )?;
 
         Ok(WebsocketTransport {
-            runtime: Arc::new(runtime),
-            inner: Arc::new(inner),
+            inner: WebsocketGeneralTransport::new(socket, control, timeouts),
+            base_url: Arc::new(RwLock::new(url)),
         })
     }
 
+    /// Sends probe packet to ensure connection is valid
+    pub(crate) fn probe(&self) -> Result<()> {
+        self.inner.probe()
+    }
+
     /// Sends probe packet to ensure connection is valid, then sends upgrade
     /// request
     pub(crate) fn upgrade(&self) -> Result<()> {
-        self.runtime.block_on(async { self.inner.upgrade().await })
+        self.inner.upgrade()
     }
 }
 
 impl Transport for WebsocketTransport {
     fn emit(&self, data: Bytes, is_binary_att: bool) -> Result<()> {
-        self.runtime
-            .block_on(async { self.inner.emit(data, is_binary_att).await })
+        self.inner.emit(data, is_binary_att)
     }
 
     fn poll(&self) -> Result<Bytes> {
-        self.runtime.block_on(async {
-            let r = self.inner.poll_next().await;
-            match r {
-                Ok(b) => b.ok_or(Error::IncompletePacket()),
-                Err(_) => Err(Error::IncompletePacket()),
-            }
-        })
+        self.inner.poll(None)
+    }
+
+    fn poll_timeout(&self, timeout: Option<Duration>) -> Result<Bytes> {
+        self.inner.poll(timeout)
     }
 
     fn base_url(&self) -> Result<url::Url> {
-        self.runtime.block_on(async { self.inner.base_url().await })
+        Ok(self.base_url.read()?.clone())
     }
 
-    fn set_base_url(&self, url: url::Url) -> Result<()> {
-        self.runtime
-            .block_on(async { self.inner.set_base_url(url).await })
+    fn set_base_url(&self, base_url: url::Url) -> Result<()> {
+        let mut url = base_url;
+        if !url
+            .query_pairs()
+            .any(|(k, v)| k == "transport" && v == "websocket")
+        {
+            url.query_pairs_mut().append_pair("transport", "websocket");
+        }
+        url.set_scheme("ws").unwrap();
+        *self.base_url.write()? = url;
+        Ok(())
     }
 }
 
@@ -85,7 +111,14 @@ mod test {
         let url = crate::test::engine_io_server()?.to_string()
             + "engine.io/?EIO="
             + &ENGINE_IO_VERSION.to_string();
-        WebsocketTransport::new(Url::from_str(&url[..])?, None)
+        WebsocketTransport::new(
+            Url::from_str(&url[..])?,
+            None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
+        )
     }
 
     #[test]
@@ -98,16 +131,14 @@ mod test {
             .append_pair("transport", "websocket");
         url.set_scheme("ws").unwrap();
         assert_eq!(transport.base_url()?.to_string(), url.to_string());
-        transport.set_base_url(reqwest::Url::parse("https://127.0.0.1")?)?;
+        transport.set_base_url(Url::parse("https://127.0.0.1")?)?;
         assert_eq!(
             transport.base_url()?.to_string(),
             "ws://127.0.0.1/?transport=websocket"
         );
         assert_ne!(transport.base_url()?.to_string(), url.to_string());
 
-        transport.set_base_url(reqwest::Url::parse(
-            "http://127.0.0.1/?transport=websocket",
-        )?)?;
+        transport.set_base_url(Url::parse("http://127.0.0.1/?transport=websocket")?)?;
         assert_eq!(
             transport.base_url()?.to_string(),
             "ws://127.0.0.1/?transport=websocket"
@@ -127,4 +158,73 @@ mod test {
         println!("{:?}", transport.poll().unwrap());
         Ok(())
     }
+
+    #[test]
+    fn websocket_transport_emit_while_polling() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/engine.io/", listener.local_addr()?))?;
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            // echo the first two messages
+            for _ in 0..2 {
+                let message = socket.read_message().unwrap();
+                socket.write_message(message).unwrap();
+            }
+        });
+
+        let transport = WebsocketTransport::new(url, None, Timeouts::default(), None, None, None)?;
+        assert!(matches!(
+            transport.poll_timeout(Some(Duration::from_millis(50))),
+            Err(crate::Error::ReadTimeout(_))
+        ));
+
+        let poller = {
+            let transport = transport.clone();
+            std::thread::spawn(move || {
+                let timeout = Some(Duration::from_secs(5));
+                (
+                    transport.poll_timeout(timeout),
+                    transport.poll_timeout(timeout),
+                )
+            })
+        };
+        // the poll is pending until the server answers the emits
+        std::thread::sleep(Duration::from_millis(100));
+        transport.emit(Bytes::from_static(b"4hello"), false)?;
+        transport.emit(Bytes::from_static(b"\x01\x02"), true)?;
+
+        let (text, binary) = poller.join().unwrap();
+        assert_eq!(text?, Bytes::from_static(b"4hello"));
+        assert_eq!(binary?, Bytes::from_static(b"\x04\x01\x02"));
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn websocket_transport_probe_timeout() -> Result<()> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/engine.io/", listener.local_addr()?))?;
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            // swallow the probe and the answer to it
+            socket.read_message().unwrap();
+            socket
+        });
+
+        let timeouts = Timeouts {
+            handshake: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let transport = WebsocketTransport::new(url, None, timeouts, None, None, None)?;
+        assert!(matches!(
+            transport.probe(),
+            Err(crate::Error::HandshakeTimeout(timeout)) if timeout == Duration::from_millis(100)
+        ));
+        server.join().unwrap();
+        Ok(())
+    }
 }
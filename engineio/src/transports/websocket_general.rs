@@ -0,0 +1,315 @@
+use std::{
+    io,
+    net::TcpStream,
+    str::from_utf8,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::Result, proxy::connect_tcp, CookieJar, Error, Packet, PacketId, Proxy, Timeouts,
+    TlsConfig,
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use http::header::{COOKIE, SET_COOKIE};
+use tungstenite::{
+    error::CapacityError,
+    handshake::client::{Request, Response},
+    protocol::WebSocketConfig,
+    stream::MaybeTlsStream,
+    HandshakeError, Message, WebSocket,
+};
+use url::Url;
+
+type Websocket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// How long a poll reads from the connection while holding it, which bounds
+/// how long an emit waits for a concurrent poll. In between polls wait for
+/// data without holding the connection.
+const READ_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Adds the cookies of `cookie_jar` to the upgrade request for `url` and
+/// returns the http(s) url the cookies of the connection are scoped to.
+pub(crate) fn add_cookies(request: &mut Request, url: &Url, cookie_jar: Option<&CookieJar>) -> Url {
+    let mut cookie_url = url.clone();
+    let scheme = if url.scheme() == "wss" {
+        "https"
+    } else {
+        "http"
+    };
+    // SAFETY: both websocket schemes are special schemes as well
+    cookie_url.set_scheme(scheme).unwrap();
+
+    if let Some(cookies) = cookie_jar
+        .and_then(|jar| jar.cookies(&cookie_url))
+        .and_then(|cookies| cookies.parse().ok())
+    {
+        request.headers_mut().insert(COOKIE, cookies);
+    }
+    cookie_url
+}
+
+/// Stores the cookies set in the response to the upgrade request.
+pub(crate) fn store_cookies(response: &Response, cookie_url: &Url, cookie_jar: Option<&CookieJar>) {
+    if let Some(jar) = cookie_jar {
+        let cookies = response.headers().get_all(SET_COOKIE);
+        jar.set_cookies(
+            cookies.iter().filter_map(|cookie| cookie.to_str().ok()),
+            cookie_url,
+        );
+    }
+}
+
+/// Opens a blocking websocket connection to `url`, tunneled through `proxy`
+/// unless the proxy excludes the host. `wss` urls are secured with
+/// `tls_config` or, if there is none, the default configuration of the TLS
+/// backend. Cookies are handled like for the async transports. Messages
+/// exceeding `max_payload` bytes are rejected while being read.
+///
+/// Returns the transport along with a handle of its tcp stream.
+pub(crate) fn connect(
+    mut request: Request,
+    url: &Url,
+    tls_config: Option<TlsConfig>,
+    timeouts: Timeouts,
+    proxy: Option<&Proxy>,
+    cookie_jar: Option<&CookieJar>,
+    max_payload: Option<usize>,
+) -> Result<(Websocket, TcpStream)> {
+    let cookie_url = add_cookies(&mut request, url, cookie_jar);
+    let start = Instant::now();
+
+    let (socket, response, control) = match (
+        open(
+            request,
+            url,
+            tls_config,
+            timeouts.connect,
+            proxy,
+            max_payload,
+        ),
+        timeouts.connect,
+    ) {
+        (Err(err), Some(timeout)) if is_timeout(&err) || start.elapsed() >= timeout => {
+            return Err(Error::ConnectTimeout(timeout))
+        }
+        (result, _) => result?,
+    };
+
+    store_cookies(&response, &cookie_url, cookie_jar);
+    Ok((socket, control))
+}
+
+/// Connects the socket and performs the TLS and websocket handshakes, all of
+/// them within `timeout`.
+fn open(
+    request: Request,
+    url: &Url,
+    tls_config: Option<TlsConfig>,
+    timeout: Option<Duration>,
+    proxy: Option<&Proxy>,
+    max_payload: Option<usize>,
+) -> Result<(Websocket, Response, TcpStream)> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::InvalidUrlScheme(url.to_string()))?
+        .trim_matches(|c| c == '[' || c == ']');
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| Error::InvalidUrlScheme(url.scheme().to_string()))?;
+
+    let stream = match proxy.and_then(|proxy| proxy.intercept(url)) {
+        Some(target) => target.connect_blocking(url, timeout)?,
+        None => connect_tcp(host, port, timeout)?,
+    };
+    if let Some(deadline) = deadline {
+        // a timeout of zero is rejected by the socket
+        let remaining = deadline
+            .saturating_duration_since(Instant::now())
+            .max(Duration::from_millis(1));
+        stream.set_read_timeout(Some(remaining))?;
+        stream.set_write_timeout(Some(remaining))?;
+    }
+    // the socket options are shared with the clone, which remains accessible
+    // once the stream is wrapped
+    let control = stream.try_clone()?;
+
+    let stream = if url.scheme() == "wss" {
+        let tls_config = match tls_config {
+            Some(tls_config) => tls_config,
+            None => TlsConfig::builder().build()?,
+        };
+        tls_config.wrap_stream(host, stream)?
+    } else {
+        MaybeTlsStream::Plain(stream)
+    };
+
+    let config = websocket_config(max_payload);
+    let (socket, response) = match tungstenite::client::client_with_config(request, stream, config)
+    {
+        Ok(connection) => connection,
+        Err(HandshakeError::Failure(err)) => return Err(err.into()),
+        Err(HandshakeError::Interrupted(_)) => {
+            return Err(io::Error::from(io::ErrorKind::TimedOut).into())
+        }
+    };
+
+    control.set_read_timeout(Some(READ_TIMEOUT))?;
+    control.set_write_timeout(None)?;
+    Ok((socket, response, control))
+}
+
+/// The configuration limiting the messages and frames read from a websocket
+/// to `max_payload` bytes, so larger ones fail before they are buffered.
+pub(crate) fn websocket_config(max_payload: Option<usize>) -> Option<WebSocketConfig> {
+    max_payload.map(|max| WebSocketConfig {
+        max_message_size: Some(max),
+        max_frame_size: Some(max),
+        ..WebSocketConfig::default()
+    })
+}
+
+/// Converts an error reading from a websocket, reporting messages exceeding
+/// the configured maximum as [`Error::InvalidPayloadSize`].
+pub(crate) fn read_error(err: tungstenite::Error) -> Error {
+    match err {
+        tungstenite::Error::Capacity(CapacityError::MessageTooLong { size, max_size }) => {
+            Error::InvalidPayloadSize(size, max_size)
+        }
+        err => err.into(),
+    }
+}
+
+/// Whether `err` is caused by a socket operation running into its timeout.
+fn is_timeout(err: &Error) -> bool {
+    match err {
+        Error::IncompleteIo(err) => is_would_block(err),
+        Error::WebsocketError(err) => {
+            matches!(&**err, tungstenite::Error::Io(err) if is_would_block(err))
+        }
+        _ => false,
+    }
+}
+
+/// Whether `err` is caused by a read or write running into the timeout of
+/// the socket, which is reported differently depending on the platform.
+fn is_would_block(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// A general purpose blocking websocket transport type. Polls only hold the
+/// connection while data is available, so emitting is possible while another
+/// thread polls.
+#[derive(Clone)]
+pub(crate) struct WebsocketGeneralTransport {
+    socket: Arc<Mutex<Websocket>>,
+    /// The underlying tcp stream, to wait for data without holding `socket`.
+    control: Arc<TcpStream>,
+    /// Serializes the polls, which change the read timeout of `control`.
+    reader: Arc<Mutex<()>>,
+    timeouts: Timeouts,
+}
+
+impl WebsocketGeneralTransport {
+    pub(crate) fn new(socket: Websocket, control: TcpStream, timeouts: Timeouts) -> Self {
+        WebsocketGeneralTransport {
+            socket: Arc::new(Mutex::new(socket)),
+            control: Arc::new(control),
+            reader: Arc::new(Mutex::new(())),
+            timeouts,
+        }
+    }
+
+    /// Sends probe packet to ensure connection is valid. The answer is awaited
+    /// for at most the handshake timeout, or else the connect timeout.
+    pub(crate) fn probe(&self) -> Result<()> {
+        self.socket
+            .lock()?
+            .write_message(Message::text(from_utf8(&Bytes::from(Packet::new(
+                PacketId::Ping,
+                Bytes::from("probe"),
+            )))?))?;
+
+        let timeout = self.timeouts.handshake.or(self.timeouts.connect);
+        let msg = self.read(timeout, Error::HandshakeTimeout)?;
+
+        if msg != Bytes::from(Packet::new(PacketId::Pong, Bytes::from("probe"))) {
+            return Err(Error::InvalidPacket());
+        }
+
+        Ok(())
+    }
+
+    /// Sends probe packet to ensure connection is valid, then sends upgrade
+    /// request
+    pub(crate) fn upgrade(&self) -> Result<()> {
+        self.probe()?;
+        self.emit(
+            Bytes::from(Packet::new(PacketId::Upgrade, Bytes::from(""))),
+            false,
+        )
+    }
+
+    pub(crate) fn emit(&self, data: Bytes, is_binary_att: bool) -> Result<()> {
+        let message = if is_binary_att {
+            Message::binary(data.as_ref())
+        } else {
+            Message::text(from_utf8(data.as_ref())?)
+        };
+
+        self.socket.lock()?.write_message(message)?;
+        Ok(())
+    }
+
+    pub(crate) fn poll(&self, timeout: Option<Duration>) -> Result<Bytes> {
+        self.read(timeout, Error::ReadTimeout)
+    }
+
+    /// Reads the next text or binary message, failing with the variant
+    /// `on_timeout` if none arrives within `timeout`.
+    fn read(&self, timeout: Option<Duration>, on_timeout: fn(Duration) -> Error) -> Result<Bytes> {
+        let _reader = self.reader.lock()?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let next = {
+                let mut socket = self.socket.lock()?;
+                self.control.set_read_timeout(Some(READ_TIMEOUT))?;
+                socket.read_message()
+            };
+            match next {
+                Ok(Message::Text(str)) => return Ok(Bytes::from(str)),
+                Ok(Message::Binary(data)) => {
+                    let mut msg = BytesMut::with_capacity(data.len() + 1);
+                    msg.put_u8(PacketId::Message as u8);
+                    msg.put(data.as_ref());
+
+                    return Ok(msg.freeze());
+                }
+                // ignore packets other than text and binary
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(err)) if is_would_block(&err) => (),
+                Err(err @ tungstenite::Error::Capacity(_)) => return Err(read_error(err)),
+                Err(_) => return Err(Error::IncompletePacket()),
+            }
+
+            // everything read so far is consumed, wait for more data
+            let remaining = match (timeout, deadline) {
+                (Some(timeout), Some(deadline)) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(on_timeout(timeout));
+                    }
+                    Some(remaining)
+                }
+                _ => None,
+            };
+            self.control.set_read_timeout(remaining)?;
+            // errors other than timeouts resurface when reading the message
+            let _ = self.control.peek(&mut [0]);
+        }
+    }
+}
@@ -1,76 +1,95 @@
-use crate::{
-    asynchronous::{
-        async_transports::WebsocketSecureTransport as AsyncWebsocketSecureTransport,
-        transport::AsyncTransport,
-    },
-    error::Result,
-    transport::Transport,
-    Error,
-};
+use super::websocket_general::{connect, WebsocketGeneralTransport};
+use crate::{error::Result, transport::Transport, CookieJar, Proxy, Timeouts, TlsConfig};
 use bytes::Bytes;
 use http::HeaderMap;
-use native_tls::TlsConnector;
-use std::sync::Arc;
-use tokio::runtime::Runtime;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tungstenite::client::IntoClientRequest;
 use url::Url;
 
 #[derive(Clone)]
 pub struct WebsocketSecureTransport {
-    runtime: Arc<Runtime>,
-    inner: Arc<AsyncWebsocketSecureTransport>,
+    inner: WebsocketGeneralTransport,
+    base_url: Arc<RwLock<Url>>,
 }
 
 impl WebsocketSecureTransport {
-    /// Creates an instance of `WebsocketSecureTransport`.
+    /// Creates an instance of `WebsocketSecureTransport`. Messages exceeding `max_payload`
+    /// bytes are rejected while being read.
     pub fn new(
         base_url: Url,
-        tls_config: Option<TlsConnector>,
+        tls_config: Option<TlsConfig>,
         headers: Option<HeaderMap>,
+        timeouts: Timeouts,
+        proxy: Option<Proxy>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        max_payload: Option<usize>,
     ) -> Result<Self> {
-        let runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()?;
+        let mut url = base_url;
+        url.query_pairs_mut().append_pair("transport", "websocket");
+        url.set_scheme("wss").unwrap();
+
+        let mut req = url.clone().into_client_request()?;
+        if let Some(map) = headers {
+            req.headers_mut().extend(map);
+        }
 
-        let inner = runtime.block_on(AsyncWebsocketSecureTransport::new(
-            base_url, tls_config, headers,
-        ))?;
+        let (socket, control) = connect(
+            req,
+            &url,
+            tls_config,
+            timeouts,
+            proxy.as_ref(),
+            cookie_jar.as_deref(),
+            max_payload,
+        )?;
 
         Ok(WebsocketSecureTransport {
-            runtime: Arc::new(runtime),
-            inner: Arc::new(inner),
+            inner: WebsocketGeneralTransport::new(socket, control, timeouts),
+            base_url: Arc::new(RwLock::new(url)),
         })
     }
 
+    /// Sends probe packet to ensure connection is valid
+    pub(crate) fn probe(&self) -> Result<()> {
+        self.inner.probe()
+    }
+
     /// Sends probe packet to ensure connection is valid, then sends upgrade
     /// request
     pub(crate) fn upgrade(&self) -> Result<()> {
-        self.runtime.block_on(async { self.inner.upgrade().await })
+        self.inner.upgrade()
     }
 }
 
 impl Transport for WebsocketSecureTransport {
     fn emit(&self, data: Bytes, is_binary_att: bool) -> Result<()> {
-        self.runtime
-            .block_on(async { self.inner.emit(data, is_binary_att).await })
+        self.inner.emit(data, is_binary_att)
     }
 
     fn poll(&self) -> Result<Bytes> {
-        self.runtime.block_on(async {
-            let r = self.inner.poll_next().await;
-            match r {
-                Ok(b) => b.ok_or(Error::IncompletePacket()),
-                Err(_) => Err(Error::IncompletePacket()),
-            }
-        })
+        self.inner.poll(None)
+    }
+
+    fn poll_timeout(&self, timeout: Option<Duration>) -> Result<Bytes> {
+        self.inner.poll(timeout)
     }
 
     fn base_url(&self) -> Result<url::Url> {
-        self.runtime.block_on(async { self.inner.base_url().await })
+        Ok(self.base_url.read()?.clone())
     }
 
-    fn set_base_url(&self, url: url::Url) -> Result<()> {
-        self.runtime
-            .block_on(async { self.inner.set_base_url(url).await })
+    fn set_base_url(&self, base_url: url::Url) -> Result<()> {
+        let mut url = base_url;
+        if !url
+            .query_pairs()
+            .any(|(k, v)| k == "transport" && v == "websocket")
+        {
+            url.query_pairs_mut().append_pair("transport", "websocket");
+        }
+        url.set_scheme("wss").unwrap();
+        *self.base_url.write()? = url;
+        Ok(())
     }
 }
 
@@ -96,6 +115,10 @@ mod test {
             Url::from_str(&url[..])?,
             Some(crate::test::tls_connector()?),
             None,
+            Timeouts::default(),
+            None,
+            None,
+            None,
         )
     }
 
@@ -109,16 +132,14 @@ mod test {
             .append_pair("transport", "websocket");
         url.set_scheme("wss").unwrap();
         assert_eq!(transport.base_url()?.to_string(), url.to_string());
-        transport.set_base_url(reqwest::Url::parse("https://127.0.0.1")?)?;
+        transport.set_base_url(Url::parse("https://127.0.0.1")?)?;
         assert_eq!(
             transport.base_url()?.to_string(),
             "wss://127.0.0.1/?transport=websocket"
         );
         assert_ne!(transport.base_url()?.to_string(), url.to_string());
 
-        transport.set_base_url(reqwest::Url::parse(
-            "http://127.0.0.1/?transport=websocket",
-        )?)?;
+        transport.set_base_url(Url::parse("http://127.0.0.1/?transport=websocket")?)?;
         assert_eq!(
             transport.base_url()?.to_string(),
             "wss://127.0.0.1/?transport=websocket"
@@ -0,0 +1,44 @@
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rust_socketio::packet::Packet;
+use std::convert::TryFrom;
+
+fn event_payload() -> Bytes {
+    Bytes::from(format!(
+        "2/admin,456[\"update\",{{\"id\":1,\"name\":\"{}\",\"tags\":[\"a\",\"b\",\"c\"]}}]",
+        "x".repeat(1024)
+    ))
+}
+
+fn binary_event_payload() -> Bytes {
+    Bytes::from_static(b"51-/admin,456[\"upload\",{\"_placeholder\":true,\"num\":0}]")
+}
+
+pub fn criterion_decode_event(c: &mut Criterion) {
+    let payload = event_payload();
+    c.bench_function("socket io decode event", |b| {
+        b.iter(|| Packet::try_from(black_box(&payload)).unwrap())
+    });
+}
+
+pub fn criterion_decode_binary_event(c: &mut Criterion) {
+    let payload = binary_event_payload();
+    c.bench_function("socket io decode binary event", |b| {
+        b.iter(|| Packet::try_from(black_box(&payload)).unwrap())
+    });
+}
+
+pub fn criterion_decode_connect(c: &mut Criterion) {
+    let payload = Bytes::from_static(b"0/admin,{\"sid\":\"oSO0OpakMV_3jnilAAAA\"}");
+    c.bench_function("socket io decode connect", |b| {
+        b.iter(|| Packet::try_from(black_box(&payload)).unwrap())
+    });
+}
+
+criterion_group!(
+    benches,
+    criterion_decode_event,
+    criterion_decode_binary_event,
+    criterion_decode_connect
+);
+criterion_main!(benches);
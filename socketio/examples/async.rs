@@ -16,6 +16,7 @@ async fn main() {
             match payload {
                 Payload::String(str) => println!("Received: {}", str),
                 Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+                _ => {}
             }
             socket
                 .emit("test", json!({"got ack": true}))
@@ -1,7 +1,7 @@
 use rust_socketio::{ClientBuilder, Event, Payload, RawClient};
 use serde_json::json;
 
-fn handle_foo(payload: Payload, socket: RawClient) -> () {
+fn handle_foo(payload: Payload, socket: RawClient) {
     socket.emit("bar", payload).expect("Server unreachable")
 }
 
@@ -13,6 +13,7 @@ fn main() {
         match payload {
             Payload::String(str) => println!("Received string: {}", str),
             Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+            _ => {}
         }
         socket
             .emit("test", json!({"got ack": true}))
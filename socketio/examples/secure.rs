@@ -1,26 +1,17 @@
-use native_tls::Certificate;
-use native_tls::TlsConnector;
-use rust_socketio::ClientBuilder;
-use std::fs::File;
-use std::io::Read;
+use rust_socketio::{ClientBuilder, TlsConfig};
 
 fn main() {
     // In case a trusted CA is needed that isn't in the trust chain.
     let cert_path = "ca.crt";
-    let mut cert_file = File::open(cert_path).expect("Failed to open cert");
-    let mut buf = vec![];
-    cert_file
-        .read_to_end(&mut buf)
-        .expect("Failed to read cert");
-    let cert: Certificate = Certificate::from_pem(&buf[..]).unwrap();
+    let cert = std::fs::read(cert_path).expect("Failed to read cert");
 
-    let tls_connector = TlsConnector::builder()
-        .add_root_certificate(cert)
+    let tls_config = TlsConfig::builder()
+        .add_root_certificate(&cert)
         .build()
-        .expect("Failed to build TLS Connector");
+        .expect("Failed to build TLS config");
 
     let socket = ClientBuilder::new("https://localhost:4200")
-        .tls_config(tls_connector)
+        .tls_config(tls_config)
         // Not strictly required for HTTPS
         .opening_header("HOST", "localhost")
         .on("error", |err, _| eprintln!("Error: {:#?}", err))
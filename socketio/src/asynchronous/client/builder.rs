@@ -1,18 +1,23 @@
-use futures_util::{future::BoxFuture, StreamExt};
-use log::trace;
-use native_tls::TlsConnector;
+use futures_util::future::BoxFuture;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use rust_engineio::TlsConfig;
 use rust_engineio::{
-    asynchronous::ClientBuilder as EngineIoClientBuilder,
+    asynchronous::{runtime::default_runtime, ClientBuilder as EngineIoClientBuilder, Runtime},
     header::{HeaderMap, HeaderValue},
+    recorder::{Frame, Recorder},
+    CookieJar, Proxy, Timeouts,
 };
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
-use crate::{error::Result, Error, Event, Payload, TransportType};
+use crate::{error::Result, ErrorEvent, Event, Payload, TransportType};
 
 use super::{
-    callback::{Callback, DynAsyncAnyCallback, DynAsyncCallback},
+    callback::{Callback, DynAsyncAnyCallback, DynAsyncCallback, DynAsyncErrorCallback},
     client::Client,
+    dispatch::{Dispatcher, HandlerOrder},
 };
 use crate::asynchronous::socket::Socket as InnerSocket;
 
@@ -24,11 +29,27 @@ pub struct ClientBuilder {
     address: String,
     on: HashMap<Event, Callback<DynAsyncCallback>>,
     on_any: Option<Callback<DynAsyncAnyCallback>>,
+    on_error: Option<Callback<DynAsyncErrorCallback>>,
     namespace: String,
-    tls_config: Option<TlsConnector>,
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    tls_config: Option<TlsConfig>,
     opening_headers: Option<HeaderMap>,
+    opening_headers_fn: Option<Arc<dyn Fn() -> BoxFuture<'static, HeaderMap> + Send + Sync>>,
     transport_type: TransportType,
     auth: Option<serde_json::Value>,
+    auth_fn: Option<Arc<dyn Fn() -> BoxFuture<'static, serde_json::Value> + Send + Sync>>,
+    timeouts: Timeouts,
+    proxy: Option<Proxy>,
+    cookie_jar: Option<Arc<CookieJar>>,
+    query_params: Vec<(String, String)>,
+    path: Option<String>,
+    timestamp_requests: Option<bool>,
+    timestamp_param: Option<String>,
+    recorder: Option<Recorder>,
+    replay: Option<Vec<Frame>>,
+    runtime: Option<Arc<dyn Runtime>>,
+    handler_order: HandlerOrder,
+    max_concurrent_handlers: Option<usize>,
 }
 
 impl ClientBuilder {
@@ -50,6 +71,7 @@ impl ClientBuilder {
     ///             match payload {
     ///                 Payload::String(str) => println!("Received: {}", str),
     ///                 Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+    ///                 _ => {}
     ///             }
     ///         }.boxed()
     ///     };
@@ -74,11 +96,27 @@ impl ClientBuilder {
             address: address.into(),
             on: HashMap::new(),
             on_any: None,
+            on_error: None,
             namespace: "/".to_owned(),
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
             tls_config: None,
             opening_headers: None,
+            opening_headers_fn: None,
             transport_type: TransportType::Any,
             auth: None,
+            auth_fn: None,
+            timeouts: Timeouts::default(),
+            proxy: None,
+            cookie_jar: None,
+            query_params: Vec::new(),
+            path: None,
+            timestamp_requests: None,
+            timestamp_param: None,
+            recorder: None,
+            replay: None,
+            runtime: None,
+            handler_order: HandlerOrder::default(),
+            max_concurrent_handlers: None,
         }
     }
 
@@ -90,7 +128,7 @@ impl ClientBuilder {
         let mut nsp = namespace.into();
         if !nsp.starts_with('/') {
             nsp = "/".to_owned() + &nsp;
-            trace!("Added `/` to the given namespace: {}", nsp);
+            event!(trace; "added `/` to the given namespace: {}", nsp);
         }
         self.namespace = nsp;
         self
@@ -114,6 +152,7 @@ impl ClientBuilder {
     ///                 match payload {
     ///                        Payload::String(str) => println!("Received: {}", str),
     ///                       Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+    ///                       _ => {}
     ///                 }
     ///             }
     ///             .boxed()
@@ -148,6 +187,7 @@ impl ClientBuilder {
     ///                 match payload {
     ///                        Payload::String(str) => println!("Received: {}", str),
     ///                       Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+    ///                       _ => {}
     ///                 }
     ///             }
     ///             .boxed() // <-- this makes sure we end up with a `BoxFuture<_>`
@@ -204,31 +244,64 @@ impl ClientBuilder {
         self
     }
 
-    /// Uses a preconfigured TLS connector for secure communication. This configures
-    /// both the `polling` as well as the `websocket` transport type.
+    /// Registers a Callback for errors, which receives an [`ErrorEvent`]
+    /// telling e.g. a transport failure from a rejection by the server. The
+    /// callbacks of [`Event::Error`] keep receiving the message of the error.
+    ///
     /// # Example
     /// ```rust
-    /// use rust_socketio::{asynchronous::ClientBuilder, Payload};
-    /// use native_tls::TlsConnector;
+    /// use rust_socketio::{asynchronous::ClientBuilder, ErrorKind};
     /// use futures_util::future::FutureExt;
     ///
     /// #[tokio::main]
     /// async fn main() {
-    ///     let tls_connector =  TlsConnector::builder()
-    ///                .use_sni(true)
+    ///     let client = ClientBuilder::new("http://localhost:4200/")
+    ///         .on_error(|error, _client| {
+    ///             async move {
+    ///                 if error.kind == ErrorKind::ConnectError {
+    ///                     eprintln!("Rejected by the server: {:?}", error.data);
+    ///                 }
+    ///             }.boxed()
+    ///         })
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn on_error<F>(mut self, callback: F) -> Self
+    where
+        F: for<'a> FnMut(ErrorEvent, Client) -> BoxFuture<'static, ()> + 'static + Send + Sync,
+    {
+        self.on_error = Some(Callback::<DynAsyncErrorCallback>::new(callback));
+        self
+    }
+
+    /// Uses a preconfigured TLS configuration for secure communication. This configures
+    /// both the `polling` as well as the `websocket` transport type. Besides a
+    /// [`TlsConfig`], a `native_tls::TlsConnector` or a `rustls::ClientConfig`
+    /// is accepted, depending on the enabled TLS backend. Available with the
+    /// `native-tls` or `rustls` feature.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{asynchronous::ClientBuilder, Payload, TlsConfig};
+    /// use futures_util::future::FutureExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tls_config = TlsConfig::builder()
     ///                .build()
     ///             .expect("Found illegal configuration");
     ///
     ///     let socket = ClientBuilder::new("http://localhost:4200/")
     ///         .namespace("/admin")
     ///         .on("error", |err, _| async move { eprintln!("Error: {:#?}", err) }.boxed())
-    ///         .tls_config(tls_connector)
+    ///         .tls_config(tls_config)
     ///         .connect()
     ///         .await;
     /// }
     /// ```
-    pub fn tls_config(mut self, tls_config: TlsConnector) -> Self {
-        self.tls_config = Some(tls_config);
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub fn tls_config<T: Into<TlsConfig>>(mut self, tls_config: T) -> Self {
+        self.tls_config = Some(tls_config.into());
         self
     }
 
@@ -264,6 +337,39 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets a closure producing http headers for the opening request, e.g. to
+    /// send short-lived credentials. It's called on every connect and its
+    /// headers take precedence over the ones set with
+    /// [`ClientBuilder::opening_header`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    /// use rust_engineio::header::HeaderMap;
+    /// use futures_util::future::FutureExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .opening_headers_with(|| {
+    ///             async {
+    ///                 let mut headers = HeaderMap::default();
+    ///                 headers.insert("authorization", "Bearer fresh-token");
+    ///                 headers
+    ///             }
+    ///             .boxed()
+    ///         })
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn opening_headers_with<F>(mut self, headers: F) -> Self
+    where
+        F: Fn() -> BoxFuture<'static, HeaderMap> + 'static + Send + Sync,
+    {
+        self.opening_headers_fn = Some(Arc::new(headers));
+        self
+    }
+
     /// Sets authentification data sent in the opening request.
     /// # Example
     /// ```rust
@@ -287,6 +393,228 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets a closure producing the data sent in the opening request. It's
+    /// called on every connect and takes precedence over
+    /// [`ClientBuilder::auth`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    /// use serde_json::json;
+    /// use futures_util::future::FutureExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4204/")
+    ///         .auth_with(|| async { json!({ "token": "fresh-token" }) }.boxed())
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn auth_with<F>(mut self, auth: F) -> Self
+    where
+        F: Fn() -> BoxFuture<'static, serde_json::Value> + 'static + Send + Sync,
+    {
+        self.auth_fn = Some(Arc::new(auth));
+        self
+    }
+
+    /// Connects through an http (`CONNECT`) or SOCKS5 proxy. This configures
+    /// both the `polling` as well as the `websocket` transport type. Use
+    /// [`Proxy::from_env`] to honor the `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `NO_PROXY` environment variables.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{asynchronous::ClientBuilder, Proxy};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let proxy = Proxy::all("socks5://proxy.local:1080")
+    ///         .expect("invalid proxy url")
+    ///         .basic_auth("user", "secret");
+    ///
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .proxy(proxy)
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Shares cookies set by the server between the handshake, the `polling`
+    /// requests and the `websocket` upgrade, as required by load balancers with
+    /// sticky sessions.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{asynchronous::ClientBuilder, CookieJar};
+    /// use std::sync::Arc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .cookie_jar(Arc::new(CookieJar::default()))
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn cookie_jar(mut self, cookie_jar: Arc<CookieJar>) -> Self {
+        self.cookie_jar = Some(cookie_jar);
+        self
+    }
+
+    /// Sets the runtime the client spawns its polling loop and callbacks on.
+    /// Defaults to the `tokio` runtime the client is connected from or to the
+    /// runtime of the enabled `async-std` or `smol` feature.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::{ClientBuilder, TokioRuntime};
+    /// use std::sync::Arc;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .runtime(Arc::new(TokioRuntime::current()))
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn runtime(mut self, runtime: Arc<dyn Runtime>) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Sets in which order the futures returned by the callbacks run. By
+    /// default each one is spawned on the runtime as soon as its packet
+    /// arrived, so a slow callback delays neither the others nor the polling.
+    /// Ordered callbacks queue up behind the running one, a full queue of 64
+    /// holds up the polling until it has room again.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::{ClientBuilder, HandlerOrder};
+    /// use futures_util::FutureExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         // the "message" callbacks run one after another
+    ///         .handler_order(HandlerOrder::PerEvent)
+    ///         .on("message", |payload, _| async move { println!("{:?}", payload) }.boxed())
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn handler_order(mut self, order: HandlerOrder) -> Self {
+        self.handler_order = order;
+        self
+    }
+
+    /// Limits how many futures returned by the callbacks run at once, they are
+    /// unlimited by default.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .max_concurrent_handlers(16)
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn max_concurrent_handlers(mut self, limit: usize) -> Self {
+        self.max_concurrent_handlers = Some(limit);
+        self
+    }
+
+    /// Appends a query parameter to the handshake, `polling` and `websocket`
+    /// urls. It is kept along with the parameters used by the protocol.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .path("/realtime/")
+    ///         .query_param("token", "secret")
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn query_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.query_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the path of the `socket.io` endpoint, overriding the path of the
+    /// address. Defaults to `/socket.io/` if the address has no path.
+    pub fn path<T: Into<String>>(mut self, path: T) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets whether `polling` requests carry a cache busting timestamp, which is
+    /// enabled by default.
+    pub fn timestamp_requests(mut self, timestamp_requests: bool) -> Self {
+        self.timestamp_requests = Some(timestamp_requests);
+        self
+    }
+
+    /// Sets the name of the timestamp query parameter, `t` by default.
+    pub fn timestamp_param<T: Into<String>>(mut self, param: T) -> Self {
+        self.timestamp_param = Some(param.into());
+        self
+    }
+
+    /// Sets the maximum time for establishing the connection to the server, for
+    /// both the `polling` and the `websocket` transport type (including the
+    /// websocket upgrade request). Exceeding it fails the connection attempt
+    /// with [`rust_engineio::Error::ConnectTimeout`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let result = ClientBuilder::new("http://localhost:4200/")
+    ///         .connect_timeout(Duration::from_secs(5))
+    ///         .handshake_timeout(Duration::from_secs(10))
+    ///         .connect()
+    ///         .await;
+    /// }
+    /// ```
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for the server to answer the engine.io
+    /// handshake. Exceeding it fails with [`rust_engineio::Error::HandshakeTimeout`].
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.handshake = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time for a single outgoing http request of the `polling`
+    /// transport. Exceeding it fails with [`rust_engineio::Error::RequestTimeout`].
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for data from the server once connected.
+    /// Exceeding it fails with [`rust_engineio::Error::ReadTimeout`]. As the
+    /// server may stay silent between heartbeats, this should be larger than the
+    /// sum of its `pingInterval` and `pingTimeout`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+
     /// Specifies which EngineIO [`TransportType`] to use.
     ///
     /// # Example
@@ -309,10 +637,46 @@ impl ClientBuilder {
         self
     }
 
+    /// Records the engine.io packets the client sends and receives with
+    /// `recorder`, see [`rust_engineio::recorder`] for the format.
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Replays the packets received in a recording instead of connecting to
+    /// the server, the sent packets are discarded. The client disconnects
+    /// once the recording ends.
+    /// # Example
+    /// ```rust,no_run
+    /// use futures_util::FutureExt;
+    /// use rust_engineio::recorder::Frame;
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    /// use std::{fs::File, io::BufReader};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let frames = Frame::read_all(BufReader::new(File::open("session.jsonl")?))?;
+    ///     let socket = ClientBuilder::new("http://localhost:4200/")
+    ///         .on("update", |payload, _| async move { println!("Received: {:?}", payload) }.boxed())
+    ///         .replay(frames)
+    ///         .connect()
+    ///         .await?;
+    ///     socket.join().await;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn replay(mut self, frames: Vec<Frame>) -> Self {
+        self.replay = Some(frames);
+        self
+    }
+
     /// Connects the socket to a certain endpoint. This returns a connected
     /// [`Client`] instance. This method returns an [`std::result::Result::Err`]
     /// value if something goes wrong during connection. Also starts a separate
-    /// thread to start polling for packets. Used with callbacks.
+    /// task to start polling for packets. Used with callbacks. The task stops
+    /// once the client disconnects or the last handle to it is dropped, which
+    /// disconnects it as well, see [`Client::join`].
     /// # Example
     /// ```rust
     /// use rust_socketio::{asynchronous::ClientBuilder, Payload};
@@ -336,26 +700,16 @@ impl ClientBuilder {
     ///     assert!(result.is_ok());
     /// }
     /// ```
-    pub async fn connect(self) -> Result<Client> {
-        let socket = self.connect_manual().await?;
-        let socket_clone = socket.clone();
-
-        // Use thread to consume items in iterator in order to call callbacks
-        tokio::runtime::Handle::current().spawn(async move {
-            let mut stream = socket_clone.as_stream();
-            loop {
-                // tries to restart a poll cycle whenever a 'normal' error occurs,
-                // it just logs on network errors, in case the poll cycle returned
-                // `Result::Ok`, the server receives a close frame so it's safe to
-                // terminate
-                if let Some(e @ Err(Error::IncompleteResponseFromEngineIo(_))) = stream.next().await
-                {
-                    trace!("Network error occured: {}", e.unwrap_err());
-                }
-            }
-        });
+    pub async fn connect(mut self) -> Result<Client> {
+        let runtime = match self.runtime.take() {
+            Some(runtime) => runtime,
+            None => default_runtime()?,
+        };
+        self.runtime = Some(runtime.clone());
 
-        Ok(socket)
+        // Use a task to consume items in the stream in order to call callbacks
+        let socket = self.connect_manual().await?;
+        Ok(socket.spawn_polling(runtime))
     }
 
     //TODO: 0.3.X stabilize
@@ -363,37 +717,203 @@ impl ClientBuilder {
         // Parse url here rather than in new to keep new returning Self.
         let mut url = Url::parse(&self.address)?;
 
-        if url.path() == "/" {
+        if let Some(path) = &self.path {
+            url.set_path(path);
+        } else if url.path() == "/" {
             url.set_path("/socket.io/");
         }
 
         let mut builder = EngineIoClientBuilder::new(url);
 
+        for (key, value) in &self.query_params {
+            builder = builder.query_param(key, value);
+        }
+        if let Some(timestamp_requests) = self.timestamp_requests {
+            builder = builder.timestamp_requests(timestamp_requests);
+        }
+        if let Some(param) = self.timestamp_param {
+            builder = builder.timestamp_param(param);
+        }
+
+        #[cfg(any(feature = "native-tls", feature = "rustls"))]
         if let Some(tls_config) = self.tls_config {
             builder = builder.tls_config(tls_config);
         }
-        if let Some(headers) = self.opening_headers {
+        let mut opening_headers = self.opening_headers;
+        if let Some(opening_headers_fn) = &self.opening_headers_fn {
+            let map = opening_headers.get_or_insert_with(HeaderMap::default);
+            for (key, value) in opening_headers_fn().await {
+                map.insert(key, value);
+            }
+        }
+        if let Some(headers) = opening_headers {
             builder = builder.headers(headers);
         }
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(cookie_jar) = self.cookie_jar {
+            builder = builder.cookie_jar(cookie_jar);
+        }
+        // without a runtime the callbacks are awaited by the stream
+        let dispatcher = Dispatcher::new(
+            self.runtime.clone(),
+            self.handler_order,
+            self.max_concurrent_handlers,
+        );
+        if let Some(runtime) = self.runtime {
+            builder = builder.runtime(runtime);
+        }
+        if let Some(timeout) = self.timeouts.connect {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.handshake {
+            builder = builder.handshake_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.request {
+            builder = builder.request_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.read {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(recorder) = self.recorder {
+            builder = builder.recorder(recorder);
+        }
 
-        let engine_client = match self.transport_type {
-            TransportType::Any => builder.build_with_fallback().await?,
-            TransportType::Polling => builder.build_polling().await?,
-            TransportType::Websocket => builder.build_websocket().await?,
-            TransportType::WebsocketUpgrade => builder.build_websocket_with_upgrade().await?,
+        let engine_client = match (self.replay, self.transport_type) {
+            (Some(frames), _) => builder.build_replay(frames)?,
+            (None, TransportType::Any) => builder.build_with_fallback().await?,
+            (None, TransportType::Polling) => builder.build_polling().await?,
+            (None, TransportType::Websocket) => builder.build_websocket().await?,
+            (None, TransportType::WebsocketUpgrade) => {
+                builder.build_websocket_with_upgrade().await?
+            }
         };
 
-        let inner_socket = InnerSocket::new(engine_client)?;
+        let inner_socket = InnerSocket::new(engine_client, Arc::default())?;
+
+        let auth = match &self.auth_fn {
+            Some(auth_fn) => Some(auth_fn().await),
+            None => self.auth,
+        };
 
         let socket = Client::new(
             inner_socket,
             &self.namespace,
             self.on,
             self.on_any,
-            self.auth,
+            self.on_error,
+            dispatcher,
+            auth,
         )?;
         socket.connect().await?;
 
         Ok(socket)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynchronous::TokioRuntime;
+    use crate::mock::MockServer;
+    use futures_util::FutureExt;
+    use serde_json::json;
+    use std::fs::File;
+    use std::io::BufReader;
+    use tokio::{task::spawn_blocking, time::timeout};
+
+    #[tokio::test]
+    async fn test_dynamic_headers_and_auth() -> Result<()> {
+        // the handshake, a pong and the connect packet
+        let (url, server) = crate::test::fake_polling_server(3);
+
+        let _client = ClientBuilder::new(url)
+            .transport_type(TransportType::Polling)
+            .opening_header("authorization", "Bearer static")
+            .opening_headers_with(|| {
+                async {
+                    let mut headers = HeaderMap::default();
+                    headers.insert("authorization", "Bearer fresh");
+                    headers
+                }
+                .boxed()
+            })
+            .auth(json!({ "token": "static" }))
+            .auth_with(|| async { json!({ "token": "fresh" }) }.boxed())
+            .connect_manual()
+            .await?;
+
+        let requests = tokio::task::spawn_blocking(|| server.join().unwrap())
+            .await
+            .unwrap();
+        assert!(requests[0].contains("authorization: Bearer fresh\r\n"));
+        assert!(requests[2].ends_with(r#"40{"token":"fresh"}"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_runtime() -> Result<()> {
+        // the handshake, a pong, the connect packet and a poll of the loop
+        let (url, server) = crate::test::fake_polling_server(4);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        // connects outside of any tokio context, so the polling loop can
+        // only be spawned on the passed runtime
+        let _client = futures_executor::block_on(
+            ClientBuilder::new(url)
+                .transport_type(TransportType::Polling)
+                .runtime(Arc::new(TokioRuntime::new(runtime.handle().clone())))
+                .connect(),
+        )?;
+
+        let requests = server.join().unwrap();
+        assert!(requests[3].starts_with("GET"));
+        assert!(requests[3].contains("sid=abc"));
+        runtime.shutdown_background();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<()> {
+        const TIMEOUT: Duration = Duration::from_secs(5);
+        let path =
+            std::env::temp_dir().join(format!("socketio-async-{}.jsonl", std::process::id()));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let builder = |address: String| {
+            let sender = sender.clone();
+            ClientBuilder::new(address)
+                .transport_type(TransportType::Polling)
+                .on("update", move |payload, _| {
+                    sender.send(payload).unwrap();
+                    async {}.boxed()
+                })
+        };
+
+        let server = Arc::new(MockServer::builder().build()?);
+        let client = builder(server.url().to_string())
+            .recorder(Recorder::create(&path)?)
+            .connect()
+            .await?;
+        let accepting = server.clone();
+        spawn_blocking(move || {
+            let socket = accepting.accept(TIMEOUT).unwrap();
+            socket.emit("update", json!(1)).unwrap();
+        })
+        .await
+        .unwrap();
+        let received = timeout(TIMEOUT, receiver.recv()).await.unwrap();
+        client.disconnect().await?;
+        drop(server);
+
+        // the replayed client receives the same event without a server
+        let frames = Frame::read_all(BufReader::new(File::open(&path)?))?;
+        std::fs::remove_file(&path)?;
+        let _client = builder("http://localhost:1".to_owned())
+            .replay(frames)
+            .connect()
+            .await?;
+        assert_eq!(timeout(TIMEOUT, receiver.recv()).await.unwrap(), received);
+        Ok(())
+    }
+}
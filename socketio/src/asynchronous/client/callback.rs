@@ -4,7 +4,7 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
-use crate::{Event, Payload};
+use crate::{ErrorEvent, Event, Payload};
 
 use super::client::Client;
 
@@ -16,6 +16,9 @@ pub(crate) type DynAsyncAnyCallback = Box<
     dyn for<'a> FnMut(Event, Payload, Client) -> BoxFuture<'static, ()> + 'static + Send + Sync,
 >;
 
+pub(crate) type DynAsyncErrorCallback =
+    Box<dyn for<'a> FnMut(ErrorEvent, Client) -> BoxFuture<'static, ()> + 'static + Send + Sync>;
+
 pub(crate) struct Callback<T> {
     inner: T,
 }
@@ -77,3 +80,29 @@ impl Callback<DynAsyncAnyCallback> {
         }
     }
 }
+
+impl Deref for Callback<DynAsyncErrorCallback> {
+    type Target =
+        dyn for<'a> FnMut(ErrorEvent, Client) -> BoxFuture<'static, ()> + 'static + Sync + Send;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.as_ref()
+    }
+}
+
+impl DerefMut for Callback<DynAsyncErrorCallback> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner.as_mut()
+    }
+}
+
+impl Callback<DynAsyncErrorCallback> {
+    pub(crate) fn new<T>(callback: T) -> Self
+    where
+        T: for<'a> FnMut(ErrorEvent, Client) -> BoxFuture<'static, ()> + 'static + Sync + Send,
+    {
+        Callback {
+            inner: Box::new(callback),
+        }
+    }
+}
@@ -1,9 +1,10 @@
-use std::{collections::HashMap, ops::DerefMut, pin::Pin, sync::Arc};
+use std::{collections::HashMap, ops::DerefMut, panic::AssertUnwindSafe, pin::Pin, sync::Arc};
 
-use futures_util::{future::BoxFuture, stream, Stream, StreamExt};
-use log::trace;
+use futures_util::{
+    future::{select, BoxFuture, Either},
+    stream, FutureExt, Stream, StreamExt,
+};
 use rand::{thread_rng, Rng};
-use serde_json::{from_str, Value};
 use tokio::{
     sync::RwLock,
     time::{Duration, Instant},
@@ -11,14 +12,24 @@ use tokio::{
 
 use super::{
     ack::Ack,
-    callback::{Callback, DynAsyncAnyCallback, DynAsyncCallback},
+    callback::{Callback, DynAsyncAnyCallback, DynAsyncCallback, DynAsyncErrorCallback},
+    dispatch::Dispatcher,
+    event_stream::{AckResponder, EventStream, ServerEvent, Subscribers},
+    lifecycle::{DropGuard, Lifecycle},
 };
 use crate::{
     asynchronous::socket::Socket as InnerSocket,
-    error::{Error, Result},
+    disconnect::DisconnectReason,
+    error::{Error, ErrorEvent, Result},
+    metrics::MetricsSnapshot,
     packet::{Packet, PacketId},
     Event, Payload,
 };
+use rust_engineio::asynchronous::{runtime::default_runtime, Runtime};
+
+/// How often [`Client::disconnect_gracefully`] checks for outstanding acks
+/// and packets.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
 
 /// A socket which handles communication with the server. It's initialized with
 /// a specific address as well as an optional namespace to connect to. If `None`
@@ -29,11 +40,19 @@ pub struct Client {
     socket: InnerSocket,
     on: Arc<RwLock<HashMap<Event, Callback<DynAsyncCallback>>>>,
     on_any: Arc<RwLock<Option<Callback<DynAsyncAnyCallback>>>>,
+    on_error: Arc<RwLock<Option<Callback<DynAsyncErrorCallback>>>>,
     outstanding_acks: Arc<RwLock<Vec<Ack>>>,
+    dispatcher: Arc<Dispatcher>,
+    events: Arc<Subscribers>,
     // namespace, for multiplexing messages
     nsp: String,
     // Data send in the opening packet (commonly used as for auth)
     auth: Option<serde_json::Value>,
+    /// The task polling for packets, if the client was connected with one.
+    lifecycle: Option<Arc<Lifecycle>>,
+    /// Held by the handles returned to the user, but not by the clones passed
+    /// to callbacks.
+    guard: Option<Arc<DropGuard>>,
 }
 
 impl Client {
@@ -46,6 +65,8 @@ impl Client {
         namespace: T,
         on: HashMap<Event, Callback<DynAsyncCallback>>,
         on_any: Option<Callback<DynAsyncAnyCallback>>,
+        on_error: Option<Callback<DynAsyncErrorCallback>>,
+        dispatcher: Dispatcher,
         auth: Option<serde_json::Value>,
     ) -> Result<Self> {
         Ok(Client {
@@ -53,16 +74,65 @@ impl Client {
             nsp: namespace.into(),
             on: Arc::new(RwLock::new(on)),
             on_any: Arc::new(RwLock::new(on_any)),
+            on_error: Arc::new(RwLock::new(on_error)),
             outstanding_acks: Arc::new(RwLock::new(Vec::new())),
+            dispatcher: Arc::new(dispatcher),
+            events: Arc::default(),
             auth,
+            lifecycle: None,
+            guard: None,
         })
     }
 
+    /// Spawns a task on `runtime` that polls for packets and calls the
+    /// callbacks, until the client disconnects or its last handle is dropped.
+    pub(crate) fn spawn_polling(mut self, runtime: Arc<dyn Runtime>) -> Self {
+        let lifecycle = Arc::new(Lifecycle::new(runtime.clone()));
+        self.lifecycle = Some(lifecycle.clone());
+        let socket = self.clone();
+        self.guard = Some(Arc::new(DropGuard(lifecycle.clone())));
+
+        runtime.spawn(lifecycle.clone().task(Box::pin(async move {
+            let mut stream = socket.as_stream();
+            loop {
+                match select(stream.next(), Box::pin(lifecycle.stopped())).await {
+                    // tries to restart a poll cycle whenever a 'normal' error occurs,
+                    // it just logs on network errors, in case the poll cycle returned
+                    // `Result::Ok`, the server receives a close frame so it's safe to
+                    // terminate
+                    Either::Left((Some(Err(error)), _)) => {
+                        if let Error::IncompleteResponseFromEngineIo(error) = &error {
+                            event!(error; "network error occurred: {}", error);
+                        }
+                        socket.handle_error(error).await;
+                    }
+                    Either::Left((Some(Ok(_)), _)) => (),
+                    // the connection closed
+                    Either::Left((None, _)) => {
+                        socket.events.close();
+                        break;
+                    }
+                    // the client stopped, the streams end on the disconnect
+                    Either::Right(_) => break,
+                }
+            }
+            drop(stream);
+            if lifecycle.disconnects() {
+                if let Err(error) = socket.disconnect().await {
+                    event!(debug, nsp = socket.nsp; "could not disconnect a dropped client: {}", error);
+                }
+            }
+            lifecycle.finish();
+        })));
+        self
+    }
+
     /// Connects the client to a server. Afterwards the `emit_*` methods can be
     /// called to interact with the server.
     pub(crate) async fn connect(&self) -> Result<()> {
         // Connect the underlying socket
         self.socket.connect().await?;
+        event!(debug, nsp = self.nsp; "connecting to namespace");
 
         // construct the opening packet
         let auth = self.auth.as_ref().map(|data| data.to_string());
@@ -75,9 +145,9 @@ impl Client {
 
     /// Sends a message to the server using the underlying `engine.io` protocol.
     /// This message takes an event, which could either be one of the common
-    /// events like "message" or "error" or a custom event like "foo". But be
-    /// careful, the data string needs to be valid JSON. It's recommended to use
-    /// a library like `serde_json` to serialize the data properly.
+    /// events like "message" or "error" or a custom event like "foo". A
+    /// [`Payload::String`] is sent as JSON if it is valid JSON, use
+    /// [`Payload::Json`] or [`Payload::Text`] to be explicit.
     ///
     /// # Example
     /// ```
@@ -115,7 +185,8 @@ impl Client {
     }
 
     /// Disconnects this client from the server by sending a `socket.io` closing
-    /// packet.
+    /// packet. The callbacks of [`Event::Close`] are passed
+    /// [`DisconnectReason::IoClientDisconnect`].
     /// # Example
     /// ```rust
     /// use rust_socketio::{asynchronous::{ClientBuilder, Client}, Payload};
@@ -148,21 +219,129 @@ impl Client {
     /// }
     /// ```
     pub async fn disconnect(&self) -> Result<()> {
+        // stops the task, which is awaited by `join`
+        if let Some(lifecycle) = &self.lifecycle {
+            lifecycle.stop(false);
+        }
         let disconnect_packet =
             Packet::new(PacketId::Disconnect, self.nsp.clone(), None, None, 0, None);
 
         self.socket.send(disconnect_packet).await?;
-        self.socket.disconnect().await?;
+        if self.socket.disconnect().await? {
+            self.close_callback(DisconnectReason::IoClientDisconnect)
+                .await;
+        }
+        self.events.close();
 
         Ok(())
     }
 
+    /// Waits up to `timeout` for the acks that did not time out yet, for the
+    /// packets that are not written yet and for running handlers, then disconnects like
+    /// [`Client::disconnect`].
+    pub async fn disconnect_gracefully(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        let runtime = self.runtime();
+        while !self.is_drained().await && Instant::now() < deadline {
+            match &runtime {
+                Some(runtime) => runtime.sleep(DRAIN_INTERVAL).await,
+                None => break,
+            }
+        }
+        self.disconnect().await
+    }
+
+    /// The runtime of the polling task or else the one passed to the builder
+    /// or the one of the current context, if any.
+    fn runtime(&self) -> Option<Arc<dyn Runtime>> {
+        match &self.lifecycle {
+            Some(lifecycle) => Some(lifecycle.runtime()),
+            None => self.dispatcher.runtime().or_else(|| default_runtime().ok()),
+        }
+    }
+
+    /// Whether no acks are awaited, all packets are written and all handlers
+    /// completed.
+    async fn is_drained(&self) -> bool {
+        let acks = self.outstanding_acks.read().await;
+        !acks
+            .iter()
+            .any(|ack| ack.time_started.elapsed() < ack.timeout)
+            && self.socket.metrics_snapshot().engine.buffered == 0
+            && self.dispatcher.is_idle()
+    }
+
+    /// Completes once the task polling for packets finished, after the
+    /// client disconnected or the connection closed. Completes at once for
+    /// clients without one and if awaited by the task itself. A handler
+    /// awaiting it holds up the handlers ordered after it, see
+    /// [`crate::asynchronous::ClientBuilder::handler_order`].
+    pub async fn join(&self) {
+        if let Some(lifecycle) = &self.lifecycle {
+            if !lifecycle.is_current() {
+                lifecycle.finished().await;
+            }
+        }
+    }
+
+    /// Calls the callbacks of [`Event::Close`] with `reason`.
+    async fn close_callback(&self, reason: DisconnectReason) {
+        if let Err(error) = self.callback(&Event::Close, reason).await {
+            event!(warn, nsp = self.nsp; "close callback failed: {}", error);
+        }
+    }
+
+    /// Returns a snapshot of the metrics of the client, including the ones of
+    /// the underlying engine.io connection. See [`crate::metrics`].
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.socket.metrics_snapshot()
+    }
+
+    /// Returns a stream of the events received from now on, including
+    /// [`Event::Connect`], [`Event::Close`] and [`Event::Error`]. It's usable
+    /// alongside or instead of the callbacks and ends once the client
+    /// disconnected or its connection closed.
+    /// # Example
+    /// ```rust,no_run
+    /// use rust_socketio::asynchronous::ClientBuilder;
+    /// use futures_util::StreamExt;
+    /// use serde_json::json;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let client = ClientBuilder::new("http://localhost:4200/")
+    ///         .connect()
+    ///         .await
+    ///         .expect("connection failed");
+    ///
+    ///     let mut events = client.events();
+    ///     while let Some(event) = events.next().await {
+    ///         println!("Received {:?}: {:?}", event.event, event.args);
+    ///         if let Some(ack) = event.ack {
+    ///             ack.send(json!({"received": true})).await.expect("Server unreachable");
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn events(&self) -> EventStream {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn nsp(&self) -> &str {
+        &self.nsp
+    }
+
+    /// Sends `packet`, e.g. the answer of an ack.
+    pub(crate) async fn send(&self, packet: Packet) -> Result<()> {
+        self.socket.send(packet).await
+    }
+
     /// Sends a message to the server but `alloc`s an `ack` to check whether the
     /// server responded in a given time span. This message takes an event, which
     /// could either be one of the common events like "message" or "error" or a
-    /// custom event like "foo", as well as a data parameter. But be careful,
-    /// in case you send a [`Payload::String`], the string needs to be valid JSON.
-    /// It's even recommended to use a library like serde_json to serialize the data properly.
+    /// custom event like "foo", as well as a data parameter. A
+    /// [`Payload::String`] is sent as JSON if it is valid JSON, use
+    /// [`Payload::Json`] or [`Payload::Text`] to be explicit.
     /// It also requires a timeout `Duration` in which the client needs to answer.
     /// If the ack is acked in the correct time span, the specified callback is
     /// called. The callback consumes a [`Payload`] which represents the data send
@@ -191,6 +370,7 @@ impl Client {
     ///             match message {
     ///                 Payload::String(str) => println!("{}", str),
     ///                 Payload::Binary(bytes) => println!("Received bytes: {:#?}", bytes),
+    ///                 _ => {}
     ///             }
     ///         }.boxed()
     ///     };    
@@ -231,71 +411,160 @@ impl Client {
         };
 
         // add the ack to the tuple of outstanding acks
-        self.outstanding_acks.write().await.push(ack);
+        let mut outstanding_acks = self.outstanding_acks.write().await;
+        outstanding_acks.push(ack);
+        self.socket
+            .metrics()
+            .set_pending_acks(outstanding_acks.len());
+        drop(outstanding_acks);
 
         self.socket.send(socket_packet).await
     }
 
+    /// Dispatches the handlers of `event`, see
+    /// [`crate::asynchronous::ClientBuilder::handler_order`]. The callbacks are
+    /// only locked to create their futures.
     async fn callback<P: Into<Payload>>(&self, event: &Event, payload: P) -> Result<()> {
-        let mut on = self.on.write().await;
-        let mut on_any = self.on_any.write().await;
-
-        let on_lock = on.deref_mut();
-        let on_any_lock = on_any.deref_mut();
-        let payload = payload.into();
+        self.event_callback(event, payload.into(), Vec::new(), None)
+            .await
+    }
 
-        if let Some(callback) = on_lock.get_mut(event) {
-            callback(payload.clone(), self.clone()).await;
+    /// Publishes `event` to the [`EventStream`]s and dispatches its handlers.
+    /// `args` and `ack` are the ones of a received event packet.
+    async fn event_callback(
+        &self,
+        event: &Event,
+        payload: Payload,
+        args: Vec<String>,
+        ack: Option<i32>,
+    ) -> Result<()> {
+        self.events.publish(ServerEvent {
+            event: event.clone(),
+            payload: payload.clone(),
+            args,
+            nsp: self.nsp.clone(),
+            ack: ack.map(|id| AckResponder::new(self.clone(), id)),
+        });
+        let mut handlers = Vec::new();
+
+        if let Some(callback) = self.on.write().await.get_mut(event) {
+            handlers.push(callback(payload.clone(), self.clone()));
         }
-
         // Call on_any for all common and custom events.
-        match event {
-            Event::Message | Event::Custom(_) => {
-                if let Some(callback) = on_any_lock {
-                    callback(event.clone(), payload, self.clone()).await;
-                }
+        if matches!(event, Event::Message | Event::Custom(_)) {
+            if let Some(callback) = self.on_any.write().await.deref_mut() {
+                handlers.push(callback(event.clone(), payload, self.clone()));
             }
-            _ => (),
         }
 
-        drop(on);
-        drop(on_any);
+        self.dispatch(Some(event), handlers).await;
         Ok(())
     }
 
+    /// Dispatches `handlers` as one, so they run one after another. A panic
+    /// of a handler is passed to the error callbacks.
+    async fn dispatch(&self, event: Option<&Event>, handlers: Vec<BoxFuture<'static, ()>>) {
+        if handlers.is_empty() {
+            return;
+        }
+        let client = self.clone();
+        let handler = async move {
+            for handler in handlers {
+                if let Err(panic) = AssertUnwindSafe(handler).catch_unwind().await {
+                    client.report(Error::callback_panicked(panic)).await;
+                }
+            }
+        };
+        self.dispatcher.dispatch(event, handler.boxed()).await;
+    }
+
+    /// Passes an error of a handler to the error callbacks. They run in place,
+    /// as dispatching them could wait for the handler itself, and their panics
+    /// are only logged.
+    async fn report(&self, error: Error) {
+        event!(warn, nsp = self.nsp; "handler failed: {}", error);
+        let handlers = self
+            .error_handlers(ErrorEvent::new(error, &self.nsp, None))
+            .await;
+        for handler in handlers {
+            if let Err(panic) = AssertUnwindSafe(handler).catch_unwind().await {
+                let error = Error::callback_panicked(panic);
+                event!(warn, nsp = self.nsp; "error callback failed: {}", error);
+            }
+        }
+    }
+
+    /// Calls the error callbacks with an error of the stream of
+    /// [`Client::as_stream`].
+    pub(crate) async fn handle_error(&self, error: Error) {
+        self.error_callback(ErrorEvent::new(error, &self.nsp, None))
+            .await
+    }
+
+    /// Calls the callbacks of [`Event::Error`] with the message of `error` and
+    /// the error callback with `error` itself.
+    async fn error_callback(&self, error: ErrorEvent) {
+        let handlers = self.error_handlers(error).await;
+        self.dispatch(Some(&Event::Error), handlers).await;
+    }
+
+    /// Publishes `error` and returns the futures of the error callbacks.
+    async fn error_handlers(&self, error: ErrorEvent) -> Vec<BoxFuture<'static, ()>> {
+        let message = error.to_string();
+        self.events.publish(ServerEvent {
+            event: Event::Error,
+            payload: Payload::Text(message.clone()),
+            args: vec![serde_json::Value::from(message).to_string()],
+            nsp: self.nsp.clone(),
+            ack: None,
+        });
+        let mut handlers = Vec::new();
+        if let Some(callback) = self.on.write().await.get_mut(&Event::Error) {
+            handlers.push(callback(Payload::Text(error.to_string()), self.clone()));
+        }
+        if let Some(callback) = self.on_error.write().await.deref_mut() {
+            handlers.push(callback(error, self.clone()));
+        }
+        handlers
+    }
+
     /// Handles the incoming acks and classifies what callbacks to call and how.
     #[inline]
     async fn handle_ack(&self, socket_packet: &Packet) -> Result<()> {
-        let mut to_be_removed = Vec::new();
         if let Some(id) = socket_packet.id {
-            for (index, ack) in self.outstanding_acks.write().await.iter_mut().enumerate() {
-                if ack.id == id {
-                    to_be_removed.push(index);
-
-                    if ack.time_started.elapsed() < ack.timeout {
-                        if let Some(ref payload) = socket_packet.data {
-                            ack.callback.deref_mut()(
-                                Payload::String(payload.to_owned()),
-                                self.clone(),
-                            )
-                            .await;
-                        }
-                        if let Some(ref attachments) = socket_packet.attachments {
-                            if let Some(payload) = attachments.get(0) {
-                                ack.callback.deref_mut()(
-                                    Payload::Binary(payload.to_owned()),
-                                    self.clone(),
-                                )
-                                .await;
-                            }
-                        }
-                    } else {
-                        trace!("Received an Ack that is now timed out (elapsed time was longer than specified duration)");
+            let mut outstanding_acks = self.outstanding_acks.write().await;
+            let (acks, pending): (Vec<Ack>, Vec<Ack>) =
+                outstanding_acks.drain(..).partition(|ack| ack.id == id);
+            *outstanding_acks = pending;
+            self.socket
+                .metrics()
+                .set_pending_acks(outstanding_acks.len());
+            drop(outstanding_acks);
+
+            for mut ack in acks {
+                let latency = ack.time_started.elapsed();
+                if latency >= ack.timeout {
+                    event!(debug, ack_id = id; "received an ack that timed out");
+                    self.socket.metrics().acked_late();
+                    continue;
+                }
+                self.socket.metrics().acked(latency);
+                let mut handlers = Vec::new();
+                if let Some(ref payload) = socket_packet.data {
+                    handlers.push(ack.callback.deref_mut()(
+                        Payload::String(payload.to_owned()),
+                        self.clone(),
+                    ));
+                }
+                if let Some(ref attachments) = socket_packet.attachments {
+                    if let Some(payload) = attachments.first() {
+                        handlers.push(ack.callback.deref_mut()(
+                            Payload::Binary(payload.to_owned()),
+                            self.clone(),
+                        ));
                     }
                 }
-            }
-            for index in to_be_removed {
-                self.outstanding_acks.write().await.remove(index);
+                self.dispatch(None, handlers).await;
             }
         }
         Ok(())
@@ -304,16 +573,21 @@ impl Client {
     /// Handles a binary event.
     #[inline]
     async fn handle_binary_event(&self, packet: &Packet) -> Result<()> {
-        let event = if let Some(string_data) = &packet.data {
-            string_data.replace('\"', "").into()
-        } else {
-            Event::Message
-        };
+        let event = packet
+            .data
+            .as_ref()
+            .and_then(|data| serde_json::from_str::<String>(data).ok())
+            .map_or(Event::Message, Event::from);
 
         if let Some(attachments) = &packet.attachments {
-            if let Some(binary_payload) = attachments.get(0) {
-                self.callback(&event, Payload::Binary(binary_payload.to_owned()))
-                    .await?;
+            if let Some(binary_payload) = attachments.first() {
+                self.event_callback(
+                    &event,
+                    Payload::Binary(binary_payload.to_owned()),
+                    Vec::new(),
+                    packet.id,
+                )
+                .await?;
             }
         }
         Ok(())
@@ -322,40 +596,9 @@ impl Client {
     /// A method that parses a packet and eventually calls the corresponding
     // callback with the supplied data.
     async fn handle_event(&self, packet: &Packet) -> Result<()> {
-        if packet.data.is_none() {
-            return Ok(());
-        }
-        let data = packet.data.as_ref().unwrap();
-
-        // a socketio message always comes in one of the following two flavors (both JSON):
-        // 1: `["event", "msg"]`
-        // 2: `["msg"]`
-        // in case 2, the message is ment for the default message event, in case 1 the event
-        // is specified
-        if let Ok(Value::Array(contents)) = from_str::<Value>(data) {
-            let (event, data) = if contents.len() > 1 {
-                // case 1
-                (
-                    contents
-                        .get(0)
-                        .map(|value| match value {
-                            Value::String(ev) => ev,
-                            _ => "message",
-                        })
-                        .unwrap_or("message")
-                        .into(),
-                    contents.get(1).ok_or(Error::IncompletePacket())?,
-                )
-            } else {
-                // case 2
-                (
-                    Event::Message,
-                    contents.get(0).ok_or(Error::IncompletePacket())?,
-                )
-            };
-
-            // call the correct callback
-            self.callback(&event, data.to_string()).await?;
+        if let Some((event, payload, args)) = packet.event_payload()? {
+            self.event_callback(&event, payload.into(), args, packet.id)
+                .await?;
         }
 
         Ok(())
@@ -370,35 +613,31 @@ impl Client {
             match packet.packet_type {
                 PacketId::Ack | PacketId::BinaryAck => {
                     if let Err(err) = self.handle_ack(packet).await {
-                        self.callback(&Event::Error, err.to_string()).await?;
-                        return Err(err);
+                        self.error_callback(ErrorEvent::new(err, &self.nsp, packet.data.clone()))
+                            .await;
                     }
                 }
                 PacketId::BinaryEvent => {
                     if let Err(err) = self.handle_binary_event(packet).await {
-                        self.callback(&Event::Error, err.to_string()).await?;
+                        self.error_callback(ErrorEvent::new(err, &self.nsp, packet.data.clone()))
+                            .await;
                     }
                 }
                 PacketId::Connect => {
                     self.callback(&Event::Connect, "").await?;
                 }
                 PacketId::Disconnect => {
-                    self.callback(&Event::Close, "").await?;
+                    self.callback(&Event::Close, DisconnectReason::IoServerDisconnect)
+                        .await?;
                 }
                 PacketId::ConnectError => {
-                    self.callback(
-                        &Event::Error,
-                        String::from("Received an ConnectError frame: ")
-                            + packet
-                                .data
-                                .as_ref()
-                                .unwrap_or(&String::from("\"No error message provided\"")),
-                    )
-                    .await?;
+                    self.error_callback(ErrorEvent::connect_error(&self.nsp, packet.data.clone()))
+                        .await;
                 }
                 PacketId::Event => {
                     if let Err(err) = self.handle_event(packet).await {
-                        self.callback(&Event::Error, err.to_string()).await?;
+                        self.error_callback(ErrorEvent::new(err, &self.nsp, packet.data.clone()))
+                            .await;
                     }
                 }
             }
@@ -406,23 +645,23 @@ impl Client {
         Ok(())
     }
 
-    /// Returns the packet stream for the client.
+    /// Returns the packet stream for the client. Its errors are returned
+    /// without calling the error callbacks, see [`Client::handle_error`].
     pub(crate) fn as_stream<'a>(
         &'a self,
     ) -> Pin<Box<dyn Stream<Item = Result<Packet>> + Send + 'a>> {
         stream::unfold(self.socket.clone(), |mut socket| async {
             // wait for the next payload
             let packet: Option<std::result::Result<Packet, Error>> = socket.next().await;
+            // the engine.io connection closed, for example as the server sent
+            // no ping in time
+            if let Some(reason) = socket.take_close_reason() {
+                self.close_callback(reason).await;
+            }
             match packet {
                 // end the stream if the underlying one is closed
                 None => None,
-                Some(Err(err)) => {
-                    // call the error callback
-                    match self.callback(&Event::Error, err.to_string()).await {
-                        Err(callback_err) => Some((Err(callback_err), socket)),
-                        Ok(_) => Some((Err(err), socket)),
-                    }
-                }
+                Some(Err(err)) => Some((Err(err), socket)),
                 Some(Ok(packet)) => match self.handle_socketio_packet(&packet).await {
                     Err(callback_err) => Some((Err(callback_err), socket)),
                     Ok(_) => Some((Ok(packet), socket)),
@@ -440,13 +679,14 @@ mod test {
 
     use bytes::Bytes;
     use futures_util::{FutureExt, StreamExt};
-    use native_tls::TlsConnector;
     use serde_json::json;
     use tokio::{
         sync::mpsc,
         time::{sleep, timeout},
     };
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    use crate::TlsConfig;
     use crate::{
         asynchronous::client::{builder::ClientBuilder, client::Client},
         error::Result,
@@ -464,6 +704,7 @@ mod test {
                     match msg {
                         Payload::String(str) => println!("Received string: {}", str),
                         Payload::Binary(bin) => println!("Received binary data: {:#?}", bin),
+                        _ => {}
                     }
                 }
                 .boxed()
@@ -549,6 +790,7 @@ mod test {
         Ok(())
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[tokio::test]
     async fn socket_io_builder_integration() -> Result<()> {
         let url = crate::test::socket_io_server();
@@ -556,14 +798,13 @@ mod test {
         // test socket build logic
         let socket_builder = ClientBuilder::new(url);
 
-        let tls_connector = TlsConnector::builder()
-            .use_sni(true)
+        let tls_config = TlsConfig::builder()
             .build()
             .expect("Found illegal configuration");
 
         let socket = socket_builder
             .namespace("/admin")
-            .tls_config(tls_connector)
+            .tls_config(tls_config)
             .opening_header("accept-encoding", "application/json")
             .on("test", |str, _| {
                 async move { println!("Received: {:#?}", str) }.boxed()
@@ -600,6 +841,7 @@ mod test {
         Ok(())
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[tokio::test]
     async fn socket_io_builder_integration_iterator() -> Result<()> {
         let url = crate::test::socket_io_server();
@@ -607,14 +849,13 @@ mod test {
         // test socket build logic
         let socket_builder = ClientBuilder::new(url);
 
-        let tls_connector = TlsConnector::builder()
-            .use_sni(true)
+        let tls_config = TlsConfig::builder()
             .build()
             .expect("Found illegal configuration");
 
         let socket = socket_builder
             .namespace("/admin")
-            .tls_config(tls_connector)
+            .tls_config(tls_config)
             .opening_header("accept-encoding", "application/json")
             .on("test", |str, _| {
                 async move { println!("Received: {:#?}", str) }.boxed()
@@ -751,6 +992,7 @@ mod test {
         test_socketio_socket(socket, "/".to_owned()).await
     }
 
+    #[allow(clippy::unnecessary_literal_unwrap)]
     async fn test_socketio_socket(socket: Client, nsp: String) -> Result<()> {
         // open packet
         let mut socket_stream = socket.as_stream();
@@ -0,0 +1,471 @@
+use futures_util::future::{BoxFuture, FutureExt};
+use rust_engineio::asynchronous::Runtime;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+use tokio::sync::{
+    mpsc::{self, Receiver, Sender},
+    Semaphore,
+};
+
+use crate::Event;
+
+/// The number of ordered handlers waiting for the running one, after which
+/// dispatching waits as well and so holds up the polling of the client.
+const QUEUE_CAPACITY: usize = 64;
+
+/// The queues of the ordered handlers by event, `None` for all of them.
+type Queues = HashMap<Option<Event>, Sender<BoxFuture<'static, ()>>>;
+
+/// In which order the handlers of a [`super::client::Client`] run, see
+/// [`crate::asynchronous::ClientBuilder::handler_order`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum HandlerOrder {
+    /// Every handler is spawned as soon as its packet arrived, so a slow
+    /// handler delays no other one.
+    #[default]
+    Unordered,
+    /// The handlers of one event run one after another, in the order the
+    /// packets arrived. The ones of different events and acks run
+    /// concurrently.
+    PerEvent,
+    /// All handlers run one after another, in the order the packets arrived.
+    Global,
+}
+
+/// Runs the futures returned by the handlers as configured by a
+/// [`HandlerOrder`] and a concurrency limit. Without a runtime, they are
+/// awaited in place.
+pub(crate) struct Dispatcher {
+    runtime: Option<Arc<dyn Runtime>>,
+    order: HandlerOrder,
+    limit: Option<Arc<Semaphore>>,
+    queues: Arc<Mutex<Queues>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(
+        runtime: Option<Arc<dyn Runtime>>,
+        order: HandlerOrder,
+        limit: Option<usize>,
+    ) -> Self {
+        Dispatcher {
+            runtime,
+            order,
+            limit: limit.map(|limit| Arc::new(Semaphore::new(limit.max(1)))),
+            queues: Arc::default(),
+            pending: Arc::default(),
+        }
+    }
+
+    /// Runs `handler`, after the ones dispatched before with the same `event`
+    /// if they are ordered. Handlers without an event, like the ones of acks,
+    /// are only ordered globally.
+    pub(crate) async fn dispatch(&self, event: Option<&Event>, handler: BoxFuture<'static, ()>) {
+        let runtime = match &self.runtime {
+            Some(runtime) => runtime,
+            None => return handler.await,
+        };
+        let mut handler = self.track(handler);
+        let key = match self.order {
+            HandlerOrder::Unordered => return runtime.spawn(handler),
+            HandlerOrder::PerEvent if event.is_none() => return runtime.spawn(handler),
+            HandlerOrder::PerEvent => event.cloned(),
+            HandlerOrder::Global => None,
+        };
+
+        loop {
+            let sender = {
+                let mut queues = lock(&self.queues);
+                match queues.get(&key) {
+                    Some(sender) => sender.clone(),
+                    None => {
+                        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+                        // a new queue has room for the first handler
+                        let _ = sender.try_send(handler);
+                        queues.insert(key.clone(), sender);
+                        return runtime.spawn(drain(self.queues.clone(), key, receiver));
+                    }
+                }
+            };
+            match sender.send(handler).await {
+                Ok(()) => return,
+                // the queue drained and was removed in the meantime
+                Err(error) => handler = error.0,
+            }
+            // or its task was cancelled before removing it
+            let mut queues = lock(&self.queues);
+            if queues
+                .get(&key)
+                .is_some_and(|queue| queue.same_channel(&sender))
+            {
+                queues.remove(&key);
+            }
+        }
+    }
+
+    /// Counts `handler` as pending until it completed and makes it wait for
+    /// the concurrency limit.
+    fn track(&self, handler: BoxFuture<'static, ()>) -> BoxFuture<'static, ()> {
+        let pending = Pending::new(self.pending.clone());
+        let limit = self.limit.clone();
+        async move {
+            let _permit = match limit {
+                Some(limit) => limit.acquire_owned().await.ok(),
+                None => None,
+            };
+            handler.await;
+            drop(pending);
+        }
+        .boxed()
+    }
+
+    /// The runtime the handlers are spawned on, if any.
+    pub(crate) fn runtime(&self) -> Option<Arc<dyn Runtime>> {
+        self.runtime.clone()
+    }
+
+    /// Whether all dispatched handlers completed.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.pending.load(Ordering::Acquire) == 0
+    }
+}
+
+impl Debug for Dispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field("order", &self.order)
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+/// Runs the handlers of the queue of `key` one after another and removes it
+/// once they all completed.
+fn drain(
+    queues: Arc<Mutex<Queues>>,
+    key: Option<Event>,
+    receiver: Receiver<BoxFuture<'static, ()>>,
+) -> BoxFuture<'static, ()> {
+    async move {
+        let mut queue = Queue {
+            queues,
+            key,
+            receiver,
+            drained: false,
+        };
+        loop {
+            let handler = match queue.receiver.try_recv() {
+                Ok(handler) => handler,
+                Err(_) => {
+                    // dispatching takes the lock to look up the queue
+                    let mut queues = lock(&queue.queues);
+                    match queue.receiver.try_recv() {
+                        Ok(handler) => handler,
+                        Err(_) => {
+                            queues.remove(&queue.key);
+                            queue.drained = true;
+                            return;
+                        }
+                    }
+                }
+            };
+            // the client reports the panics of its handlers, see
+            // `Client::dispatch`
+            let _ = AssertUnwindSafe(handler).catch_unwind().await;
+        }
+    }
+    .boxed()
+}
+
+/// A queue being drained, which is removed when dropped before it drained,
+/// i.e. if the task was cancelled.
+struct Queue {
+    queues: Arc<Mutex<Queues>>,
+    key: Option<Event>,
+    receiver: Receiver<BoxFuture<'static, ()>>,
+    drained: bool,
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        if !self.drained {
+            // the queue is still registered, as only draining removes it
+            lock(&self.queues).remove(&self.key);
+        }
+    }
+}
+
+fn lock(queues: &Mutex<Queues>) -> MutexGuard<'_, Queues> {
+    // a poisoned lock only means that another dispatch panicked
+    queues
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Decrements the count of pending handlers when dropped, also if the handler
+/// panicked or its task was cancelled.
+struct Pending(Arc<AtomicUsize>);
+
+impl Pending {
+    fn new(pending: Arc<AtomicUsize>) -> Self {
+        pending.fetch_add(1, Ordering::AcqRel);
+        Pending(pending)
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynchronous::{ClientBuilder, TokioRuntime};
+    use crate::{error::Result, mock::MockServer, TransportType};
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::{sync::oneshot, task::spawn_blocking, time::timeout};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn dispatcher(order: HandlerOrder, limit: Option<usize>) -> Dispatcher {
+        Dispatcher::new(Some(Arc::new(TokioRuntime::current())), order, limit)
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_order() {
+        let foo = Event::from("foo");
+        let bar = Event::from("bar");
+
+        // "bar" runs while "foo" is blocked, unless all handlers are ordered
+        for (order, concurrent) in [
+            (HandlerOrder::Unordered, true),
+            (HandlerOrder::PerEvent, true),
+            (HandlerOrder::Global, false),
+        ] {
+            let dispatcher = dispatcher(order, None);
+            let (unblock, blocked) = oneshot::channel::<()>();
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            dispatcher
+                .dispatch(Some(&foo), Box::pin(async move { blocked.await.unwrap() }))
+                .await;
+            for i in 0..10 {
+                let sender = sender.clone();
+                dispatcher
+                    .dispatch(Some(&bar), Box::pin(async move { sender.send(i).unwrap() }))
+                    .await;
+            }
+            let first = timeout(Duration::from_millis(200), receiver.recv()).await;
+            assert_eq!(first.is_ok(), concurrent);
+            assert!(!dispatcher.is_idle());
+
+            unblock.send(()).unwrap();
+            let mut received = Vec::new();
+            if let Ok(Some(i)) = first {
+                received.push(i);
+            }
+            while received.len() < 10 {
+                received.push(timeout(TIMEOUT, receiver.recv()).await.unwrap().unwrap());
+            }
+            if order != HandlerOrder::Unordered {
+                assert_eq!(received, (0..10).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_limit() {
+        let dispatcher = dispatcher(HandlerOrder::Unordered, Some(1));
+        let (unblock, blocked) = oneshot::channel::<()>();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        dispatcher
+            .dispatch(None, Box::pin(async move { blocked.await.unwrap() }))
+            .await;
+        dispatcher
+            .dispatch(None, Box::pin(async move { sender.send(()).unwrap() }))
+            .await;
+        assert!(timeout(Duration::from_millis(200), receiver.recv())
+            .await
+            .is_err());
+
+        unblock.send(()).unwrap();
+        assert!(timeout(TIMEOUT, receiver.recv()).await.is_ok());
+        while !dispatcher.is_idle() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_queues() {
+        let dispatcher = dispatcher(HandlerOrder::PerEvent, None);
+        let foo = Event::from("foo");
+        let (unblock, blocked) = oneshot::channel::<()>();
+        dispatcher
+            .dispatch(Some(&foo), Box::pin(async move { blocked.await.unwrap() }))
+            .await;
+        for _ in 0..QUEUE_CAPACITY {
+            dispatcher.dispatch(Some(&foo), Box::pin(async {})).await;
+        }
+        // a full queue holds up dispatching
+        let dispatched = dispatcher.dispatch(Some(&foo), Box::pin(async {}));
+        tokio::pin!(dispatched);
+        assert!(timeout(Duration::from_millis(200), &mut dispatched)
+            .await
+            .is_err());
+
+        unblock.send(()).unwrap();
+        timeout(TIMEOUT, dispatched).await.unwrap();
+        // the queue is removed once drained
+        while !dispatcher.is_idle() || !lock(&dispatcher.queues).is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        for i in 0..3 {
+            let bar = Event::from(format!("bar{}", i));
+            dispatcher.dispatch(Some(&bar), Box::pin(async {})).await;
+        }
+        while !lock(&dispatcher.queues).is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_panic() {
+        let foo = Event::from("foo");
+        for order in [HandlerOrder::PerEvent, HandlerOrder::Global] {
+            let dispatcher = dispatcher(order, None);
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            dispatcher
+                .dispatch(Some(&foo), Box::pin(async { panic!("handler panicked") }))
+                .await;
+            // the queue of the panicked handler is removed
+            timeout(
+                TIMEOUT,
+                dispatcher.dispatch(
+                    Some(&foo),
+                    Box::pin(async move { sender.send(()).unwrap() }),
+                ),
+            )
+            .await
+            .unwrap();
+            assert!(timeout(TIMEOUT, receiver.recv()).await.unwrap().is_some());
+            while !dispatcher.is_idle() || !lock(&dispatcher.queues).is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_panic() -> Result<()> {
+        let server = Arc::new(MockServer::builder().build()?);
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let errors = sender.clone();
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .handler_order(HandlerOrder::Global)
+            .on("panic", |_, _| async { panic!("handler panicked") }.boxed())
+            .on("update", move |payload, _| {
+                let sender = sender.clone();
+                async move {
+                    let _ = sender.send(format!("{:?}", payload));
+                }
+                .boxed()
+            })
+            .on_error(move |error, _| {
+                let errors = errors.clone();
+                async move {
+                    let _ = errors.send(error.to_string());
+                }
+                .boxed()
+            })
+            .connect()
+            .await?;
+        let accepting = server.clone();
+        let _socket = spawn_blocking(move || {
+            let socket = accepting.accept(TIMEOUT).unwrap();
+            socket.emit("panic", json!(1)).unwrap();
+            socket.emit("update", json!(2)).unwrap();
+            socket
+        })
+        .await
+        .unwrap();
+
+        let error = timeout(TIMEOUT, receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(error, "A callback panicked: handler panicked");
+        // the handlers after the panicked one still run
+        assert!(timeout(TIMEOUT, receiver.recv()).await.unwrap().is_some());
+        client.disconnect().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_handlers() -> Result<()> {
+        let server = Arc::new(MockServer::builder().build()?);
+        let (unblock, blocked) = oneshot::channel::<()>();
+        let blocked = Arc::new(Mutex::new(Some(blocked)));
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .on("slow", move |_, _| {
+                let blocked = blocked.lock().unwrap().take();
+                async move {
+                    if let Some(blocked) = blocked {
+                        let _ = blocked.await;
+                    }
+                }
+                .boxed()
+            })
+            .on("fast", move |payload, _| {
+                let _ = sender.send(payload);
+                async {}.boxed()
+            })
+            .connect()
+            .await?;
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (acked, mut ack) = mpsc::unbounded_channel();
+        client
+            .emit_with_ack("ping", json!(1), TIMEOUT, move |_, client| {
+                let acked = acked.clone();
+                async move {
+                    // acks may be sent from an ack callback
+                    let sent = client
+                        .emit_with_ack("pong", json!(2), TIMEOUT, |_, _| async {}.boxed())
+                        .await;
+                    let _ = acked.send(sent.is_ok());
+                }
+                .boxed()
+            })
+            .await?;
+        let _socket = spawn_blocking(move || {
+            let event = socket.expect_event("ping", TIMEOUT).unwrap();
+            socket.ack(&event, json!(3)).unwrap();
+            socket.emit("slow", json!(4)).unwrap();
+            socket.emit("fast", json!(5)).unwrap();
+            socket
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(timeout(TIMEOUT, ack.recv()).await.unwrap(), Some(true));
+        // "fast" isn't held up by "slow"
+        assert!(timeout(TIMEOUT, receiver.recv()).await.is_ok());
+        unblock.send(()).unwrap();
+        client.disconnect().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use futures_util::Stream;
+use std::{
+    fmt::Debug,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::client::Client;
+use crate::{error::Result, packet::Packet, Payload};
+
+/// An event received by a [`Client`], as yielded by [`Client::events`].
+pub type ServerEvent = crate::event::ServerEvent<Client>;
+
+/// Answers an ack the server requested, see [`ServerEvent::ack`](crate::event::ServerEvent::ack).
+pub type AckResponder = crate::event::AckResponder<Client>;
+
+impl AckResponder {
+    /// Sends `data` as the answer of the ack.
+    pub async fn send<D: Into<Payload>>(self, data: D) -> Result<()> {
+        let packet = Packet::ack(self.client.nsp(), self.id, data.into())?;
+        self.client.send(packet).await
+    }
+}
+
+/// A stream of the events received by a [`Client`], see [`Client::events`].
+/// It ends once the client disconnected or its connection closed.
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: UnboundedReceiver<ServerEvent>,
+}
+
+impl Stream for EventStream {
+    type Item = ServerEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// The senders of the [`EventStream`]s of a client, `None` once it closed.
+#[derive(Debug)]
+pub(crate) struct Subscribers(Mutex<Option<Vec<UnboundedSender<ServerEvent>>>>);
+
+impl Default for Subscribers {
+    fn default() -> Self {
+        Subscribers(Mutex::new(Some(Vec::new())))
+    }
+}
+
+impl Subscribers {
+    /// Returns a stream of the events published from now on, which ended
+    /// already if the client closed.
+    pub(crate) fn subscribe(&self) -> EventStream {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if let Some(senders) = self.lock().as_mut() {
+            senders.push(sender);
+        }
+        EventStream { receiver }
+    }
+
+    /// Passes `event` to the streams that were not dropped.
+    pub(crate) fn publish(&self, event: ServerEvent) {
+        if let Some(senders) = self.lock().as_mut() {
+            senders.retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Ends the streams once they yielded the published events.
+    pub(crate) fn close(&self) {
+        self.lock().take();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Vec<UnboundedSender<ServerEvent>>>> {
+        // a poisoned lock only means that another publish panicked
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynchronous::ClientBuilder;
+    use crate::{mock::MockServer, Event, TransportType};
+    use futures_util::StreamExt;
+    use serde_json::json;
+    use std::{sync::Arc, time::Duration};
+    use tokio::{task::spawn_blocking, time::timeout};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_events() -> Result<()> {
+        let server = Arc::new(MockServer::builder().build()?);
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .connect()
+            .await?;
+        let mut events = client.events();
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (socket, id) = spawn_blocking(move || {
+            let id = socket.emit_with_ack("question", json!({"id": 1})).unwrap();
+            (socket, id)
+        })
+        .await
+        .unwrap();
+        let event = loop {
+            let event = timeout(TIMEOUT, events.next()).await.unwrap().unwrap();
+            if event.event != Event::Connect {
+                break event;
+            }
+        };
+        assert_eq!(event.event, Event::from("question"));
+        assert_eq!(event.args, vec![r#"{"id":1}"#]);
+        assert_eq!(event.nsp, "/");
+        let ack = event.ack.unwrap();
+        assert_eq!(ack.id(), id);
+        ack.send(json!("answer")).await?;
+        let answer = spawn_blocking(move || socket.expect_ack(id, TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.args, vec![r#""answer""#]);
+
+        client.disconnect().await?;
+        let event = timeout(TIMEOUT, events.next()).await.unwrap().unwrap();
+        assert_eq!(event.event, Event::Close);
+        assert!(timeout(TIMEOUT, events.next()).await.unwrap().is_none());
+        // streams of closed clients end at once
+        assert!(client.events().next().await.is_none());
+        Ok(())
+    }
+}
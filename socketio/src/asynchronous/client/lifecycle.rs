@@ -0,0 +1,258 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    pin::Pin,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_util::future::BoxFuture;
+use rust_engineio::asynchronous::Runtime;
+use tokio::sync::watch;
+
+thread_local! {
+    /// The lifecycle whose task is being polled on this thread, if any.
+    static POLLED: Cell<*const Lifecycle> = const { Cell::new(ptr::null()) };
+}
+
+/// The background task of a client, which polls for packets and calls the
+/// callbacks, shared by the clones of the client.
+pub(crate) struct Lifecycle {
+    runtime: Arc<dyn Runtime>,
+    stop: watch::Sender<bool>,
+    stopping: watch::Receiver<bool>,
+    /// Whether the task disconnects the client once it stops.
+    disconnect: AtomicBool,
+    finish: watch::Sender<bool>,
+    finished: watch::Receiver<bool>,
+}
+
+impl Lifecycle {
+    pub(crate) fn new(runtime: Arc<dyn Runtime>) -> Self {
+        let (stop, stopping) = watch::channel(false);
+        let (finish, finished) = watch::channel(false);
+        Lifecycle {
+            runtime,
+            stop,
+            stopping,
+            disconnect: AtomicBool::new(false),
+            finish,
+            finished,
+        }
+    }
+
+    pub(crate) fn runtime(&self) -> Arc<dyn Runtime> {
+        self.runtime.clone()
+    }
+
+    /// Wraps `future` as the task of the lifecycle, see [`Lifecycle::is_current`].
+    pub(crate) fn task(self: Arc<Self>, future: BoxFuture<'static, ()>) -> BoxFuture<'static, ()> {
+        Box::pin(Task {
+            lifecycle: self,
+            future,
+        })
+    }
+
+    /// Whether the task of the lifecycle is being polled, i.e. the caller runs
+    /// on it.
+    pub(crate) fn is_current(&self) -> bool {
+        POLLED.with(|polled| ptr::eq(polled.get(), self))
+    }
+
+    /// Asks the task to stop, disconnecting the client first if `disconnect`.
+    pub(crate) fn stop(&self, disconnect: bool) {
+        if disconnect {
+            self.disconnect.store(true, Ordering::Release);
+        }
+        let _ = self.stop.send(true);
+    }
+
+    /// Whether the task should disconnect the client once it stops.
+    pub(crate) fn disconnects(&self) -> bool {
+        self.disconnect.load(Ordering::Acquire)
+    }
+
+    /// Completes once the task is asked to stop.
+    pub(crate) async fn stopped(&self) {
+        wait(self.stopping.clone()).await
+    }
+
+    /// Marks the task as finished.
+    pub(crate) fn finish(&self) {
+        let _ = self.finish.send(true);
+    }
+
+    /// Completes once the task finished.
+    pub(crate) async fn finished(&self) {
+        wait(self.finished.clone()).await
+    }
+}
+
+/// The task of a [`Lifecycle`], which marks it as polled on the thread
+/// polling it.
+struct Task {
+    lifecycle: Arc<Lifecycle>,
+    future: BoxFuture<'static, ()>,
+}
+
+impl Future for Task {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let polled = Arc::as_ptr(&self.lifecycle);
+        let _polling = Polling(POLLED.with(|current| current.replace(polled)));
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Restores the previously polled lifecycle once a [`Task`] was polled, even
+/// if polling panicked.
+struct Polling(*const Lifecycle);
+
+impl Drop for Polling {
+    fn drop(&mut self) {
+        POLLED.with(|current| current.set(self.0));
+    }
+}
+
+/// Completes once `receiver` holds `true`.
+async fn wait(mut receiver: watch::Receiver<bool>) {
+    while !*receiver.borrow() {
+        if receiver.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Stops the task and disconnects the client once the last handle of the
+/// client is dropped. The clones passed to callbacks hold none.
+pub(crate) struct DropGuard(pub(crate) Arc<Lifecycle>);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        self.0.stop(true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asynchronous::{ClientBuilder, TokioRuntime};
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::TransportType;
+    use futures_util::FutureExt;
+    use serde_json::json;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tokio::task::spawn_blocking;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_disconnect_gracefully() -> Result<()> {
+        let server = Arc::new(MockServer::builder().build()?);
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .connect()
+            .await?;
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (acked, ack) = mpsc::channel();
+        client
+            .emit_with_ack("ping", json!(1), TIMEOUT, move |_, _| {
+                let _ = acked.send(());
+                async {}.boxed()
+            })
+            .await?;
+        let answering = spawn_blocking(move || {
+            let event = socket.expect_event("ping", TIMEOUT).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            socket.ack(&event, json!(2)).unwrap();
+            socket.expect_disconnect(TIMEOUT)
+        });
+
+        client.disconnect_gracefully(TIMEOUT).await?;
+        assert!(ack.try_recv().is_ok());
+        client.join().await;
+        assert!(answering.await.unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_gracefully_without_task() -> Result<()> {
+        let server = Arc::new(MockServer::builder().build()?);
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .connect_manual()
+            .await?;
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+
+        client
+            .emit_with_ack("ping", json!(1), TIMEOUT, |_, _| async {}.boxed())
+            .await?;
+        let timeout = Duration::from_millis(300);
+        let started = std::time::Instant::now();
+        client.disconnect_gracefully(timeout).await?;
+        assert!(started.elapsed() >= timeout);
+        let disconnected = spawn_blocking(move || socket.expect_disconnect(TIMEOUT));
+        assert!(disconnected.await.unwrap());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_current() {
+        let lifecycle = Arc::new(Lifecycle::new(Arc::new(TokioRuntime::current())));
+        assert!(!lifecycle.is_current());
+        let polled = lifecycle.clone();
+        let current = lifecycle
+            .clone()
+            .task(Box::pin(async move { assert!(polled.is_current()) }));
+        current.await;
+        assert!(!lifecycle.is_current());
+    }
+
+    #[tokio::test]
+    async fn test_is_current_after_panic() {
+        let lifecycle = Arc::new(Lifecycle::new(Arc::new(TokioRuntime::current())));
+        let panicking = lifecycle
+            .clone()
+            .task(Box::pin(async { panic!("callback panicked") }));
+        assert!(std::panic::AssertUnwindSafe(panicking)
+            .catch_unwind()
+            .await
+            .is_err());
+        assert!(!lifecycle.is_current());
+    }
+
+    #[tokio::test]
+    async fn test_drop() -> Result<()> {
+        let server = Arc::new(MockServer::builder().build()?);
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .connect()
+            .await?;
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+
+        drop(client);
+        let disconnected = spawn_blocking(move || socket.expect_disconnect(TIMEOUT));
+        assert!(disconnected.await.unwrap());
+        Ok(())
+    }
+}
@@ -2,4 +2,8 @@ mod ack;
 pub(crate) mod builder;
 #[cfg(feature = "async-callbacks")]
 mod callback;
+#[allow(clippy::module_inception)]
 pub(crate) mod client;
+pub(crate) mod dispatch;
+pub(crate) mod event_stream;
+mod lifecycle;
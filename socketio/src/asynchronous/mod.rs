@@ -5,3 +5,10 @@ mod socket;
 #[cfg(feature = "async")]
 pub use client::builder::ClientBuilder;
 pub use client::client::Client;
+pub use client::dispatch::HandlerOrder;
+pub use client::event_stream::{AckResponder, EventStream, ServerEvent};
+#[cfg(feature = "async-std")]
+pub use rust_engineio::asynchronous::AsyncStdRuntime;
+#[cfg(feature = "smol")]
+pub use rust_engineio::asynchronous::SmolRuntime;
+pub use rust_engineio::asynchronous::{Runtime, TokioRuntime};
@@ -1,8 +1,9 @@
 use super::generator::StreamGenerator;
 use crate::{
     error::Result,
+    metrics::{Metrics, MetricsSnapshot},
     packet::{Packet, PacketId},
-    Error, Event, Payload,
+    DisconnectReason, Error, Event, Payload,
 };
 use async_stream::try_stream;
 use bytes::Bytes;
@@ -24,16 +25,22 @@ pub(crate) struct Socket {
     engine_client: Arc<EngineClient>,
     connected: Arc<AtomicBool>,
     generator: StreamGenerator<Packet>,
+    metrics: Arc<Metrics>,
 }
 
 impl Socket {
     /// Creates an instance of `Socket`.
-    pub(super) fn new(engine_client: EngineClient) -> Result<Self> {
+    pub(super) fn new(engine_client: EngineClient, metrics: Arc<Metrics>) -> Result<Self> {
         let connected = Arc::new(AtomicBool::default());
         Ok(Socket {
             engine_client: Arc::new(engine_client.clone()),
             connected: connected.clone(),
-            generator: StreamGenerator::new(Self::stream(engine_client, connected)),
+            generator: StreamGenerator::new(Self::stream(
+                engine_client,
+                connected,
+                metrics.clone(),
+            )),
+            metrics,
         })
     }
 
@@ -50,15 +57,26 @@ impl Socket {
     }
 
     /// Disconnects from the server by sending a socket.io `Disconnect` packet. This results
-    /// in the underlying engine.io transport to get closed as well.
-    pub async fn disconnect(&self) -> Result<()> {
+    /// in the underlying engine.io transport to get closed as well. Returns
+    /// whether the socket was connected.
+    pub async fn disconnect(&self) -> Result<bool> {
         if self.is_engineio_connected() {
             self.engine_client.disconnect().await?;
         }
-        if self.connected.load(Ordering::Acquire) {
-            self.connected.store(false, Ordering::Release);
+        Ok(self.connected.swap(false, Ordering::AcqRel))
+    }
+
+    /// Returns the reason the socket closed for if the engine.io connection
+    /// closed while it was connected, once.
+    pub(crate) fn take_close_reason(&self) -> Option<DisconnectReason> {
+        if self.is_engineio_connected() || !self.connected.swap(false, Ordering::AcqRel) {
+            return None;
         }
-        Ok(())
+        Some(
+            self.engine_client
+                .close_reason()
+                .map_or(DisconnectReason::TransportClose, Into::into),
+        )
     }
 
     /// Sends a `socket.io` packet to the server using the `engine.io` client.
@@ -67,22 +85,32 @@ impl Socket {
             return Err(Error::IllegalActionBeforeOpen());
         }
 
-        // the packet, encoded as an engine.io message packet
-        let engine_packet = EnginePacket::new(EnginePacketId::Message, Bytes::from(&packet));
-        self.engine_client.emit(engine_packet).await?;
-
+        // the packet, encoded as an engine.io message packet, is sent together
+        // with its attachments
+        let data = Bytes::from(&packet);
+        event!(
+            trace,
+            packet_type = packet.packet_type,
+            nsp = packet.nsp,
+            size = data.len(),
+            ack_id = packet.id,
+            attachments = packet.attachment_count;
+            "sending packet"
+        );
+        let attachments_len: usize = packet.attachments.iter().flatten().map(Bytes::len).sum();
+        self.metrics.sent(&packet, data.len() + attachments_len);
+        let mut engine_packets = vec![EnginePacket::new(EnginePacketId::Message, data)];
         if let Some(attachments) = packet.attachments {
             for attachment in attachments {
-                let engine_packet = EnginePacket::new(EnginePacketId::MessageBinary, attachment);
-                self.engine_client.emit(engine_packet).await?;
+                engine_packets.push(EnginePacket::new(EnginePacketId::MessageBinary, attachment));
             }
         }
+        self.engine_client.emit_batch(engine_packets).await?;
 
         Ok(())
     }
 
-    /// Emits to certain event with given data. The data needs to be JSON,
-    /// otherwise this returns an `InvalidJson` error.
+    /// Emits to certain event with given data.
     pub async fn emit(&self, nsp: &str, event: Event, data: Payload) -> Result<()> {
         let socket_packet = self.build_packet_for_payload(data, event, nsp, None)?;
 
@@ -99,6 +127,7 @@ impl Socket {
         nsp: &'a str,
         id: Option<i32>,
     ) -> Result<Packet> {
+        let data = payload.event_data(event)?;
         match payload {
             Payload::Binary(bin_data) => Ok(Packet::new(
                 if id.is_some() {
@@ -107,31 +136,26 @@ impl Socket {
                     PacketId::BinaryEvent
                 },
                 nsp.to_owned(),
-                Some(serde_json::Value::String(event.into()).to_string()),
+                Some(data),
                 id,
                 1,
                 Some(vec![bin_data]),
             )),
-            Payload::String(str_data) => {
-                serde_json::from_str::<serde_json::Value>(&str_data)?;
-
-                let payload = format!("[\"{}\",{}]", String::from(event), str_data);
-
-                Ok(Packet::new(
-                    PacketId::Event,
-                    nsp.to_owned(),
-                    Some(payload),
-                    id,
-                    0,
-                    None,
-                ))
-            }
+            _ => Ok(Packet::new(
+                PacketId::Event,
+                nsp.to_owned(),
+                Some(data),
+                id,
+                0,
+                None,
+            )),
         }
     }
 
     fn stream(
         client: EngineClient,
         is_connected: Arc<AtomicBool>,
+        metrics: Arc<Metrics>,
     ) -> Pin<Box<impl Stream<Item = Result<Packet>> + Send>> {
         Box::pin(try_stream! {
                 for await received_data in client.clone() {
@@ -140,7 +164,7 @@ impl Socket {
                     if packet.packet_id == EnginePacketId::Message
                         || packet.packet_id == EnginePacketId::MessageBinary
                     {
-                        let packet = Self::handle_engineio_packet(packet, client.clone()).await?;
+                        let packet = Self::handle_engineio_packet(packet, client.clone(), &metrics).await?;
                         Self::handle_socketio_packet(&packet, is_connected.clone());
 
                         yield packet;
@@ -170,8 +194,18 @@ impl Socket {
     async fn handle_engineio_packet(
         packet: EnginePacket,
         mut client: EngineClient,
+        metrics: &Metrics,
     ) -> Result<Packet> {
         let mut socket_packet = Packet::try_from(&packet.data)?;
+        event!(
+            trace,
+            packet_type = socket_packet.packet_type,
+            nsp = socket_packet.nsp,
+            size = packet.data.len(),
+            ack_id = socket_packet.id,
+            attachments = socket_packet.attachment_count;
+            "received packet"
+        );
 
         // Only handle attachments if there are any
         if socket_packet.attachment_count > 0 {
@@ -197,10 +231,27 @@ impl Socket {
             }
             socket_packet.attachments = Some(attachments);
         }
+        let attachments_len: usize = socket_packet
+            .attachments
+            .iter()
+            .flatten()
+            .map(Bytes::len)
+            .sum();
+        metrics.received(&socket_packet, packet.data.len() + attachments_len);
 
         Ok(socket_packet)
     }
 
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns a snapshot of the metrics, including the ones of the engine.io
+    /// client.
+    pub(crate) fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot(self.engine_client.metrics())
+    }
+
     fn is_engineio_connected(&self) -> bool {
         self.engine_client.is_connected()
     }
@@ -1,16 +1,25 @@
 use super::super::{event::Event, payload::Payload};
 use super::callback::Callback;
 use super::client::Client;
-use crate::RawClient;
-use native_tls::TlsConnector;
+use super::dispatch::{Dispatch, Dispatcher, DEFAULT_CAPACITY};
+use super::events::EventQueue;
+use crate::{ErrorEvent, RawClient};
 use rust_engineio::client::ClientBuilder as EngineIoClientBuilder;
 use rust_engineio::header::{HeaderMap, HeaderValue};
+use rust_engineio::recorder::{Frame, Recorder};
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use rust_engineio::TlsConfig;
+use rust_engineio::{CookieJar, Proxy, Timeouts};
 use url::Url;
 
-use crate::client::callback::{SocketAnyCallback, SocketCallback};
+use crate::client::callback::{
+    EventCallbacks, SocketAnyCallback, SocketCallback, SocketErrorCallback,
+};
 use crate::error::Result;
+use crate::metrics::Metrics;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::socket::Socket as InnerSocket;
 
@@ -33,20 +42,41 @@ pub enum TransportType {
 /// acts the `build` method and returns a connected [`Client`].
 #[derive(Clone)]
 pub struct ClientBuilder {
-    address: String,
-    on: Arc<Mutex<HashMap<Event, Callback<SocketCallback>>>>,
+    pub(crate) address: String,
+    on: Arc<Mutex<EventCallbacks>>,
     on_any: Arc<Mutex<Option<Callback<SocketAnyCallback>>>>,
+    on_error: Arc<Mutex<Option<Callback<SocketErrorCallback>>>>,
     namespace: String,
-    tls_config: Option<TlsConnector>,
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    tls_config: Option<TlsConfig>,
     opening_headers: Option<HeaderMap>,
+    opening_headers_fn: Option<Arc<dyn Fn() -> HeaderMap + Send + Sync>>,
     transport_type: TransportType,
     auth: Option<serde_json::Value>,
+    auth_fn: Option<Arc<dyn Fn() -> serde_json::Value + Send + Sync>>,
+    timeouts: Timeouts,
+    proxy: Option<Proxy>,
+    cookie_jar: Option<Arc<CookieJar>>,
+    query_params: Vec<(String, String)>,
+    path: Option<String>,
+    timestamp_requests: Option<bool>,
+    timestamp_param: Option<String>,
+    recorder: Option<Recorder>,
+    replay: Option<Vec<Frame>>,
     pub(crate) reconnect: bool,
     pub(crate) reconnect_on_disconnect: bool,
     // None reconnect attempts represent infinity.
     pub(crate) max_reconnect_attempts: Option<u8>,
     pub(crate) reconnect_delay_min: u64,
     pub(crate) reconnect_delay_max: u64,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) dispatch: Dispatch,
+    pub(crate) dispatch_capacity: usize,
+    // shared with the clients of the reconnects, `None` creates a new one
+    pub(crate) dispatcher: Option<Arc<Dispatcher>>,
+    pub(crate) event_channel: bool,
+    // shared with the clients of the reconnects, `None` creates a new one
+    pub(crate) events: Option<Arc<EventQueue>>,
 }
 
 impl ClientBuilder {
@@ -64,6 +94,7 @@ impl ClientBuilder {
     ///            match payload {
     ///                Payload::String(str) => println!("Received: {}", str),
     ///                Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+    ///                _ => {}
     ///            }
     /// };
     ///
@@ -85,17 +116,36 @@ impl ClientBuilder {
             address: address.into(),
             on: Arc::new(Mutex::new(HashMap::new())),
             on_any: Arc::new(Mutex::new(None)),
+            on_error: Arc::new(Mutex::new(None)),
             namespace: "/".to_owned(),
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
             tls_config: None,
             opening_headers: None,
+            opening_headers_fn: None,
             transport_type: TransportType::Any,
             auth: None,
+            auth_fn: None,
+            timeouts: Timeouts::default(),
+            proxy: None,
+            cookie_jar: None,
+            query_params: Vec::new(),
+            path: None,
+            timestamp_requests: None,
+            timestamp_param: None,
+            recorder: None,
+            replay: None,
             reconnect: true,
             reconnect_on_disconnect: false,
             // None means infinity
             max_reconnect_attempts: None,
             reconnect_delay_min: 1000,
             reconnect_delay_max: 5000,
+            metrics: Arc::default(),
+            dispatch: Dispatch::default(),
+            dispatch_capacity: DEFAULT_CAPACITY,
+            dispatcher: None,
+            event_channel: false,
+            events: None,
         }
     }
 
@@ -158,6 +208,7 @@ impl ClientBuilder {
     ///            match payload {
     ///                Payload::String(str) => println!("Received: {}", str),
     ///                Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+    ///                _ => {}
     ///            }
     ///     })
     ///     .on("error", |err, _| eprintln!("Error: {:#?}", err))
@@ -170,7 +221,7 @@ impl ClientBuilder {
     where
         F: FnMut(Payload, RawClient) + 'static + Send,
     {
-        let callback = Callback::<SocketCallback>::new(callback);
+        let callback = Arc::new(Mutex::new(Callback::<SocketCallback>::new(callback)));
         // SAFETY: Lock is held for such amount of time no code paths lead to a panic while lock is held
         self.on.lock().unwrap().insert(event.into(), callback);
         self
@@ -204,27 +255,110 @@ impl ClientBuilder {
         self
     }
 
-    /// Uses a preconfigured TLS connector for secure communication. This configures
-    /// both the `polling` as well as the `websocket` transport type.
+    /// Registers a Callback for errors, which receives an [`ErrorEvent`]
+    /// telling e.g. a transport failure from a rejection by the server. The
+    /// callbacks of [`Event::Error`] keep receiving the message of the error.
+    ///
     /// # Example
     /// ```rust
-    /// use rust_socketio::{ClientBuilder, Payload};
-    /// use native_tls::TlsConnector;
+    /// use rust_socketio::{ClientBuilder, ErrorKind};
+    ///
+    /// let client = ClientBuilder::new("http://localhost:4200/")
+    ///     .on_error(|error, _client| {
+    ///         if error.kind == ErrorKind::ConnectError {
+    ///             eprintln!("Rejected by the server: {:?}", error.data);
+    ///         }
+    ///     })
+    ///     .connect();
+    ///
+    /// ```
+    // While present implementation doesn't require mut, it's reasonable to require mutability.
+    #[allow(unused_mut)]
+    pub fn on_error<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(ErrorEvent, RawClient) + 'static + Send,
+    {
+        let callback = Some(Callback::<SocketErrorCallback>::new(callback));
+        // SAFETY: Lock is held for such amount of time no code paths lead to a panic while lock is held
+        *self.on_error.lock().unwrap() = callback;
+        self
+    }
+
+    /// Sets where the callbacks are called, on the polling thread by default.
+    /// See [`Dispatch`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{ClientBuilder, Dispatch};
+    /// use std::{thread::sleep, time::Duration};
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .dispatch(Dispatch::Pool(4))
+    ///     // doesn't delay the other events and the heartbeats
+    ///     .on("test", |_, _| sleep(Duration::from_secs(1)))
+    ///     .connect();
+    /// ```
+    pub fn dispatch(mut self, dispatch: Dispatch) -> Self {
+        self.dispatch = dispatch;
+        self
+    }
+
+    /// Sets how many callbacks each worker thread of [`Dispatch::Worker`] and
+    /// [`Dispatch::Pool`] queues, 1024 by default. Once a queue is full, the
+    /// polling thread waits for it to have room, which holds up the
+    /// heartbeats as well. Callbacks of the worker itself don't wait for it,
+    /// e.g. the close callbacks of a `disconnect()` then run right away.
+    pub fn dispatch_capacity(mut self, capacity: usize) -> Self {
+        self.dispatch_capacity = capacity;
+        self
+    }
+
+    /// Queues the received events for [`Client::recv`] and
+    /// [`RawClient::recv`], so they can be handled on any thread instead of in
+    /// callbacks. The callbacks are called nonetheless. The queue is kept
+    /// across the reconnects of a [`Client`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::ClientBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .event_channel(true)
+    ///     .connect();
+    ///
+    /// if let Ok(socket) = socket {
+    ///     while let Ok(event) = socket.recv_timeout(Duration::from_secs(1)) {
+    ///         println!("Received {:?}: {:?}", event.event, event.args);
+    ///     }
+    /// }
+    /// ```
+    pub fn event_channel(mut self, enabled: bool) -> Self {
+        self.event_channel = enabled;
+        self
+    }
+
+    /// Uses a preconfigured TLS configuration for secure communication. This configures
+    /// both the `polling` as well as the `websocket` transport type. Besides a
+    /// [`TlsConfig`], a `native_tls::TlsConnector` or a `rustls::ClientConfig`
+    /// is accepted, depending on the enabled TLS backend. Available with the
+    /// `native-tls` or `rustls` feature.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{ClientBuilder, Payload, TlsConfig};
     ///
-    /// let tls_connector =  TlsConnector::builder()
-    ///            .use_sni(true)
+    /// let tls_config = TlsConfig::builder()
     ///            .build()
     ///            .expect("Found illegal configuration");
     ///
     /// let socket = ClientBuilder::new("http://localhost:4200/")
     ///     .namespace("/admin")
     ///     .on("error", |err, _| eprintln!("Error: {:#?}", err))
-    ///     .tls_config(tls_connector)
+    ///     .tls_config(tls_config)
     ///     .connect();
     ///
     /// ```
-    pub fn tls_config(mut self, tls_config: TlsConnector) -> Self {
-        self.tls_config = Some(tls_config);
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub fn tls_config<T: Into<TlsConfig>>(mut self, tls_config: T) -> Self {
+        self.tls_config = Some(tls_config.into());
         self
     }
 
@@ -257,6 +391,31 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets a closure producing http headers for the opening request, e.g. to
+    /// send short-lived credentials. It's called on every connect and
+    /// reconnect attempt and its headers take precedence over the ones set with
+    /// [`ClientBuilder::opening_header`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::ClientBuilder;
+    /// use rust_engineio::header::HeaderMap;
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .opening_headers_with(|| {
+    ///         let mut headers = HeaderMap::default();
+    ///         headers.insert("authorization", "Bearer fresh-token");
+    ///         headers
+    ///     })
+    ///     .connect();
+    /// ```
+    pub fn opening_headers_with<F>(mut self, headers: F) -> Self
+    where
+        F: Fn() -> HeaderMap + 'static + Send + Sync,
+    {
+        self.opening_headers_fn = Some(Arc::new(headers));
+        self
+    }
+
     /// Sets data sent in the opening request.
     /// # Example
     /// ```rust
@@ -277,6 +436,142 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets a closure producing the data sent in the opening request. It's
+    /// called on every connect and reconnect attempt and takes precedence over
+    /// [`ClientBuilder::auth`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::ClientBuilder;
+    /// use serde_json::json;
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4204/")
+    ///     .auth_with(|| json!({ "token": "fresh-token" }))
+    ///     .connect();
+    /// ```
+    pub fn auth_with<F>(mut self, auth: F) -> Self
+    where
+        F: Fn() -> serde_json::Value + 'static + Send + Sync,
+    {
+        self.auth_fn = Some(Arc::new(auth));
+        self
+    }
+
+    /// Connects through an http (`CONNECT`) or SOCKS5 proxy. This configures
+    /// both the `polling` as well as the `websocket` transport type. Use
+    /// [`Proxy::from_env`] to honor the `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `NO_PROXY` environment variables.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{ClientBuilder, Proxy};
+    ///
+    /// let proxy = Proxy::all("socks5://proxy.local:1080")
+    ///     .expect("invalid proxy url")
+    ///     .basic_auth("user", "secret");
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .proxy(proxy)
+    ///     .connect();
+    /// ```
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Shares cookies set by the server between the handshake, the `polling`
+    /// requests and the `websocket` upgrade, as required by load balancers with
+    /// sticky sessions. The jar is kept across reconnects.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::{ClientBuilder, CookieJar};
+    /// use std::sync::Arc;
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .cookie_jar(Arc::new(CookieJar::default()))
+    ///     .connect();
+    /// ```
+    pub fn cookie_jar(mut self, cookie_jar: Arc<CookieJar>) -> Self {
+        self.cookie_jar = Some(cookie_jar);
+        self
+    }
+
+    /// Appends a query parameter to the handshake, `polling` and `websocket`
+    /// urls. It is kept along with the parameters used by the protocol.
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::ClientBuilder;
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .path("/realtime/")
+    ///     .query_param("token", "secret")
+    ///     .connect();
+    /// ```
+    pub fn query_param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.query_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets the path of the `socket.io` endpoint, overriding the path of the
+    /// address. Defaults to `/socket.io/` if the address has no path.
+    pub fn path<T: Into<String>>(mut self, path: T) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets whether `polling` requests carry a cache busting timestamp, which is
+    /// enabled by default.
+    pub fn timestamp_requests(mut self, timestamp_requests: bool) -> Self {
+        self.timestamp_requests = Some(timestamp_requests);
+        self
+    }
+
+    /// Sets the name of the timestamp query parameter, `t` by default.
+    pub fn timestamp_param<T: Into<String>>(mut self, param: T) -> Self {
+        self.timestamp_param = Some(param.into());
+        self
+    }
+
+    /// Sets the maximum time for establishing the connection to the server, for
+    /// both the `polling` and the `websocket` transport type (including the
+    /// websocket upgrade request). Exceeding it fails the connection attempt
+    /// with [`rust_engineio::Error::ConnectTimeout`].
+    /// # Example
+    /// ```rust
+    /// use rust_socketio::ClientBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .connect_timeout(Duration::from_secs(5))
+    ///     .handshake_timeout(Duration::from_secs(10))
+    ///     .connect();
+    /// ```
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for the server to answer the engine.io
+    /// handshake. Exceeding it fails with [`rust_engineio::Error::HandshakeTimeout`].
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.handshake = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time for a single outgoing http request of the `polling`
+    /// transport. Exceeding it fails with [`rust_engineio::Error::RequestTimeout`].
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.request = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for data from the server once connected.
+    /// Exceeding it fails with [`rust_engineio::Error::ReadTimeout`]. As the
+    /// server may stay silent between heartbeats, this should be larger than the
+    /// sum of its `pingInterval` and `pingTimeout`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+
     /// Specifies which EngineIO [`TransportType`] to use.
     /// # Example
     /// ```rust
@@ -302,6 +597,35 @@ impl ClientBuilder {
         self
     }
 
+    /// Records the engine.io packets the client sends and receives with
+    /// `recorder`, see [`rust_engineio::recorder`] for the format.
+    pub fn recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Replays the packets received in a recording instead of connecting to
+    /// the server, the sent packets are discarded. The client disconnects
+    /// once the recording ends, without reconnecting.
+    /// # Example
+    /// ```rust,no_run
+    /// use rust_engineio::recorder::Frame;
+    /// use rust_socketio::ClientBuilder;
+    /// use std::{fs::File, io::BufReader};
+    ///
+    /// let frames = Frame::read_all(BufReader::new(File::open("session.jsonl")?))?;
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .on("update", |payload, _| println!("Received: {:?}", payload))
+    ///     .replay(frames)
+    ///     .connect()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn replay(mut self, frames: Vec<Frame>) -> Self {
+        self.replay = Some(frames);
+        self.reconnect = false;
+        self
+    }
+
     /// Connects the socket to a certain endpoint. This returns a connected
     /// [`Client`] instance. This method returns an [`std::result::Result::Err`]
     /// value if something goes wrong during connection. Also starts a separate
@@ -329,41 +653,186 @@ impl ClientBuilder {
         Client::new(self)
     }
 
-    pub fn connect_raw(self) -> Result<RawClient> {
+    /// Connects a [`RawClient`] without a thread polling it and without
+    /// reconnects. It has to be driven with [`RawClient::try_poll`] or
+    /// [`RawClient::poll_timeout`] on the thread of the caller.
+    pub fn connect_raw(mut self) -> Result<RawClient> {
+        self.metrics = Arc::default();
+        self.dispatcher = None;
+        self.events = None;
+        self.connect_raw_with_metrics()
+    }
+
+    /// Connects a [`RawClient`] which counts into the metrics of the builder.
+    pub(crate) fn connect_raw_with_metrics(self) -> Result<RawClient> {
         // Parse url here rather than in new to keep new returning Self.
         let mut url = Url::parse(&self.address)?;
 
-        if url.path() == "/" {
+        if let Some(path) = &self.path {
+            url.set_path(path);
+        } else if url.path() == "/" {
             url.set_path("/socket.io/");
         }
 
         let mut builder = EngineIoClientBuilder::new(url);
 
+        for (key, value) in &self.query_params {
+            builder = builder.query_param(key, value);
+        }
+        if let Some(timestamp_requests) = self.timestamp_requests {
+            builder = builder.timestamp_requests(timestamp_requests);
+        }
+        if let Some(param) = self.timestamp_param {
+            builder = builder.timestamp_param(param);
+        }
+
+        #[cfg(any(feature = "native-tls", feature = "rustls"))]
         if let Some(tls_config) = self.tls_config {
             builder = builder.tls_config(tls_config);
         }
-        if let Some(headers) = self.opening_headers {
+        let mut opening_headers = self.opening_headers;
+        if let Some(opening_headers_fn) = &self.opening_headers_fn {
+            let map = opening_headers.get_or_insert_with(HeaderMap::default);
+            for (key, value) in opening_headers_fn() {
+                map.insert(key, value);
+            }
+        }
+        if let Some(headers) = opening_headers {
             builder = builder.headers(headers);
         }
+        if let Some(proxy) = self.proxy {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(cookie_jar) = self.cookie_jar {
+            builder = builder.cookie_jar(cookie_jar);
+        }
+        if let Some(timeout) = self.timeouts.connect {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.handshake {
+            builder = builder.handshake_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.request {
+            builder = builder.request_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeouts.read {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(recorder) = self.recorder {
+            builder = builder.recorder(recorder);
+        }
 
-        let engine_client = match self.transport_type {
-            TransportType::Any => builder.build_with_fallback()?,
-            TransportType::Polling => builder.build_polling()?,
-            TransportType::Websocket => builder.build_websocket()?,
-            TransportType::WebsocketUpgrade => builder.build_websocket_with_upgrade()?,
+        let engine_client = match (self.replay, self.transport_type) {
+            (Some(frames), _) => builder.build_replay(frames)?,
+            (None, TransportType::Any) => builder.build_with_fallback()?,
+            (None, TransportType::Polling) => builder.build_polling()?,
+            (None, TransportType::Websocket) => builder.build_websocket()?,
+            (None, TransportType::WebsocketUpgrade) => builder.build_websocket_with_upgrade()?,
         };
 
-        let inner_socket = InnerSocket::new(engine_client)?;
+        let inner_socket = InnerSocket::new(engine_client, self.metrics)?;
+
+        let auth = match &self.auth_fn {
+            Some(auth_fn) => Some(auth_fn()),
+            None => self.auth,
+        };
 
+        let dispatcher = match self.dispatcher {
+            Some(dispatcher) => dispatcher,
+            None => Arc::new(Dispatcher::new(self.dispatch, self.dispatch_capacity)?),
+        };
         let socket = RawClient::new(
             inner_socket,
             &self.namespace,
             self.on,
             self.on_any,
-            self.auth,
+            self.on_error,
+            dispatcher,
+            self.events
+                .unwrap_or_else(|| Arc::new(EventQueue::new(self.event_channel, true))),
+            auth,
         )?;
         socket.connect()?;
 
         Ok(socket)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::time::Instant;
+
+    #[test]
+    fn test_dynamic_headers_and_auth() -> Result<()> {
+        // every connect performs the handshake, sends a pong and the connect packet
+        let (url, server) = crate::test::fake_polling_server(6);
+
+        let counter = Arc::new(AtomicUsize::new(0));
+        let headers_counter = counter.clone();
+        let auth_counter = counter.clone();
+        let builder = ClientBuilder::new(url)
+            .transport_type(TransportType::Polling)
+            .opening_header("authorization", "Bearer static")
+            .opening_headers_with(move || {
+                let token = headers_counter.fetch_add(1, Ordering::SeqCst);
+                let mut headers = HeaderMap::default();
+                headers.insert("authorization", format!("Bearer {}", token));
+                headers
+            })
+            .auth(json!({ "token": "static" }))
+            .auth_with(move || json!({ "token": auth_counter.load(Ordering::SeqCst) }));
+
+        builder.clone().connect_raw()?;
+        builder.connect_raw()?;
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("authorization: Bearer 0\r\n"));
+        assert!(requests[2].ends_with(r#"40{"token":1}"#));
+        assert!(requests[3].contains("authorization: Bearer 1\r\n"));
+        assert!(requests[5].ends_with(r#"40{"token":2}"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_and_replay() -> Result<()> {
+        let timeout = Duration::from_secs(5);
+        let path = std::env::temp_dir().join(format!("socketio-{}.jsonl", std::process::id()));
+        let server = crate::mock::MockServer::builder().build()?;
+        let (sender, receiver) = mpsc::channel();
+        let builder = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .on("update", move |payload, _| sender.send(payload).unwrap());
+        let receive = |socket: &RawClient| -> Result<Payload> {
+            let start = Instant::now();
+            loop {
+                if let Ok(payload) = receiver.try_recv() {
+                    return Ok(payload);
+                }
+                assert!(start.elapsed() < timeout);
+                socket.poll_timeout(timeout)?;
+            }
+        };
+
+        let socket = builder
+            .clone()
+            .recorder(Recorder::create(&path)?)
+            .connect_raw()?;
+        server.accept(timeout).unwrap().emit("update", json!(1))?;
+        let received = receive(&socket)?;
+        socket.disconnect()?;
+        drop(server);
+
+        // the replayed client receives the same event without a server
+        let frames = Frame::read_all(BufReader::new(File::open(&path)?))?;
+        std::fs::remove_file(&path)?;
+        let socket = builder.replay(frames).connect_raw()?;
+        assert_eq!(receive(&socket)?, received);
+        Ok(())
+    }
+}
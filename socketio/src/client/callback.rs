@@ -1,13 +1,18 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
     ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
 };
 
 use super::RawClient;
-use crate::{Event, Payload};
+use crate::{ErrorEvent, Event, Payload};
 
 pub(crate) type SocketCallback = Box<dyn FnMut(Payload, RawClient) + 'static + Send>;
 pub(crate) type SocketAnyCallback = Box<dyn FnMut(Event, Payload, RawClient) + 'static + Send>;
+pub(crate) type SocketErrorCallback = Box<dyn FnMut(ErrorEvent, RawClient) + 'static + Send>;
+/// The callbacks of the events, each locked on its own.
+pub(crate) type EventCallbacks = HashMap<Event, Arc<Mutex<Callback<SocketCallback>>>>;
 
 pub(crate) struct Callback<T> {
     inner: T,
@@ -78,3 +83,36 @@ impl Callback<SocketAnyCallback> {
         }
     }
 }
+
+// SocketErrorCallback implementations
+
+impl Debug for Callback<SocketErrorCallback> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+impl Deref for Callback<SocketErrorCallback> {
+    type Target = dyn FnMut(ErrorEvent, RawClient) + 'static + Send;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.as_ref()
+    }
+}
+
+impl DerefMut for Callback<SocketErrorCallback> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner.as_mut()
+    }
+}
+
+impl Callback<SocketErrorCallback> {
+    pub(crate) fn new<T>(callback: T) -> Self
+    where
+        T: FnMut(ErrorEvent, RawClient) + 'static + Send,
+    {
+        Callback {
+            inner: Box::new(callback),
+        }
+    }
+}
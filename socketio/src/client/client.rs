@@ -1,13 +1,20 @@
 use std::{
-    sync::{Arc, RwLock},
-    time::Duration,
+    sync::{
+        mpsc::{RecvError, RecvTimeoutError, TryRecvError},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
+use super::dispatch::Dispatcher;
+use super::events::{EventQueue, Events, ServerEvent};
+use super::lifecycle::{DropGuard, Lifecycle};
 use super::{ClientBuilder, RawClient};
 use crate::{
     error::Result,
+    metrics::MetricsSnapshot,
     packet::{Packet, PacketId},
-    Error,
+    DisconnectReason, Error,
 };
 pub(crate) use crate::{event::Event, payload::Payload};
 use backoff::ExponentialBackoff;
@@ -18,32 +25,54 @@ pub struct Client {
     builder: ClientBuilder,
     client: Arc<RwLock<RawClient>>,
     backoff: ExponentialBackoff,
+    lifecycle: Arc<Lifecycle>,
+    events: Arc<EventQueue>,
+    /// Held by the handles returned to the user, but not by the poll thread.
+    guard: Option<Arc<DropGuard>>,
 }
 
+/// How often [`Client::disconnect_gracefully`] checks for outstanding acks
+/// and packets.
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
 impl Client {
-    pub(crate) fn new(builder: ClientBuilder) -> Result<Self> {
+    pub(crate) fn new(mut builder: ClientBuilder) -> Result<Self> {
+        // the metrics are shared with the clients of the reconnects
+        builder.metrics = Arc::default();
+        builder.dispatcher = Some(Arc::new(Dispatcher::new(
+            builder.dispatch,
+            builder.dispatch_capacity,
+        )?));
+        let events = Arc::new(EventQueue::new(builder.event_channel, false));
+        builder.events = Some(events.clone());
         let builder_clone = builder.clone();
-        let client = builder_clone.connect_raw()?;
+        let client = builder_clone.connect_raw_with_metrics()?;
         let backoff = ExponentialBackoffBuilder::new()
             .with_initial_interval(Duration::from_millis(builder.reconnect_delay_min))
             .with_max_interval(Duration::from_millis(builder.reconnect_delay_max))
             .build();
 
-        let s = Self {
+        let client = Arc::new(RwLock::new(client));
+        let lifecycle = Arc::new(Lifecycle::default());
+        let mut s = Self {
             builder,
-            client: Arc::new(RwLock::new(client)),
+            client: client.clone(),
             backoff,
+            lifecycle: lifecycle.clone(),
+            events,
+            guard: None,
         };
-        s.poll_callback();
+        s.poll_callback()?;
+        s.guard = Some(Arc::new(DropGuard { lifecycle, client }));
 
         Ok(s)
     }
 
     /// Sends a message to the server using the underlying `engine.io` protocol.
     /// This message takes an event, which could either be one of the common
-    /// events like "message" or "error" or a custom event like "foo". But be
-    /// careful, the data string needs to be valid JSON. It's recommended to use
-    /// a library like `serde_json` to serialize the data properly.
+    /// events like "message" or "error" or a custom event like "foo". A
+    /// [`Payload::String`] is sent as JSON if it is valid JSON, use
+    /// [`Payload::Json`] or [`Payload::Text`] to be explicit.
     ///
     /// # Example
     /// ```
@@ -77,9 +106,9 @@ impl Client {
     /// Sends a message to the server but `alloc`s an `ack` to check whether the
     /// server responded in a given time span. This message takes an event, which
     /// could either be one of the common events like "message" or "error" or a
-    /// custom event like "foo", as well as a data parameter. But be careful,
-    /// in case you send a [`Payload::String`], the string needs to be valid JSON.
-    /// It's even recommended to use a library like serde_json to serialize the data properly.
+    /// custom event like "foo", as well as a data parameter. A
+    /// [`Payload::String`] is sent as JSON if it is valid JSON, use
+    /// [`Payload::Json`] or [`Payload::Text`] to be explicit.
     /// It also requires a timeout `Duration` in which the client needs to answer.
     /// If the ack is acked in the correct time span, the specified callback is
     /// called. The callback consumes a [`Payload`] which represents the data send
@@ -101,6 +130,7 @@ impl Client {
     ///     match message {
     ///         Payload::String(str) => println!("{}", str),
     ///         Payload::Binary(bytes) => println!("Received bytes: {:#?}", bytes),
+    ///         _ => {}
     ///    }
     /// };
     ///
@@ -127,7 +157,8 @@ impl Client {
     }
 
     /// Disconnects this client from the server by sending a `socket.io` closing
-    /// packet.
+    /// packet. The callbacks of [`Event::Close`] are passed
+    /// [`DisconnectReason::IoClientDisconnect`].
     /// # Example
     /// ```rust
     /// use rust_socketio::{ClientBuilder, Payload, RawClient};
@@ -152,16 +183,81 @@ impl Client {
     ///
     /// ```
     pub fn disconnect(&self) -> Result<()> {
+        // stops the poll thread, which is awaited by `join`
+        self.lifecycle.stop();
         let client = self.client.read()?;
-        client.disconnect()
+        let result = client.disconnect();
+        // after the close event was queued
+        self.events.close();
+        result
+    }
+
+    /// Waits up to `timeout` for the acks that did not time out yet and for the
+    /// packets that are not written yet, then disconnects like
+    /// [`Client::disconnect`].
+    pub fn disconnect_gracefully(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        while !self.client.read()?.is_drained()? && Instant::now() < deadline {
+            std::thread::sleep(DRAIN_INTERVAL);
+        }
+        self.disconnect()
+    }
+
+    /// Blocks until the thread polling for packets finished, after the client
+    /// disconnected or the connection closed. Returns at once if called from
+    /// a callback.
+    pub fn join(&self) -> Result<()> {
+        self.lifecycle.join()
     }
 
-    fn reconnect(&mut self) {
+    /// Returns a snapshot of the metrics of the client, which are kept across
+    /// reconnects. See [`crate::metrics`].
+    pub fn metrics(&self) -> Result<MetricsSnapshot> {
+        Ok(self.client.read()?.metrics())
+    }
+
+    /// Waits for the next received event, see
+    /// [`crate::ClientBuilder::event_channel`]. The events of all reconnects
+    /// are received. Fails once the client stopped and the queued events were
+    /// taken, or if the channel isn't enabled.
+    pub fn recv(&self) -> std::result::Result<ServerEvent, RecvError> {
+        self.events.recv()
+    }
+
+    /// Waits up to `timeout` for the next received event, like
+    /// [`Client::recv`].
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<ServerEvent, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Returns the next received event if there is one, like
+    /// [`Client::recv`].
+    pub fn try_recv(&self) -> std::result::Result<ServerEvent, TryRecvError> {
+        self.events.try_recv()
+    }
+
+    /// Returns an iterator waiting for the received events, which ends once
+    /// [`Client::recv`] fails.
+    pub fn events(&self) -> Events<'_> {
+        Events::new(&self.events)
+    }
+
+    /// Reconnects if enabled, returns whether it did.
+    fn reconnect(&mut self) -> bool {
         let mut reconnect_attempts = 0;
         if self.builder.reconnect {
+            let span = span!(INFO, "socketio.reconnect", url = self.builder.address);
+            let _enter = span.enter();
             loop {
+                if self.lifecycle.is_stopped() {
+                    break;
+                }
                 if let Some(max_reconnect_attempts) = self.builder.max_reconnect_attempts {
                     if reconnect_attempts > max_reconnect_attempts {
+                        event!(warn, attempts = reconnect_attempts; "giving up reconnecting");
                         break;
                     }
                 }
@@ -171,54 +267,113 @@ impl Client {
                     std::thread::sleep(backoff);
                 }
 
-                if self.do_reconnect().is_ok() {
-                    break;
+                match self.do_reconnect() {
+                    Ok(()) => {
+                        event!(info, attempt = reconnect_attempts; "reconnected");
+                        self.builder.metrics.reconnected();
+                        return true;
+                    }
+                    Err(error) => {
+                        event!(debug, attempt = reconnect_attempts; "reconnect failed: {}", error);
+                    }
                 }
             }
         }
+        false
     }
 
     fn do_reconnect(&self) -> Result<()> {
-        let new_client = self.builder.clone().connect_raw()?;
+        let new_client = self.builder.clone().connect_raw_with_metrics()?;
         let mut client = self.client.write()?;
         *client = new_client;
 
         Ok(())
     }
 
+    /// Calls the error callbacks of the current client with `error`.
+    fn handle_error(&self, error: Error) -> Result<()> {
+        self.client.read()?.handle_error(error)
+    }
+
     pub(crate) fn iter(&self) -> Iter {
         Iter {
             socket: self.client.clone(),
         }
     }
 
-    fn poll_callback(&self) {
-        let mut self_clone = self.clone();
+    fn poll_callback(&self) -> Result<()> {
+        let mut self_clone = Client {
+            guard: None,
+            ..self.clone()
+        };
         // Use thread to consume items in iterator in order to call callbacks
-        std::thread::spawn(move || {
+        let thread = std::thread::spawn(move || {
             // tries to restart a poll cycle whenever a 'normal' error occurs,
             // it just panics on network errors, in case the poll cycle returned
             // `Result::Ok`, the server receives a close frame so it's safe to
             // terminate
+            let mut disconnected_by_server = false;
             for packet in self_clone.iter() {
+                if self_clone.lifecycle.is_stopped() {
+                    break;
+                }
+                let stopped = matches!(packet, Err(Error::StoppedEngineIoSocket));
                 let should_reconnect = match packet {
-                    Err(Error::IncompleteResponseFromEngineIo(_)) => {
-                        //TODO: 0.3.X handle errors
-                        //TODO: logging error
+                    // the server closed the engine.io connection, which isn't an
+                    // error by itself, but ends the client unless it reconnects
+                    Err(Error::StoppedEngineIoSocket) => {
+                        if disconnected_by_server || !self_clone.builder.reconnect {
+                            break;
+                        }
                         true
                     }
+                    Err(error) => {
+                        let is_network_error =
+                            matches!(error, Error::IncompleteResponseFromEngineIo(_));
+                        if is_network_error {
+                            //TODO: 0.3.X handle errors
+                            event!(error; "network error occurred: {}", error);
+                        }
+                        if let Err(error) = self_clone.handle_error(error) {
+                            event!(warn; "error callback failed: {}", error);
+                        }
+                        is_network_error
+                    }
                     Ok(Packet {
                         packet_type: PacketId::Disconnect,
                         ..
-                    }) => self_clone.builder.reconnect_on_disconnect,
+                    }) => {
+                        disconnected_by_server = !self_clone.builder.reconnect_on_disconnect;
+                        self_clone.builder.reconnect_on_disconnect
+                    }
                     _ => false,
                 };
                 if should_reconnect {
-                    let _ = self_clone.disconnect();
-                    self_clone.reconnect();
+                    // a client disconnected by the server is closed already
+                    let disconnected =
+                        self_clone
+                            .client
+                            .read()
+                            .map_err(Error::from)
+                            .and_then(|client| {
+                                client.disconnect_with_reason(DisconnectReason::TransportError)
+                            });
+                    if let Err(error) = disconnected {
+                        event!(debug; "could not disconnect before reconnecting: {}", error);
+                    }
+                    // polling a closed connection again would fail at once
+                    if !self_clone.reconnect() && stopped {
+                        break;
+                    }
                 }
             }
+            // a stopped client closes the events itself once disconnected
+            if !self_clone.lifecycle.is_stopped() {
+                self_clone.events.close();
+            }
+            self_clone.lifecycle.finish();
         });
+        self.lifecycle.started(thread.thread().id())
     }
 }
 
@@ -253,7 +408,8 @@ mod test {
 
     use super::*;
     use crate::error::Result;
-    use crate::ClientBuilder;
+    use crate::mock::MockServer;
+    use crate::{ClientBuilder, TransportType};
     use serde_json::json;
     use std::time::Duration;
 
@@ -333,6 +489,9 @@ mod test {
             builder,
             client,
             backoff: Default::default(),
+            lifecycle: Default::default(),
+            events: Arc::new(EventQueue::new(false, false)),
+            guard: None,
         };
         let socket_clone = socket.clone();
 
@@ -376,6 +535,43 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_reconnect_after_server_close() -> Result<()> {
+        let timeout = Duration::from_secs(5);
+        let server = MockServer::builder().build()?;
+        let (connected, connects) = std::sync::mpsc::channel();
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .reconnect_delay(10, 10)
+            .on(Event::Connect, move |_, _| connected.send(()).unwrap())
+            .connect()?;
+        let socket = server.accept(timeout).unwrap();
+        connects.recv_timeout(timeout).unwrap();
+        socket.close();
+
+        let socket = server.accept(timeout).expect("should reconnect");
+        connects.recv_timeout(timeout).unwrap();
+        client.emit("message", json!(1))?;
+        assert!(socket.expect_event("message", timeout).is_some());
+        client.disconnect()?;
+        client.join()
+    }
+
+    #[test]
+    fn test_server_close_without_reconnect() -> Result<()> {
+        let timeout = Duration::from_secs(5);
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .reconnect(false)
+            .connect()?;
+        server.accept(timeout).unwrap().close();
+
+        client.join()?;
+        assert!(server.accept(Duration::from_millis(100)).is_none());
+        Ok(())
+    }
+
     fn load(num: &Arc<AtomicUsize>) -> usize {
         num.load(Ordering::SeqCst)
     }
@@ -0,0 +1,253 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, ThreadId},
+};
+
+use crate::error::Result;
+
+/// The number of callbacks a worker thread queues by default, see
+/// [`crate::ClientBuilder::dispatch_capacity`].
+pub(crate) const DEFAULT_CAPACITY: usize = 1024;
+
+/// Where the callbacks of a [`crate::client::Client`] are called, see
+/// [`crate::ClientBuilder::dispatch`]. Panics of callbacks are caught in all
+/// cases and passed to the error callbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Dispatch {
+    /// On the thread polling the connection, so a slow callback delays all
+    /// incoming packets and heartbeats.
+    #[default]
+    Inline,
+    /// On a dedicated worker thread, in the order the packets arrived.
+    Worker,
+    /// On a pool of the given number of threads. The callbacks of one event
+    /// are called in order, the ones of different events may run concurrently.
+    Pool(usize),
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs the callbacks as configured by a [`Dispatch`]. The worker threads
+/// exit once the dispatcher is dropped and their queued jobs ran. Dispatching
+/// to a worker which queued `capacity` jobs blocks until one of them ran,
+/// unless the worker dispatches itself, which runs the job right away then.
+#[derive(Debug)]
+pub(crate) struct Dispatcher {
+    workers: Vec<Worker>,
+    pending: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct Worker {
+    sender: SyncSender<Job>,
+    thread: ThreadId,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(dispatch: Dispatch, capacity: usize) -> Result<Self> {
+        let threads = match dispatch {
+            Dispatch::Inline => 0,
+            Dispatch::Worker => 1,
+            Dispatch::Pool(threads) => threads.max(1),
+        };
+        let pending = Arc::new(AtomicUsize::new(0));
+        let workers = (0..threads)
+            .map(|index| {
+                let (sender, receiver) = mpsc::sync_channel::<Job>(capacity);
+                let pending = pending.clone();
+                let handle = thread::Builder::new()
+                    .name(format!("socketio-dispatch-{}", index))
+                    .spawn(move || {
+                        for job in receiver {
+                            job();
+                            pending.fetch_sub(1, Ordering::AcqRel);
+                        }
+                    })?;
+                Ok(Worker {
+                    sender,
+                    thread: handle.thread().id(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Dispatcher { workers, pending })
+    }
+
+    /// Runs `job` right away or queues it on the worker `key` maps to. Jobs
+    /// with the same key run in the order they were dispatched.
+    pub(crate) fn dispatch<K, F>(&self, key: &K, job: F)
+    where
+        K: Hash + ?Sized,
+        F: FnOnce() + Send + 'static,
+    {
+        if self.workers.is_empty() {
+            return job();
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let worker = &self.workers[hasher.finish() as usize % self.workers.len()];
+
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        let sent = if worker.thread == thread::current().id() {
+            // waiting for the queue to drain would wait for the worker itself
+            match worker.sender.try_send(Box::new(job)) {
+                Err(TrySendError::Full(job)) => {
+                    job();
+                    self.pending.fetch_sub(1, Ordering::AcqRel);
+                    return;
+                }
+                sent => sent.is_ok(),
+            }
+        } else {
+            worker.sender.send(Box::new(job)).is_ok()
+        };
+        if !sent {
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+            event!(warn; "a callback was dropped as its worker thread stopped");
+        }
+    }
+
+    /// Whether all dispatched jobs ran.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.pending.load(Ordering::Acquire) == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::packet::PacketId;
+    use crate::{ClientBuilder, ErrorKind, TransportType};
+    use serde_json::json;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_dispatch() {
+        let dispatcher = Dispatcher::new(Dispatch::Inline, DEFAULT_CAPACITY).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        dispatcher.dispatch("foo", move || sender.send(thread::current().id()).unwrap());
+        assert_eq!(receiver.try_recv(), Ok(thread::current().id()));
+
+        let dispatcher = Dispatcher::new(Dispatch::Pool(4), DEFAULT_CAPACITY).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..20 {
+            let sender = sender.clone();
+            dispatcher.dispatch("foo", move || sender.send(i).unwrap());
+        }
+        let (unblock, blocked) = mpsc::channel::<()>();
+        dispatcher.dispatch("bar", move || blocked.recv().unwrap());
+        let received: Vec<_> = receiver.iter().take(20).collect();
+        // the jobs of one key keep their order
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+        assert!(!dispatcher.is_idle());
+
+        unblock.send(()).unwrap();
+        let mut waited = Duration::ZERO;
+        while !dispatcher.is_idle() && waited < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+            waited += Duration::from_millis(10);
+        }
+        assert!(dispatcher.is_idle());
+    }
+
+    #[test]
+    fn test_dispatch_capacity() {
+        let dispatcher = Arc::new(Dispatcher::new(Dispatch::Worker, 1).unwrap());
+        let (unblock, blocked) = mpsc::channel::<()>();
+        let (sender, receiver) = mpsc::channel();
+        dispatcher.dispatch("foo", move || {
+            sender
+                .send(thread::current().name().map(str::to_owned))
+                .unwrap();
+            blocked.recv().unwrap();
+        });
+        assert_eq!(
+            receiver.recv_timeout(TIMEOUT).unwrap().as_deref(),
+            Some("socketio-dispatch-0")
+        );
+        // the worker is blocked, the next job fills its queue
+        dispatcher.dispatch("foo", || {});
+
+        let (dispatched, done) = mpsc::channel();
+        let dispatching = dispatcher.clone();
+        thread::spawn(move || {
+            dispatching.dispatch("foo", || {});
+            dispatched.send(()).unwrap();
+        });
+        // a full queue blocks dispatching
+        assert!(done.recv_timeout(Duration::from_millis(200)).is_err());
+        unblock.send(()).unwrap();
+        assert!(done.recv_timeout(TIMEOUT).is_ok());
+    }
+
+    #[test]
+    fn test_dispatch_from_worker() {
+        let dispatcher = Arc::new(Dispatcher::new(Dispatch::Worker, 1).unwrap());
+        let (sender, receiver) = mpsc::channel();
+        let dispatching = dispatcher.clone();
+        dispatcher.dispatch("foo", move || {
+            // the first job fills the queue, the second one runs right away
+            for i in 0..2 {
+                let sender = sender.clone();
+                dispatching.dispatch("foo", move || sender.send(i).unwrap());
+            }
+            sender.send(2).unwrap();
+        });
+        let received: Vec<_> = (0..3)
+            .map(|_| receiver.recv_timeout(TIMEOUT).unwrap())
+            .collect();
+        assert_eq!(received, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn test_client_dispatch() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let (unblock, blocked) = mpsc::channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let (events, received) = mpsc::channel();
+        let (errors, errored) = mpsc::channel();
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .dispatch(Dispatch::Worker)
+            .on("slow", move |_, _| blocked.lock().unwrap().recv().unwrap())
+            .on("boom", |_, _| panic!("boom"))
+            .on("fast", move |payload, _| events.send(payload).unwrap())
+            .on_error(move |error, _| errors.send(error).unwrap())
+            .connect()?;
+        let socket = server.accept(TIMEOUT).unwrap();
+
+        socket.emit("boom", json!(1))?;
+        let error = errored.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(error.kind, ErrorKind::Callback);
+        assert!(error.to_string().ends_with("boom"));
+
+        // the packets are still read while the worker is blocked
+        socket.emit("slow", json!(2))?;
+        socket.emit("fast", json!(3))?;
+        let mut waited = Duration::ZERO;
+        while client.metrics()?.received[&PacketId::Event].packets < 3 && waited < TIMEOUT {
+            thread::sleep(Duration::from_millis(10));
+            waited += Duration::from_millis(10);
+        }
+        assert_eq!(client.metrics()?.received[&PacketId::Event].packets, 3);
+        assert!(received.try_recv().is_err());
+
+        unblock.send(()).unwrap();
+        assert!(received.recv_timeout(TIMEOUT).is_ok());
+        client.emit("alive", json!(4))?;
+        assert!(socket.expect_event("alive", TIMEOUT).is_some());
+        client.disconnect()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{RecvError, RecvTimeoutError, TryRecvError},
+        Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use super::RawClient;
+use crate::{error::Result, packet::Packet, Payload};
+
+/// An event received by a client, as returned by [`crate::client::Client::recv`]
+/// if enabled with [`crate::ClientBuilder::event_channel`].
+pub type ServerEvent = crate::event::ServerEvent<RawClient>;
+
+/// Answers an ack the server requested, see [`ServerEvent::ack`](crate::event::ServerEvent::ack).
+pub type AckResponder = crate::event::AckResponder<RawClient>;
+
+impl AckResponder {
+    /// Sends `data` as the answer of the ack.
+    pub fn send<D: Into<Payload>>(self, data: D) -> Result<()> {
+        let packet = Packet::ack(self.client.nsp(), self.id, data.into())?;
+        self.client.send(packet)
+    }
+}
+
+/// The received events waiting to be taken, shared by the clients of the
+/// reconnects of a [`crate::client::Client`].
+#[derive(Debug)]
+pub(crate) struct EventQueue {
+    enabled: bool,
+    /// Whether the queue belongs to a single [`RawClient`] and closes along
+    /// with its connection.
+    standalone: bool,
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    events: VecDeque<ServerEvent>,
+    closed: bool,
+}
+
+impl EventQueue {
+    pub(crate) fn new(enabled: bool, standalone: bool) -> Self {
+        EventQueue {
+            enabled,
+            standalone,
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Queues `event` if the queue is enabled and open.
+    pub(crate) fn push(&self, event: ServerEvent) {
+        if !self.enabled {
+            return;
+        }
+        let mut state = self.lock();
+        if !state.closed {
+            state.events.push_back(event);
+            self.changed.notify_all();
+        }
+    }
+
+    /// Lets the receivers return an error once they took the queued events.
+    pub(crate) fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+
+    /// Closes the queue if it belongs to the [`RawClient`] whose connection
+    /// closed.
+    pub(crate) fn connection_closed(&self) {
+        if self.standalone {
+            self.close();
+        }
+    }
+
+    pub(crate) fn try_recv(&self) -> std::result::Result<ServerEvent, TryRecvError> {
+        let mut state = self.lock();
+        match state.events.pop_front() {
+            Some(event) => Ok(event),
+            None if state.closed || !self.enabled => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub(crate) fn recv(&self) -> std::result::Result<ServerEvent, RecvError> {
+        let mut state = self.lock();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Ok(event);
+            }
+            if state.closed || !self.enabled {
+                return Err(RecvError);
+            }
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    pub(crate) fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<ServerEvent, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                return Ok(event);
+            }
+            if state.closed || !self.enabled {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .changed
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        // the lock is never held while calling code that could panic
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// An iterator over the received events, which ends once the client closed.
+/// See [`crate::client::Client::events`].
+#[derive(Debug)]
+pub struct Events<'a> {
+    queue: &'a EventQueue,
+}
+
+impl<'a> Events<'a> {
+    pub(crate) fn new(queue: &'a EventQueue) -> Self {
+        Events { queue }
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = ServerEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{mock::MockServer, ClientBuilder, Event, TransportType};
+    use serde_json::json;
+    use std::{sync::Arc, thread};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn event(name: &str) -> ServerEvent {
+        ServerEvent {
+            event: Event::from(name),
+            payload: Payload::from("1"),
+            args: vec![String::from("1")],
+            nsp: String::from("/"),
+            ack: None,
+        }
+    }
+
+    #[test]
+    fn test_event_queue() {
+        let queue = Arc::new(EventQueue::new(true, false));
+        assert_eq!(queue.try_recv().unwrap_err(), TryRecvError::Empty);
+        assert_eq!(
+            queue.recv_timeout(Duration::from_millis(10)).unwrap_err(),
+            RecvTimeoutError::Timeout
+        );
+
+        let pushing = queue.clone();
+        let pusher = thread::spawn(move || {
+            pushing.push(event("foo"));
+            pushing.push(event("bar"));
+            pushing.connection_closed();
+            pushing.close();
+        });
+        let events: Vec<_> = Events::new(&queue).map(|event| event.event).collect();
+        assert_eq!(events, vec![Event::from("foo"), Event::from("bar")]);
+        pusher.join().unwrap();
+        assert!(queue.recv().is_err());
+
+        let disabled = EventQueue::new(false, true);
+        disabled.push(event("foo"));
+        assert_eq!(disabled.try_recv().unwrap_err(), TryRecvError::Disconnected);
+    }
+
+    #[test]
+    fn test_recv() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .event_channel(true)
+            .reconnect_on_disconnect(true)
+            .reconnect_delay(10, 10)
+            .connect()?;
+        let socket = server.accept(TIMEOUT).unwrap();
+        assert_eq!(client.recv_timeout(TIMEOUT).unwrap().event, Event::Connect);
+
+        let id = socket.emit_with_ack("question", json!({"id": 1}))?;
+        let event = client.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(event.event, Event::from("question"));
+        assert_eq!(event.args, vec![r#"{"id":1}"#]);
+        event.ack.unwrap().send(json!("answer"))?;
+        assert_eq!(
+            socket.expect_ack(id, TIMEOUT).unwrap().args,
+            vec![r#""answer""#]
+        );
+
+        // the events of the reconnected client are received as well
+        socket.disconnect();
+        assert_eq!(client.recv_timeout(TIMEOUT).unwrap().event, Event::Close);
+        let socket = server.accept(TIMEOUT).unwrap();
+        socket.emit("again", json!(2))?;
+        let events: Vec<_> = client.events().take(2).map(|event| event.event).collect();
+        assert_eq!(events, vec![Event::Connect, Event::from("again")]);
+        assert_eq!(client.try_recv().unwrap_err(), TryRecvError::Empty);
+
+        client.disconnect()?;
+        assert_eq!(client.recv_timeout(TIMEOUT).unwrap().event, Event::Close);
+        assert_eq!(
+            client.recv_timeout(TIMEOUT).unwrap_err(),
+            RecvTimeoutError::Disconnected
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex, RwLock,
+};
+use std::thread::{self, ThreadId};
+
+use super::RawClient;
+use crate::error::Result;
+
+/// The thread of a client, which polls for packets and calls the callbacks,
+/// shared by the clones of the client.
+#[derive(Debug, Default)]
+pub(crate) struct Lifecycle {
+    stopped: AtomicBool,
+    thread: Mutex<Option<ThreadId>>,
+    finished: Mutex<bool>,
+    finished_changed: Condvar,
+}
+
+impl Lifecycle {
+    /// Asks the thread to stop once it polled the next packet.
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn started(&self, thread: ThreadId) -> Result<()> {
+        *self.thread.lock()? = Some(thread);
+        Ok(())
+    }
+
+    /// Marks the thread as finished.
+    pub(crate) fn finish(&self) {
+        if let Ok(mut finished) = self.finished.lock() {
+            *finished = true;
+            self.finished_changed.notify_all();
+        }
+    }
+
+    /// Blocks until the thread finished. Returns at once if called by the
+    /// thread itself, e.g. from a callback.
+    pub(crate) fn join(&self) -> Result<()> {
+        if *self.thread.lock()? == Some(thread::current().id()) {
+            return Ok(());
+        }
+        let mut finished = self.finished.lock()?;
+        while !*finished {
+            finished = self.finished_changed.wait(finished)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stops the thread and disconnects the client once the last handle of the
+/// client is dropped.
+pub(crate) struct DropGuard {
+    pub(crate) lifecycle: Arc<Lifecycle>,
+    pub(crate) client: Arc<RwLock<RawClient>>,
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        self.lifecycle.stop();
+        if let Ok(client) = self.client.read() {
+            if let Err(error) = client.disconnect() {
+                event!(debug; "could not disconnect a dropped client: {}", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::{ClientBuilder, TransportType};
+    use serde_json::json;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_disconnect_gracefully() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .connect()?;
+        let socket = server.accept(TIMEOUT).unwrap();
+
+        let (acked, ack) = mpsc::channel();
+        client.emit_with_ack("ping", json!(1), TIMEOUT, move |_, _| {
+            let _ = acked.send(());
+        })?;
+        let answering = std::thread::spawn(move || {
+            let event = socket.expect_event("ping", TIMEOUT).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            socket.ack(&event, json!(2)).unwrap();
+            socket.expect_disconnect(TIMEOUT)
+        });
+
+        client.disconnect_gracefully(TIMEOUT)?;
+        assert!(ack.try_recv().is_ok());
+        client.join()?;
+        assert!(answering.join().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .connect()?;
+        let socket = server.accept(TIMEOUT).unwrap();
+
+        let clone = client.clone();
+        drop(client);
+        assert!(!socket.expect_disconnect(Duration::from_millis(100)));
+        drop(clone);
+        assert!(socket.expect_disconnect(TIMEOUT));
+        Ok(())
+    }
+}
@@ -1,11 +1,17 @@
 mod builder;
+mod dispatch;
+mod events;
+mod lifecycle;
 mod raw_client;
 
 pub use builder::ClientBuilder;
 pub use builder::TransportType;
 pub use client::Client;
+pub use dispatch::Dispatch;
+pub use events::{AckResponder, Events, ServerEvent};
 pub use raw_client::RawClient;
 
 /// Internal callback type
 mod callback;
+#[allow(clippy::module_inception)]
 mod client;
@@ -1,12 +1,19 @@
 use super::callback::Callback;
+use super::dispatch::Dispatcher;
+use super::events::{AckResponder, EventQueue, Events, ServerEvent};
 use crate::packet::{Packet, PacketId};
 pub(crate) use crate::{event::Event, payload::Payload};
 use rand::{thread_rng, Rng};
 
-use crate::client::callback::{SocketAnyCallback, SocketCallback};
-use crate::error::Result;
-use std::collections::HashMap;
+use crate::client::callback::{
+    EventCallbacks, SocketAnyCallback, SocketCallback, SocketErrorCallback,
+};
+use crate::disconnect::DisconnectReason;
+use crate::error::{Error, ErrorEvent, Result};
+use crate::metrics::MetricsSnapshot;
 use std::ops::DerefMut;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, TryRecvError};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::time::Instant;
@@ -32,9 +39,12 @@ pub struct Ack {
 pub struct RawClient {
     /// The inner socket client to delegate the methods to.
     socket: InnerSocket,
-    on: Arc<Mutex<HashMap<Event, Callback<SocketCallback>>>>,
+    on: Arc<Mutex<EventCallbacks>>,
     on_any: Arc<Mutex<Option<Callback<SocketAnyCallback>>>>,
+    on_error: Arc<Mutex<Option<Callback<SocketErrorCallback>>>>,
     outstanding_acks: Arc<Mutex<Vec<Ack>>>,
+    dispatcher: Arc<Dispatcher>,
+    events: Arc<EventQueue>,
     // namespace, for multiplexing messages
     nsp: String,
     // Data send in the opening packet (commonly used as for auth)
@@ -46,19 +56,28 @@ impl RawClient {
     /// namespace. If `None` is passed in as namespace, the default namespace
     /// `"/"` is taken.
     /// ```
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new<T: Into<String>>(
         socket: InnerSocket,
         namespace: T,
-        on: Arc<Mutex<HashMap<Event, Callback<SocketCallback>>>>,
+        on: Arc<Mutex<EventCallbacks>>,
         on_any: Arc<Mutex<Option<Callback<SocketAnyCallback>>>>,
+        on_error: Arc<Mutex<Option<Callback<SocketErrorCallback>>>>,
+        dispatcher: Arc<Dispatcher>,
+        events: Arc<EventQueue>,
         auth: Option<serde_json::Value>,
     ) -> Result<Self> {
+        // the acks of a previous connection are dropped
+        socket.metrics().set_pending_acks(0);
         Ok(RawClient {
             socket,
             nsp: namespace.into(),
             on,
             on_any,
+            on_error,
             outstanding_acks: Arc::new(Mutex::new(Vec::new())),
+            dispatcher,
+            events,
             auth,
         })
     }
@@ -69,6 +88,7 @@ impl RawClient {
     pub(crate) fn connect(&self) -> Result<()> {
         // Connect the underlying socket
         self.socket.connect()?;
+        event!(debug, nsp = self.nsp; "connecting to namespace");
 
         let auth = self.auth.as_ref().map(|data| data.to_string());
 
@@ -82,9 +102,9 @@ impl RawClient {
 
     /// Sends a message to the server using the underlying `engine.io` protocol.
     /// This message takes an event, which could either be one of the common
-    /// events like "message" or "error" or a custom event like "foo". But be
-    /// careful, the data string needs to be valid JSON. It's recommended to use
-    /// a library like `serde_json` to serialize the data properly.
+    /// events like "message" or "error" or a custom event like "foo". A
+    /// [`Payload::String`] is sent as JSON if it is valid JSON, use
+    /// [`Payload::Json`] or [`Payload::Text`] to be explicit.
     ///
     /// # Example
     /// ```
@@ -115,7 +135,8 @@ impl RawClient {
     }
 
     /// Disconnects this client from the server by sending a `socket.io` closing
-    /// packet.
+    /// packet. The callbacks of [`Event::Close`] are passed
+    /// [`DisconnectReason::IoClientDisconnect`].
     /// # Example
     /// ```rust
     /// use rust_socketio::{ClientBuilder, Payload, RawClient};
@@ -140,23 +161,92 @@ impl RawClient {
     ///
     /// ```
     pub fn disconnect(&self) -> Result<()> {
+        self.disconnect_with_reason(DisconnectReason::IoClientDisconnect)
+    }
+
+    /// Disconnects like [`RawClient::disconnect`], passing `reason` to the
+    /// callbacks of [`Event::Close`] if the client was connected.
+    pub(crate) fn disconnect_with_reason(&self, reason: DisconnectReason) -> Result<()> {
         let disconnect_packet =
             Packet::new(PacketId::Disconnect, self.nsp.clone(), None, None, 0, None);
 
-        // TODO: logging
-        let _ = self.socket.send(disconnect_packet);
-        self.socket.disconnect()?;
-
-        let _ = self.callback(&Event::Close, ""); // trigger on_close
+        if let Err(error) = self.socket.send(disconnect_packet) {
+            event!(debug, nsp = self.nsp; "could not send the disconnect packet: {}", error);
+        }
+        if self.socket.disconnect()? {
+            self.close_callback(reason);
+        }
         Ok(())
     }
 
+    /// Calls the callbacks of [`Event::Close`] with `reason`.
+    fn close_callback(&self, reason: DisconnectReason) {
+        if let Err(error) = self.callback(&Event::Close, reason) {
+            event!(warn, nsp = self.nsp; "close callback failed: {}", error);
+        }
+    }
+
+    /// Returns a snapshot of the metrics of the client, including the ones of
+    /// the underlying engine.io connection. See [`crate::metrics`].
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.socket.metrics_snapshot()
+    }
+
+    /// Waits for the next received event, see
+    /// [`crate::ClientBuilder::event_channel`]. Fails once the connection
+    /// closed and the queued events were taken, or if the channel isn't
+    /// enabled.
+    pub fn recv(&self) -> std::result::Result<ServerEvent, RecvError> {
+        self.events.recv()
+    }
+
+    /// Waits up to `timeout` for the next received event, like
+    /// [`RawClient::recv`].
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<ServerEvent, RecvTimeoutError> {
+        self.events.recv_timeout(timeout)
+    }
+
+    /// Returns the next received event if there is one, like
+    /// [`RawClient::recv`].
+    pub fn try_recv(&self) -> std::result::Result<ServerEvent, TryRecvError> {
+        self.events.try_recv()
+    }
+
+    /// Returns an iterator waiting for the received events, which ends once
+    /// [`RawClient::recv`] fails.
+    pub fn events(&self) -> Events<'_> {
+        Events::new(&self.events)
+    }
+
+    pub(crate) fn nsp(&self) -> &str {
+        &self.nsp
+    }
+
+    /// Sends `packet`, e.g. the answer of an ack.
+    pub(crate) fn send(&self, packet: Packet) -> Result<()> {
+        self.socket.send(packet)
+    }
+
+    /// Whether no acks that did not time out yet are awaited, all packets are
+    /// written and all dispatched callbacks ran.
+    pub(crate) fn is_drained(&self) -> Result<bool> {
+        let acks = self.outstanding_acks.lock()?;
+        Ok(!acks
+            .iter()
+            .any(|ack| ack.time_started.elapsed() < ack.timeout)
+            && self.socket.metrics_snapshot().engine.buffered == 0
+            && self.dispatcher.is_idle())
+    }
+
     /// Sends a message to the server but `alloc`s an `ack` to check whether the
     /// server responded in a given time span. This message takes an event, which
     /// could either be one of the common events like "message" or "error" or a
-    /// custom event like "foo", as well as a data parameter. But be careful,
-    /// in case you send a [`Payload::String`], the string needs to be valid JSON.
-    /// It's even recommended to use a library like serde_json to serialize the data properly.
+    /// custom event like "foo", as well as a data parameter. A
+    /// [`Payload::String`] is sent as JSON if it is valid JSON, use
+    /// [`Payload::Json`] or [`Payload::Text`] to be explicit.
     /// It also requires a timeout `Duration` in which the client needs to answer.
     /// If the ack is acked in the correct time span, the specified callback is
     /// called. The callback consumes a [`Payload`] which represents the data send
@@ -178,6 +268,7 @@ impl RawClient {
     ///     match message {
     ///         Payload::String(str) => println!("{}", str),
     ///         Payload::Binary(bytes) => println!("Received bytes: {:#?}", bytes),
+    ///         _ => {}
     ///    }
     /// };
     ///
@@ -212,19 +303,72 @@ impl RawClient {
         };
 
         // add the ack to the tuple of outstanding acks
-        self.outstanding_acks.lock()?.push(ack);
+        let mut outstanding_acks = self.outstanding_acks.lock()?;
+        outstanding_acks.push(ack);
+        self.socket
+            .metrics()
+            .set_pending_acks(outstanding_acks.len());
+        drop(outstanding_acks);
 
         self.socket.send(socket_packet)?;
         Ok(())
     }
 
+    /// Polls for the next packet of the namespace. Errors are returned without
+    /// calling the error callbacks, see [`RawClient::handle_error`].
     pub(crate) fn poll(&self) -> Result<Option<Packet>> {
+        self.poll_with(|socket| socket.poll())
+    }
+
+    /// Handles the next packet if one arrived, without blocking, which lets a
+    /// client from [`crate::ClientBuilder::connect_raw`] be driven manually,
+    /// e.g. once per frame of a game loop. The callbacks run on the calling
+    /// thread unless configured otherwise with
+    /// [`crate::ClientBuilder::dispatch`], and pings are answered while
+    /// polling. Returns whether a packet was handled. Errors are returned
+    /// instead of being passed to the error callbacks.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rust_socketio::{ClientBuilder, Payload, RawClient};
+    ///
+    /// let socket = ClientBuilder::new("http://localhost:4200/")
+    ///     .on("update", |payload: Payload, _: RawClient| println!("{:?}", payload))
+    ///     .connect_raw()
+    ///     .expect("connection failed");
+    ///
+    /// loop {
+    ///     while socket.try_poll().expect("connection failed") {}
+    ///     // render the frame
+    /// }
+    /// ```
+    pub fn try_poll(&self) -> Result<bool> {
+        self.poll_timeout(Duration::ZERO)
+    }
+
+    /// Handles the next packet, waiting at most `timeout` for it to arrive.
+    /// Returns whether a packet was handled, see [`RawClient::try_poll`].
+    pub fn poll_timeout(&self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        let packet = self.poll_with(|socket| {
+            socket.poll_timeout(deadline.saturating_duration_since(Instant::now()))
+        })?;
+        Ok(packet.is_some())
+    }
+
+    fn poll_with<F>(&self, poll: F) -> Result<Option<Packet>>
+    where
+        F: Fn(&InnerSocket) -> Result<Option<Packet>>,
+    {
         loop {
-            match self.socket.poll() {
-                Err(err) => {
-                    self.callback(&Event::Error, err.to_string())?;
-                    return Err(err);
-                }
+            let result = poll(&self.socket);
+            // the engine.io connection closed, for example as the server sent
+            // no ping in time
+            if let Some(reason) = self.socket.take_close_reason() {
+                self.close_callback(reason);
+            }
+            match result {
+                Err(err) => return Err(err),
                 Ok(Some(packet)) => {
                     if packet.nsp == self.nsp {
                         self.handle_socketio_packet(&packet)?;
@@ -239,65 +383,155 @@ impl RawClient {
     }
 
     #[cfg(test)]
-    pub(crate) fn iter(&self) -> Iter {
+    pub(crate) fn iter(&self) -> Iter<'_> {
         Iter { socket: self }
     }
 
+    /// Dispatches the callbacks of `event`, see [`crate::client::Dispatch`].
     fn callback<P: Into<Payload>>(&self, event: &Event, payload: P) -> Result<()> {
-        let mut on = self.on.lock()?;
-        let mut on_any = self.on_any.lock()?;
-        let lock = on.deref_mut();
-        let on_any_lock = on_any.deref_mut();
+        self.event_callback(event, payload.into(), Vec::new(), None)
+    }
+
+    /// Queues `event` for [`RawClient::recv`] and dispatches its callbacks.
+    /// `args` and `ack` are the ones of a received event packet.
+    fn event_callback(
+        &self,
+        event: &Event,
+        payload: Payload,
+        args: Vec<String>,
+        ack: Option<i32>,
+    ) -> Result<()> {
+        self.events.push(ServerEvent {
+            event: event.clone(),
+            payload: payload.clone(),
+            args,
+            nsp: self.nsp.clone(),
+            ack: ack.map(|id| AckResponder::new(self.clone(), id)),
+        });
+        if *event == Event::Close {
+            self.events.connection_closed();
+        }
+        // the callback is locked on its own, so the ones of other events can
+        // run concurrently
+        let callback = self.on.lock()?.get(event).cloned();
+
+        let client = self.clone();
+        let event = event.clone();
+        self.dispatcher.dispatch(&event.clone(), move || {
+            let mut result = Ok(());
+            if let Some(callback) = callback {
+                result = client.call(&callback, |callback| {
+                    callback(payload.clone(), client.clone())
+                });
+            }
+            if matches!(event, Event::Message | Event::Custom(_)) {
+                result = result.and(client.call(&client.on_any, |on_any| {
+                    if let Some(callback) = on_any {
+                        callback(event.clone(), payload, client.clone())
+                    }
+                }));
+            }
+            if let Err(error) = result {
+                client.report(error);
+            }
+        });
+        Ok(())
+    }
 
-        let payload = payload.into();
+    /// Calls `call` with the locked `callback`, turning a panic into an error.
+    fn call<T>(&self, callback: &Mutex<T>, call: impl FnOnce(&mut T)) -> Result<()> {
+        let mut callback = callback.lock()?;
+        panic::catch_unwind(AssertUnwindSafe(|| call(callback.deref_mut())))
+            .map_err(Error::callback_panicked)
+    }
 
-        if let Some(callback) = lock.get_mut(event) {
-            callback(payload.clone(), self.clone());
+    /// Passes an error of a callback to the error callbacks.
+    fn report(&self, error: Error) {
+        event!(warn, nsp = self.nsp; "callback failed: {}", error);
+        if let Err(error) = self.error_callback(ErrorEvent::new(error, &self.nsp, None)) {
+            event!(warn, nsp = self.nsp; "error callback failed: {}", error);
         }
-        match event {
-            Event::Message | Event::Custom(_) => {
-                if let Some(callback) = on_any_lock {
-                    callback(event.clone(), payload, self.clone())
+    }
+
+    /// Calls the error callbacks with an error returned by [`RawClient::poll`].
+    pub(crate) fn handle_error(&self, error: Error) -> Result<()> {
+        self.error_callback(ErrorEvent::new(error, &self.nsp, None))
+    }
+
+    /// Dispatches the callbacks of [`Event::Error`] with the message of `error`
+    /// and the error callback with `error` itself. Their panics are only
+    /// logged.
+    fn error_callback(&self, error: ErrorEvent) -> Result<()> {
+        let message = error.to_string();
+        self.events.push(ServerEvent {
+            event: Event::Error,
+            payload: Payload::Text(message.clone()),
+            args: vec![serde_json::Value::from(message).to_string()],
+            nsp: self.nsp.clone(),
+            ack: None,
+        });
+        let callback = self.on.lock()?.get(&Event::Error).cloned();
+
+        let client = self.clone();
+        self.dispatcher.dispatch(&Event::Error, move || {
+            let mut result = Ok(());
+            if let Some(callback) = callback {
+                result = client.call(&callback, |callback| {
+                    callback(Payload::Text(error.to_string()), client.clone())
+                });
+            }
+            result = result.and(client.call(&client.on_error, |on_error| {
+                if let Some(callback) = on_error {
+                    callback(error, client.clone())
                 }
+            }));
+            if let Err(error) = result {
+                event!(warn, nsp = client.nsp; "error callback failed: {}", error);
             }
-            _ => {}
-        }
-        drop(on);
-        drop(on_any);
+        });
         Ok(())
     }
 
     /// Handles the incoming acks and classifies what callbacks to call and how.
     #[inline]
     fn handle_ack(&self, socket_packet: &Packet) -> Result<()> {
-        let mut to_be_removed = Vec::new();
         if let Some(id) = socket_packet.id {
-            for (index, ack) in self.outstanding_acks.lock()?.iter_mut().enumerate() {
-                if ack.id == id {
-                    to_be_removed.push(index);
-
-                    if ack.time_started.elapsed() < ack.timeout {
-                        if let Some(ref payload) = socket_packet.data {
-                            ack.callback.deref_mut()(
-                                Payload::String(payload.to_owned()),
-                                self.clone(),
-                            );
+            let mut outstanding_acks = self.outstanding_acks.lock()?;
+            let (acks, pending): (Vec<Ack>, Vec<Ack>) =
+                outstanding_acks.drain(..).partition(|ack| ack.id == id);
+            *outstanding_acks = pending;
+            self.socket
+                .metrics()
+                .set_pending_acks(outstanding_acks.len());
+            drop(outstanding_acks);
+
+            for ack in acks {
+                let latency = ack.time_started.elapsed();
+                if latency >= ack.timeout {
+                    self.socket.metrics().acked_late();
+                    continue;
+                }
+                self.socket.metrics().acked(latency);
+                let data = socket_packet.data.clone();
+                let attachment = socket_packet
+                    .attachments
+                    .as_ref()
+                    .and_then(|attachments| attachments.first().cloned());
+                let client = self.clone();
+                let callback = Mutex::new(ack.callback);
+                self.dispatcher.dispatch(&id, move || {
+                    let result = client.call(&callback, |callback| {
+                        if let Some(payload) = data {
+                            callback(Payload::String(payload), client.clone());
                         }
-                        if let Some(ref attachments) = socket_packet.attachments {
-                            if let Some(payload) = attachments.get(0) {
-                                ack.callback.deref_mut()(
-                                    Payload::Binary(payload.to_owned()),
-                                    self.clone(),
-                                );
-                            }
+                        if let Some(payload) = attachment {
+                            callback(Payload::Binary(payload), client.clone());
                         }
-                    } else {
-                        // Do something with timed out acks?
+                    });
+                    if let Err(error) = result {
+                        client.report(error);
                     }
-                }
-            }
-            for index in to_be_removed {
-                self.outstanding_acks.lock()?.remove(index);
+                });
             }
         }
         Ok(())
@@ -306,15 +540,20 @@ impl RawClient {
     /// Handles a binary event.
     #[inline]
     fn handle_binary_event(&self, packet: &Packet) -> Result<()> {
-        let event = if let Some(string_data) = &packet.data {
-            string_data.replace('\"', "").into()
-        } else {
-            Event::Message
-        };
+        let event = packet
+            .data
+            .as_ref()
+            .and_then(|data| serde_json::from_str::<String>(data).ok())
+            .map_or(Event::Message, Event::from);
 
         if let Some(attachments) = &packet.attachments {
-            if let Some(binary_payload) = attachments.get(0) {
-                self.callback(&event, Payload::Binary(binary_payload.to_owned()))?;
+            if let Some(binary_payload) = attachments.first() {
+                self.event_callback(
+                    &event,
+                    Payload::Binary(binary_payload.to_owned()),
+                    Vec::new(),
+                    packet.id,
+                )?;
             }
         }
         Ok(())
@@ -323,33 +562,8 @@ impl RawClient {
     /// A method for handling the Event Client Packets.
     // this could only be called with an event
     fn handle_event(&self, packet: &Packet) -> Result<()> {
-        // unwrap the potential data
-        if let Some(data) = &packet.data {
-            // the string must be a valid json array with the event at index 0 and the
-            // payload at index 1. if no event is specified, the message callback is used
-            if let Ok(serde_json::Value::Array(contents)) =
-                serde_json::from_str::<serde_json::Value>(data)
-            {
-                let event: Event = if contents.len() > 1 {
-                    contents
-                        .get(0)
-                        .map(|value| match value {
-                            serde_json::Value::String(ev) => ev,
-                            _ => "message",
-                        })
-                        .unwrap_or("message")
-                        .into()
-                } else {
-                    Event::Message
-                };
-                self.callback(
-                    &event,
-                    contents
-                        .get(1)
-                        .unwrap_or_else(|| contents.get(0).unwrap())
-                        .to_string(),
-                )?;
-            }
+        if let Some((event, payload, args)) = packet.event_payload()? {
+            self.event_callback(&event, payload.into(), args, packet.id)?;
         }
         Ok(())
     }
@@ -363,34 +577,26 @@ impl RawClient {
             match packet.packet_type {
                 PacketId::Ack | PacketId::BinaryAck => {
                     if let Err(err) = self.handle_ack(packet) {
-                        self.callback(&Event::Error, err.to_string())?;
-                        return Err(err);
+                        self.error_callback(ErrorEvent::new(err, &self.nsp, packet.data.clone()))?;
                     }
                 }
                 PacketId::BinaryEvent => {
                     if let Err(err) = self.handle_binary_event(packet) {
-                        self.callback(&Event::Error, err.to_string())?;
+                        self.error_callback(ErrorEvent::new(err, &self.nsp, packet.data.clone()))?;
                     }
                 }
                 PacketId::Connect => {
                     self.callback(&Event::Connect, "")?;
                 }
                 PacketId::Disconnect => {
-                    self.callback(&Event::Close, "")?;
+                    self.callback(&Event::Close, DisconnectReason::IoServerDisconnect)?;
                 }
                 PacketId::ConnectError => {
-                    self.callback(
-                        &Event::Error,
-                        String::from("Received an ConnectError frame: ")
-                            + &packet
-                                .clone()
-                                .data
-                                .unwrap_or_else(|| String::from("\"No error message provided\"")),
-                    )?;
+                    self.error_callback(ErrorEvent::connect_error(&self.nsp, packet.data.clone()))?;
                 }
                 PacketId::Event => {
                     if let Err(err) = self.handle_event(packet) {
-                        self.callback(&Event::Error, err.to_string())?;
+                        self.error_callback(ErrorEvent::new(err, &self.nsp, packet.data.clone()))?;
                     }
                 }
             }
@@ -399,10 +605,12 @@ impl RawClient {
     }
 }
 
+#[cfg(test)]
 pub struct Iter<'a> {
     socket: &'a RawClient,
 }
 
+#[cfg(test)]
 impl<'a> Iterator for Iter<'a> {
     type Item = Result<Packet>;
     fn next(&mut self) -> std::option::Option<<Self as std::iter::Iterator>::Item> {
@@ -420,9 +628,10 @@ mod test {
     use std::thread::sleep;
 
     use super::*;
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    use crate::TlsConfig;
     use crate::{client::TransportType, payload::Payload, ClientBuilder};
     use bytes::Bytes;
-    use native_tls::TlsConnector;
     use serde_json::json;
     use std::time::Duration;
 
@@ -434,6 +643,7 @@ mod test {
             .on("test", |msg, _| match msg {
                 Payload::String(str) => println!("Received string: {}", str),
                 Payload::Binary(bin) => println!("Received binary data: {:#?}", bin),
+                _ => {}
             })
             .connect()?;
 
@@ -471,6 +681,7 @@ mod test {
         Ok(())
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[test]
     fn socket_io_builder_integration() -> Result<()> {
         let url = crate::test::socket_io_server();
@@ -478,14 +689,13 @@ mod test {
         // test socket build logic
         let socket_builder = ClientBuilder::new(url);
 
-        let tls_connector = TlsConnector::builder()
-            .use_sni(true)
+        let tls_config = TlsConfig::builder()
             .build()
             .expect("Found illegal configuration");
 
         let socket = socket_builder
             .namespace("/admin")
-            .tls_config(tls_connector)
+            .tls_config(tls_config)
             .opening_header("accept-encoding", "application/json")
             .on("test", |str, _| println!("Received: {:#?}", str))
             .on("message", |payload, _| println!("{:#?}", payload))
@@ -512,6 +722,7 @@ mod test {
         Ok(())
     }
 
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
     #[test]
     fn socket_io_builder_integration_iterator() -> Result<()> {
         let url = crate::test::socket_io_server();
@@ -519,14 +730,13 @@ mod test {
         // test socket build logic
         let socket_builder = ClientBuilder::new(url);
 
-        let tls_connector = TlsConnector::builder()
-            .use_sni(true)
+        let tls_config = TlsConfig::builder()
             .build()
             .expect("Found illegal configuration");
 
         let socket = socket_builder
             .namespace("/admin")
-            .tls_config(tls_connector)
+            .tls_config(tls_config)
             .opening_header("accept-encoding", "application/json")
             .on("test", |str, _| println!("Received: {:#?}", str))
             .on("message", |payload, _| println!("{:#?}", payload))
@@ -654,6 +864,53 @@ mod test {
         test_socketio_socket(socket, "/".to_owned())
     }
 
+    #[test]
+    fn test_try_poll() -> Result<()> {
+        let timeout = Duration::from_secs(5);
+        let server = crate::mock::MockServer::builder()
+            .engine(
+                rust_engineio::mock::MockServerBuilder::new()
+                    .ping_interval(Duration::from_millis(200))
+                    .ping_timeout(Duration::from_millis(200))
+                    .upgrades(false),
+            )
+            .build()?;
+        let (sender, receiver) = mpsc::channel();
+        let closed = Arc::new(Mutex::new(false));
+        let closing = closed.clone();
+        let socket = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .on("update", move |_, _| {
+                sender.send(std::thread::current().id()).unwrap()
+            })
+            .on(Event::Close, move |_, _| *closing.lock().unwrap() = true)
+            .connect_raw()?;
+        let server_socket = server.accept(timeout).unwrap();
+
+        // the heartbeats are answered while the client is pumped
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            while socket.try_poll()? {}
+            sleep(Duration::from_millis(5));
+        }
+        assert!(!*closed.lock().unwrap());
+        assert!(socket.metrics().engine.pong_latency.is_some());
+
+        server_socket.emit("update", json!(1))?;
+        let start = Instant::now();
+        let thread = loop {
+            if let Ok(thread) = receiver.try_recv() {
+                break thread;
+            }
+            assert!(start.elapsed() < timeout);
+            socket.poll_timeout(timeout)?;
+        };
+        // the callbacks run on the polling thread
+        assert_eq!(thread, std::thread::current().id());
+        socket.disconnect()?;
+        Ok(())
+    }
+
     fn test_socketio_socket(socket: RawClient, nsp: String) -> Result<()> {
         let mut iter = socket
             .iter()
@@ -0,0 +1,187 @@
+use crate::Payload;
+use std::fmt::{self, Display, Formatter};
+
+/// The reason a client disconnected for, which is passed to the callbacks of
+/// [`crate::Event::Close`] as a [`Payload::Text`]. The names follow the
+/// reasons of the JavaScript client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// The server disconnected the client, `"io server disconnect"`.
+    IoServerDisconnect,
+    /// The client disconnected itself, `"io client disconnect"`.
+    IoClientDisconnect,
+    /// The server sent no ping in time, `"ping timeout"`.
+    PingTimeout,
+    /// The server closed the connection, `"transport close"`.
+    TransportClose,
+    /// The connection failed, `"transport error"`.
+    TransportError,
+}
+
+impl DisconnectReason {
+    /// The reason as named by the JavaScript client.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisconnectReason::IoServerDisconnect => "io server disconnect",
+            DisconnectReason::IoClientDisconnect => "io client disconnect",
+            DisconnectReason::PingTimeout => "ping timeout",
+            DisconnectReason::TransportClose => "transport close",
+            DisconnectReason::TransportError => "transport error",
+        }
+    }
+
+    /// Parses the reason from the payload of a [`crate::Event::Close`]
+    /// callback.
+    pub fn from_payload(payload: &Payload) -> Option<Self> {
+        let reason = match payload {
+            Payload::Text(reason) => reason,
+            _ => return None,
+        };
+        [
+            DisconnectReason::IoServerDisconnect,
+            DisconnectReason::IoClientDisconnect,
+            DisconnectReason::PingTimeout,
+            DisconnectReason::TransportClose,
+            DisconnectReason::TransportError,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == reason)
+    }
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<rust_engineio::DisconnectReason> for DisconnectReason {
+    fn from(reason: rust_engineio::DisconnectReason) -> Self {
+        match reason {
+            rust_engineio::DisconnectReason::ForcedClose => DisconnectReason::IoClientDisconnect,
+            rust_engineio::DisconnectReason::PingTimeout => DisconnectReason::PingTimeout,
+            _ => DisconnectReason::TransportClose,
+        }
+    }
+}
+
+impl From<DisconnectReason> for Payload {
+    fn from(reason: DisconnectReason) -> Self {
+        Payload::Text(reason.as_str().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::{ClientBuilder, TransportType};
+    use rust_engineio::mock::MockServerBuilder as EngineBuilder;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_from_payload() {
+        let payload = Payload::from(DisconnectReason::IoServerDisconnect);
+        assert_eq!(payload, Payload::Text("io server disconnect".to_owned()));
+        assert_eq!(
+            DisconnectReason::from_payload(&payload),
+            Some(DisconnectReason::IoServerDisconnect)
+        );
+        assert_eq!(DisconnectReason::from_payload(&Payload::from("")), None);
+        assert_eq!(
+            DisconnectReason::from_payload(&Payload::from("ping timeout")),
+            None
+        );
+        assert_eq!(
+            DisconnectReason::from(rust_engineio::DisconnectReason::ForcedClose),
+            DisconnectReason::IoClientDisconnect
+        );
+    }
+
+    fn connect(server: &MockServer) -> Result<(crate::client::Client, mpsc::Receiver<Payload>)> {
+        let (sender, receiver) = mpsc::channel();
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .on("close", move |payload, _| {
+                let _ = sender.send(payload);
+            })
+            .connect()?;
+        Ok((client, receiver))
+    }
+
+    fn reason(receiver: &mpsc::Receiver<Payload>) -> Option<DisconnectReason> {
+        DisconnectReason::from_payload(&receiver.recv_timeout(TIMEOUT).ok()?)
+    }
+
+    #[test]
+    fn test_close_reasons() -> Result<()> {
+        let server = MockServer::builder().build()?;
+
+        let (_client, receiver) = connect(&server)?;
+        server.accept(TIMEOUT).unwrap().disconnect();
+        assert_eq!(
+            reason(&receiver),
+            Some(DisconnectReason::IoServerDisconnect)
+        );
+
+        let (_client, receiver) = connect(&server)?;
+        server.accept(TIMEOUT).unwrap().close();
+        assert_eq!(reason(&receiver), Some(DisconnectReason::TransportClose));
+
+        let (client, receiver) = connect(&server)?;
+        let _socket = server.accept(TIMEOUT).unwrap();
+        client.disconnect()?;
+        assert_eq!(
+            reason(&receiver),
+            Some(DisconnectReason::IoClientDisconnect)
+        );
+        // the close callbacks are called once
+        client.disconnect()?;
+        assert_eq!(receiver.recv_timeout(Duration::from_millis(100)).ok(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ping_timeout() -> Result<()> {
+        let server = MockServer::builder()
+            .engine(
+                EngineBuilder::new()
+                    .ping_interval(Duration::from_millis(50))
+                    .ping_timeout(Duration::from_millis(50))
+                    .pings(false),
+            )
+            .build()?;
+        let (_client, receiver) = connect(&server)?;
+        let _socket = server.accept(TIMEOUT).unwrap();
+        assert_eq!(reason(&receiver), Some(DisconnectReason::PingTimeout));
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_close_reason() -> Result<()> {
+        use crate::asynchronous::ClientBuilder as AsyncClientBuilder;
+        use futures_util::FutureExt;
+
+        let server = MockServer::builder().build()?;
+        let (sender, receiver) = mpsc::channel();
+        let client = AsyncClientBuilder::new(server.url())
+            .on("close", move |payload, _| {
+                let _ = sender.send(payload);
+                async {}.boxed()
+            })
+            .connect()
+            .await?;
+        client.disconnect().await?;
+        assert_eq!(
+            reason(&receiver),
+            Some(DisconnectReason::IoClientDisconnect)
+        );
+        Ok(())
+    }
+}
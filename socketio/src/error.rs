@@ -1,5 +1,7 @@
 use base64::DecodeError;
 use serde_json::Error as JsonError;
+use std::any::Any;
+use std::fmt::{self, Display, Formatter};
 use std::io::Error as IoError;
 use std::num::ParseIntError;
 use std::str::Utf8Error;
@@ -46,10 +48,114 @@ pub enum Error {
     InvalidAttachmentPacketType(u8),
     #[error("Underlying Engine.IO connection has closed")]
     StoppedEngineIoSocket,
+    #[error("A callback panicked: {0}")]
+    CallbackPanicked(String),
 }
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
+/// The kind of an [`ErrorEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The underlying engine.io connection failed or was closed.
+    Transport,
+    /// The server rejected the connection to the namespace.
+    ConnectError,
+    /// A packet of the server could not be decoded.
+    InvalidPacket,
+    /// A callback panicked.
+    Callback,
+    /// Any other error, e.g. a poisoned lock.
+    Other,
+}
+
+impl From<&Error> for ErrorKind {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::IncompleteResponseFromEngineIo(_)
+            | Error::StoppedEngineIoSocket
+            | Error::IncompleteIo(_) => ErrorKind::Transport,
+            Error::InvalidPacketId(_)
+            | Error::IncompletePacket()
+            | Error::InvalidPacket()
+            | Error::InvalidUtf8(_)
+            | Error::InvalidBase64(_)
+            | Error::InvalidJson(_)
+            | Error::InvalidInteger(_)
+            | Error::InvalidAttachmentPacketType(_) => ErrorKind::InvalidPacket,
+            Error::CallbackPanicked(_) => ErrorKind::Callback,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// An error passed to the error callback of a client, see
+/// [`crate::ClientBuilder::on_error`]. Its `Display` is the message the
+/// callbacks of [`crate::Event::Error`] receive.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ErrorEvent {
+    pub kind: ErrorKind,
+    /// The error, `None` if the server rejected the connection.
+    pub error: Option<Error>,
+    /// The namespace of the client.
+    pub nsp: String,
+    /// The data of the packet the error occurred with as sent by the server,
+    /// e.g. the JSON of a `CONNECT_ERROR` packet.
+    pub data: Option<String>,
+}
+
+impl ErrorEvent {
+    pub(crate) fn new(error: Error, nsp: &str, data: Option<String>) -> Self {
+        ErrorEvent {
+            kind: ErrorKind::from(&error),
+            error: Some(error),
+            nsp: nsp.to_owned(),
+            data,
+        }
+    }
+
+    /// The server rejected the connection to `nsp` with `data`.
+    pub(crate) fn connect_error(nsp: &str, data: Option<String>) -> Self {
+        ErrorEvent {
+            kind: ErrorKind::ConnectError,
+            error: None,
+            nsp: nsp.to_owned(),
+            data,
+        }
+    }
+}
+
+impl Display for ErrorEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            Some(error) => write!(f, "{}", error),
+            None => write!(
+                f,
+                "Received an ConnectError frame: {}",
+                self.data
+                    .as_deref()
+                    .unwrap_or("\"No error message provided\"")
+            ),
+        }
+    }
+}
+
+impl Error {
+    /// A callback panicked with `panic`.
+    pub(crate) fn callback_panicked(panic: Box<dyn Any + Send>) -> Self {
+        let message = match panic.downcast::<String>() {
+            Ok(message) => *message,
+            Err(panic) => panic.downcast_ref::<&str>().map_or_else(
+                || "unknown panic".to_owned(),
+                |message| (*message).to_owned(),
+            ),
+        };
+        Error::CallbackPanicked(message)
+    }
+}
+
 impl<T> From<std::sync::PoisonError<T>> for Error {
     fn from(_: std::sync::PoisonError<T>) -> Self {
         Self::InvalidPoisonedLock()
@@ -58,7 +164,7 @@ impl<T> From<std::sync::PoisonError<T>> for Error {
 
 impl From<Error> for std::io::Error {
     fn from(err: Error) -> std::io::Error {
-        std::io::Error::new(std::io::ErrorKind::Other, err)
+        std::io::Error::other(err)
     }
 }
 
@@ -76,7 +182,27 @@ mod tests {
         assert!(matches!(Error::InvalidPoisonedLock(), _error));
 
         let _io_error = std::io::Error::from(Error::IncompletePacket());
-        let _error = std::io::Error::new(std::io::ErrorKind::Other, Error::IncompletePacket());
+        let _error = std::io::Error::other(Error::IncompletePacket());
         assert!(matches!(_io_error, _error));
     }
+
+    #[test]
+    fn test_error_event() {
+        let event = ErrorEvent::new(Error::InvalidPacket(), "/", Some("data".to_owned()));
+        assert_eq!(event.kind, ErrorKind::InvalidPacket);
+        assert_eq!(
+            event.to_string(),
+            "Got an invalid packet which did not follow the protocol format"
+        );
+
+        let event = ErrorEvent::new(Error::StoppedEngineIoSocket, "/", None);
+        assert_eq!(event.kind, ErrorKind::Transport);
+
+        let event = ErrorEvent::connect_error("/admin", Some(r#"{"message":"no"}"#.to_owned()));
+        assert_eq!(event.kind, ErrorKind::ConnectError);
+        assert_eq!(
+            event.to_string(),
+            r#"Received an ConnectError frame: {"message":"no"}"#
+        );
+    }
 }
@@ -1,3 +1,6 @@
+use crate::Payload;
+use std::fmt::{self, Debug};
+
 /// An `Event` in `socket.io` could either (`Message`, `Error`) or custom.
 #[derive(Debug, PartialEq, PartialOrd, Clone, Eq, Hash)]
 pub enum Event {
@@ -37,3 +40,59 @@ impl From<Event> for String {
         }
     }
 }
+
+/// An event received by a client, `C` is the client answering its ack. See
+/// [`crate::client::ServerEvent`] and `asynchronous::ServerEvent`.
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct ServerEvent<C> {
+    pub event: Event,
+    /// The payload the callbacks of the event receive, e.g. the first
+    /// argument or the binary attachment.
+    pub payload: Payload,
+    /// The JSON of all arguments of the event.
+    pub args: Vec<String>,
+    /// The namespace of the client.
+    pub nsp: String,
+    /// Answers the ack the server requested with the event, if it did.
+    pub ack: Option<AckResponder<C>>,
+}
+
+impl<C> Debug for ServerEvent<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerEvent")
+            .field("event", &self.event)
+            .field("payload", &self.payload)
+            .field("args", &self.args)
+            .field("nsp", &self.nsp)
+            .field("ack", &self.ack)
+            .finish()
+    }
+}
+
+/// Answers an ack the server requested, see [`ServerEvent::ack`]. Only the
+/// first answer is taken into account by the server.
+#[derive(Clone)]
+pub struct AckResponder<C> {
+    pub(crate) client: C,
+    pub(crate) id: i32,
+}
+
+impl<C> AckResponder<C> {
+    pub(crate) fn new(client: C, id: i32) -> Self {
+        AckResponder { client, id }
+    }
+
+    /// The id of the ack.
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+impl<C> Debug for AckResponder<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AckResponder")
+            .field("id", &self.id)
+            .finish()
+    }
+}
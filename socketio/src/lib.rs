@@ -13,6 +13,7 @@
 //!        match payload {
 //!            Payload::String(str) => println!("Received: {}", str),
 //!            Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+//!            _ => {}
 //!        }
 //!        socket.emit("test", json!({"got ack": true})).expect("Server unreachable")
 //! };
@@ -70,13 +71,15 @@
 //!     - message
 //!     - custom events like "foo", "on_payment", etc.
 //! - send JSON data to the server (via `serde_json` which provides safe
-//! handling).
+//!   handling).
 //! - send JSON data to the server and receive an `ack`.
 //! - send and handle Binary data.
 //!
 //! ## Async version
 //! This library provides an ability for being executed in an asynchronous context using `tokio` as
-//! the execution runtime.
+//! the default execution runtime. Enable the `async-std` or `smol` feature to run on those
+//! runtimes instead, or pass any implementation of [`asynchronous::Runtime`] to the `runtime`
+//! option of the `ClientBuilder`.
 //! Please note that the current async implementation is in beta, the interface can be object to
 //! drastic changes.
 //! The async `Client` and `ClientBuilder` support a similar interface to the sync version and live
@@ -107,6 +110,7 @@
 //!             match payload {
 //!                 Payload::String(str) => println!("Received: {}", str),
 //!                 Payload::Binary(bin_data) => println!("Received bytes: {:#?}", bin_data),
+//!                 _ => {}
 //!             }
 //!             socket
 //!                 .emit("test", json!({"got ack": true}))
@@ -159,17 +163,25 @@
 #![warn(clippy::perf)]
 #![warn(clippy::correctness)]
 
+#[macro_use]
+mod trace;
+
 /// Defines client only structs
 pub mod client;
+mod disconnect;
 /// Deprecated import since 0.3.0-alpha-2, use Event in the crate root instead.
 /// Defines the events that could be sent or received.
 pub mod event;
-pub(crate) mod packet;
+pub mod metrics;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock;
+#[doc(hidden)]
+pub mod packet;
 /// Deprecated import since 0.3.0-alpha-2, use Event in the crate root instead.
 /// Defines the types of payload (binary or string), that
 /// could be sent or received.
 pub mod payload;
-pub(self) mod socket;
+mod socket;
 
 /// Deprecated import since 0.3.0-alpha-2, use Error in the crate root instead.
 /// Contains the error type which will be returned with every result in this
@@ -182,11 +194,14 @@ pub mod error;
 /// ([`crate::asynchronous::ClientBuilder`]) that allows for configuring a client.
 pub mod asynchronous;
 
-pub use error::Error;
+pub use disconnect::DisconnectReason;
+pub use error::{Error, ErrorEvent, ErrorKind};
 
 pub use {event::Event, payload::Payload};
 
-pub use client::{ClientBuilder, RawClient, TransportType};
+pub use client::{ClientBuilder, Dispatch, RawClient, TransportType};
+
+pub use rust_engineio::{tls, CookieJar, Proxy, TlsConfig};
 
 // TODO: 0.4.0 remove
 #[deprecated(since = "0.3.0-alpha-2", note = "Socket renamed to Client")]
@@ -239,4 +254,59 @@ pub(crate) mod test {
 
         url
     }
+
+    /// Serves `count` requests of the engine.io polling transport on a local
+    /// port, answering handshakes and accepting everything else. Returns the
+    /// url of the server and the received requests, including their bodies.
+    pub(crate) fn fake_polling_server(count: usize) -> (Url, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        const HANDSHAKE: &str =
+            r#"0{"sid":"abc","upgrades":[],"pingInterval":25000,"pingTimeout":20000}"#;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..count {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = String::new();
+                reader
+                    .by_ref()
+                    .take(content_length)
+                    .read_to_string(&mut body)
+                    .unwrap();
+                request.push_str(&body);
+
+                let body = if request.starts_with("GET") && !request.contains("sid=") {
+                    HANDSHAKE
+                } else {
+                    "ok"
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, server)
+    }
 }
@@ -0,0 +1,288 @@
+//! Metrics of a client, see [`crate::client::Client::metrics`].
+//!
+//! With the `metrics` feature they are reported to the [`metrics`](https://docs.rs/metrics)
+//! facade as well, as the counters `socketio_packets_sent_total`,
+//! `socketio_bytes_sent_total`, `socketio_packets_received_total` and
+//! `socketio_bytes_received_total` labeled by packet `type`, the counter
+//! `socketio_reconnects_total`, the gauge `socketio_pending_acks`, the
+//! histogram `socketio_ack_latency_seconds` and the counter
+//! `socketio_late_acks_total`, next to the metrics of the engine.io client.
+//! The gauge sums over the live clients, a dropped client takes its value off.
+use crate::packet::{Packet, PacketId};
+use rust_engineio::metrics::TrafficCounter;
+pub use rust_engineio::metrics::{MetricsSnapshot as EngineMetricsSnapshot, Traffic};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+const PACKET_IDS: [PacketId; 7] = [
+    PacketId::Connect,
+    PacketId::Disconnect,
+    PacketId::Event,
+    PacketId::Ack,
+    PacketId::ConnectError,
+    PacketId::BinaryEvent,
+    PacketId::BinaryAck,
+];
+
+/// The upper bounds of the buckets of the ack latency histogram in
+/// milliseconds, followed by an unbounded bucket.
+const ACK_LATENCY_BOUNDS: [u64; 10] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 5000];
+
+/// A histogram of latencies.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    /// The upper bound of each bucket and the number of latencies up to it but
+    /// above the previous bound. The last bucket is bounded by [`Duration::MAX`].
+    pub buckets: Vec<(Duration, u64)>,
+    /// The number of latencies.
+    pub count: u64,
+    /// The sum of the latencies.
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    /// The mean of the latencies, if any.
+    pub fn mean(&self) -> Option<Duration> {
+        u32::try_from(self.count)
+            .ok()
+            .filter(|count| *count > 0)
+            .map(|count| self.sum / count)
+    }
+}
+
+/// A snapshot of the metrics of a socket.io client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetricsSnapshot {
+    /// The packets sent by type, the bytes include binary attachments.
+    pub sent: HashMap<PacketId, Traffic>,
+    /// The packets received by type.
+    pub received: HashMap<PacketId, Traffic>,
+    /// The number of successful reconnects.
+    pub reconnects: u64,
+    /// The number of acks waiting for an answer of the server.
+    pub pending_acks: usize,
+    /// The time from emitting with an ack to the answer of the server, for
+    /// the answers within the timeout of the ack.
+    pub ack_latency: LatencyHistogram,
+    /// The number of answers that arrived after the timeout of their ack.
+    pub late_acks: u64,
+    /// The metrics of the current engine.io connection.
+    pub engine: EngineMetricsSnapshot,
+}
+
+/// The metrics of a client, shared by its clones and kept across reconnects.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    sent: [TrafficCounter; 7],
+    received: [TrafficCounter; 7],
+    reconnects: AtomicU64,
+    pending_acks: AtomicUsize,
+    ack_buckets: [AtomicU64; ACK_LATENCY_BOUNDS.len() + 1],
+    ack_count: AtomicU64,
+    /// The sum of the ack latencies in nanoseconds.
+    ack_sum: AtomicU64,
+    late_acks: AtomicU64,
+}
+
+impl Metrics {
+    /// Counts a packet of `bytes` bytes, including its attachments.
+    pub(crate) fn sent(&self, packet: &Packet, bytes: usize) {
+        self.sent[packet.packet_type as usize].add(bytes);
+        #[cfg(feature = "metrics")]
+        {
+            let packet_type = packet_type(packet.packet_type);
+            ::metrics::counter!("socketio_packets_sent_total", "type" => packet_type).increment(1);
+            ::metrics::counter!("socketio_bytes_sent_total", "type" => packet_type)
+                .increment(bytes as u64);
+        }
+    }
+
+    pub(crate) fn received(&self, packet: &Packet, bytes: usize) {
+        self.received[packet.packet_type as usize].add(bytes);
+        #[cfg(feature = "metrics")]
+        {
+            let packet_type = packet_type(packet.packet_type);
+            ::metrics::counter!("socketio_packets_received_total", "type" => packet_type)
+                .increment(1);
+            ::metrics::counter!("socketio_bytes_received_total", "type" => packet_type)
+                .increment(bytes as u64);
+        }
+    }
+
+    pub(crate) fn reconnected(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!("socketio_reconnects_total").increment(1);
+    }
+
+    pub(crate) fn set_pending_acks(&self, pending_acks: usize) {
+        let _previous = self.pending_acks.swap(pending_acks, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!("socketio_pending_acks")
+            .increment(pending_acks as f64 - _previous as f64);
+    }
+
+    pub(crate) fn acked(&self, latency: Duration) {
+        let bucket = ACK_LATENCY_BOUNDS
+            .iter()
+            .position(|bound| latency <= Duration::from_millis(*bound))
+            .unwrap_or(ACK_LATENCY_BOUNDS.len());
+        self.ack_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.ack_count.fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.ack_sum.fetch_add(nanos, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::histogram!("socketio_ack_latency_seconds").record(latency.as_secs_f64());
+    }
+
+    /// Counts an answer to an ack that timed out, its latency isn't recorded.
+    pub(crate) fn acked_late(&self) {
+        self.late_acks.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!("socketio_late_acks_total").increment(1);
+    }
+
+    pub(crate) fn snapshot(&self, engine: EngineMetricsSnapshot) -> MetricsSnapshot {
+        let traffic = |counters: &[TrafficCounter; 7]| {
+            PACKET_IDS
+                .iter()
+                .map(|id| (*id, counters[*id as usize].get()))
+                .filter(|(_, traffic)| traffic.packets > 0)
+                .collect()
+        };
+        let bounds = ACK_LATENCY_BOUNDS
+            .iter()
+            .map(|bound| Duration::from_millis(*bound))
+            .chain([Duration::MAX]);
+        MetricsSnapshot {
+            sent: traffic(&self.sent),
+            received: traffic(&self.received),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            pending_acks: self.pending_acks.load(Ordering::Relaxed),
+            ack_latency: LatencyHistogram {
+                buckets: bounds
+                    .zip(&self.ack_buckets)
+                    .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+                    .collect(),
+                count: self.ack_count.load(Ordering::Relaxed),
+                sum: Duration::from_nanos(self.ack_sum.load(Ordering::Relaxed)),
+            },
+            late_acks: self.late_acks.load(Ordering::Relaxed),
+            engine,
+        }
+    }
+}
+
+/// Takes the pending acks of the client off the gauge, which adds up those
+/// of all clients.
+#[cfg(feature = "metrics")]
+impl Drop for Metrics {
+    fn drop(&mut self) {
+        ::metrics::gauge!("socketio_pending_acks").decrement(*self.pending_acks.get_mut() as f64);
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn packet_type(packet_id: PacketId) -> &'static str {
+    match packet_id {
+        PacketId::Connect => "connect",
+        PacketId::Disconnect => "disconnect",
+        PacketId::Event => "event",
+        PacketId::Ack => "ack",
+        PacketId::ConnectError => "connect_error",
+        PacketId::BinaryEvent => "binary_event",
+        PacketId::BinaryAck => "binary_ack",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Result;
+    use crate::mock::MockServer;
+    use crate::ClientBuilder;
+    use serde_json::json;
+    use std::thread::sleep;
+    use std::time::Instant;
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = Metrics::default();
+        let event = Packet::new(PacketId::Event, "/".to_owned(), None, None, 0, None);
+        metrics.sent(&event, 10);
+        metrics.sent(&event, 20);
+        metrics.received(&event, 5);
+        metrics.reconnected();
+        metrics.set_pending_acks(2);
+        metrics.acked(Duration::from_millis(3));
+        metrics.acked(Duration::from_millis(7));
+        metrics.acked(Duration::from_secs(10));
+        metrics.acked_late();
+
+        let snapshot = metrics.snapshot(EngineMetricsSnapshot::default());
+        assert_eq!(
+            snapshot.sent[&PacketId::Event],
+            Traffic {
+                packets: 2,
+                bytes: 30
+            }
+        );
+        assert_eq!(snapshot.received.len(), 1);
+        assert_eq!(snapshot.reconnects, 1);
+        assert_eq!(snapshot.pending_acks, 2);
+        assert_eq!(snapshot.late_acks, 1);
+
+        let histogram = snapshot.ack_latency;
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.buckets.len(), ACK_LATENCY_BOUNDS.len() + 1);
+        assert_eq!(histogram.buckets[1], (Duration::from_millis(5), 1));
+        assert_eq!(histogram.buckets[2], (Duration::from_millis(10), 1));
+        assert_eq!(histogram.buckets[10], (Duration::MAX, 1));
+        assert_eq!(histogram.sum, Duration::from_millis(10_010));
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(10_010) / 3));
+    }
+
+    #[test]
+    fn test_client_metrics() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let client = ClientBuilder::new(server.url())
+            .transport_type(crate::TransportType::Polling)
+            .connect()?;
+        let socket = server.accept(Duration::from_secs(5)).unwrap();
+
+        client.emit_with_ack("ping", json!(1), Duration::from_secs(5), |_, _| {})?;
+        assert_eq!(client.metrics()?.pending_acks, 1);
+        let event = socket.expect_event("ping", Duration::from_secs(5)).unwrap();
+        socket.ack(&event, json!(2))?;
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.metrics()?.ack_latency.count == 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        let snapshot = client.metrics()?;
+        assert_eq!(snapshot.ack_latency.count, 1);
+        assert_eq!(snapshot.pending_acks, 0);
+        assert_eq!(snapshot.reconnects, 0);
+        assert_eq!(snapshot.sent[&PacketId::Connect].packets, 1);
+        assert_eq!(snapshot.sent[&PacketId::Event].packets, 1);
+        assert_eq!(snapshot.received[&PacketId::Ack].packets, 1);
+        assert_eq!(snapshot.engine.transport, "polling");
+        assert!(snapshot.engine.total_sent().packets >= 2);
+
+        // an answer after the timeout is counted but not recorded
+        client.emit_with_ack("ping", json!(3), Duration::from_millis(10), |_, _| {})?;
+        let event = socket.expect_event("ping", Duration::from_secs(5)).unwrap();
+        sleep(Duration::from_millis(50));
+        socket.ack(&event, json!(4))?;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.metrics()?.late_acks == 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        let snapshot = client.metrics()?;
+        assert_eq!(snapshot.late_acks, 1);
+        assert_eq!(snapshot.ack_latency.count, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,649 @@
+//! A scriptable `socket.io` server on localhost, which runs inside the test
+//! process instead of the node servers in `ci/`. It is built on the
+//! `engine.io` server of [`rust_engineio::mock`], so it speaks polling and
+//! websocket as well. Tests decide whether a connection is accepted, expect
+//! the events the client emits, and emit events, acks and disconnects.
+//!
+//! # Example
+//! ```rust
+//! use rust_socketio::mock::MockServer;
+//! use rust_socketio::ClientBuilder;
+//! use serde_json::json;
+//! use std::time::Duration;
+//!
+//! let server = MockServer::builder().build().unwrap();
+//! let client = ClientBuilder::new(server.url()).connect().unwrap();
+//! let socket = server.accept(Duration::from_secs(5)).unwrap();
+//!
+//! client.emit("greet", json!({"name": "ferris"})).unwrap();
+//! let event = socket.expect_event("greet", Duration::from_secs(5)).unwrap();
+//! assert_eq!(event.args, vec![r#"{"name":"ferris"}"#]);
+//! ```
+use crate::error::Result;
+use crate::packet::{Packet, PacketId};
+use crate::{Event, Payload};
+use bytes::Bytes;
+use rust_engineio::mock::MockSession;
+use rust_engineio::mock::{MockServer as EngineServer, MockServerBuilder as EngineBuilder};
+use rust_engineio::{Packet as EnginePacket, PacketId as EnginePacketId};
+use serde_json::value::RawValue;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// How long a wait for the client goes on before it checks whether the
+/// session was closed.
+const CLOSE_INTERVAL: Duration = Duration::from_millis(50);
+
+type AuthCheck = Arc<dyn Fn(&str, Option<&str>) -> std::result::Result<(), String> + Send + Sync>;
+
+/// Configures and starts a [`MockServer`].
+#[derive(Clone)]
+pub struct MockServerBuilder {
+    engine: EngineBuilder,
+    auth: Option<AuthCheck>,
+}
+
+impl MockServerBuilder {
+    pub fn new() -> Self {
+        MockServerBuilder {
+            engine: EngineBuilder::new(),
+            auth: None,
+        }
+    }
+
+    /// Configures the underlying `engine.io` server.
+    pub fn engine(mut self, engine: EngineBuilder) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Decides whether a client may connect, given the namespace and the auth
+    /// data it sent as JSON. Returning an error rejects the connection with
+    /// the error as message. All connections are accepted by default.
+    pub fn auth<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&str, Option<&str>) -> std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        self.auth = Some(Arc::new(check));
+        self
+    }
+
+    /// Rejects every connection with `message`.
+    pub fn reject_auth<T: Into<String>>(self, message: T) -> Self {
+        let message = message.into();
+        self.auth(move |_, _| Err(message.clone()))
+    }
+
+    /// Binds the server to a free port on localhost and starts serving.
+    pub fn build(self) -> Result<MockServer> {
+        Ok(MockServer {
+            engine: self.engine.build()?,
+            auth: self.auth,
+        })
+    }
+}
+
+impl Default for MockServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for MockServerBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockServerBuilder")
+            .field("engine", &self.engine)
+            .finish()
+    }
+}
+
+/// A `socket.io` server for tests, see the [module documentation](self).
+pub struct MockServer {
+    engine: EngineServer,
+    auth: Option<AuthCheck>,
+}
+
+impl MockServer {
+    pub fn builder() -> MockServerBuilder {
+        MockServerBuilder::new()
+    }
+
+    /// The url to connect clients to.
+    pub fn url(&self) -> Url {
+        self.engine.url()
+    }
+
+    /// Waits for the next client to connect to a namespace, `None` if no
+    /// client did within `timeout`. Rejected connections are returned as
+    /// well, see [`MockSocket::is_rejected`].
+    pub fn accept(&self, timeout: Duration) -> Option<MockSocket> {
+        let deadline = Instant::now() + timeout;
+        let session = self.engine.accept(timeout)?;
+        let mut socket = MockSocket {
+            session,
+            nsp: String::from("/"),
+            auth: None,
+            rejected: false,
+            state: Mutex::new(State::default()),
+        };
+
+        loop {
+            let packet = socket.read(deadline.saturating_duration_since(Instant::now()))?;
+            if packet.packet_type != PacketId::Connect {
+                continue;
+            }
+            socket.nsp = packet.nsp;
+            socket.auth = packet.data;
+
+            let check = self.auth.as_ref();
+            let response = match check.map_or(Ok(()), |check| check(&socket.nsp, socket.auth())) {
+                Ok(()) => Packet::new(
+                    PacketId::Connect,
+                    socket.nsp.clone(),
+                    Some(serde_json::json!({ "sid": socket.session.sid() }).to_string()),
+                    None,
+                    0,
+                    None,
+                ),
+                Err(message) => {
+                    socket.rejected = true;
+                    Packet::new(
+                        PacketId::ConnectError,
+                        socket.nsp.clone(),
+                        Some(serde_json::json!({ "message": message }).to_string()),
+                        None,
+                        0,
+                        None,
+                    )
+                }
+            };
+            socket.send(response);
+            return Some(socket);
+        }
+    }
+}
+
+impl Debug for MockServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockServer")
+            .field("engine", &self.engine)
+            .finish()
+    }
+}
+
+/// An event emitted by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedEvent {
+    pub event: String,
+    /// The arguments following the event as JSON, without the placeholders of
+    /// binary attachments.
+    pub args: Vec<String>,
+    pub attachments: Vec<Bytes>,
+    /// The id of the ack the client waits for.
+    pub id: Option<i32>,
+}
+
+/// An ack the client answered, see [`MockSocket::emit_with_ack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedAck {
+    pub id: i32,
+    /// The arguments of the answer as JSON.
+    pub args: Vec<String>,
+    pub attachments: Vec<Bytes>,
+}
+
+/// The server side of the connection of a client to a namespace.
+#[derive(Debug)]
+pub struct MockSocket {
+    session: MockSession,
+    nsp: String,
+    auth: Option<String>,
+    rejected: bool,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    events: VecDeque<ReceivedEvent>,
+    acks: VecDeque<ReceivedAck>,
+    next_ack_id: i32,
+    disconnected: bool,
+}
+
+impl MockSocket {
+    /// The namespace the client connected to.
+    pub fn nsp(&self) -> &str {
+        &self.nsp
+    }
+
+    /// The auth data the client sent as JSON.
+    pub fn auth(&self) -> Option<&str> {
+        self.auth.as_deref()
+    }
+
+    /// Whether the server rejected the connection.
+    pub fn is_rejected(&self) -> bool {
+        self.rejected
+    }
+
+    /// The underlying `engine.io` session.
+    pub fn engine(&self) -> &MockSession {
+        &self.session
+    }
+
+    /// Waits for the next event the client emits, `None` if it emitted none
+    /// within `timeout`.
+    pub fn next_event(&self, timeout: Duration) -> Option<ReceivedEvent> {
+        self.wait_for(timeout, |state| state.events.pop_front())
+    }
+
+    /// Waits for the client to emit `event`, `None` if it didn't within
+    /// `timeout`. Other events are kept for later calls.
+    pub fn expect_event(&self, event: &str, timeout: Duration) -> Option<ReceivedEvent> {
+        self.wait_for(timeout, |state| {
+            let index = state.events.iter().position(|ev| ev.event == event)?;
+            state.events.remove(index)
+        })
+    }
+
+    /// Waits for the client to disconnect from the namespace or to close the
+    /// session, returns whether it did within `timeout`.
+    pub fn expect_disconnect(&self, timeout: Duration) -> bool {
+        self.wait_for(timeout, |state| state.disconnected.then_some(()))
+            .is_some()
+    }
+
+    /// Waits for the client to answer the ack `id`, `None` if it didn't within
+    /// `timeout`. Other acks are kept for later calls.
+    pub fn expect_ack(&self, id: i32, timeout: Duration) -> Option<ReceivedAck> {
+        self.wait_for(timeout, |state| {
+            let index = state.acks.iter().position(|ack| ack.id == id)?;
+            state.acks.remove(index)
+        })
+    }
+
+    /// Emits `event` with `data` to the client.
+    pub fn emit<D: Into<Payload>>(&self, event: &str, data: D) -> Result<()> {
+        self.emit_packet(event, data.into(), None)
+    }
+
+    /// Emits `event` with `data` to the client and requests an ack, returns
+    /// its id, see [`MockSocket::expect_ack`].
+    pub fn emit_with_ack<D: Into<Payload>>(&self, event: &str, data: D) -> Result<i32> {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            state.next_ack_id += 1;
+            state.next_ack_id
+        };
+        self.emit_packet(event, data.into(), Some(id))?;
+        Ok(id)
+    }
+
+    fn emit_packet(&self, event: &str, data: Payload, id: Option<i32>) -> Result<()> {
+        let packet = match data {
+            Payload::Binary(ref bin_data) => Packet::new(
+                PacketId::BinaryEvent,
+                self.nsp.clone(),
+                Some(data.event_data(Event::from(event))?),
+                id,
+                1,
+                Some(vec![bin_data.clone()]),
+            ),
+            _ => Packet::new(
+                PacketId::Event,
+                self.nsp.clone(),
+                Some(data.event_data(Event::from(event))?),
+                id,
+                0,
+                None,
+            ),
+        };
+        self.send(packet);
+        Ok(())
+    }
+
+    /// Answers the ack the client requested with `event`. Does nothing if the
+    /// client requested none.
+    pub fn ack<D: Into<Payload>>(&self, event: &ReceivedEvent, data: D) -> Result<()> {
+        let id = match event.id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let packet = Packet::ack(&self.nsp, id, data.into())?;
+        self.send(packet);
+        Ok(())
+    }
+
+    /// Disconnects the client from the namespace.
+    pub fn disconnect(&self) {
+        self.send(Packet::new(
+            PacketId::Disconnect,
+            self.nsp.clone(),
+            None,
+            None,
+            0,
+            None,
+        ));
+    }
+
+    /// Closes the underlying `engine.io` session.
+    pub fn close(&self) {
+        self.session.close();
+    }
+
+    fn send(&self, packet: Packet) {
+        self.session.send(EnginePacket::new(
+            EnginePacketId::Message,
+            Bytes::from(&packet),
+        ));
+        for attachment in packet.attachments.unwrap_or_default() {
+            self.session
+                .send(EnginePacket::new(EnginePacketId::MessageBinary, attachment));
+        }
+    }
+
+    /// Reads packets until `take` returns something, `None` if it didn't
+    /// within `timeout`.
+    fn wait_for<T>(&self, timeout: Duration, take: impl Fn(&mut State) -> Option<T>) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(result) = take(&mut self.state.lock().unwrap()) {
+                return Some(result);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.read(remaining.min(CLOSE_INTERVAL)) {
+                Some(packet) => self.handle(packet),
+                None if self.session.is_closed() => {
+                    let mut state = self.state.lock().unwrap();
+                    state.disconnected = true;
+                    return take(&mut state);
+                }
+                None if remaining.is_zero() => return None,
+                None => (),
+            }
+        }
+    }
+
+    /// Stores a packet received from the client.
+    fn handle(&self, packet: Packet) {
+        let mut state = self.state.lock().unwrap();
+        match packet.packet_type {
+            PacketId::Event | PacketId::BinaryEvent => {
+                let args = match (packet.packet_type, packet.data.as_ref()) {
+                    (PacketId::Event, Some(data)) => parse_args(data),
+                    // the decoder strips the brackets of binary events
+                    (_, Some(data)) => parse_args(&format!("[{}]", data)),
+                    (_, None) => Vec::new(),
+                };
+                let mut args = args.into_iter();
+                let event = args
+                    .next()
+                    .and_then(|event| serde_json::from_str(&event).ok())
+                    .unwrap_or_else(|| String::from("message"));
+                state.events.push_back(ReceivedEvent {
+                    event,
+                    args: args.collect(),
+                    attachments: packet.attachments.unwrap_or_default(),
+                    id: packet.id,
+                });
+            }
+            PacketId::Ack | PacketId::BinaryAck => {
+                if let Some(id) = packet.id {
+                    state.acks.push_back(ReceivedAck {
+                        id,
+                        args: packet.data.as_deref().map(parse_args).unwrap_or_default(),
+                        attachments: packet.attachments.unwrap_or_default(),
+                    });
+                }
+            }
+            PacketId::Disconnect => state.disconnected = true,
+            _ => (),
+        }
+    }
+
+    /// Reads the next packet of the client along with its attachments.
+    fn read(&self, timeout: Duration) -> Option<Packet> {
+        let deadline = Instant::now() + timeout;
+        let message = self.session.recv(timeout)?;
+        let mut packet = Packet::try_from(&message.data).ok()?;
+
+        let mut attachments = Vec::new();
+        while attachments.len() < packet.attachment_count as usize {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            attachments.push(self.session.recv(remaining)?.data);
+        }
+        if !attachments.is_empty() {
+            packet.attachments = Some(attachments);
+        }
+        Some(packet)
+    }
+}
+
+/// Splits a JSON array into the JSON of its elements.
+fn parse_args(data: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<&RawValue>>(data)
+        .map(|args| args.into_iter().map(|arg| arg.get().to_owned()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ClientBuilder, ErrorKind, RawClient, TransportType};
+    use serde_json::json;
+    use std::sync::mpsc;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_events_and_acks() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let (received, updates) = mpsc::channel();
+        let (acked, acks) = mpsc::channel();
+        let client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Polling)
+            .auth(json!({"token": "123"}))
+            .on("update", move |payload, _| {
+                received.send(payload).unwrap();
+            })
+            .connect()?;
+
+        let socket = server.accept(TIMEOUT).unwrap();
+        assert_eq!(socket.nsp(), "/");
+        assert_eq!(socket.auth(), Some(r#"{"token":"123"}"#));
+        assert!(!socket.is_rejected());
+
+        client.emit("first", json!(1))?;
+        client.emit("second", Payload::Text(String::from("a \"quote\"")))?;
+        client.emit("binary", vec![1, 2, 3])?;
+        let event = socket.expect_event("second", TIMEOUT).unwrap();
+        assert_eq!(event.args, vec![r#""a \"quote\"""#]);
+        let event = socket.next_event(TIMEOUT).unwrap();
+        assert_eq!(
+            (event.event.as_str(), event.args),
+            ("first", vec!["1".to_owned()])
+        );
+        let event = socket.next_event(TIMEOUT).unwrap();
+        assert_eq!(event.event, "binary");
+        assert_eq!(event.attachments, vec![Bytes::from_static(&[1, 2, 3])]);
+
+        socket.emit("update", json!({"id": 1}))?;
+        assert_eq!(
+            updates.recv_timeout(TIMEOUT).unwrap(),
+            Payload::String(String::from(r#"{"id":1}"#))
+        );
+
+        client.emit_with_ack(
+            "request",
+            json!("data"),
+            TIMEOUT,
+            move |payload: Payload, _: RawClient| {
+                acked.send(payload).unwrap();
+            },
+        )?;
+        let event = socket.expect_event("request", TIMEOUT).unwrap();
+        assert!(event.id.is_some());
+        socket.ack(&event, json!("response"))?;
+        assert_eq!(
+            acks.recv_timeout(TIMEOUT).unwrap(),
+            Payload::String(String::from(r#"["response"]"#))
+        );
+
+        client.disconnect()?;
+        assert!(socket.expect_disconnect(TIMEOUT));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_auth() -> Result<()> {
+        let server = MockServer::builder()
+            .auth(|nsp, auth| match auth {
+                Some(r#"{"token":"123"}"#) if nsp == "/admin" => Ok(()),
+                _ => Err(String::from("Not authorized")),
+            })
+            .build()?;
+        let (errors, error) = mpsc::channel();
+        let _client = ClientBuilder::new(server.url())
+            .namespace("/admin")
+            .auth(json!({"token": "456"}))
+            .on("error", move |payload, _| {
+                let _ = errors.send(payload);
+            })
+            .connect()?;
+
+        let socket = server.accept(TIMEOUT).unwrap();
+        assert_eq!(socket.nsp(), "/admin");
+        assert!(socket.is_rejected());
+        assert!(matches!(
+            error.recv_timeout(TIMEOUT).unwrap(),
+            Payload::Text(message) if message.ends_with(r#"{"message":"Not authorized"}"#)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_event() -> Result<()> {
+        let server = MockServer::builder()
+            .reject_auth("Not authorized")
+            .build()?;
+        let (errors, error) = mpsc::channel();
+        let _client = ClientBuilder::new(server.url())
+            .namespace("/admin")
+            .on_error(move |error, _| {
+                let _ = errors.send(error);
+            })
+            .connect()?;
+
+        assert!(server.accept(TIMEOUT).unwrap().is_rejected());
+        let error = error.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(error.kind, ErrorKind::ConnectError);
+        assert!(error.error.is_none());
+        assert_eq!(error.nsp, "/admin");
+        assert_eq!(
+            error.data.as_deref(),
+            Some(r#"{"message":"Not authorized"}"#)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_forced_disconnect() -> Result<()> {
+        let server = MockServer::builder().build()?;
+        let (closed, close) = mpsc::channel();
+        let _client = ClientBuilder::new(server.url())
+            .transport_type(TransportType::Websocket)
+            .on("close", move |_, _| {
+                let _ = closed.send(());
+            })
+            .connect()?;
+
+        let socket = server.accept(TIMEOUT).unwrap();
+        assert!(socket.engine().is_websocket());
+        socket.disconnect();
+        assert!(close.recv_timeout(TIMEOUT).is_ok());
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_client() -> Result<()> {
+        use crate::asynchronous::ClientBuilder as AsyncClientBuilder;
+        use futures_util::FutureExt;
+        use tokio::task::spawn_blocking;
+
+        let server = Arc::new(MockServer::builder().build()?);
+        let (received, updates) = mpsc::channel();
+        let client = AsyncClientBuilder::new(server.url())
+            .on("update", move |payload, _| {
+                let received = received.clone();
+                async move {
+                    received.send(payload).unwrap();
+                }
+                .boxed()
+            })
+            .connect()
+            .await?;
+
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+        client.emit("hello", json!("world")).await?;
+        let (socket, event) = spawn_blocking(move || {
+            let event = socket.expect_event("hello", TIMEOUT);
+            (socket, event)
+        })
+        .await
+        .unwrap();
+        assert_eq!(event.unwrap().args, vec![r#""world""#]);
+
+        socket.emit("update", json!([1]))?;
+        let update = spawn_blocking(move || updates.recv_timeout(TIMEOUT))
+            .await
+            .unwrap();
+        assert_eq!(update.unwrap(), Payload::String(String::from("[1]")));
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_async_error_event() -> Result<()> {
+        use crate::asynchronous::ClientBuilder as AsyncClientBuilder;
+        use futures_util::FutureExt;
+        use tokio::task::spawn_blocking;
+
+        let server = Arc::new(
+            MockServer::builder()
+                .reject_auth("Not authorized")
+                .build()?,
+        );
+        let (errors, error) = mpsc::channel();
+        let _client = AsyncClientBuilder::new(server.url())
+            .on_error(move |error, _| {
+                let _ = errors.send(error);
+                async {}.boxed()
+            })
+            .connect()
+            .await?;
+
+        let accepting = server.clone();
+        let socket = spawn_blocking(move || accepting.accept(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(socket.is_rejected());
+        let error = spawn_blocking(move || error.recv_timeout(TIMEOUT))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(error.kind, ErrorKind::ConnectError);
+        assert_eq!(error.nsp, "/");
+        Ok(())
+    }
+}
@@ -1,11 +1,14 @@
 use crate::error::{Error, Result};
 use crate::Error::{InvalidJson, InvalidUtf8};
+use crate::{Event, Payload};
 use bytes::{BufMut, Bytes, BytesMut};
-use regex::Regex;
+use serde::Deserialize;
+use serde_json::value::RawValue;
 use std::convert::TryFrom;
+use std::str::from_utf8;
 
 /// An enumeration of the different `Packet` types in the `socket.io` protocol.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum PacketId {
     Connect = 0,
     Disconnect = 1,
@@ -82,6 +85,61 @@ impl Packet {
             attachments,
         }
     }
+
+    /// Returns the packet answering the ack `id` with `payload`.
+    pub(crate) fn ack(nsp: &str, id: i32, payload: Payload) -> Result<Self> {
+        Ok(match payload {
+            Payload::Binary(bin_data) => Packet::new(
+                PacketId::BinaryAck,
+                nsp.to_owned(),
+                None,
+                Some(id),
+                1,
+                Some(vec![bin_data]),
+            ),
+            payload => Packet::new(
+                PacketId::Ack,
+                nsp.to_owned(),
+                payload.ack_data()?,
+                Some(id),
+                0,
+                None,
+            ),
+        })
+    }
+
+    /// Splits the data of an event packet into the event, its payload and the
+    /// JSON of its arguments. The data is an array with the event at index 0
+    /// and the arguments, the first of which is the payload, after it, or only
+    /// the payload of a message. Data other than arrays is ignored. Binary
+    /// events carry their argument as attachment only.
+    pub(crate) fn event_payload(&self) -> Result<Option<(Event, String, Vec<String>)>> {
+        let data = match self.data.as_ref() {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let contents: Vec<&RawValue> = match serde_json::from_str(data) {
+            Ok(contents) => contents,
+            Err(err) if err.is_data() => return Ok(None),
+            Err(err) => return Err(InvalidJson(err)),
+        };
+
+        let (event, args) = match contents.as_slice() {
+            [] => return Err(Error::IncompletePacket()),
+            [_] => (Event::Message, contents.as_slice()),
+            [event, args @ ..] => {
+                let event =
+                    serde_json::from_str::<String>(event.get()).map_or(Event::Message, Event::from);
+                (event, args)
+            }
+        };
+        let payload = args[0].get().to_owned();
+        let args = match self.packet_type {
+            PacketId::Event => args.iter().map(|arg| arg.get().to_owned()).collect(),
+            _ => Vec::new(),
+        };
+        Ok(Some((event, payload, args)))
+    }
 }
 
 impl From<Packet> for Bytes {
@@ -160,107 +218,98 @@ impl TryFrom<&Bytes> for Packet {
     /// this member. This is done because the attachment is usually
     /// send in another packet.
     fn try_from(payload: &Bytes) -> Result<Packet> {
-        let mut packet: Packet = Default::default();
-        let payload_utf8 =
-            String::from_utf8(payload.to_vec()).map_err(|e| InvalidUtf8(e.utf8_error()))?;
-        let mut utf8_iter = payload_utf8.chars().peekable();
-        let mut next_utf8;
-        let mut char_buf: Vec<char> = vec![];
-
-        // packet_type
-        packet.packet_type =
-            PacketId::try_from(utf8_iter.next().ok_or(Error::IncompletePacket())?)?;
-
-        // attachment_count
-        if let PacketId::BinaryAck | PacketId::BinaryEvent = packet.packet_type {
-            loop {
-                next_utf8 = utf8_iter.peek().ok_or(Error::IncompletePacket())?;
-                if *next_utf8 == '-' {
-                    let _ = utf8_iter.next(); // consume '-' char
-                    break;
-                }
-                char_buf.push(utf8_iter.next().unwrap()); // SAFETY: already peeked
-            }
-        }
-        let count_str: String = char_buf.iter().collect();
-        if let Ok(count) = count_str.parse::<u8>() {
-            packet.attachment_count = count;
-        }
-
-        // namespace
-        char_buf.clear();
-        next_utf8 = match utf8_iter.peek() {
-            Some(c) => c,
-            None => return Ok(packet),
+        let payload = from_utf8(payload).map_err(InvalidUtf8)?;
+        let bytes = payload.as_bytes();
+        let mut packet = Packet {
+            packet_type: PacketId::try_from(*bytes.first().ok_or(Error::IncompletePacket())?)?,
+            ..Default::default()
         };
+        let mut pos = 1;
 
-        if *next_utf8 == '/' {
-            char_buf.push(utf8_iter.next().unwrap()); // SAFETY: already peeked
-            loop {
-                next_utf8 = utf8_iter.peek().ok_or(Error::IncompletePacket())?;
-                if *next_utf8 == ',' {
-                    let _ = utf8_iter.next(); // consume ','
-                    break;
-                }
-                char_buf.push(utf8_iter.next().unwrap()); // SAFETY: already peeked
+        // attachment_count, followed by '-'
+        if let PacketId::BinaryAck | PacketId::BinaryEvent = packet.packet_type {
+            let end = pos + payload[pos..].find('-').ok_or(Error::IncompletePacket())?;
+            if let Ok(count) = payload[pos..end].parse::<u8>() {
+                packet.attachment_count = count;
             }
-        }
-        if !char_buf.is_empty() {
-            packet.nsp = char_buf.iter().collect();
+            pos = end + 1;
         }
 
-        // id
-        char_buf.clear();
-        next_utf8 = match utf8_iter.peek() {
-            None => return Ok(packet),
-            Some(c) => c,
-        };
-
-        loop {
-            if !next_utf8.is_ascii_digit() {
-                break;
-            }
-            char_buf.push(utf8_iter.next().unwrap()); // SAFETY: already peeked
-            next_utf8 = match utf8_iter.peek() {
-                None => return Ok(packet),
-                Some(c) => c,
-            };
+        // namespace, followed by ','
+        if bytes.get(pos) == Some(&b'/') {
+            let end = pos + payload[pos..].find(',').ok_or(Error::IncompletePacket())?;
+            packet.nsp = payload[pos..end].to_owned();
+            pos = end + 1;
         }
 
-        let count_str: String = char_buf.iter().collect();
-        if let Ok(count) = count_str.parse::<i32>() {
-            packet.id = Some(count);
+        // id
+        let digits = bytes[pos..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        if let Ok(id) = payload[pos..pos + digits].parse::<i32>() {
+            packet.id = Some(id);
         }
+        pos += digits;
 
         // data
-        let json_str: String = utf8_iter.collect();
-        let json_data: serde_json::Value = serde_json::from_str(&json_str).map_err(InvalidJson)?;
-
-        match packet.packet_type {
-            PacketId::BinaryAck | PacketId::BinaryEvent => {
-                let re_close = Regex::new(r",]$|]$").unwrap();
-                let mut str = json_data
-                    .to_string()
-                    .replace("{\"_placeholder\":true,\"num\":0}", "");
-
-                if str.starts_with('[') {
-                    str.remove(0);
-                }
-                str = re_close.replace(&str, "").to_string();
-
-                if str.is_empty() {
-                    packet.data = None
-                } else {
-                    packet.data = Some(str)
-                }
+        let data = &payload[pos..];
+        if data.is_empty() {
+            return Ok(packet);
+        }
+        packet.data = match packet.packet_type {
+            PacketId::BinaryAck | PacketId::BinaryEvent => strip_placeholders(data)?,
+            // the data of events is parsed once they are handled
+            PacketId::Event => Some(data.to_owned()),
+            _ => {
+                serde_json::from_str::<&RawValue>(data).map_err(InvalidJson)?;
+                Some(data.to_owned())
             }
-            _ => packet.data = Some(json_data.to_string()),
         };
 
         Ok(packet)
     }
 }
 
+/// Joins the elements of the JSON array `data` other than the placeholders of
+/// the attachments with ','.
+fn strip_placeholders(data: &str) -> Result<Option<String>> {
+    let elements: Vec<&RawValue> = serde_json::from_str(data).map_err(InvalidJson)?;
+    let mut stripped = String::with_capacity(data.len());
+    for element in elements.into_iter().map(RawValue::get) {
+        if is_placeholder(element) {
+            continue;
+        }
+        if !stripped.is_empty() {
+            stripped.push(',');
+        }
+        stripped.push_str(element);
+    }
+
+    if stripped.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(stripped))
+    }
+}
+
+/// The placeholder of an attachment in the data of a binary packet.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Placeholder {
+    #[serde(rename = "_placeholder")]
+    placeholder: bool,
+    #[serde(rename = "num")]
+    _num: u64,
+}
+
+/// Whether the JSON `element` is exactly the placeholder of an attachment,
+/// i.e. `{"_placeholder":true,"num":<n>}`.
+fn is_placeholder(element: &str) -> bool {
+    element.starts_with('{')
+        && serde_json::from_str::<Placeholder>(element).is_ok_and(|element| element.placeholder)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -586,6 +635,94 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_decode_edge_cases() {
+        let packet = Packet::try_from(&Bytes::from_static(b"3/admin,456")).unwrap();
+        assert_eq!(packet.id, Some(456));
+        assert_eq!(packet.data, None);
+
+        let packet = Packet::try_from(&Bytes::from_static(
+            b"52-[\"hello\",{\"_placeholder\":true,\"num\":0},{\"_placeholder\":true,\"num\":1}]",
+        ))
+        .unwrap();
+        assert_eq!(packet.attachment_count, 2);
+        assert_eq!(packet.data, Some(String::from("\"hello\"")));
+
+        let packet = Packet::try_from(&Bytes::from_static(
+            b"51-[\"hello\",{ \"_placeholder\" : true, \"num\" : 0 }]",
+        ))
+        .unwrap();
+        assert_eq!(packet.data, Some(String::from("\"hello\"")));
+
+        let packet = Packet::try_from(&Bytes::from_static(
+            b"51-[\"hello\",{\"file\":{\"_placeholder\":true,\"num\":0}},{\"_placeholder\":true,\"num\":0,\"id\":1}]",
+        ))
+        .unwrap();
+        assert_eq!(
+            packet.data,
+            Some(String::from(
+                "\"hello\",{\"file\":{\"_placeholder\":true,\"num\":0}},{\"_placeholder\":true,\"num\":0,\"id\":1}"
+            ))
+        );
+
+        assert!(matches!(
+            Packet::try_from(&Bytes::from_static(b"0{\"sid\":")),
+            Err(Error::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Packet::try_from(&Bytes::from_static(b"51/admin,[]")),
+            Err(Error::IncompletePacket())
+        ));
+    }
+
+    #[test]
+    fn test_event_payload() {
+        let packet = |data: &str| {
+            Packet::new(
+                PacketId::Event,
+                "/".to_owned(),
+                Some(data.to_owned()),
+                None,
+                0,
+                None,
+            )
+        };
+
+        assert_eq!(
+            packet("[\"update\",{\"id\":1},2]").event_payload().unwrap(),
+            Some((
+                Event::from("update"),
+                String::from("{\"id\":1}"),
+                vec![String::from("{\"id\":1}"), String::from("2")]
+            ))
+        );
+        assert_eq!(
+            packet("[\"hello\"]").event_payload().unwrap(),
+            Some((
+                Event::Message,
+                String::from("\"hello\""),
+                vec![String::from("\"hello\"")]
+            ))
+        );
+        assert_eq!(
+            packet("[1,\"hello\"]").event_payload().unwrap(),
+            Some((
+                Event::Message,
+                String::from("\"hello\""),
+                vec![String::from("\"hello\"")]
+            ))
+        );
+        assert_eq!(packet("{\"id\":1}").event_payload().unwrap(), None);
+        assert!(matches!(
+            packet("[\"update\",").event_payload(),
+            Err(Error::InvalidJson(_))
+        ));
+        assert!(matches!(
+            packet("[]").event_payload(),
+            Err(Error::IncompletePacket())
+        ));
+    }
+
     #[test]
     fn test_illegal_packet_id() {
         let _sut = PacketId::try_from(42).expect_err("error!");
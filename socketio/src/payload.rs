@@ -1,16 +1,76 @@
+use crate::error::Result;
+use crate::Event;
 use bytes::Bytes;
+use serde_json::value::RawValue;
+use std::borrow::Cow;
 
 /// A type which represents a `payload` in the `socket.io` context.
 /// A payload could either be of the type `Payload::Binary`, which holds
 /// data in the [`Bytes`] type that represents the payload or of the type
 /// `Payload::String` which holds a [`std::string::String`]. The enum is
 /// used for both representing data that's send and data that's received.
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// Received payloads are either binary or strings holding JSON, only the
+/// messages passed to the callbacks of [`Event::Close`] and [`Event::Error`]
+/// are texts.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Payload {
     Binary(Bytes),
+    /// A string holding JSON. Strings which aren't valid JSON are sent like
+    /// [`Payload::Text`].
     String(String),
+    /// JSON data, which is sent as it is.
+    Json(Box<RawValue>),
+    /// A text, which is always sent as a JSON string.
+    Text(String),
 }
 
+impl Payload {
+    /// Encodes the payload as the data of an event packet, `["event",payload]`.
+    /// Binary payloads are sent as attachments, so their data is only the event.
+    pub(crate) fn event_data(&self, event: Event) -> Result<String> {
+        let event = serde_json::to_string(&String::from(event))?;
+        match self.to_json()? {
+            Some(json) => Ok(format!("[{},{}]", event, json)),
+            None => Ok(event),
+        }
+    }
+
+    /// Encodes the payload as the data of an ack packet, `[payload]`, which is
+    /// `None` for binary payloads.
+    pub(crate) fn ack_data(&self) -> Result<Option<String>> {
+        Ok(self.to_json()?.map(|json| format!("[{}]", json)))
+    }
+
+    /// Encodes the payload as JSON, `None` for binary payloads.
+    fn to_json(&self) -> Result<Option<Cow<'_, str>>> {
+        match self {
+            Payload::Binary(_) => Ok(None),
+            Payload::String(string) if serde_json::from_str::<&RawValue>(string).is_ok() => {
+                Ok(Some(Cow::Borrowed(string)))
+            }
+            Payload::String(text) | Payload::Text(text) => {
+                Ok(Some(Cow::Owned(serde_json::to_string(text)?)))
+            }
+            Payload::Json(json) => Ok(Some(Cow::Borrowed(json.get()))),
+        }
+    }
+}
+
+impl PartialEq for Payload {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Payload::Binary(a), Payload::Binary(b)) => a == b,
+            (Payload::String(a), Payload::String(b)) => a == b,
+            (Payload::Json(a), Payload::Json(b)) => a.get() == b.get(),
+            (Payload::Text(a), Payload::Text(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Payload {}
+
 impl From<&str> for Payload {
     fn from(string: &str) -> Self {
         Self::String(string.to_owned())
@@ -25,7 +85,14 @@ impl From<String> for Payload {
 
 impl From<serde_json::Value> for Payload {
     fn from(value: serde_json::Value) -> Self {
-        Self::String(value.to_string())
+        // SAFETY: a value always serializes
+        Self::Json(serde_json::value::to_raw_value(&value).unwrap())
+    }
+}
+
+impl From<Box<RawValue>> for Payload {
+    fn from(json: Box<RawValue>) -> Self {
+        Self::Json(json)
     }
 }
 
@@ -51,7 +118,8 @@ impl AsRef<[u8]> for Payload {
     fn as_ref(&self) -> &[u8] {
         match self {
             Payload::Binary(b) => b.as_ref(),
-            Payload::String(s) => s.as_ref(),
+            Payload::String(s) | Payload::Text(s) => s.as_ref(),
+            Payload::Json(json) => json.get().as_ref(),
         }
     }
 }
@@ -72,7 +140,11 @@ mod tests {
         assert_eq!(Payload::String(String::from("foo ™")), sut);
 
         let sut = Payload::from(json!("foo ™"));
-        assert_eq!(Payload::String(String::from("\"foo ™\"")), sut);
+        assert_eq!(
+            Payload::Json(RawValue::from_string(String::from("\"foo ™\"")).unwrap()),
+            sut
+        );
+        assert_eq!(sut.as_ref(), "\"foo ™\"".as_bytes());
 
         let sut = Payload::from(vec![1, 2, 3]);
         assert_eq!(Payload::Binary(Bytes::from_static(&[1, 2, 3])), sut);
@@ -83,4 +155,36 @@ mod tests {
         let sut = Payload::from(Bytes::from_static(&[1, 2, 3]));
         assert_eq!(Payload::Binary(Bytes::from_static(&[1, 2, 3])), sut);
     }
+
+    #[test]
+    fn test_event_data() {
+        let event = Event::from("say \"hi\"");
+
+        let sut = Payload::from(json!({"text": "a \"quote\"\n"}));
+        assert_eq!(
+            sut.event_data(event.clone()).unwrap(),
+            r#"["say \"hi\"",{"text":"a \"quote\"\n"}]"#
+        );
+
+        let sut = Payload::Text(String::from("{\"not\": \"json\"}"));
+        assert_eq!(
+            sut.event_data(event.clone()).unwrap(),
+            r#"["say \"hi\"","{\"not\": \"json\"}"]"#
+        );
+
+        let sut = Payload::from("[1, 2]");
+        assert_eq!(
+            sut.event_data(event.clone()).unwrap(),
+            r#"["say \"hi\"",[1, 2]]"#
+        );
+
+        let sut = Payload::from("a \"quote\"\n");
+        assert_eq!(
+            sut.event_data(event.clone()).unwrap(),
+            r#"["say \"hi\"","a \"quote\"\n"]"#
+        );
+
+        let sut = Payload::from(vec![1, 2, 3]);
+        assert_eq!(sut.event_data(event).unwrap(), r#""say \"hi\"""#);
+    }
 }
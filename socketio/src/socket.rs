@@ -1,9 +1,12 @@
+use crate::disconnect::DisconnectReason;
 use crate::error::{Error, Result};
+use crate::metrics::{Metrics, MetricsSnapshot};
 use crate::packet::{Packet, PacketId};
 use bytes::Bytes;
 use rust_engineio::{Client as EngineClient, Packet as EnginePacket, PacketId as EnginePacketId};
 use std::convert::TryFrom;
 use std::sync::{atomic::AtomicBool, Arc};
+use std::time::{Duration, Instant};
 use std::{fmt::Debug, sync::atomic::Ordering};
 
 use super::{event::Event, payload::Payload};
@@ -14,15 +17,16 @@ pub(crate) struct Socket {
     //TODO: 0.4.0 refactor this
     engine_client: Arc<EngineClient>,
     connected: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
 }
 
 impl Socket {
     /// Creates an instance of `Socket`.
-
-    pub(super) fn new(engine_client: EngineClient) -> Result<Self> {
+    pub(super) fn new(engine_client: EngineClient, metrics: Arc<Metrics>) -> Result<Self> {
         Ok(Socket {
             engine_client: Arc::new(engine_client),
             connected: Arc::new(AtomicBool::default()),
+            metrics,
         })
     }
 
@@ -39,15 +43,28 @@ impl Socket {
     }
 
     /// Disconnects from the server by sending a socket.io `Disconnect` packet. This results
-    /// in the underlying engine.io transport to get closed as well.
-    pub fn disconnect(&self) -> Result<()> {
+    /// in the underlying engine.io transport to get closed as well. Returns
+    /// whether the socket was connected.
+    pub fn disconnect(&self) -> Result<bool> {
         if self.is_engineio_connected()? {
             self.engine_client.disconnect()?;
         }
-        if self.connected.load(Ordering::Acquire) {
-            self.connected.store(false, Ordering::Release);
+        Ok(self.connected.swap(false, Ordering::AcqRel))
+    }
+
+    /// Returns the reason the socket closed for if the engine.io connection
+    /// closed while it was connected, once.
+    pub(crate) fn take_close_reason(&self) -> Option<DisconnectReason> {
+        if self.engine_client.is_connected().unwrap_or(false)
+            || !self.connected.swap(false, Ordering::AcqRel)
+        {
+            return None;
         }
-        Ok(())
+        Some(
+            self.engine_client
+                .close_reason()
+                .map_or(DisconnectReason::TransportClose, Into::into),
+        )
     }
 
     /// Sends a `socket.io` packet to the server using the `engine.io` client.
@@ -56,22 +73,32 @@ impl Socket {
             return Err(Error::IllegalActionBeforeOpen());
         }
 
-        // the packet, encoded as an engine.io message packet
-        let engine_packet = EnginePacket::new(EnginePacketId::Message, Bytes::from(&packet));
-        self.engine_client.emit(engine_packet)?;
-
+        // the packet, encoded as an engine.io message packet, is sent together
+        // with its attachments
+        let data = Bytes::from(&packet);
+        event!(
+            trace,
+            packet_type = packet.packet_type,
+            nsp = packet.nsp,
+            size = data.len(),
+            ack_id = packet.id,
+            attachments = packet.attachment_count;
+            "sending packet"
+        );
+        let attachments_len: usize = packet.attachments.iter().flatten().map(Bytes::len).sum();
+        self.metrics.sent(&packet, data.len() + attachments_len);
+        let mut engine_packets = vec![EnginePacket::new(EnginePacketId::Message, data)];
         if let Some(attachments) = packet.attachments {
             for attachment in attachments {
-                let engine_packet = EnginePacket::new(EnginePacketId::MessageBinary, attachment);
-                self.engine_client.emit(engine_packet)?;
+                engine_packets.push(EnginePacket::new(EnginePacketId::MessageBinary, attachment));
             }
         }
+        self.engine_client.emit_batch(engine_packets)?;
 
         Ok(())
     }
 
-    /// Emits to certain event with given data. The data needs to be JSON,
-    /// otherwise this returns an `InvalidJson` error.
+    /// Emits to certain event with given data.
     pub fn emit(&self, nsp: &str, event: Event, data: Payload) -> Result<()> {
         let socket_packet = self.build_packet_for_payload(data, event, nsp, None)?;
 
@@ -88,6 +115,7 @@ impl Socket {
         nsp: &'a str,
         id: Option<i32>,
     ) -> Result<Packet> {
+        let data = payload.event_data(event)?;
         match payload {
             Payload::Binary(bin_data) => Ok(Packet::new(
                 if id.is_some() {
@@ -96,33 +124,43 @@ impl Socket {
                     PacketId::BinaryEvent
                 },
                 nsp.to_owned(),
-                Some(serde_json::Value::String(event.into()).to_string()),
+                Some(data),
                 id,
                 1,
                 Some(vec![bin_data]),
             )),
-            Payload::String(str_data) => {
-                let payload = if serde_json::from_str::<serde_json::Value>(&str_data).is_ok() {
-                    format!("[\"{}\",{}]", String::from(event), str_data)
-                } else {
-                    format!("[\"{}\",\"{}\"]", String::from(event), str_data)
-                };
-
-                Ok(Packet::new(
-                    PacketId::Event,
-                    nsp.to_owned(),
-                    Some(payload),
-                    id,
-                    0,
-                    None,
-                ))
-            }
+            _ => Ok(Packet::new(
+                PacketId::Event,
+                nsp.to_owned(),
+                Some(data),
+                id,
+                0,
+                None,
+            )),
         }
     }
 
     pub(crate) fn poll(&self) -> Result<Option<Packet>> {
+        self.poll_with(|client| client.poll())
+    }
+
+    /// Polls for the next packet, waiting at most `timeout` for it. Returns
+    /// `None` if none arrived in time or the connection closed.
+    pub(crate) fn poll_timeout(&self, timeout: Duration) -> Result<Option<Packet>> {
+        let deadline = Instant::now() + timeout;
+        self.poll_with(|client| {
+            client.poll_timeout(deadline.saturating_duration_since(Instant::now()))
+        })
+    }
+
+    /// Polls the engine.io client with `poll` until a message arrived, pings
+    /// are answered by the client in between.
+    fn poll_with<F>(&self, poll: F) -> Result<Option<Packet>>
+    where
+        F: Fn(&EngineClient) -> std::result::Result<Option<EnginePacket>, rust_engineio::Error>,
+    {
         loop {
-            match self.engine_client.poll() {
+            match poll(&self.engine_client) {
                 Ok(Some(packet)) => {
                     if packet.packet_id == EnginePacketId::Message
                         || packet.packet_id == EnginePacketId::MessageBinary
@@ -162,6 +200,15 @@ impl Socket {
     /// Handles new incoming engineio packets
     fn handle_engineio_packet(&self, packet: EnginePacket) -> Result<Packet> {
         let mut socket_packet = Packet::try_from(&packet.data)?;
+        event!(
+            trace,
+            packet_type = socket_packet.packet_type,
+            nsp = socket_packet.nsp,
+            size = packet.data.len(),
+            ack_id = socket_packet.id,
+            attachments = socket_packet.attachment_count;
+            "received packet"
+        );
 
         // Only handle attachments if there are any
         if socket_packet.attachment_count > 0 {
@@ -190,10 +237,28 @@ impl Socket {
             }
             socket_packet.attachments = Some(attachments);
         }
+        let attachments_len: usize = socket_packet
+            .attachments
+            .iter()
+            .flatten()
+            .map(Bytes::len)
+            .sum();
+        self.metrics
+            .received(&socket_packet, packet.data.len() + attachments_len);
 
         Ok(socket_packet)
     }
 
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns a snapshot of the metrics, including the ones of the engine.io
+    /// client.
+    pub(crate) fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot(self.engine_client.metrics())
+    }
+
     fn is_engineio_connected(&self) -> Result<bool> {
         Ok(self.engine_client.is_connected()?)
     }
@@ -0,0 +1,54 @@
+//! Shims over `tracing`, which fall back to `log` without the `tracing`
+//! feature.
+
+/// Emits an event at `$level` with fields recorded as `Debug` values, e.g.
+/// `event!(warn, nsp = nsp; "callback failed")`.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident $(, $field:ident = $value:expr)* ; $($arg:tt)+) => {
+        tracing::$level!($($field = ?$value,)* $($arg)+)
+    };
+}
+
+/// Logs the message of an event at `$level`, the fields are dropped.
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident $(, $field:ident = $value:expr)* ; $($arg:tt)+) => {{
+        let _ = ($(&$value,)*);
+        log::$level!($($arg)+)
+    }};
+}
+
+/// Creates a span at `$level` with fields recorded as `Debug` values, e.g.
+/// `span!(INFO, "socketio.reconnect", url = url)`.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($level:ident, $name:literal $(, $field:ident = $value:expr)*) => {
+        tracing::span!(tracing::Level::$level, $name $(, $field = ?$value)*)
+    };
+}
+
+/// Creates a span which records nothing.
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($level:ident, $name:literal $(, $field:ident = $value:expr)*) => {{
+        let _ = ($(&$value,)*);
+        $crate::trace::Span
+    }};
+}
+
+/// A span which records nothing.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Span;
+
+/// The guard of an entered [`Span`].
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+}